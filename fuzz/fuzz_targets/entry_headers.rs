@@ -0,0 +1,31 @@
+//! 逐个遍历条目头部并读取尾部区段，不读取条目内容
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use xpak::{read_entry_header, read_trailer, read_trailer_at, PathLength, RawArchive};
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    /// 单独解析条目头部时使用的路径长度编码
+    encoding: PathLength,
+    data: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let mut cursor = Cursor::new(input.data);
+    while read_entry_header(&mut cursor, input.encoding).is_ok() {}
+
+    if let Ok(mut archive) = RawArchive::from_bytes(input.data) {
+        // 条目数量由输入决定，读取失败或读完后停止
+        while let Ok(Some(entry)) = archive.next_entry() {
+            assert!(entry.offset <= input.data.len() as u64);
+        }
+        let _ = archive.trailer();
+    }
+
+    let mut cursor = Cursor::new(input.data);
+    let _ = read_trailer(&mut cursor);
+    let _ = read_trailer_at(&mut cursor, 0);
+});
@@ -0,0 +1,19 @@
+//! 解析包头：Magic Number、metadata长度字段、结束标记和条目数量，以及metadata中的版本探测
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use xpak::{probe_format_version, PathLength, RawArchive};
+
+fuzz_target!(|data: &[u8]| {
+    // 直接把输入当作metadata，检查不解析JSON的版本探测
+    let _ = PathLength::for_metadata(data);
+
+    // 把输入当作整个包，metadata长度字段由输入决定
+    if let Ok(archive) = RawArchive::from_bytes(data) {
+        assert!(archive.data_offset() <= data.len() as u64);
+        let metadata = archive.metadata();
+        if let Some(version) = probe_format_version(metadata) {
+            let _ = PathLength::for_version(version);
+        }
+    }
+});
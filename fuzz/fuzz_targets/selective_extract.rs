@@ -0,0 +1,49 @@
+//! 只读取选中条目的内容并跳过其余条目，与`unpak --only`的读取方式相同
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use std::io::Read;
+use xpak::RawArchive;
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    /// 要读取内容的条目序号
+    selected: Vec<u32>,
+    /// 读取选中条目时每次读取的字节数，为0时一次读完
+    chunk: u16,
+    data: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let Ok(mut archive) = RawArchive::from_bytes(input.data) else {
+        return;
+    };
+    // 第二遍在读取尾部区段后回到开头，检查重新定位
+    for _ in 0..2 {
+        while let Ok(Some(entry)) = archive.next_entry() {
+            if !input.selected.contains(&entry.index) {
+                continue;
+            }
+            let mut content = Vec::new();
+            let result = if input.chunk == 0 {
+                archive.by_ref().read_to_end(&mut content).map(|_| ())
+            } else {
+                let mut buf = vec![0u8; input.chunk as usize];
+                loop {
+                    match archive.read(&mut buf) {
+                        Ok(0) => break Ok(()),
+                        Ok(n) => content.extend_from_slice(&buf[..n]),
+                        Err(e) => break Err(e),
+                    }
+                }
+            };
+            if result.is_ok() {
+                assert_eq!(content.len() as u64, entry.size);
+            }
+        }
+        if archive.trailer().is_err() {
+            return;
+        }
+    }
+});
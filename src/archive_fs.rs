@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use vfs::error::VfsErrorKind;
+use vfs::{FileSystem, SeekAndRead, VfsMetadata, VfsFileType, VfsPath, VfsResult};
+
+use crate::common::format_size;
+use crate::names::printable;
+use crate::reader::XpakReader;
+use crate::vpath::{lookup_key, PathPolicy};
+
+/// 把包作为只读文件系统使用，实现`vfs`库的`FileSystem`
+///
+/// 目录由条目路径推出，不占用包内的条目。读取文件时通过`open_entry`按需解压，
+/// 每个打开的文件使用独立的文件句柄；加密的条目无法打开。
+pub struct ArchiveFs {
+    reader: XpakReader,
+    /// 目录 -> 其中的文件和子目录名，根目录为空字符串
+    dirs: BTreeMap<String, BTreeSet<String>>,
+    /// 文件 -> 解压后的大小，路径重复时以最后一个条目为准
+    files: HashMap<String, u64>,
+}
+
+impl ArchiveFs {
+    pub fn new(reader: XpakReader) -> Self {
+        let mut dirs: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut files = HashMap::new();
+        dirs.insert(String::new(), BTreeSet::new());
+        for info in reader.metadata.live_files() {
+            let key = lookup_key(&info.path, PathPolicy::Exact);
+            let mut parent = String::new();
+            for component in key.split('/') {
+                dirs.entry(parent.clone()).or_default().insert(component.to_string());
+                if !parent.is_empty() {
+                    parent.push('/');
+                }
+                parent.push_str(component);
+            }
+            files.insert(key, info.size);
+        }
+        Self { reader, dirs, files }
+    }
+}
+
+impl fmt::Debug for ArchiveFs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveFs").field("files", &self.files.len()).finish()
+    }
+}
+
+impl FileSystem for ArchiveFs {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let Some(children) = self.dirs.get(&lookup_key(path, PathPolicy::Exact)) else {
+            return Err(VfsErrorKind::FileNotFound.into());
+        };
+        Ok(Box::new(children.clone().into_iter()))
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        Ok(Box::new(self.reader.open_entry(&lookup_key(path, PathPolicy::Exact))?))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let key = lookup_key(path, PathPolicy::Exact);
+        if let Some(&len) = self.files.get(&key) {
+            Ok(VfsMetadata { file_type: VfsFileType::File, len })
+        } else if self.dirs.contains_key(&key) {
+            Ok(VfsMetadata { file_type: VfsFileType::Directory, len: 0 })
+        } else {
+            Err(VfsErrorKind::FileNotFound.into())
+        }
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        let key = lookup_key(path, PathPolicy::Exact);
+        Ok(self.files.contains_key(&key) || self.dirs.contains_key(&key))
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn Write + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn Write + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+}
+
+fn to_io(e: vfs::VfsError) -> io::Error {
+    match e.kind() {
+        VfsErrorKind::FileNotFound => io::Error::new(io::ErrorKind::NotFound, e.to_string()),
+        _ => io::Error::other(e.to_string()),
+    }
+}
+
+/// 像目录一样列出包内某一层的文件和子目录
+pub fn ls(input: &str, dir: &str) -> io::Result<()> {
+    let root = VfsPath::new(XpakReader::open(input)?.to_vfs()?);
+    let dir_path = root.join(dir.trim_matches('/')).map_err(to_io)?;
+    if !dir_path.is_dir().map_err(to_io)? {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("包中没有目录 {}", dir)));
+    }
+    for entry in dir_path.read_dir().map_err(to_io)? {
+        let metadata = entry.metadata().map_err(to_io)?;
+        match metadata.file_type {
+            VfsFileType::Directory => println!("{:>10}  {}/", "-", printable(&entry.filename())),
+            VfsFileType::File => println!("{:>10}  {}", format_size(metadata.len), printable(&entry.filename())),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! 修改包之前保存备份，修改中断或结果不对时用`rollback`恢复
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::common::format_size;
+use crate::config::Config;
+use crate::reader::XpakReader;
+use crate::temp::TempFile;
+
+/// 包`input`的备份路径
+fn backup_path(input: &str) -> String {
+    format!("{}.bak", input)
+}
+
+/// 按命令行参数或配置文件的`backup.required`在修改包之前保存备份
+///
+/// 通过临时文件重写再替换的命令（`in_place`为假）用硬链接保存备份，不占用额外空间；
+/// 原地写入的命令会修改同一个文件，只能复制。已有的备份会被覆盖。
+pub fn prepare(input: &str, requested: bool, in_place: bool) -> io::Result<()> {
+    if !requested && !Config::load()?.backup.required {
+        return Ok(());
+    }
+    let backup = backup_path(input);
+    if !in_place {
+        let _ = fs::remove_file(&backup);
+    }
+    if in_place || fs::hard_link(input, &backup).is_err() {
+        // 先复制到临时文件，复制中断时不会留下不完整的备份
+        let temp = TempFile::new(&backup)?;
+        fs::copy(input, temp.path())?;
+        temp.commit(&backup)?;
+    }
+    println!("已备份到 {}", backup);
+    Ok(())
+}
+
+/// 用备份替换包，备份本身不能打开时不替换
+pub fn rollback(input: &str) -> io::Result<()> {
+    let backup = backup_path(input);
+    if !Path::new(&backup).exists() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("没有找到备份 {}", backup)));
+    }
+    XpakReader::open(&backup)
+        .map_err(|e| io::Error::new(e.kind(), format!("备份 {} 无法打开，没有恢复: {}", backup, e)))?;
+    let size = fs::metadata(&backup)?.len();
+    fs::rename(&backup, input)?;
+    // 包没有被修改过时备份与包是同一个文件的硬链接，此时rename不做任何事
+    if Path::new(&backup).exists() {
+        fs::remove_file(&backup)?;
+    }
+    println!("已用 {} 恢复 {}（{}）", backup, input, format_size(size));
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! 分组：把条目归入命名的分组，下载和解包都可以按分组进行
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+use crate::metadata::XpakMetadata;
+
+/// 读取分组清单，每行一条`GLOB=BUNDLE`规则，忽略空行和`#`开头的注释
+///
+/// 与优先级不同，条目属于所有匹配规则的分组，没有匹配任何规则的条目不属于任何分组。
+pub fn load_manifest(path: &str) -> io::Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(path)?;
+    let mut rules = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let rule = line.rsplit_once('=')
+            .map(|(glob, bundle)| (glob.trim(), bundle.trim()))
+            .filter(|(glob, bundle)| !glob.is_empty() && valid_name(bundle))
+            .ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} 第 {} 行: 应为 GLOB=BUNDLE，分组名只能包含字母、数字、'_'和'-'", path, number + 1)
+            ))?;
+        rules.push((rule.0.to_string(), rule.1.to_string()));
+    }
+    Ok(rules)
+}
+
+/// 分组名会出现在命令行的逗号分隔列表中
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// 各分组中的条目路径，不存在的分组报错并列出包中已有的分组
+pub fn resolve(bundles: &BTreeMap<String, Vec<String>>, names: &[String]) -> io::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for name in names {
+        let members = bundles.get(name).ok_or_else(|| {
+            let known = if bundles.is_empty() {
+                "包中没有分组".to_string()
+            } else {
+                format!("包中的分组: {}", bundles.keys().cloned().collect::<Vec<_>>().join(", "))
+            };
+            io::Error::new(io::ErrorKind::NotFound, format!("没有名为 {} 的分组，{}", name, known))
+        })?;
+        paths.extend(members.iter().cloned());
+    }
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// 各分组的名称和条目数，用于`info`
+pub fn summary(metadata: &XpakMetadata) -> String {
+    if metadata.bundles.is_empty() {
+        return "无".to_string();
+    }
+    metadata.bundles.iter()
+        .map(|(name, paths)| format!("{}（{} 个条目）", name, paths.len()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
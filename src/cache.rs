@@ -0,0 +1,88 @@
+//! `XpakReader::read`使用的解压结果缓存，按总大小限制，超出时淘汰最久未使用的条目
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// 缓存的命中统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// 因超出容量被淘汰的条目数
+    pub evictions: u64,
+    /// 当前缓存的条目数和总大小
+    pub entries: usize,
+    pub bytes: u64,
+    pub capacity: u64,
+}
+
+impl CacheStats {
+    /// 命中率，还没有读取时为0
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 { 0.0 } else { self.hits as f64 / total as f64 }
+    }
+}
+
+struct Cached {
+    data: Arc<[u8]>,
+    last_used: u64,
+}
+
+/// 条目序号 -> 解压后的内容
+pub struct EntryCache {
+    entries: HashMap<usize, Cached>,
+    /// 每次访问递增，用于找出最久未使用的条目
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl EntryCache {
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            clock: 0,
+            stats: CacheStats { capacity, ..Default::default() },
+        }
+    }
+
+    /// 查找缓存的内容，同时记录命中或未命中
+    pub fn get(&mut self, index: usize) -> Option<Arc<[u8]>> {
+        self.clock += 1;
+        match self.entries.get_mut(&index) {
+            Some(cached) => {
+                cached.last_used = self.clock;
+                self.stats.hits += 1;
+                Some(cached.data.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// 放入缓存，超过容量的条目不缓存；放入后超出容量时淘汰最久未使用的条目
+    pub fn insert(&mut self, index: usize, data: Arc<[u8]>) {
+        let len = data.len() as u64;
+        if len > self.stats.capacity {
+            return;
+        }
+        while self.stats.bytes + len > self.stats.capacity {
+            let Some(oldest) = self.entries.iter().min_by_key(|(_, c)| c.last_used).map(|(&i, _)| i) else {
+                break;
+            };
+            let evicted = self.entries.remove(&oldest).unwrap();
+            self.stats.bytes -= evicted.data.len() as u64;
+            self.stats.evictions += 1;
+        }
+        self.stats.bytes += len;
+        if let Some(replaced) = self.entries.insert(index, Cached { data, last_used: self.clock }) {
+            self.stats.bytes -= replaced.data.len() as u64;
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats { entries: self.entries.len(), ..self.stats }
+    }
+}
@@ -0,0 +1,325 @@
+//! 条目头部无法按顺序读取时，按启发式规则在包中搜索条目边界并提取文件
+//!
+//! 在每个位置尝试把字节解析为条目头部：路径长度字段、可打印的UTF-8相对路径、不超出
+//! 数据区段的大小。一个命中的可信度取决于它与其他结构能否对上：metadata中是否有同样
+//! 路径和大小的条目，条目之后是否紧接另一个合理的条目头部或数据区段的末尾。
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::common::{format_size, PathLength, MAGIC_METADATA_END, MAGIC_NUMBER, MAX_PATH_LEN};
+use crate::metadata::XpakMetadata;
+use crate::migrate::{has_metadata_end, parse_metadata};
+use crate::names::printable;
+use crate::trailer::read_trailer;
+use xpak::read_metadata;
+
+/// 每次读入内存的范围
+const WINDOW_SIZE: usize = 1024 * 1024;
+/// 评估命中时最多向后检查的条目头部数
+const MAX_LINKS: u32 = 3;
+
+/// 命中的可信度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    fn from_score(score: u32) -> Self {
+        match score {
+            0..=1 => Confidence::Low,
+            2 => Confidence::Medium,
+            _ => Confidence::High,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Confidence::Low => "低",
+            Confidence::Medium => "中",
+            Confidence::High => "高",
+        }
+    }
+}
+
+/// 解析出的条目头部
+struct Header {
+    path: String,
+    /// 头部占用的字节数
+    len: u64,
+    size: u64,
+}
+
+/// 一个可能的条目
+struct Hit {
+    offset: u64,
+    header: Header,
+    encoding: PathLength,
+    confidence: Confidence,
+    reasons: Vec<&'static str>,
+    /// metadata中同一条目的内容经过压缩或加密
+    encoded: bool,
+}
+
+impl Hit {
+    fn data_start(&self) -> u64 {
+        self.offset + self.header.len
+    }
+
+    fn data_end(&self) -> u64 {
+        self.data_start() + self.header.size
+    }
+}
+
+/// 在文件上滑动的读取窗口，扫描基本是顺序的，很少需要重新读取
+struct Window {
+    file: File,
+    base: u64,
+    data: Vec<u8>,
+    /// 扫描范围的终点，之后的字节（尾部区段）不视为条目的一部分
+    end: u64,
+}
+
+impl Window {
+    /// `position`起的`len`字节，超出扫描范围时返回`None`
+    fn get(&mut self, position: u64, len: usize) -> io::Result<Option<&[u8]>> {
+        if position + len as u64 > self.end {
+            return Ok(None);
+        }
+        if position < self.base || position + len as u64 > self.base + self.data.len() as u64 {
+            let want = (self.end - position).min(WINDOW_SIZE.max(len) as u64);
+            self.file.seek(SeekFrom::Start(position))?;
+            self.data.clear();
+            (&mut self.file).take(want).read_to_end(&mut self.data)?;
+            self.base = position;
+            if (self.data.len() as u64) < len as u64 {
+                return Ok(None);
+            }
+        }
+        let start = (position - self.base) as usize;
+        Ok(Some(&self.data[start..start + len]))
+    }
+
+    fn header_at(&mut self, position: u64, encoding: PathLength) -> io::Result<Option<Header>> {
+        let field_len = match encoding {
+            PathLength::U32 => 4,
+            PathLength::Varint => 1,
+        };
+        let Some(field) = self.get(position, field_len)? else { return Ok(None) };
+        // 变长整数先只看第一个字节，绝大多数位置在这里就能排除
+        let (path_len, field_len) = match encoding {
+            PathLength::U32 => (u32::from_le_bytes(field.try_into().unwrap()) as usize, 4),
+            PathLength::Varint if field[0] & 0x80 == 0 => (field[0] as usize, 1),
+            PathLength::Varint => {
+                let Some(field) = self.get(position, 3)? else { return Ok(None) };
+                match encoding.read(&mut &field[..]) {
+                    Ok((len, n)) => (len, n as usize),
+                    Err(_) => return Ok(None),
+                }
+            }
+        };
+        if path_len == 0 || path_len > MAX_PATH_LEN {
+            return Ok(None);
+        }
+        let path_start = position + field_len as u64;
+        let end = self.end;
+        let Some(bytes) = self.get(path_start, path_len + 4)? else { return Ok(None) };
+        let Ok(path) = std::str::from_utf8(&bytes[..path_len]) else { return Ok(None) };
+        if !plausible_path(path) {
+            return Ok(None);
+        }
+        let size = u32::from_le_bytes(bytes[path_len..].try_into().unwrap()) as u64;
+        let len = field_len as u64 + path_len as u64 + 4;
+        if size > end - position - len {
+            return Ok(None);
+        }
+        Ok(Some(Header { path: path.to_string(), len, size }))
+    }
+}
+
+/// 像是打包时记录的相对路径：没有控制字符、空组件、`.`或`..`，不以`/`开头
+fn plausible_path(path: &str) -> bool {
+    path.split('/').all(|c| !c.is_empty() && c != "." && c != "..")
+        && !path.chars().any(|c| c.is_control() || c == '\\' || c == char::REPLACEMENT_CHARACTER)
+        && path.chars().any(char::is_alphanumeric)
+}
+
+/// 从损坏的包中尽量找回文件，`all`时可信度低的命中也提取
+pub fn carve(input: &str, output: &str, all: bool) -> io::Result<()> {
+    let mut file = File::open(input)?;
+    let file_len = file.metadata()?.len();
+
+    // 能读出metadata时从数据区段开始扫描，并用其中的条目列表核对命中
+    let (metadata, start) = match read_header(&mut file, file_len) {
+        Some((metadata, data_offset)) => (Some(metadata), data_offset),
+        None => {
+            println!("无法读取metadata，从文件开头扫描，只能根据条目之间的衔接判断可信度");
+            (None, 0)
+        }
+    };
+    let end = match read_trailer(&mut file) {
+        Ok(Some(trailer)) if trailer.offset >= start => trailer.offset,
+        _ => file_len,
+    };
+    let known: HashMap<&str, (u64, bool)> = metadata.iter()
+        .flat_map(|m| &m.files)
+        .map(|f| (f.path.as_str(), (f.stored_size.unwrap_or(f.size), f.compression.is_some() || f.encryption.is_some())))
+        .collect();
+    let mut encodings = match &metadata {
+        Some(metadata) => vec![metadata.path_length()],
+        None => vec![PathLength::U32, PathLength::Varint],
+    };
+
+    let mut window = Window { file: File::open(input)?, base: 0, data: Vec::new(), end };
+    let mut hits = Vec::new();
+    let mut position = start;
+    while position < end {
+        let mut best: Option<Hit> = None;
+        for &encoding in &encodings {
+            let Some(header) = window.header_at(position, encoding)? else { continue };
+            let hit = evaluate(&mut window, &known, position, header, encoding)?;
+            if best.as_ref().is_none_or(|b| hit.confidence > b.confidence) {
+                best = Some(hit);
+            }
+        }
+        match best {
+            // 可信的命中之后从条目末尾继续，跳过内容中可能出现的类似头部的字节
+            Some(hit) if hit.confidence >= Confidence::Medium => {
+                encodings = vec![hit.encoding];
+                position = hit.data_end().max(position + 1);
+                hits.push(hit);
+            }
+            Some(hit) => {
+                hits.push(hit);
+                position += 1;
+            }
+            None => position += 1,
+        }
+    }
+
+    let output = Path::new(output);
+    fs::create_dir_all(output)?;
+    let mut extracted = 0;
+    let mut skipped = 0;
+    for hit in &hits {
+        print!(
+            "[{}] {:#x} {} ({})",
+            hit.confidence.label(), hit.offset, printable(&hit.header.path), format_size(hit.header.size)
+        );
+        if !hit.reasons.is_empty() {
+            print!(" — {}", hit.reasons.join("，"));
+        }
+        if hit.encoded {
+            print!(" — 内容经过压缩或加密，按包内存储的原样保存");
+        }
+        if hit.confidence == Confidence::Low && !all {
+            println!(" — 未提取");
+            skipped += 1;
+            continue;
+        }
+        println!();
+        // 误判的命中可能与已提取的文件冲突（如同名的文件和目录），不影响其余命中
+        match extract(&mut file, output, hit) {
+            Ok(path) => {
+                if path != output.join(&hit.header.path) {
+                    println!("    已保存为 {}", path.display());
+                }
+                extracted += 1;
+            }
+            Err(e) => println!("    无法保存: {}", e),
+        }
+    }
+
+    println!("----------------------------------------");
+    println!("找到 {} 个可能的条目，已提取 {} 个到 {}", hits.len(), extracted, output.display());
+    if skipped > 0 {
+        println!("{} 个可信度低的命中未提取，可使用 --all 一并提取", skipped);
+    }
+    Ok(())
+}
+
+/// 读取包头和metadata，返回metadata和数据区段的起点，任何一步失败都返回`None`
+fn read_header(file: &mut File, file_len: u64) -> Option<(XpakMetadata, u64)> {
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic).ok()?;
+    if magic != MAGIC_NUMBER {
+        return None;
+    }
+    let metadata_bytes = read_metadata(file, file_len).ok()?;
+    let mut metadata = parse_metadata(&metadata_bytes).ok()?;
+    let mut data_offset = 8 + metadata_bytes.len() as u64;
+    if has_metadata_end(&metadata.format_version) {
+        data_offset += MAGIC_METADATA_END.len() as u64;
+    }
+    data_offset += 4;
+    // 条目列表拆分到尾部区段且无法读取时只核对包头中的部分
+    let _ = metadata.load_files(file, data_offset);
+    Some((metadata, data_offset))
+}
+
+/// 根据metadata和条目之后的内容给命中打分
+fn evaluate(
+    window: &mut Window,
+    known: &HashMap<&str, (u64, bool)>,
+    offset: u64,
+    header: Header,
+    encoding: PathLength
+) -> io::Result<Hit> {
+    let mut score = 0;
+    let mut reasons = Vec::new();
+    let mut encoded = false;
+    match known.get(header.path.as_str()) {
+        Some(&(size, is_encoded)) if size == header.size => {
+            score += 2;
+            reasons.push("路径和大小与metadata一致");
+            encoded = is_encoded;
+        }
+        Some(_) => {
+            score += 1;
+            reasons.push("metadata中有该路径，大小不一致");
+        }
+        None => {}
+    }
+
+    // 之后再看至多`MAX_LINKS`个条目头部，恰好结束在数据区段末尾算作完整的衔接
+    let mut next = offset + header.len + header.size;
+    let mut links = 0;
+    while links < MAX_LINKS && next < window.end {
+        let Some(following) = window.header_at(next, encoding)? else { break };
+        next += following.len + following.size;
+        links += 1;
+    }
+    if next == window.end {
+        reasons.push("与之后的条目衔接到数据区段末尾");
+        links = MAX_LINKS;
+    } else if links == 1 {
+        reasons.push("之后紧接一个合理的条目头部");
+    } else if links > 1 {
+        reasons.push("之后紧接多个合理的条目头部");
+    }
+    score += links;
+
+    Ok(Hit { offset, header, encoding, confidence: Confidence::from_score(score), reasons, encoded })
+}
+
+/// 把命中的内容写到`output`下，与已提取的文件重名时在文件名后加上偏移
+fn extract(file: &mut File, output: &Path, hit: &Hit) -> io::Result<PathBuf> {
+    let mut path = output.join(&hit.header.path);
+    if path.exists() {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!("~{:x}", hit.offset));
+        path = PathBuf::from(name);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    file.seek(SeekFrom::Start(hit.data_start()))?;
+    let mut writer = BufWriter::new(File::create(&path)?);
+    io::copy(&mut (&mut *file).take(hit.header.size), &mut writer)?;
+    Ok(path)
+}
@@ -0,0 +1,182 @@
+//! 目录文件：记录一批包的名称、摘要、metadata概要和条目列表，用于查找文件所在的包
+//!
+//! `catalog build`扫描目录（包括子目录）中的所有包写出JSON目录文件，`catalog find`只读取
+//! 目录文件，不需要打开每个包。
+
+use chrono::{DateTime, Utc};
+use globset::GlobBuilder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::common::{format_size, ARCHIVE_EXTENSION};
+use crate::hash::{hash_file, HashAlgo};
+use crate::reader::XpakReader;
+use crate::vpath::{lookup_key, PathPolicy};
+
+/// 目录文件格式的版本，不兼容的修改时增加
+const CATALOG_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Catalog {
+    version: u32,
+    created_at: DateTime<Utc>,
+    /// 扫描的目录，包的路径相对于该目录
+    root: String,
+    archives: Vec<CatalogArchive>,
+}
+
+/// 目录中的一个包
+#[derive(Serialize, Deserialize)]
+struct CatalogArchive {
+    path: String,
+    /// 包的名称，取自metadata中的`name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    format_version: String,
+    created_at: DateTime<Utc>,
+    /// 包文件的大小
+    size: u64,
+    /// 包文件的摘要，格式为`算法:十六进制摘要`
+    hash: String,
+    /// 有效条目的原始大小之和
+    total_size: u64,
+    files: Vec<CatalogFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CatalogFile {
+    path: String,
+    size: u64,
+}
+
+/// 扫描`dir`中的包，把目录写入`output`
+pub fn build(dir: &str, output: &str) -> io::Result<()> {
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("目录 '{}' 不存在", dir)));
+    }
+    let output_path = fs::canonicalize(output).ok();
+    let mut archives = Vec::new();
+    for entry in WalkDir::new(root).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::from)?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().is_none_or(|e| e != ARCHIVE_EXTENSION) {
+            continue;
+        }
+        if output_path.is_some() && fs::canonicalize(path).ok() == output_path {
+            continue;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        // 目录中的一个包损坏不影响其余包的记录
+        match read_archive(path, relative) {
+            Ok(archive) => archives.push(archive),
+            Err(e) => println!("跳过 {}: {}", path.display(), e),
+        }
+    }
+
+    let catalog = Catalog { version: CATALOG_VERSION, created_at: Utc::now(), root: dir.to_string(), archives };
+    let mut writer = BufWriter::new(fs::File::create(output)?);
+    serde_json::to_writer_pretty(&mut writer, &catalog)?;
+    writeln!(writer)?;
+    writer.flush()?;
+
+    let files: usize = catalog.archives.iter().map(|a| a.files.len()).sum();
+    println!("已记录 {} 个包、{} 个文件到 {}", catalog.archives.len(), files, output);
+    Ok(())
+}
+
+fn read_archive(path: &Path, relative: String) -> io::Result<CatalogArchive> {
+    let metadata = XpakReader::open(path)?.metadata;
+    let files: Vec<CatalogFile> = metadata.live_files().into_iter()
+        .map(|f| CatalogFile { path: f.path.clone(), size: f.size })
+        .collect();
+    let algo = HashAlgo::Sha256;
+    Ok(CatalogArchive {
+        path: relative,
+        name: metadata.common_text("name"),
+        content_version: metadata.content_version.clone(),
+        description: metadata.description.clone(),
+        format_version: metadata.format_version.clone(),
+        created_at: metadata.created_at,
+        size: fs::metadata(path)?.len(),
+        hash: format!("{}:{}", algo, hash_file(algo, path)?),
+        total_size: files.iter().map(|f| f.size).sum(),
+        files,
+    })
+}
+
+/// 查找结果
+#[derive(Serialize)]
+struct Found<'a> {
+    archive: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_version: Option<&'a str>,
+    path: &'a str,
+    size: u64,
+}
+
+/// 在目录文件中查找包含`pattern`的包，`pattern`可以是路径或glob，返回是否找到
+pub fn find(catalog_path: &str, pattern: &str, policy: PathPolicy, json: bool) -> io::Result<bool> {
+    let catalog: Catalog = serde_json::from_reader(BufReader::new(fs::File::open(catalog_path)?))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("无法解析目录文件 {}: {}", catalog_path, e)))?;
+    if catalog.version > CATALOG_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("目录文件的版本 {} 高于支持的版本 {}，请升级xpak", catalog.version, CATALOG_VERSION)
+        ));
+    }
+
+    let matcher = if pattern.contains(['*', '?', '[', '{']) {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(policy == PathPolicy::IgnoreCase)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的匹配规则 '{}': {}", pattern, e)))?
+            .compile_matcher();
+        Box::new(move |path: &str| glob.is_match(path)) as Box<dyn Fn(&str) -> bool>
+    } else {
+        let key = lookup_key(pattern, policy);
+        Box::new(move |path: &str| lookup_key(path, policy) == key)
+    };
+
+    let root = Path::new(&catalog.root);
+    let mut found = Vec::new();
+    for archive in &catalog.archives {
+        for file in archive.files.iter().filter(|f| matcher(&f.path)) {
+            found.push(Found {
+                archive: root.join(&archive.path).to_string_lossy().to_string(),
+                name: archive.name.as_deref(),
+                content_version: archive.content_version.as_deref(),
+                path: &file.path,
+                size: file.size,
+            });
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&found)?);
+        return Ok(!found.is_empty());
+    }
+    if found.is_empty() {
+        println!("{} 中没有包含 {} 的包", catalog_path, pattern);
+        return Ok(false);
+    }
+    for f in &found {
+        let identity = match (f.name, f.content_version) {
+            (Some(name), Some(version)) => format!("（{} {}）", name, version),
+            (Some(name), None) => format!("（{}）", name),
+            _ => String::new(),
+        };
+        println!("{}{}: {} ({})", f.archive, identity, f.path, format_size(f.size));
+    }
+    Ok(true)
+}
@@ -0,0 +1,145 @@
+//! 变更记录：把两个版本的包之间的差异写成发布说明
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::common::format_size;
+use crate::compat::{Bump, Change, ContentDiff};
+use crate::metadata::XpakMetadata;
+use crate::reader::XpakReader;
+
+/// 变更记录的格式
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChangelogFormat {
+    /// Markdown，可以直接放进发布说明
+    #[default]
+    Md,
+    /// JSON，供其他工具处理
+    Json,
+}
+
+/// 变更记录中的一个版本
+#[derive(Serialize)]
+struct Release {
+    file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_version: Option<String>,
+    files: usize,
+    total_size: u64,
+}
+
+impl Release {
+    fn new(path: &str, metadata: &XpakMetadata) -> Self {
+        let files = metadata.live_files();
+        Self {
+            file: path.to_string(),
+            content_version: metadata.content_version.clone(),
+            files: files.len(),
+            total_size: files.iter().map(|f| f.size).sum(),
+        }
+    }
+
+    /// 标题中的名称，有内容版本时使用版本号
+    fn label(&self) -> String {
+        match &self.content_version {
+            Some(version) => version.clone(),
+            None => Path::new(&self.file).file_name().map_or(self.file.clone(), |n| n.to_string_lossy().to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Changelog {
+    old: Release,
+    new: Release,
+    added: Vec<Change>,
+    changed: Vec<Change>,
+    removed: Vec<Change>,
+    required_bump: Bump,
+}
+
+/// 生成`old`到`new`的变更记录，`output`为`None`时写到标准输出
+pub fn changelog(old: &str, new: &str, format: ChangelogFormat, output: Option<&str>) -> io::Result<()> {
+    let old_metadata = XpakReader::open(old)?.metadata;
+    let new_metadata = XpakReader::open(new)?.metadata;
+    let diff = ContentDiff::new(&old_metadata, &new_metadata);
+    let changelog = Changelog {
+        old: Release::new(old, &old_metadata),
+        new: Release::new(new, &new_metadata),
+        required_bump: diff.required_bump(),
+        added: diff.added,
+        changed: diff.modified,
+        removed: diff.removed,
+    };
+
+    let text = match format {
+        ChangelogFormat::Md => markdown(&changelog),
+        ChangelogFormat::Json => serde_json::to_string_pretty(&changelog)? + "\n",
+    };
+    match output {
+        Some(path) => {
+            fs::write(path, text)?;
+            println!("变更记录已写入 {}", path);
+        }
+        None => io::stdout().lock().write_all(text.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// 大小的变化，如`+1.2 KB`
+fn size_delta(old: u64, new: u64) -> String {
+    if new >= old {
+        format!("+{}", format_size(new - old))
+    } else {
+        format!("-{}", format_size(old - new))
+    }
+}
+
+fn markdown(changelog: &Changelog) -> String {
+    let (old, new) = (&changelog.old, &changelog.new);
+    let mut lines = vec![
+        format!("## {} → {}", old.label(), new.label()),
+        String::new(),
+        format!(
+            "新增 {} 个文件，修改 {} 个，删除 {} 个；共 {} 个文件，{}（{}）",
+            changelog.added.len(),
+            changelog.changed.len(),
+            changelog.removed.len(),
+            new.files,
+            format_size(new.total_size),
+            size_delta(old.total_size, new.total_size)
+        ),
+    ];
+
+    let sections: [(&str, &[Change]); 3] = [
+        ("新增", &changelog.added),
+        ("修改", &changelog.changed),
+        ("删除", &changelog.removed),
+    ];
+    for (title, changes) in sections {
+        if changes.is_empty() {
+            continue;
+        }
+        lines.push(String::new());
+        lines.push(format!("### {}（{}）", title, changes.len()));
+        lines.push(String::new());
+        for change in changes {
+            let size = match (change.old_size, change.new_size) {
+                (Some(old), Some(new)) if old != new => {
+                    format!("{} → {}，{}", format_size(old), format_size(new), size_delta(old, new))
+                }
+                (_, Some(size)) | (Some(size), None) => format_size(size),
+                (None, None) => continue,
+            };
+            lines.push(format!("- `{}`（{}）", change.path, size));
+        }
+    }
+    if changelog.added.is_empty() && changelog.changed.is_empty() && changelog.removed.is_empty() {
+        lines.push(String::new());
+        lines.push("内容没有变化。".to_string());
+    }
+    lines.join("\n") + "\n"
+}
@@ -0,0 +1,63 @@
+use std::io::{self, Write};
+use std::thread;
+
+use crate::compress::hash_decompressed;
+use crate::hash::{hash_reader, HashAlgo};
+use crate::reader::XpakReader;
+
+/// 以`sha256sum`兼容的格式输出包内每个文件的校验和
+///
+/// `jobs`个线程各自使用独立的读取句柄，按条目序号分配需要现场计算的条目，输出顺序与包内一致。
+pub fn print_checksums(input: &str, algo: HashAlgo, jobs: usize) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    let jobs = jobs.max(1);
+
+    // 按条目序号对应metadata中的记录，同一路径追加的多个条目各自使用自己的记录
+    let files = &reader.metadata.files;
+
+    // 每个线程顺序读取一遍，只计算分给自己的条目，其余条目直接跳过
+    let worker = |mut reader: XpakReader, job: usize| -> io::Result<Vec<(u32, Option<String>)>> {
+        let mut results = Vec::new();
+        while let Some(entry) = reader.next_entry()? {
+            if !reader.is_live(entry.index) || entry.index as usize % jobs != job {
+                continue;
+            }
+            let info = files.get(entry.index as usize);
+            // metadata中已记录相同算法的摘要时直接使用，否则现场计算；加密条目的内容是密文，
+            // 无法现场计算明文摘要；压缩条目需要解压后计算摘要
+            let digest = match info.filter(|f| f.algo == Some(algo)).and_then(|f| f.hash.as_ref()) {
+                Some(hash) => Some(hash.clone()),
+                None if info.is_some_and(|f| f.encryption.is_some()) => None,
+                None => Some(match info.and_then(|f| f.compression.as_ref()) {
+                    Some(compression) => hash_decompressed(algo, &mut reader, compression)?,
+                    None => hash_reader(algo, &mut reader)?,
+                }),
+            };
+            results.push((entry.index, digest));
+        }
+        Ok(results)
+    };
+    let mut results = thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs)
+            .map(|job| {
+                let reader = reader.clone();
+                scope.spawn(move || worker(reader, job))
+            })
+            .collect();
+        handles.into_iter()
+            .map(|h| h.join().unwrap_or_else(|_| Err(io::Error::other("计算校验和的线程异常退出"))))
+            .collect::<io::Result<Vec<_>>>()
+    })?.concat();
+    results.sort_by_key(|(index, _)| *index);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (index, digest) in results {
+        let path = &reader.metadata.files[index as usize].path;
+        match digest {
+            Some(digest) => writeln!(out, "{}  {}", digest, path)?,
+            None => eprintln!("跳过加密文件 {}：包内没有记录 {} 摘要", path, algo),
+        }
+    }
+    out.flush()
+}
@@ -1,5 +1,4 @@
-pub const MAGIC_NUMBER: &[u8] = b"XPAK";
-pub const MAGIC_METADATA_END: [u8; 8] = [0x4d, 0x45, 0x54, 0x41, 0x45, 0x4e, 0x44, 0x5f]; // METAEND_
+pub use xpak::{PathLength, BINARY_METADATA_TAG, COMPACT_FORMAT_VERSION, MAGIC_METADATA_END, MAGIC_NUMBER, MAX_PATH_LEN};
 
 // pub const MAGIC_FILE_START: [u8; 8] = [0x5f, 0x46, 0x53, 0x54, 0x41, 0x52, 0x54, 0x5f]; // _FSTART_
 // pub const MAGIC_FILE_END: [u8; 8] = [0x5f, 0x46, 0x49, 0x4c, 0x45, 0x4e, 0x44, 0x5f]; // _FILEND_
@@ -7,13 +6,48 @@ pub const MAGIC_METADATA_END: [u8; 8] = [0x4d, 0x45, 0x54, 0x41, 0x45, 0x4e, 0x4
 
 pub const BUFFER_SIZE: usize = 65536;  // 64KB 缓冲区
 
-pub const FORMAT_VERSION: &str = "1.3";
+pub const FORMAT_VERSION: &str = "1.4";
+
+/// 条目列表与metadata分开存放的紧凑布局，条目列表在尾部区段中
+pub const SPLIT_FORMAT_VERSION: &str = "2.1";
+
+/// 包文件的标准扩展名
+pub const ARCHIVE_EXTENSION: &str = "xpak";
+
+/// 条目大小字段为u32，单个条目存储的大小不能超过该值
+pub const MAX_ENTRY_SIZE: u64 = u32::MAX as u64;
 
 // pub const DIRECT_COPY_THRESHOLD: usize = 1024 * 1024;  // 1MB，大文件直接复制阈值
 
-#[allow(dead_code)]
 pub const KB: usize = 1024;  // 1KB
 #[allow(dead_code)]
 pub const MB: usize = 1024 * 1024;  // 1MB
-#[allow(dead_code)]
-pub const GB: usize = 1024 * 1024 * 1024;  // 1GB
\ No newline at end of file
+pub const GB: usize = 1024 * 1024 * 1024;  // 1GB
+/// 格式化文件大小显示
+pub fn format_size(size: u64) -> String {
+    if size > GB as u64 {
+        format!("{:.2} GB", size as f64 / GB as f64)
+    } else if size > MB as u64 {
+        format!("{:.2} MB", size as f64 / MB as f64)
+    } else if size > KB as u64 {
+        format!("{:.2} KB", size as f64 / KB as f64)
+    } else {
+        format!("{} 字节", size)
+    }
+}
+
+/// 解析大小，支持`K`、`M`、`G`后缀（也可写作`KB`、`MB`、`GB`），如`500M`、`60G`
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let upper = value.trim().to_ascii_uppercase();
+    let digits = upper.strip_suffix('B').filter(|d| d.ends_with(['K', 'M', 'G'])).unwrap_or(&upper);
+    let (digits, unit) = match digits.char_indices().last() {
+        Some((i, 'K')) => (&digits[..i], KB as u64),
+        Some((i, 'M')) => (&digits[..i], MB as u64),
+        Some((i, 'G')) => (&digits[..i], GB as u64),
+        _ => (digits, 1),
+    };
+    digits.trim().parse::<u64>().ok()
+        .and_then(|n| n.checked_mul(unit))
+        .filter(|size| *size > 0)
+        .ok_or_else(|| format!("无效的大小 '{}'，应为正整数，可带 K、M、G 后缀，如 500M", value))
+}
@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::fs::File;
+use std::io;
+use walkdir::WalkDir;
+
+use crate::compress::hash_decompressed;
+use crate::hash::{hash_file, hash_reader, HashAlgo};
+use crate::metadata::FileInfo;
+use crate::reader::XpakReader;
+
+/// 包与目录的比较结果
+#[derive(Serialize, Debug, Default)]
+pub struct CompareResult {
+    /// 包内有但目录中缺失的文件
+    pub missing: Vec<String>,
+    /// 目录中有但包内没有的文件
+    pub extra: Vec<String>,
+    /// 内容不一致的文件
+    pub modified: Vec<String>,
+    /// 无法校验内容的文件（加密且没有记录摘要），只比较了大小
+    pub unverified: Vec<String>,
+    pub matched: u32,
+}
+
+impl CompareResult {
+    pub fn is_identical(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// 比较目录内容是否与包一致，返回是否完全一致
+pub fn compare(input: &str, dir: &str, json: bool) -> io::Result<bool> {
+    let dir_path = Path::new(dir);
+    if !dir_path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("目录 '{}' 不存在", dir)
+        ));
+    }
+
+    let mut reader = XpakReader::open(input)?;
+    // 按条目序号对应metadata中的记录，同一路径的多个条目各自使用自己的记录
+    let file_infos: Vec<FileInfo> = reader.metadata.files.clone();
+    let mut result = CompareResult::default();
+    let mut archived = HashSet::new();
+
+    while let Some(entry) = reader.next_entry()? {
+        if !reader.is_live(entry.index) {
+            continue;
+        }
+        archived.insert(entry.path.clone());
+        let info = file_infos.get(entry.index as usize);
+        let local = dir_path.join(&entry.path);
+
+        let local_size = match local.metadata() {
+            Ok(meta) if meta.is_file() => meta.len(),
+            _ => {
+                result.missing.push(entry.path);
+                continue;
+            }
+        };
+
+        // 先比较大小，大小不同无需计算摘要
+        let size = info.map_or(entry.size, |f| f.size);
+        if size != local_size {
+            result.modified.push(entry.path);
+            continue;
+        }
+
+        let same = match info.and_then(|f| Some((f.algo?, f.hash.as_deref()?))) {
+            Some((algo, hash)) => hash_file(algo, &local)? == hash,
+            None if info.is_some_and(|f| f.encryption.is_some()) => {
+                result.unverified.push(entry.path);
+                result.matched += 1;
+                continue;
+            }
+            None => {
+                let archived = match info.and_then(|f| f.compression.as_ref()) {
+                    Some(compression) => hash_decompressed(HashAlgo::Sha256, &mut reader, compression)?,
+                    None => hash_reader(HashAlgo::Sha256, &mut reader)?,
+                };
+                archived == hash_reader(HashAlgo::Sha256, &mut File::open(&local)?)?
+            }
+        };
+
+        if same {
+            result.matched += 1;
+        } else {
+            result.modified.push(entry.path);
+        }
+    }
+
+    // 查找目录中多出的文件
+    for entry in WalkDir::new(dir_path).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()) {
+        let relative = entry.path().strip_prefix(dir_path).unwrap();
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        if !archived.contains(&relative) {
+            result.extra.push(relative);
+        }
+    }
+    result.extra.sort();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        print_result(&result);
+    }
+
+    Ok(result.is_identical())
+}
+
+fn print_result(result: &CompareResult) {
+    for path in &result.missing {
+        println!("缺失: {}", path);
+    }
+    for path in &result.extra {
+        println!("多余: {}", path);
+    }
+    for path in &result.modified {
+        println!("修改: {}", path);
+    }
+    for path in &result.unverified {
+        println!("未校验: {} (加密文件，仅比较了大小)", path);
+    }
+    println!("----------------------------------------");
+    println!(
+        "一致 {} 个，缺失 {} 个，多余 {} 个，修改 {} 个",
+        result.matched, result.missing.len(), result.extra.len(), result.modified.len()
+    );
+    if result.is_identical() {
+        println!("目录与包内容一致");
+    }
+}
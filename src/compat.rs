@@ -0,0 +1,215 @@
+//! 内容版本：比较两个版本的包，判断变化的类型并建议内容版本号的增量
+//!
+//! 删除文件会破坏依赖旧内容的使用者，需要增加主版本号；只新增文件时增加次版本号；
+//! 只修改已有文件时增加修订号。
+
+use semver::Version;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+
+use crate::metadata::{FileInfo, XpakMetadata};
+use crate::reader::XpakReader;
+
+/// 解析`--content-version`，按semver格式检查
+pub fn parse_content_version(value: &str) -> Result<String, String> {
+    Version::parse(value.trim())
+        .map(|version| version.to_string())
+        .map_err(|e| format!("无效的内容版本 '{}'，应为semver格式（如 1.2.0）: {}", value, e))
+}
+
+/// 版本号的增量，按影响从小到大排列
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Bump {
+    None,
+    Patch,
+    Minor,
+    Major,
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Bump::None => "无需更改",
+            Bump::Patch => "修订号",
+            Bump::Minor => "次版本号",
+            Bump::Major => "主版本号",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Bump {
+    /// 从`old`到`new`实际增加的部分，版本没有增加时为`None`
+    fn between(old: &Version, new: &Version) -> Self {
+        if new <= old {
+            Bump::None
+        } else if new.major > old.major {
+            Bump::Major
+        } else if new.minor > old.minor {
+            Bump::Minor
+        } else {
+            Bump::Patch
+        }
+    }
+
+    /// 按增量计算下一个版本
+    fn apply(self, version: &Version) -> Version {
+        match self {
+            Bump::None => version.clone(),
+            Bump::Patch => Version::new(version.major, version.minor, version.patch + 1),
+            Bump::Minor => Version::new(version.major, version.minor + 1, 0),
+            Bump::Major => Version::new(version.major + 1, 0, 0),
+        }
+    }
+}
+
+/// 两个版本的包的比较结果
+#[derive(Serialize, Debug)]
+pub struct CompatResult {
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+    /// 内容变化要求的版本号增量
+    pub required: Bump,
+    /// 按旧包的内容版本和要求的增量建议的新版本
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested: Option<String>,
+    /// 两个包都记录了内容版本时，新版本号是否满足要求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compatible: Option<bool>,
+}
+
+/// 条目内容是否不同：大小不同，或两边用同一算法记录的摘要不同
+fn differs(old: &FileInfo, new: &FileInfo) -> bool {
+    if old.size != new.size {
+        return true;
+    }
+    match (old.algo, old.hash.as_deref(), new.algo, new.hash.as_deref()) {
+        (Some(a), Some(old_hash), Some(b), Some(new_hash)) if a == b => old_hash != new_hash,
+        _ => false,
+    }
+}
+
+/// 一个有变化的条目，新增的条目没有旧大小，删除的条目没有新大小
+#[derive(Serialize, Debug, Clone)]
+pub struct Change {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_size: Option<u64>,
+}
+
+/// 两个版本的包中有效条目的差异，各部分按路径排列
+#[derive(Debug, Default)]
+pub struct ContentDiff {
+    pub added: Vec<Change>,
+    pub modified: Vec<Change>,
+    pub removed: Vec<Change>,
+}
+
+impl ContentDiff {
+    pub fn new(old: &XpakMetadata, new: &XpakMetadata) -> Self {
+        let old_files: HashMap<&str, &FileInfo> = old.live_files().into_iter().map(|f| (f.path.as_str(), f)).collect();
+        let new_files = new.live_files();
+        let mut diff = Self::default();
+        for file in &new_files {
+            let change = |old_size| Change { path: file.path.clone(), old_size, new_size: Some(file.size) };
+            match old_files.get(file.path.as_str()) {
+                None => diff.added.push(change(None)),
+                Some(old) if differs(old, file) => diff.modified.push(change(Some(old.size))),
+                Some(_) => {}
+            }
+        }
+        let new_paths: HashSet<&str> = new_files.iter().map(|f| f.path.as_str()).collect();
+        diff.removed = old_files.values()
+            .filter(|f| !new_paths.contains(f.path.as_str()))
+            .map(|f| Change { path: f.path.clone(), old_size: Some(f.size), new_size: None })
+            .collect();
+        for changes in [&mut diff.added, &mut diff.modified, &mut diff.removed] {
+            changes.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        diff
+    }
+
+    /// 内容变化要求的版本号增量
+    pub fn required_bump(&self) -> Bump {
+        if !self.removed.is_empty() {
+            Bump::Major
+        } else if !self.added.is_empty() {
+            Bump::Minor
+        } else if !self.modified.is_empty() {
+            Bump::Patch
+        } else {
+            Bump::None
+        }
+    }
+}
+
+/// 比较`old`和`new`两个包，返回新包的内容版本是否满足要求（未记录版本时视为满足）
+pub fn compat(old: &str, new: &str, json: bool) -> io::Result<bool> {
+    let old_metadata = XpakReader::open(old)?.metadata;
+    let new_metadata = XpakReader::open(new)?.metadata;
+    let diff = ContentDiff::new(&old_metadata, &new_metadata);
+    let required = diff.required_bump();
+    let paths = |changes: Vec<Change>| changes.into_iter().map(|c| c.path).collect();
+
+    let parse = |version: &Option<String>| version.as_deref().and_then(|v| Version::parse(v).ok());
+    let (old_version, new_version) = (parse(&old_metadata.content_version), parse(&new_metadata.content_version));
+    let result = CompatResult {
+        suggested: old_version.as_ref().map(|v| required.apply(v).to_string()),
+        compatible: old_version.as_ref().zip(new_version.as_ref()).map(|(old, new)| Bump::between(old, new) >= required),
+        old_version: old_metadata.content_version,
+        new_version: new_metadata.content_version,
+        added: paths(diff.added),
+        modified: paths(diff.modified),
+        removed: paths(diff.removed),
+        required,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        print_result(&result);
+    }
+    Ok(result.compatible != Some(false))
+}
+
+fn print_result(result: &CompatResult) {
+    for path in &result.added {
+        println!("新增: {}", path);
+    }
+    for path in &result.modified {
+        println!("修改: {}", path);
+    }
+    for path in &result.removed {
+        println!("删除: {}", path);
+    }
+    println!("----------------------------------------");
+    println!("新增 {} 个，修改 {} 个，删除 {} 个", result.added.len(), result.modified.len(), result.removed.len());
+    let kind = match result.required {
+        Bump::Major => "删除了文件，不向后兼容",
+        Bump::Minor => "新增了文件，没有删除",
+        Bump::Patch => "只修改了已有文件",
+        Bump::None => "内容没有变化",
+    };
+    match result.required {
+        Bump::None => println!("变化类型: {}", kind),
+        required => println!("变化类型: {}，需要增加{}", kind, required),
+    }
+    let version = |v: &Option<String>| v.clone().unwrap_or_else(|| "未记录".to_string());
+    println!("内容版本: {} -> {}", version(&result.old_version), version(&result.new_version));
+    if let Some(suggested) = &result.suggested {
+        println!("建议的新版本: {}", suggested);
+    }
+    match result.compatible {
+        Some(true) => println!("新版本号符合内容的变化"),
+        Some(false) => println!("新版本号不足，内容的变化需要增加{}", result.required),
+        None => {}
+    }
+}
@@ -0,0 +1,335 @@
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::common::{KB, MB};
+use crate::config::AutoCompress;
+use crate::hash::{HashAlgo, HashingWriter};
+use crate::mime::{sniff, sniff_file};
+
+/// zstd 默认压缩级别
+pub const ZSTD_LEVEL: i32 = 3;
+/// 默认每个压缩帧的大小（MB）
+pub const DEFAULT_FRAME_MB: u32 = 4;
+/// 压缩帧大小的上限（MB）
+const MAX_FRAME_MB: u32 = 256;
+/// 判断是否值得压缩时读取的文件头部长度
+const PROBE_LEN: u64 = 64 * KB as u64;
+/// 试压缩头部后至少要减小的比例（百分比），否则视为无法压缩
+const PROBE_MIN_SAVING: usize = 3;
+/// 本身已经压缩过的格式，再压缩几乎不会变小
+const PRECOMPRESSED_MIME: &[&str] = &[
+    "image/jpeg", "image/png", "image/gif", "image/webp", "image/avif", "image/heif", "image/jxl",
+    "audio/mpeg", "audio/ogg", "audio/opus", "audio/aac", "audio/mp4", "audio/m4a", "audio/x-m4a", "audio/x-flac",
+    "application/zip", "application/gzip", "application/x-bzip2", "application/x-xz", "application/zstd",
+    "application/x-7z-compressed", "application/vnd.rar", "application/x-rar-compressed", "application/x-lzip",
+    "application/java-archive", "application/epub+zip", "font/woff", "font/woff2",
+];
+
+/// 支持的压缩算法
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+    /// 压缩率低于zstd，但压缩和解压都快得多
+    Lz4,
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::Zstd => f.write_str("zstd"),
+            Compression::Lz4 => f.write_str("lz4"),
+        }
+    }
+}
+
+/// 打包时的压缩方式
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressMode {
+    /// 全部使用zstd
+    Zstd,
+    /// 全部使用lz4
+    Lz4,
+    /// 按文件大小和类型逐个选择算法和级别，阈值可在配置文件中调整
+    Auto,
+}
+
+impl fmt::Display for CompressMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressMode::Zstd => f.write_str("zstd"),
+            CompressMode::Lz4 => f.write_str("lz4"),
+            CompressMode::Auto => f.write_str("auto"),
+        }
+    }
+}
+
+/// 为单个文件选定的压缩算法和级别
+#[derive(Debug, Clone, Copy)]
+pub struct Codec {
+    pub algo: Compression,
+    /// zstd的压缩级别，lz4没有级别
+    pub level: Option<i32>,
+}
+
+impl Codec {
+    fn new(algo: Compression) -> Self {
+        match algo {
+            Compression::Zstd => Self { algo, level: Some(ZSTD_LEVEL) },
+            Compression::Lz4 => Self { algo, level: None },
+        }
+    }
+
+    /// 按压缩方式选择文件使用的算法
+    ///
+    /// 自动选择时很大的文件使用lz4以保证速度，较小的文本文件使用高级别的zstd，
+    /// 其余文件使用配置的zstd级别。
+    pub fn choose(mode: CompressMode, path: &Path, size: u64, auto: &AutoCompress) -> io::Result<Self> {
+        match mode {
+            CompressMode::Zstd => Ok(Self::new(Compression::Zstd)),
+            CompressMode::Lz4 => Ok(Self::new(Compression::Lz4)),
+            CompressMode::Auto if size > auto.fast_above_mb * MB as u64 => Ok(Self::new(Compression::Lz4)),
+            CompressMode::Auto => {
+                let level = if size < auto.small_text_below_kb * KB as u64 && is_text(&sniff_file(path)?) {
+                    auto.small_text_level
+                } else {
+                    auto.level
+                };
+                Ok(Self { algo: Compression::Zstd, level: Some(level) })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            Some(level) => write!(f, "{}-{}", self.algo, level),
+            None => write!(f, "{}", self.algo),
+        }
+    }
+}
+
+/// 文本类的MIME类型
+fn is_text(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(mime, "application/json" | "application/xml" | "application/javascript" | "application/toml" | "application/yaml")
+}
+
+/// 条目的分帧压缩信息
+///
+/// 条目内容按`frame_size`切分后逐帧独立压缩，压缩后的帧依次存放。帧表记录每帧
+/// 压缩后的大小，由此可以定位任意一帧，无需从头解压。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntryCompression {
+    pub algo: Compression,
+    /// 压缩时使用的级别，只用于记录，解压时不需要
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<i32>,
+    /// 每帧解压后的大小，只有最后一帧可以更小
+    pub frame_size: u32,
+    /// 每帧压缩后的大小
+    pub frames: Vec<u32>,
+}
+
+impl EntryCompression {
+    /// 压缩时使用的算法和级别
+    pub fn codec(&self) -> Codec {
+        Codec { algo: self.algo, level: self.level }
+    }
+
+    /// 压缩后的总大小
+    pub fn compressed_size(&self) -> u64 {
+        self.frames.iter().map(|&len| len as u64).sum()
+    }
+
+    /// 每帧压缩数据相对条目内容起点的偏移
+    pub fn frame_offsets(&self) -> Vec<u64> {
+        self.frames.iter()
+            .scan(0u64, |offset, &len| {
+                let start = *offset;
+                *offset += len as u64;
+                Some(start)
+            })
+            .collect()
+    }
+
+    /// 解压第`index`帧并检查解压后的大小
+    pub fn decode_frame(&self, index: usize, data: &[u8]) -> io::Result<Vec<u8>> {
+        let frame_size = self.frame_size as usize;
+        let decoded = decompress(self.algo, data, frame_size)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("第 {} 帧解压失败: {}", index + 1, e)))?;
+        let last = index + 1 == self.frames.len();
+        if decoded.len() > frame_size || (!last && decoded.len() != frame_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("第 {} 帧解压后为 {} 字节，与帧大小 {} 不符", index + 1, decoded.len(), frame_size)
+            ));
+        }
+        Ok(decoded)
+    }
+}
+
+/// 以默认级别压缩一段数据
+pub fn compress(algo: Compression, data: &[u8]) -> io::Result<Vec<u8>> {
+    compress_with(Codec::new(algo), data)
+}
+
+fn compress_with(codec: Codec, data: &[u8]) -> io::Result<Vec<u8>> {
+    match codec.algo {
+        Compression::Zstd => zstd::bulk::compress(data, codec.level.unwrap_or(ZSTD_LEVEL)),
+        Compression::Lz4 => Ok(lz4_flex::block::compress(data)),
+    }
+}
+
+/// 判断文件是否已经是压缩格式，这类文件直接存储，不浪费时间再压缩
+///
+/// 先按魔数和扩展名识别常见的图片、音视频和压缩包格式，无法识别时试压缩文件头部，
+/// 几乎没有变小的同样视为无法压缩。
+pub fn is_incompressible(path: &Path) -> io::Result<bool> {
+    let mut head = Vec::with_capacity(PROBE_LEN as usize);
+    File::open(path)?.take(PROBE_LEN).read_to_end(&mut head)?;
+    let mime = sniff(path, &head);
+    if mime.starts_with("video/") || PRECOMPRESSED_MIME.contains(&mime.as_str()) {
+        return Ok(true);
+    }
+    // 太小的文件试压缩的结果没有参考价值，交给压缩后的大小比较
+    if head.len() < PROBE_LEN as usize {
+        return Ok(false);
+    }
+    let probe = zstd::bulk::compress(&head, 1)?;
+    Ok(probe.len() * 100 > head.len() * (100 - PROBE_MIN_SAVING))
+}
+
+/// 解压一帧数据，`capacity`为解压后大小的上限
+fn decompress(algo: Compression, data: &[u8], capacity: usize) -> io::Result<Vec<u8>> {
+    match algo {
+        Compression::Zstd => zstd::bulk::decompress(data, capacity),
+        Compression::Lz4 => lz4_flex::block::decompress(data, capacity)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+    }
+}
+
+/// 解析压缩帧大小（MB）
+pub fn parse_frame_size(value: &str) -> Result<u32, String> {
+    match value.trim().trim_end_matches("MB").trim().parse::<u32>() {
+        Ok(mb) if (1..=MAX_FRAME_MB).contains(&mb) => Ok(mb),
+        _ => Err(format!("无效的帧大小 '{}'，应为 1 到 {} 之间的 MB 数", value, MAX_FRAME_MB)),
+    }
+}
+
+/// 按帧压缩`reader`的全部内容并写入`writer`
+///
+/// 每次读取与线程数相同的帧并行压缩，再按顺序写出，内存占用约为线程数乘以帧大小。
+pub fn compress_frames(
+    codec: Codec,
+    frame_size: usize,
+    reader: &mut impl Read,
+    writer: &mut impl Write
+) -> io::Result<EntryCompression> {
+    let batch = rayon::current_num_threads();
+    let mut frames = Vec::new();
+    loop {
+        let mut chunks = Vec::with_capacity(batch);
+        let mut eof = false;
+        while chunks.len() < batch {
+            let mut chunk = Vec::with_capacity(frame_size);
+            reader.by_ref().take(frame_size as u64).read_to_end(&mut chunk)?;
+            eof = chunk.len() < frame_size;
+            if !chunk.is_empty() {
+                chunks.push(chunk);
+            }
+            if eof {
+                break;
+            }
+        }
+
+        let compressed = chunks.par_iter()
+            .map(|chunk| compress_with(codec, chunk))
+            .collect::<io::Result<Vec<_>>>()?;
+        for frame in compressed {
+            writer.write_all(&frame)?;
+            frames.push(frame.len() as u32);
+        }
+        if eof {
+            break;
+        }
+    }
+    Ok(EntryCompression { algo: codec.algo, level: codec.level, frame_size: frame_size as u32, frames })
+}
+
+/// 按帧表解压写入的数据，再写入内部的Writer
+pub struct FrameDecoder<'a, W: Write> {
+    inner: W,
+    compression: &'a EntryCompression,
+    /// 正在接收的帧
+    next: usize,
+    buffer: Vec<u8>,
+    written: u64,
+}
+
+impl<'a, W: Write> FrameDecoder<'a, W> {
+    pub fn new(inner: W, compression: &'a EntryCompression) -> Self {
+        Self { inner, compression, next: 0, buffer: Vec::new(), written: 0 }
+    }
+
+    fn decode_frame(&mut self) -> io::Result<()> {
+        let data = self.compression.decode_frame(self.next, &self.buffer)?;
+        self.inner.write_all(&data)?;
+        self.written += data.len() as u64;
+        self.buffer.clear();
+        self.next += 1;
+        Ok(())
+    }
+
+    /// 检查所有帧都已解压，返回解压后的字节数
+    pub fn finish(mut self) -> io::Result<u64> {
+        if self.next != self.compression.frames.len() || !self.buffer.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "压缩数据不完整"));
+        }
+        self.inner.flush()?;
+        Ok(self.written)
+    }
+}
+
+impl<W: Write> Write for FrameDecoder<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let Some(&len) = self.compression.frames.get(self.next) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "压缩数据超出帧表的范围"));
+        };
+        let n = (len as usize - self.buffer.len()).min(buf.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == len as usize {
+            self.decode_frame()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 解压当前条目并写入`writer`，返回解压后的字节数
+fn decompress_stream(reader: &mut impl Read, writer: &mut impl Write, compression: &EntryCompression) -> io::Result<u64> {
+    let mut decoder = FrameDecoder::new(writer, compression);
+    io::copy(reader, &mut decoder)?;
+    decoder.finish()
+}
+
+/// 计算条目解压后内容的摘要
+pub fn hash_decompressed(algo: HashAlgo, reader: &mut impl Read, compression: &EntryCompression) -> io::Result<String> {
+    let mut hasher = HashingWriter::new(io::sink(), Some(algo));
+    decompress_stream(reader, &mut hasher, compression)?;
+    Ok(hasher.finish().1.unwrap_or_default())
+}
@@ -0,0 +1,90 @@
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::compress::ZSTD_LEVEL;
+
+/// 指定配置文件路径的环境变量
+const CONFIG_ENV: &str = "XPAK_CONFIG";
+
+/// 配置文件中的设置，没有配置文件或缺少的项使用默认值
+///
+/// 配置文件为TOML格式，路径取自`XPAK_CONFIG`环境变量，默认为用户配置目录下的
+/// `xpak/config.toml`。
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub auto_compress: AutoCompress,
+    pub verify: VerifyConfig,
+    pub backup: BackupConfig,
+}
+
+/// 修改包之前的备份设置
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    /// 为真时`update`、`touch`、`add`、`remove`、`optimize`和`rekey`总是先备份，相当于指定了`--backup`
+    pub required: bool,
+}
+
+/// `verify --daemon`的设置
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerifyConfig {
+    /// 校验结果POST到的地址，命令行的`--webhook`优先
+    pub webhook: Option<String>,
+}
+
+/// `--compress auto`按文件大小和类型选择算法的阈值
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoCompress {
+    /// 大于此大小（MB）的文件使用lz4，优先保证速度
+    pub fast_above_mb: u64,
+    /// 小于此大小（KB）的文本文件使用`small_text_level`级别的zstd
+    pub small_text_below_kb: u64,
+    pub small_text_level: i32,
+    /// 其余文件使用的zstd级别
+    pub level: i32,
+}
+
+impl Default for AutoCompress {
+    fn default() -> Self {
+        Self {
+            fast_above_mb: 256,
+            small_text_below_kb: 1024,
+            small_text_level: 19,
+            level: ZSTD_LEVEL,
+        }
+    }
+}
+
+/// 默认的配置文件路径
+fn default_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    dir.map(|dir| dir.join("xpak").join("config.toml"))
+}
+
+impl Config {
+    /// 读取配置文件，默认路径下没有配置文件时返回默认配置
+    pub fn load() -> io::Result<Self> {
+        let explicit = std::env::var_os(CONFIG_ENV).map(PathBuf::from);
+        let Some(path) = explicit.clone().or_else(default_path) else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && explicit.is_none() => return Ok(Self::default()),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("无法读取配置文件 {}: {}", path.display(), e))),
+        };
+        toml::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("配置文件 {} 无效: {}", path.display(), e)))
+    }
+}
@@ -0,0 +1,121 @@
+use clap::ValueEnum;
+use indicatif::ProgressBar;
+use std::collections::HashSet;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+/// 解包时输出文件已存在的处理方式
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExistingPolicy {
+    /// 在终端中逐个询问，非交互环境下报错
+    #[default]
+    Ask,
+    /// 覆盖已有文件
+    Overwrite,
+    /// 保留已有文件，跳过该条目
+    Skip,
+    /// 以`name (N).ext`的形式另存
+    Rename,
+    /// 报错并停止解包
+    Error,
+}
+
+/// 扁平化或按模板解包时不同条目得到相同输出路径的处理方式
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// 报错并停止解包
+    #[default]
+    Error,
+    /// 只解包第一个条目
+    Skip,
+    /// 为后出现的条目添加序号后缀
+    Rename,
+}
+
+/// 对一个已存在文件的处理结果
+pub enum Resolution {
+    Write(PathBuf),
+    Skip,
+}
+
+/// 解包过程中处理与已有文件的冲突
+///
+/// 本次解包写出的文件再次出现时（例如包内的重复路径）直接覆盖，不视为冲突。
+pub struct ConflictResolver {
+    policy: ExistingPolicy,
+    written: HashSet<PathBuf>,
+}
+
+impl ConflictResolver {
+    pub fn new(policy: ExistingPolicy) -> Self {
+        Self { policy, written: HashSet::new() }
+    }
+
+    /// 确定条目实际写入的位置，返回`Skip`时不解包该条目
+    pub fn resolve(&mut self, file_path: &Path, progress: &ProgressBar) -> io::Result<Resolution> {
+        if self.written.contains(file_path) || file_path.symlink_metadata().is_err() {
+            return Ok(self.write(file_path.to_path_buf()));
+        }
+        let policy = match self.policy {
+            ExistingPolicy::Ask => progress.suspend(|| self.ask(file_path))?,
+            policy => policy,
+        };
+        match policy {
+            ExistingPolicy::Overwrite => Ok(self.write(file_path.to_path_buf())),
+            ExistingPolicy::Skip => Ok(Resolution::Skip),
+            ExistingPolicy::Rename => Ok(self.write(free_path(file_path))),
+            ExistingPolicy::Ask | ExistingPolicy::Error => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} 已存在，可使用 --on-existing 指定处理方式", file_path.display())
+            )),
+        }
+    }
+
+    fn write(&mut self, path: PathBuf) -> Resolution {
+        self.written.insert(path.clone());
+        Resolution::Write(path)
+    }
+
+    /// 询问如何处理，选择“全部”时记住该选择，之后不再询问
+    fn ask(&mut self, file_path: &Path) -> io::Result<ExistingPolicy> {
+        let term = console::Term::stderr();
+        if !term.is_term() {
+            return Ok(ExistingPolicy::Error);
+        }
+        loop {
+            term.write_line(&format!("{} 已存在", file_path.display()))?;
+            term.write_str("[o]覆盖 [s]跳过 [r]重命名 [O]全部覆盖 [S]全部跳过 [R]全部重命名 [a]中止: ")?;
+            let mut answer = String::new();
+            if io::stdin().lock().read_line(&mut answer)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "已取消解包"));
+            }
+            let (policy, all) = match answer.trim() {
+                "o" => (ExistingPolicy::Overwrite, false),
+                "s" => (ExistingPolicy::Skip, false),
+                "r" => (ExistingPolicy::Rename, false),
+                "O" => (ExistingPolicy::Overwrite, true),
+                "S" => (ExistingPolicy::Skip, true),
+                "R" => (ExistingPolicy::Rename, true),
+                "a" | "A" => return Err(io::Error::new(io::ErrorKind::Interrupted, "已取消解包")),
+                _ => continue,
+            };
+            if all {
+                self.policy = policy;
+            }
+            return Ok(policy);
+        }
+    }
+}
+
+/// 在同一目录下找一个不存在的`name (N).ext`
+fn free_path(path: &Path) -> PathBuf {
+    let file = path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+    let (stem, ext) = match file.rfind('.') {
+        Some(i) if i > 0 => (&file[..i], &file[i..]),
+        _ => (file.as_str(), ""),
+    };
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| p.symlink_metadata().is_err())
+        .unwrap()
+}
@@ -0,0 +1,315 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{KeyInit, OsRng, rand_core::RngCore};
+use chacha20poly1305::XChaCha20Poly1305;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+
+use crate::common::KB;
+use crate::hash::to_hex;
+
+/// 加密时每个分块的明文大小
+pub const CHUNK_SIZE: usize = 64 * KB;
+/// 每个分块附带的认证标签大小
+const TAG_SIZE: usize = 16;
+/// STREAM 构造使用的nonce长度（XChaCha20 的24字节减去5字节计数器）
+const STREAM_NONCE_SIZE: usize = 19;
+const SALT_SIZE: usize = 16;
+
+/// 使用age接收者公钥包装的密钥的key_id
+pub const RECIPIENT_KEY_ID: &str = "age";
+
+/// 包内使用的某个密钥的派生参数
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyInfo {
+    pub kdf: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub salt: String,
+    /// 派生密钥的校验值，用于提前发现错误的密钥
+    pub check: String,
+    /// 使用age加密给接收者的密钥（Base64）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapped: Option<String>,
+    /// 可以解开`wrapped`的接收者公钥
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+}
+
+/// 条目的加密信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntryEncryption {
+    pub key_id: String,
+    pub nonce: String,
+}
+
+/// 派生后的对称密钥
+#[derive(Clone)]
+pub struct EntryKey([u8; 32]);
+
+impl EntryKey {
+    /// 使用 Argon2id 从口令派生密钥
+    pub fn derive(passphrase: &str, salt: &[u8]) -> io::Result<Self> {
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| io::Error::other(format!("密钥派生失败: {}", e)))?;
+        Ok(Self(key))
+    }
+
+    /// 为口令生成新的盐并派生密钥
+    pub fn generate(passphrase: &str) -> io::Result<(Self, KeyInfo)> {
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let key = Self::derive(passphrase, &salt)?;
+        let info = KeyInfo {
+            kdf: "argon2id".to_string(),
+            salt: STANDARD.encode(salt),
+            check: key.check_value(),
+            wrapped: None,
+            recipients: Vec::new(),
+        };
+        Ok((key, info))
+    }
+
+    /// 生成随机密钥，并用age加密给所有接收者
+    pub fn generate_for_recipients(recipients: &[String]) -> io::Result<(Self, KeyInfo)> {
+        let parsed = recipients.iter()
+            .map(|r| r.parse::<age::x25519::Recipient>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的接收者 '{}': {}", r, e))))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        let key = Self(key);
+
+        let encryptor = age::Encryptor::with_recipients(parsed.iter().map(|r| r as &dyn age::Recipient))
+            .map_err(|e| io::Error::other(format!("包装密钥失败: {}", e)))?;
+        let mut wrapped = Vec::new();
+        let mut writer = encryptor.wrap_output(&mut wrapped)?;
+        writer.write_all(&key.0)?;
+        writer.finish()?;
+
+        let info = KeyInfo {
+            kdf: "age".to_string(),
+            salt: String::new(),
+            check: key.check_value(),
+            wrapped: Some(STANDARD.encode(wrapped)),
+            recipients: recipients.to_vec(),
+        };
+        Ok((key, info))
+    }
+
+    /// 使用age身份解开包内的密钥，没有匹配的身份时返回`None`
+    pub fn from_identities(key_id: &str, info: &KeyInfo, identities: &[Box<dyn age::Identity>]) -> io::Result<Option<Self>> {
+        let wrapped = info.wrapped.as_deref().ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("密钥 {} 缺少包装数据", key_id)
+        ))?;
+        let wrapped = STANDARD.decode(wrapped)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let decryptor = age::Decryptor::new(&wrapped[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("密钥 {} 包装数据无效: {}", key_id, e)))?;
+
+        let mut reader = match decryptor.decrypt(identities.iter().map(|i| i.as_ref())) {
+            Ok(reader) => reader,
+            Err(age::DecryptError::NoMatchingKeys) => return Ok(None),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("解开密钥 {} 失败: {}", key_id, e))),
+        };
+        let mut key = [0u8; 32];
+        reader.read_exact(&mut key)?;
+        let key = Self(key);
+        if key.check_value() != info.check {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("密钥 {} 校验失败", key_id)));
+        }
+        Ok(Some(key))
+    }
+
+    /// 按包内记录的参数派生密钥，并校验口令是否正确
+    pub fn from_info(key_id: &str, passphrase: &str, info: &KeyInfo) -> io::Result<Self> {
+        if info.kdf != "argon2id" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("不支持的密钥派生算法: {}", info.kdf)
+            ));
+        }
+        let salt = STANDARD.decode(&info.salt)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let key = Self::derive(passphrase, &salt)?;
+        if key.check_value() != info.check {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("密钥 {} 不正确", key_id)
+            ));
+        }
+        Ok(key)
+    }
+
+    fn check_value(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(b"xpak-key-check");
+        hasher.update(self.0);
+        to_hex(&hasher.finalize()[..8])
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+}
+
+/// 读取age身份文件
+pub fn load_identities(paths: &[String]) -> io::Result<Vec<Box<dyn age::Identity>>> {
+    let mut identities = Vec::new();
+    for path in paths {
+        let file = age::IdentityFile::from_file(path.clone())?;
+        let mut loaded = file.into_identities()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("无效的身份文件 '{}': {}", path, e)))?;
+        identities.append(&mut loaded);
+    }
+    Ok(identities)
+}
+
+/// 解析`KEY_ID=PASSPHRASE`形式的密钥参数
+pub fn parse_key_spec(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((id, pass)) if !id.is_empty() => Ok((id.to_string(), pass.to_string())),
+        _ => Err(format!("无效的密钥参数 '{}'，应为 KEY_ID=PASSPHRASE", spec)),
+    }
+}
+
+/// 解析`GLOB=KEY_ID`形式的加密规则
+pub fn parse_encrypt_rule(spec: &str) -> Result<(String, String), String> {
+    match spec.rsplit_once('=') {
+        Some((glob, id)) if !glob.is_empty() && !id.is_empty() => Ok((glob.to_string(), id.to_string())),
+        _ => Err(format!("无效的加密规则 '{}'，应为 GLOB=KEY_ID", spec)),
+    }
+}
+
+/// 生成新的条目nonce（Base64编码）
+pub fn generate_nonce() -> String {
+    let mut nonce = [0u8; STREAM_NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    STANDARD.encode(nonce)
+}
+
+fn decode_nonce(nonce: &str) -> io::Result<[u8; STREAM_NONCE_SIZE]> {
+    let bytes = STANDARD.decode(nonce)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    bytes.try_into()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "无效的nonce长度"))
+}
+
+/// 计算明文加密后的大小
+pub fn encrypted_size(plain_len: u64) -> u64 {
+    let chunks = plain_len.div_ceil(CHUNK_SIZE as u64).max(1);
+    plain_len + chunks * TAG_SIZE as u64
+}
+
+/// 分块加密`plain_len`字节的明文，返回写入的密文字节数
+pub fn encrypt_stream(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    key: &EntryKey,
+    nonce: &str,
+    plain_len: u64
+) -> io::Result<u64> {
+    let nonce = decode_nonce(nonce)?;
+    let mut encryptor = EncryptorBE32::from_aead(key.cipher(), &nonce.into());
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut remaining = plain_len;
+    let mut written = 0u64;
+
+    loop {
+        let to_read = remaining.min(CHUNK_SIZE as u64) as usize;
+        reader.read_exact(&mut buffer[..to_read])?;
+        remaining -= to_read as u64;
+
+        if remaining == 0 {
+            let chunk = encryptor.encrypt_last(&buffer[..to_read])
+                .map_err(|_| io::Error::other("加密失败"))?;
+            writer.write_all(&chunk)?;
+            written += chunk.len() as u64;
+            break;
+        }
+        let chunk = encryptor.encrypt_next(&buffer[..to_read])
+            .map_err(|_| io::Error::other("加密失败"))?;
+        writer.write_all(&chunk)?;
+        written += chunk.len() as u64;
+    }
+
+    Ok(written)
+}
+
+/// 分块解密`stored_len`字节的密文，返回写入的明文字节数
+pub fn decrypt_stream(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    key: &EntryKey,
+    nonce: &str,
+    stored_len: u64
+) -> io::Result<u64> {
+    let nonce = decode_nonce(nonce)?;
+    let mut decryptor = DecryptorBE32::from_aead(key.cipher(), &nonce.into());
+    let mut buffer = vec![0u8; CHUNK_SIZE + TAG_SIZE];
+    let mut remaining = stored_len;
+    let mut written = 0u64;
+
+    loop {
+        let to_read = remaining.min((CHUNK_SIZE + TAG_SIZE) as u64) as usize;
+        reader.read_exact(&mut buffer[..to_read])?;
+        remaining -= to_read as u64;
+
+        if remaining == 0 {
+            let chunk = decryptor.decrypt_last(&buffer[..to_read]).map_err(|_| decrypt_error())?;
+            writer.write_all(&chunk)?;
+            written += chunk.len() as u64;
+            break;
+        }
+        let chunk = decryptor.decrypt_next(&buffer[..to_read]).map_err(|_| decrypt_error())?;
+        writer.write_all(&chunk)?;
+        written += chunk.len() as u64;
+    }
+
+    Ok(written)
+}
+
+/// 用旧密钥逐块解密`stored_len`字节的密文并立即用新密钥加密，明文不落盘
+///
+/// 分块方式不变，写入的密文与原来一样长。
+pub fn reencrypt_stream(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    from: (&EntryKey, &str),
+    to: (&EntryKey, &str),
+    stored_len: u64
+) -> io::Result<u64> {
+    let mut decryptor = DecryptorBE32::from_aead(from.0.cipher(), &decode_nonce(from.1)?.into());
+    let mut encryptor = EncryptorBE32::from_aead(to.0.cipher(), &decode_nonce(to.1)?.into());
+    let mut buffer = vec![0u8; CHUNK_SIZE + TAG_SIZE];
+    let mut remaining = stored_len;
+    let mut written = 0u64;
+
+    loop {
+        let to_read = remaining.min((CHUNK_SIZE + TAG_SIZE) as u64) as usize;
+        reader.read_exact(&mut buffer[..to_read])?;
+        remaining -= to_read as u64;
+
+        if remaining == 0 {
+            let plain = decryptor.decrypt_last(&buffer[..to_read]).map_err(|_| decrypt_error())?;
+            let chunk = encryptor.encrypt_last(&plain[..]).map_err(|_| io::Error::other("加密失败"))?;
+            writer.write_all(&chunk)?;
+            written += chunk.len() as u64;
+            break;
+        }
+        let plain = decryptor.decrypt_next(&buffer[..to_read]).map_err(|_| decrypt_error())?;
+        let chunk = encryptor.encrypt_next(&plain[..]).map_err(|_| io::Error::other("加密失败"))?;
+        writer.write_all(&chunk)?;
+        written += chunk.len() as u64;
+    }
+
+    Ok(written)
+}
+
+fn decrypt_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "解密失败：数据已损坏或密钥错误")
+}
@@ -0,0 +1,186 @@
+//! 包之间的依赖：补丁包可以声明需要的基础包，安装前用`deps check`检查是否齐全
+//!
+//! 包的名称取自用户metadata中的`name`字段（`pak --metadata`），版本为内容版本
+//! （`pak --content-version`），没有记录时使用用户metadata中的`version`字段。依赖记录
+//! 名称、版本要求和可选的包文件摘要，指定摘要时只接受内容完全相同的包。
+
+use clap::ValueEnum;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use walkdir::WalkDir;
+
+use crate::common::ARCHIVE_EXTENSION;
+use crate::hash::{hash_file, HashAlgo};
+use crate::metadata::XpakMetadata;
+use crate::reader::XpakReader;
+
+/// 对另一个包的依赖
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Dependency {
+    pub name: String,
+    /// 版本要求，如`^1.2`、`>=1.0, <2`、`=1.4.0`
+    pub version: String,
+    /// 包文件的摘要，格式为`算法:十六进制摘要`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl Dependency {
+    fn requirement(&self) -> io::Result<VersionReq> {
+        parse_requirement(&self.version)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("依赖 {} 的{}", self.name, e)))
+    }
+}
+
+fn parse_requirement(version: &str) -> Result<VersionReq, String> {
+    VersionReq::parse(version).map_err(|e| format!("版本要求 '{}' 无效: {}", version, e))
+}
+
+/// 包的名称和版本，没有记录或版本无法解析时为`None`
+pub fn identity(metadata: &XpakMetadata) -> Option<(String, Version)> {
+    let name = metadata.common_text("name")?;
+    let version = metadata.content_version.clone().or_else(|| metadata.common_text("version"))?;
+    let version = Version::parse(version.trim_start_matches('v')).ok()?;
+    Some((name, version))
+}
+
+/// 解析`pak --depends`的参数
+///
+/// `NAME=VERSION_REQ`直接声明版本要求；其余参数视为已有包的路径，依赖该包的名称、
+/// 确切版本和文件摘要。
+pub fn resolve_specs(specs: &[String]) -> io::Result<Vec<Dependency>> {
+    specs.iter().map(|spec| {
+        if let Some((name, version)) = spec.split_once('=').filter(|_| !Path::new(spec).is_file()) {
+            let (name, version) = (name.trim(), version.trim());
+            if name.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("无效的依赖 '{}'，应为 NAME=VERSION_REQ 或包文件路径", spec)));
+            }
+            parse_requirement(version).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            return Ok(Dependency { name: name.to_string(), version: version.to_string(), hash: None });
+        }
+        let metadata = XpakReader::open(spec)?.metadata;
+        let (name, version) = identity(&metadata).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} 没有记录名称（metadata中的 name）或semver格式的内容版本，无法作为依赖", spec)
+        ))?;
+        let algo = HashAlgo::Sha256;
+        let hash = hash_file(algo, Path::new(spec))?;
+        Ok(Dependency { name, version: format!("={}", version), hash: Some(format!("{}:{}", algo, hash)) })
+    }).collect()
+}
+
+/// 搜索目录中找到的包
+struct Candidate {
+    path: PathBuf,
+    name: String,
+    version: Version,
+}
+
+/// 在目录（包括子目录）中查找有名称和版本的包，跳过`exclude`
+fn find_archives(dirs: &[PathBuf], exclude: Option<&Path>) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    for dir in dirs {
+        for entry in WalkDir::new(dir).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if !entry.file_type().is_file() || path.extension().is_none_or(|e| e != ARCHIVE_EXTENSION) {
+                continue;
+            }
+            if exclude.is_some_and(|exclude| fs::canonicalize(path).is_ok_and(|p| p == exclude)) {
+                continue;
+            }
+            match XpakReader::open(path) {
+                Ok(reader) => {
+                    if let Some((name, version)) = identity(&reader.metadata) {
+                        candidates.push(Candidate { path: path.to_path_buf(), name, version });
+                    }
+                }
+                Err(e) => debug!(path = %path.display(), "跳过无法读取的包: {}", e),
+            }
+        }
+    }
+    candidates
+}
+
+/// 包文件的摘要是否与依赖中记录的一致
+fn hash_matches(expected: &str, path: &Path) -> io::Result<bool> {
+    let (algo, hash) = expected.split_once(':').unwrap_or(("sha256", expected));
+    let algo = HashAlgo::from_str(algo, true)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("不支持的摘要算法 '{}'", algo)))?;
+    Ok(hash_file(algo, path)?.eq_ignore_ascii_case(hash))
+}
+
+/// 检查包声明的依赖能否在搜索目录中找到，未指定目录时在包所在的目录中查找
+pub fn check(input: &str, search_dirs: &[String]) -> io::Result<()> {
+    let metadata = XpakReader::open(input)?.metadata;
+    if metadata.dependencies.is_empty() {
+        println!("{} 没有声明依赖", input);
+        return Ok(());
+    }
+    let dirs: Vec<PathBuf> = if search_dirs.is_empty() {
+        let parent = Path::new(input).parent().filter(|p| !p.as_os_str().is_empty());
+        vec![parent.map_or_else(|| PathBuf::from("."), Path::to_path_buf)]
+    } else {
+        search_dirs.iter().map(PathBuf::from).collect()
+    };
+    let candidates = find_archives(&dirs, fs::canonicalize(input).ok().as_deref());
+
+    let mut failed = 0;
+    for dependency in &metadata.dependencies {
+        let requirement = dependency.requirement()?;
+        let named: Vec<&Candidate> = candidates.iter().filter(|c| c.name == dependency.name).collect();
+        let compatible: Vec<&Candidate> = named.iter().copied().filter(|c| requirement.matches(&c.version)).collect();
+        let label = format!("{} {}", dependency.name, dependency.version);
+
+        let found = match &dependency.hash {
+            Some(hash) => {
+                let mut found = None;
+                for candidate in &compatible {
+                    if hash_matches(hash, &candidate.path)? {
+                        found = Some(*candidate);
+                        break;
+                    }
+                }
+                found
+            }
+            // 有多个版本满足要求时选择最新的
+            None => compatible.iter().copied().max_by(|a, b| a.version.cmp(&b.version)),
+        };
+        match found {
+            Some(candidate) => {
+                println!("[满足] {} -> {}（{}）", label, candidate.path.display(), candidate.version);
+                continue;
+            }
+            None if named.is_empty() => println!("[缺失] {}: 没有找到名为 {} 的包", label, dependency.name),
+            None if compatible.is_empty() => {
+                let versions: Vec<String> = named.iter().map(|c| format!("{}（{}）", c.version, c.path.display())).collect();
+                println!("[版本不兼容] {}: 找到 {}", label, versions.join(", "));
+            }
+            None => println!("[摘要不一致] {}: 版本满足要求的包与记录的摘要不同", label),
+        }
+        failed += 1;
+    }
+
+    let searched: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+    println!("----------------------------------------");
+    println!("在 {} 中检查了 {} 个依赖", searched.join(", "), metadata.dependencies.len());
+    if failed > 0 {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} 个依赖未满足", failed)));
+    }
+    println!("所有依赖都已满足");
+    Ok(())
+}
+
+/// 依赖的概括，用于`info`
+pub fn summary(metadata: &XpakMetadata) -> String {
+    if metadata.dependencies.is_empty() {
+        return "无".to_string();
+    }
+    metadata.dependencies.iter()
+        .map(|d| format!("{} {}", d.name, d.version))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,35 @@
+//! 输出到块设备、预分配输出文件和检查磁盘的可用空间
+
+use fs4::fs_std::FileExt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// `path`是否是块设备（如分区），块设备不能截断或删除，大小也不能从metadata得到
+pub fn is_block_device(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        path.metadata().is_ok_and(|m| m.file_type().is_block_device())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// 为输出文件预先分配`len`字节的磁盘空间，减少碎片，写入时也不会因磁盘已满中途失败
+///
+/// Linux上使用fallocate，macOS上使用F_PREALLOCATE，Windows上设置文件的分配大小。
+/// 文件长度至少为`len`，写完后需要截断到实际长度。
+pub fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    FileExt::allocate(file, len)
+        .map_err(|e| io::Error::new(e.kind(), format!("无法预分配 {} 字节: {}", len, e)))
+}
+
+/// `path`所在文件系统中当前用户可用的空间
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    fs4::available_space(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("无法获取 {} 所在磁盘的可用空间: {}", path.display(), e)))
+}
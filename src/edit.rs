@@ -0,0 +1,587 @@
+use chrono::DateTime;
+use globset::{Glob, GlobSetBuilder};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::common::{format_size, PathLength, BUFFER_SIZE, FORMAT_VERSION, MAGIC_METADATA_END, MAGIC_NUMBER, MAX_ENTRY_SIZE, MAX_PATH_LEN};
+use crate::crypto::{generate_nonce, reencrypt_stream, EntryKey};
+use crate::ext::{Extension, EXT_TAG};
+use crate::frames::{create_archive, entry_ranges, finish_data_section, FrameTable, FrameWriter, FRAMES_TAG, FRAME_SIZE};
+use crate::hash::hash_file;
+use crate::metadata::{FileInfo, XpakMetadata, FILES_TAG, METADATA_SLACK};
+use crate::migrate::has_metadata_end;
+use crate::mime::sniff_file;
+use crate::parity::{Parity, PARITY_TAG};
+use crate::reader::XpakReader;
+use crate::temp::TempFile;
+use crate::trailer::{unaccounted, Trailer, TrailerRecord};
+use crate::vpath::{build_lookup_index, check_entry_ids, lookup_key, PathPolicy};
+
+/// 打开后准备修改的包
+///
+/// 调用方先修改`metadata`，再用`splice`写入数据区段的改动。
+struct EditableArchive {
+    input: String,
+    metadata: XpakMetadata,
+    data_offset: u64,
+    /// 修改前数据区段的长度
+    data_len: u64,
+    /// 修改前包头中metadata的长度
+    meta_len: usize,
+    trailer: Trailer,
+    /// 数据区段和尾部区段之外的多余数据，重写时丢弃
+    unaccounted: Vec<Range<u64>>,
+}
+
+impl EditableArchive {
+    fn open(input: &str) -> io::Result<Self> {
+        let mut reader = XpakReader::open(input)?;
+        let trailer = reader.trailer()?;
+        let unaccounted = unaccounted(reader.data_end(), trailer.as_ref(), fs::metadata(input)?.len());
+        let trailer = trailer.unwrap_or_default();
+        let data_offset = reader.data_offset;
+        let metadata = std::mem::take(&mut reader.metadata);
+        drop(reader);
+
+        let mut file = File::open(input)?;
+        let mut meta_len_bytes = [0u8; 4];
+        file.seek(SeekFrom::Start(MAGIC_NUMBER.len() as u64))?;
+        file.read_exact(&mut meta_len_bytes)?;
+        Ok(Self {
+            input: input.to_string(),
+            data_len: entry_ranges(&metadata).last().map_or(0, |r| r.end),
+            metadata,
+            data_offset,
+            meta_len: u32::from_le_bytes(meta_len_bytes) as usize,
+            trailer,
+            unaccounted,
+        })
+    }
+
+    /// 最后一个路径为`path`的有效条目
+    fn find_live(&self, path: &str) -> io::Result<usize> {
+        let live = self.metadata.live_entries();
+        self.metadata.files.iter().enumerate()
+            .rposition(|(i, f)| f.path == path && live[i])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("包中没有 {}", path)))
+    }
+
+    /// 帧校验表，没有帧校验表的旧版本包计算`len`之前各帧的校验值
+    fn frames(&self, source: &mut File, len: u64) -> io::Result<FrameTable> {
+        if let Some(record) = self.trailer.get(&FRAMES_TAG) {
+            return FrameTable::from_record(record);
+        }
+        source.seek(SeekFrom::Start(self.data_offset))?;
+        let mut writer = FrameWriter::new(io::sink(), FRAME_SIZE);
+        io::copy(&mut source.take(len), &mut writer)?;
+        Ok(writer.finish().1)
+    }
+
+    /// 准备重新生成尾部区段，条目列表拆分到尾部区段时按修改后数据区段的长度`data_len`拆分
+    fn new_trailer(&mut self, frames: &FrameTable, data_len: u64) -> io::Result<NewTrailer> {
+        let parity = self.trailer.get(&PARITY_TAG)
+            .map(|record| Parity::from_record(record, frames))
+            .transpose()?
+            .map(|p| p.percent());
+        let files_record = match self.metadata.files_table {
+            Some(_) => Some(self.metadata.split_files(data_len)?),
+            None => None,
+        };
+        let extra_records = std::mem::take(&mut self.trailer.records).into_iter()
+            .filter(|r| r.tag != FRAMES_TAG && r.tag != PARITY_TAG && r.tag != FILES_TAG)
+            .collect();
+        Ok(NewTrailer { parity, files_record, extra_records })
+    }
+
+    /// 把数据区段中`range`的内容替换为`write`写出的`len`字节，返回重写的字节数
+    ///
+    /// 只重写从`range.start`所在的帧开始的部分：之后的条目复制到新内容之后，再重新生成
+    /// 尾部区段。metadata不比原来长时用空白补齐后原地写入，否则重写整个包。
+    fn splice(mut self, range: Range<u64>, len: u64, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<u64> {
+        let new_data_len = self.data_len - (range.end - range.start) + len;
+        let mut source = File::open(&self.input)?;
+        let frames = self.frames(&mut source, range.start)?;
+        let trailer = self.new_trailer(&frames, new_data_len)?;
+
+        let metadata_bytes = self.metadata.to_bytes()?;
+        if !has_metadata_end(&self.metadata.format_version) || metadata_bytes.len() > self.meta_len {
+            if self.metadata.files_table.is_none() {
+                println!("metadata变长，需要重写整个包；使用 pak --split-metadata 打包的包通常只需写入改动的部分");
+            }
+            return self.rewrite(source, frames.frame_size, range, write, trailer);
+        }
+
+        // 之后的条目先复制到临时文件，截断后再写回
+        let dir = Path::new(&self.input).parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let mut tail = tempfile::tempfile_in(dir)?;
+        source.seek(SeekFrom::Start(self.data_offset + range.end))?;
+        io::copy(&mut (&mut source).take(self.data_len - range.end), &mut tail)?;
+        tail.seek(SeekFrom::Start(0))?;
+
+        // 修改位置所在帧中位于它之前的部分需要重新计算校验值
+        let frame_size = frames.frame_size as u64;
+        let kept_frames = (range.start / frame_size) as usize;
+        let boundary = kept_frames as u64 * frame_size;
+        let mut head = vec![0u8; (range.start - boundary) as usize];
+        source.seek(SeekFrom::Start(self.data_offset + boundary))?;
+        source.read_exact(&mut head)?;
+
+        // JSON允许末尾有空白，MessagePack只读取第一个值，补齐后包头长度不变
+        let mut metadata_bytes = metadata_bytes;
+        metadata_bytes.resize(self.meta_len, b' ');
+        let mut file = OpenOptions::new().read(true).write(true).open(&self.input)?;
+        file.seek(SeekFrom::Start(8))?;
+        file.write_all(&metadata_bytes)?;
+        file.seek(SeekFrom::Start(self.data_offset - 4))?;
+        file.write_all(&self.metadata.files_count.to_le_bytes())?;
+        file.set_len(self.data_offset + boundary)?;
+        file.seek(SeekFrom::Start(self.data_offset + boundary))?;
+
+        let mut writer = FrameWriter::resume(
+            BufWriter::with_capacity(BUFFER_SIZE, file),
+            frames.frame_size,
+            frames.checksums[..kept_frames].to_vec()
+        );
+        writer.write_all(&head)?;
+        write(&mut writer)?;
+        io::copy(&mut tail, &mut writer)?;
+        trailer.finish(writer, self.data_offset)?;
+        Ok(new_data_len - boundary)
+    }
+
+    /// 重写整个包，旧版本的包与update一样升级到当前格式
+    fn rewrite(
+        mut self,
+        mut source: File,
+        frame_size: u32,
+        range: Range<u64>,
+        write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+        trailer: NewTrailer
+    ) -> io::Result<u64> {
+        if !has_metadata_end(&self.metadata.format_version) {
+            self.metadata.format_version = FORMAT_VERSION.to_string();
+        }
+        let temp = TempFile::new(&self.input)?;
+        let (mut writer, data_offset) = create_rewrite(temp.path(), &self.metadata, frame_size)?;
+        source.seek(SeekFrom::Start(self.data_offset))?;
+        io::copy(&mut (&mut source).take(range.start), &mut writer)?;
+        write(&mut writer)?;
+        source.seek(SeekFrom::Start(self.data_offset + range.end))?;
+        io::copy(&mut source.take(self.data_len - range.end), &mut writer)?;
+        trailer.finish(writer, data_offset)?;
+        temp.commit(&self.input)?;
+        fs::metadata(&self.input).map(|m| m.len())
+    }
+}
+
+/// 修改后重新生成的尾部区段，帧校验表和校验块按新的数据区段计算
+struct NewTrailer {
+    parity: Option<u32>,
+    files_record: Option<TrailerRecord>,
+    extra_records: Vec<TrailerRecord>,
+}
+
+impl NewTrailer {
+    fn finish(self, writer: FrameWriter<BufWriter<File>>, data_offset: u64) -> io::Result<()> {
+        finish_data_section(writer, data_offset, self.parity, self.files_record, self.extra_records)?;
+        Ok(())
+    }
+}
+
+/// 创建重写后的包并写入包头，返回数据区段的写入器和数据区段的偏移
+///
+/// 条目列表拆分到尾部区段的包在metadata之后预留空白，之后的追加更新可以原地写入包头。
+fn create_rewrite(path: &Path, metadata: &XpakMetadata, frame_size: u32) -> io::Result<(FrameWriter<BufWriter<File>>, u64)> {
+    let mut metadata_bytes = metadata.to_bytes()?;
+    if metadata.files_table.is_some() {
+        metadata_bytes.resize(metadata_bytes.len() + METADATA_SLACK, b' ');
+    }
+    let mut pak_file = BufWriter::with_capacity(BUFFER_SIZE, create_archive(path)?);
+    pak_file.write_all(MAGIC_NUMBER)?;
+    pak_file.write_all(&(metadata_bytes.len() as u32).to_le_bytes())?;
+    pak_file.write_all(&metadata_bytes)?;
+    pak_file.write_all(&MAGIC_METADATA_END)?;
+    pak_file.write_all(&metadata.files_count.to_le_bytes())?;
+    let data_offset = (MAGIC_NUMBER.len() + 4 + metadata_bytes.len() + MAGIC_METADATA_END.len() + 4) as u64;
+    Ok((FrameWriter::new(pak_file, frame_size), data_offset))
+}
+
+/// 本地文件作为条目内容时的大小，超过条目大小上限时报错
+fn local_size(from: &Path) -> io::Result<u64> {
+    let size = fs::metadata(from)?.len();
+    if size > MAX_ENTRY_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} 超过单个条目的大小上限 {}", from.display(), format_size(MAX_ENTRY_SIZE))
+        ));
+    }
+    Ok(size)
+}
+
+/// 按本地文件更新条目的大小、摘要、类型和修改时间，新内容不压缩也不加密
+fn update_info(info: &mut FileInfo, from: &Path, size: u64) -> io::Result<()> {
+    info.size = size;
+    info.stored_size = None;
+    info.compression = None;
+    if let Some(algo) = info.algo {
+        info.hash = Some(hash_file(algo, from)?);
+    }
+    info.mime = Some(sniff_file(from)?);
+    info.mtime = fs::metadata(from)?.modified().ok().map(DateTime::from);
+    Ok(())
+}
+
+/// 条目（含头部）在数据区段中的长度
+fn entry_len(path: &str, size: u64, path_length: PathLength) -> u64 {
+    path_length.field_len(path.len()) + path.len() as u64 + 4 + size
+}
+
+/// 写入一个未压缩的条目，`from`为`None`时写入删除标记
+fn write_entry(writer: &mut dyn Write, path: &str, from: Option<&Path>, size: u64, path_length: PathLength) -> io::Result<()> {
+    writer.write_all(&path_length.encode(path.len()))?;
+    writer.write_all(path.as_bytes())?;
+    writer.write_all(&(size as u32).to_le_bytes())?;
+    let Some(from) = from else {
+        return Ok(());
+    };
+    let copied = io::copy(&mut File::open(from)?.take(size), writer)?;
+    if copied != size {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} 在写入过程中被修改", from.display())));
+    }
+    Ok(())
+}
+
+/// 有效条目变化后重新生成大小写查找表
+fn refresh_lookup(metadata: &mut XpakMetadata) -> io::Result<()> {
+    if metadata.lookup.is_some() {
+        let live: Vec<String> = metadata.live_files().into_iter().map(|f| f.path.clone()).collect();
+        metadata.lookup = Some(build_lookup_index(live.iter().map(String::as_str))?);
+    }
+    Ok(())
+}
+
+/// 用本地文件替换包内一个条目的内容
+///
+/// 默认只重写从该条目开始的部分；`append`时把新内容作为同一路径的条目追加到数据区段
+/// 末尾，旧的条目留在原处，由`optimize`清除。已加密的条目无法替换。
+pub fn touch(input: &str, path: &str, from: &str, append: bool) -> io::Result<()> {
+    let from = Path::new(from);
+    let mut archive = EditableArchive::open(input)?;
+    let index = archive.find_live(path)?;
+    if archive.metadata.files[index].encryption.is_some() {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!("条目 {} 已加密，无法替换内容", path)));
+    }
+    let size = local_size(from)?;
+    let path_length = archive.metadata.path_length();
+    let len = entry_len(path, size, path_length);
+
+    let old = entry_ranges(&archive.metadata)[index].clone();
+    let mut info = archive.metadata.files[index].clone();
+    update_info(&mut info, from, size)?;
+    let range = if append {
+        // 旧的条目不再有效，不计入总大小
+        let metadata = &mut archive.metadata;
+        metadata.files_count += 1;
+        metadata.total_size = metadata.total_size - metadata.files[index].size + size;
+        metadata.files.push(info);
+        archive.data_len..archive.data_len
+    } else {
+        let metadata = &mut archive.metadata;
+        metadata.total_size = metadata.total_size - metadata.files[index].size + size;
+        metadata.files[index] = info;
+        old
+    };
+    let rewritten = archive.splice(range, len, |w| write_entry(w, path, Some(from), size, path_length))?;
+    println!("已替换 {}（{}），重写了 {}", path, format_size(size), format_size(rewritten));
+    Ok(())
+}
+
+/// 把本地文件作为新条目追加到包的末尾
+pub fn add(input: &str, path: &str, from: &str) -> io::Result<()> {
+    let from = Path::new(from);
+    let path = lookup_key(path, PathPolicy::Exact);
+    if path.is_empty() || path.len() > MAX_PATH_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("无效的条目路径 {}", path)));
+    }
+    let mut archive = EditableArchive::open(input)?;
+    if archive.find_live(&path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("包中已有 {}，替换内容请使用 touch", path)));
+    }
+    let size = local_size(from)?;
+    let path_length = archive.metadata.path_length();
+
+    // 与包内已有的条目使用相同的摘要算法
+    let algo = archive.metadata.files.iter().find_map(|f| f.algo).unwrap_or_default();
+    let mut info = FileInfo::new(&path, size);
+    info.algo = Some(algo);
+    update_info(&mut info, from, size)?;
+    archive.metadata.files_count += 1;
+    archive.metadata.total_size += size;
+    archive.metadata.files.push(info);
+    refresh_lookup(&mut archive.metadata)?;
+    check_entry_ids(archive.metadata.live_files().into_iter().map(|f| f.path.as_str()))?;
+
+    let end = archive.data_len;
+    let len = entry_len(&path, size, path_length);
+    let rewritten = archive.splice(end..end, len, |w| write_entry(w, &path, Some(from), size, path_length))?;
+    println!("已添加 {}（{}），写入了 {}", path, format_size(size), format_size(rewritten));
+    Ok(())
+}
+
+/// 在包的末尾追加删除标记，条目的内容留在原处，由`optimize`清除
+pub fn remove(input: &str, path: &str) -> io::Result<()> {
+    let mut archive = EditableArchive::open(input)?;
+    let index = archive.find_live(path)?;
+    let path_length = archive.metadata.path_length();
+    let mut tombstone = FileInfo::new(path, 0);
+    tombstone.removed = true;
+    archive.metadata.total_size -= archive.metadata.files[index].size;
+    archive.metadata.files_count += 1;
+    archive.metadata.files.push(tombstone);
+    refresh_lookup(&mut archive.metadata)?;
+    for paths in archive.metadata.bundles.values_mut() {
+        paths.retain(|p| p != path);
+    }
+
+    let end = archive.data_len;
+    let len = entry_len(path, 0, path_length);
+    let rewritten = archive.splice(end..end, len, |w| write_entry(w, path, None, 0, path_length))?;
+    println!("已删除 {}，写入了 {}", path, format_size(rewritten));
+    Ok(())
+}
+
+/// 把`source`中匹配`patterns`的有效条目原样追加到`input`的末尾
+///
+/// 条目按包内存储的字节复制，压缩和加密的条目不解压也不解密，摘要、压缩信息、平台等
+/// 属性随条目一起复制。加密条目使用的密钥信息写入目标包，目标包中同名的密钥不同时报错。
+/// `replace`时目标包中已有的同路径条目被取代，旧的条目留在原处，由`optimize`清除。
+pub fn copy(source: &str, input: &str, patterns: &[String], replace: bool) -> io::Result<()> {
+    if fs::canonicalize(source)? == fs::canonicalize(input)? {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "来源和目标是同一个包"));
+    }
+    let reader = XpakReader::open(source)?;
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的匹配规则 '{}': {}", pattern, e)))?;
+        builder.add(glob);
+    }
+    let globs = builder.build().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let live = reader.metadata.live_entries();
+    let ranges = entry_ranges(&reader.metadata);
+    let mut hit = vec![false; patterns.len()];
+    let mut selected = Vec::new();
+    for (i, file) in reader.metadata.files.iter().enumerate().filter(|(i, _)| live[*i]) {
+        let matches = globs.matches(&file.path);
+        for &m in &matches {
+            hit[m] = true;
+        }
+        if !matches.is_empty() {
+            let stored = file.stored_size.unwrap_or(file.size);
+            selected.push((file, reader.data_offset + ranges[i].end - stored, stored));
+        }
+    }
+    let unmatched: Vec<&str> = patterns.iter().zip(&hit).filter(|(_, hit)| !**hit).map(|(p, _)| p.as_str()).collect();
+    if !unmatched.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} 中没有匹配 {} 的条目", source, unmatched.join(", "))));
+    }
+
+    let mut archive = EditableArchive::open(input)?;
+    let path_length = archive.metadata.path_length();
+    let mut len = 0;
+    for (file, _, stored) in &selected {
+        let superseded = archive.find_live(&file.path).ok();
+        if !replace && superseded.is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} 中已有 {}，覆盖请使用 --replace", input, file.path)));
+        }
+        if let Some(encryption) = &file.encryption {
+            let key = reader.metadata.keys.get(&encryption.key_id).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} 中的条目 {} 使用的密钥 {} 没有记录在metadata中", source, file.path, encryption.key_id)
+            ))?;
+            match archive.metadata.keys.get(&encryption.key_id) {
+                Some(existing) if existing.salt != key.salt || existing.check != key.check => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} 中的密钥 {} 与来源包中的不同，无法复制用它加密的 {}", input, encryption.key_id, file.path)
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    archive.metadata.keys.insert(encryption.key_id.clone(), key.clone());
+                }
+            }
+        }
+        len += entry_len(&file.path, *stored, path_length);
+        if let Some(index) = superseded {
+            archive.metadata.total_size -= archive.metadata.files[index].size;
+        }
+        archive.metadata.files_count += 1;
+        archive.metadata.total_size += file.size;
+        archive.metadata.files.push((*file).clone());
+    }
+    refresh_lookup(&mut archive.metadata)?;
+    check_entry_ids(archive.metadata.live_files().into_iter().map(|f| f.path.as_str()))?;
+
+    let mut from = File::open(source)?;
+    let end = archive.data_len;
+    let rewritten = archive.splice(end..end, len, |w| {
+        for (file, offset, stored) in &selected {
+            w.write_all(&path_length.encode(file.path.len()))?;
+            w.write_all(file.path.as_bytes())?;
+            w.write_all(&(*stored as u32).to_le_bytes())?;
+            from.seek(SeekFrom::Start(*offset))?;
+            if io::copy(&mut (&mut from).take(*stored), w)? != *stored {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} 中的条目 {} 不完整", source, file.path)));
+            }
+        }
+        Ok(())
+    })?;
+    let size: u64 = selected.iter().map(|(file, _, _)| file.size).sum();
+    println!("已从 {} 复制 {} 个条目（{}），写入了 {}", source, selected.len(), format_size(size), format_size(rewritten));
+    Ok(())
+}
+
+/// 清除被替换的条目、删除标记和多余数据，重写整个包
+///
+/// `keep_trailing`时多余数据不丢弃，而是保存为名为`trailing-data`的扩展，可以用`ext get`导出。
+pub fn optimize(input: &str, keep_trailing: bool) -> io::Result<()> {
+    let mut archive = EditableArchive::open(input)?;
+    let live = archive.metadata.live_entries();
+    let stale = live.iter().filter(|l| !**l).count();
+    let trailing: u64 = archive.unaccounted.iter().map(|r| r.end - r.start).sum();
+    if stale == 0 && trailing == 0 {
+        println!("没有被替换或删除的条目，也没有多余数据");
+        return Ok(());
+    }
+
+    let mut source = File::open(input)?;
+    let frames = archive.frames(&mut source, 0)?;
+    let ranges = entry_ranges(&archive.metadata);
+    let metadata = &mut archive.metadata;
+    let mut kept_ranges = Vec::new();
+    let mut kept = Vec::new();
+    for ((file, range), live) in metadata.files.drain(..).zip(ranges).zip(live) {
+        if live {
+            kept.push(file);
+            kept_ranges.push(range);
+        }
+    }
+    metadata.files_count = kept.len() as u32;
+    metadata.total_size = kept.iter().map(|f| f.size).sum();
+    metadata.files = kept;
+    if !has_metadata_end(&metadata.format_version) {
+        metadata.format_version = FORMAT_VERSION.to_string();
+    }
+    let mut trailer = archive.new_trailer(&frames, kept_ranges.iter().map(|r| r.end - r.start).sum())?;
+    if keep_trailing && trailing > 0 {
+        quarantine(&mut source, &archive.unaccounted, &mut trailer.extra_records)?;
+    }
+    let metadata = &archive.metadata;
+
+    let before = fs::metadata(input)?.len();
+    let temp = TempFile::new(input)?;
+    let (mut writer, data_offset) = create_rewrite(temp.path(), metadata, frames.frame_size)?;
+    for range in &kept_ranges {
+        source.seek(SeekFrom::Start(archive.data_offset + range.start))?;
+        io::copy(&mut (&mut source).take(range.end - range.start), &mut writer)?;
+    }
+    trailer.finish(writer, data_offset)?;
+    temp.commit(input)?;
+    let after = fs::metadata(input)?.len();
+    if stale > 0 {
+        println!("已清除 {} 个被替换或删除的条目", stale);
+    }
+    if trailing > 0 && keep_trailing {
+        println!("已把多余数据（{}）保存到扩展 {}", format_size(trailing), TRAILING_EXT);
+    } else if trailing > 0 {
+        println!("已清除多余数据（{}）", format_size(trailing));
+    }
+    if after < before {
+        println!("释放 {}", format_size(before - after));
+    }
+    Ok(())
+}
+
+/// 保存多余数据的扩展名
+const TRAILING_EXT: &str = "trailing-data";
+
+/// 把多余数据追加到`trailing-data`扩展中，没有该扩展时新建
+fn quarantine(source: &mut File, ranges: &[Range<u64>], records: &mut Vec<TrailerRecord>) -> io::Result<()> {
+    let mut data = Vec::new();
+    for range in ranges {
+        source.seek(SeekFrom::Start(range.start))?;
+        source.take(range.end - range.start).read_to_end(&mut data)?;
+    }
+    let existing = records.iter().position(|r| r.tag == EXT_TAG && Extension::from_record(r).is_ok_and(|e| e.name == TRAILING_EXT));
+    let mut extension = match existing {
+        Some(index) => Extension::from_record(&records.remove(index))?,
+        None => Extension { name: TRAILING_EXT.to_string(), data: Vec::new() },
+    };
+    extension.data.append(&mut data);
+    records.push(extension.to_record());
+    Ok(())
+}
+
+/// 把用`old`加密的条目改用`new`加密，同时更新metadata中的密钥信息
+///
+/// 每个条目逐块解密后立即重新加密写入新的包，不解出到磁盘。`new`的KEY_ID已被其他条目
+/// 使用时沿用包内的派生参数，否则为新口令生成新的盐。
+pub fn rekey(input: &str, old: &(String, String), new: &(String, String)) -> io::Result<()> {
+    let mut archive = EditableArchive::open(input)?;
+    let (old_id, new_id) = (&old.0, &new.0);
+    let old_info = archive.metadata.keys.get(old_id).ok_or_else(|| io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("包中没有密钥 {}", old_id)
+    ))?;
+    let old_key = EntryKey::from_info(old_id, &old.1, old_info)?;
+    let (new_key, new_info) = match archive.metadata.keys.get(new_id).filter(|_| new_id != old_id) {
+        Some(info) => (EntryKey::from_info(new_id, &new.1, info)?, info.clone()),
+        None => EntryKey::generate(&new.1)?,
+    };
+
+    let mut source = File::open(input)?;
+    let frames = archive.frames(&mut source, 0)?;
+    let ranges = entry_ranges(&archive.metadata);
+    // 需要重新加密的条目的密文长度和前后的nonce；拆分条目列表后metadata中不再有条目，先记下
+    let mut rekeyed = Vec::with_capacity(ranges.len());
+    for file in &mut archive.metadata.files {
+        let plan = match file.encryption.as_mut().filter(|e| e.key_id == *old_id) {
+            Some(encryption) => {
+                let old_nonce = std::mem::replace(&mut encryption.nonce, generate_nonce());
+                encryption.key_id = new_id.clone();
+                Some((file.stored_size.unwrap_or(file.size), old_nonce, encryption.nonce.clone()))
+            }
+            None => None,
+        };
+        rekeyed.push(plan);
+    }
+    let count = rekeyed.iter().filter(|p| p.is_some()).count();
+    archive.metadata.keys.remove(old_id);
+    archive.metadata.keys.insert(new_id.clone(), new_info);
+    if !has_metadata_end(&archive.metadata.format_version) {
+        archive.metadata.format_version = FORMAT_VERSION.to_string();
+    }
+    let data_len = archive.data_len;
+    let trailer = archive.new_trailer(&frames, data_len)?;
+
+    let temp = TempFile::new(input)?;
+    let (mut writer, data_offset) = create_rewrite(temp.path(), &archive.metadata, frames.frame_size)?;
+    source.seek(SeekFrom::Start(archive.data_offset))?;
+    let mut source = io::BufReader::with_capacity(BUFFER_SIZE, source);
+    for (range, plan) in ranges.iter().zip(&rekeyed) {
+        let Some((stored, old_nonce, new_nonce)) = plan else {
+            io::copy(&mut (&mut source).take(range.end - range.start), &mut writer)?;
+            continue;
+        };
+        io::copy(&mut (&mut source).take(range.end - range.start - stored), &mut writer)?;
+        reencrypt_stream(&mut source, &mut writer, (&old_key, old_nonce), (&new_key, new_nonce), *stored)?;
+    }
+    trailer.finish(writer, data_offset)?;
+    temp.commit(input)?;
+    println!("已将 {} 个条目从密钥 {} 改用密钥 {} 加密", count, old_id, new_id);
+    Ok(())
+}
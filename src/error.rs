@@ -0,0 +1,113 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// 错误输出格式
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+/// 附带出错文件和位置的错误信息，包装在`io::Error`中传递
+#[derive(Debug)]
+pub struct ErrorContext {
+    pub message: String,
+    pub path: Option<String>,
+    /// 损坏数据在包内的字节偏移
+    pub offset: Option<u64>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ErrorContext {}
+
+/// 取出错误中已有的上下文，没有时以错误信息新建一个
+fn into_context(err: io::Error) -> (io::ErrorKind, ErrorContext) {
+    let kind = err.kind();
+    if err.get_ref().is_some_and(|inner| inner.is::<ErrorContext>()) {
+        let context = err.into_inner().unwrap().downcast::<ErrorContext>().unwrap();
+        return (kind, *context);
+    }
+    (kind, ErrorContext { message: err.to_string(), path: None, offset: None })
+}
+
+/// 为错误附加出错的文件路径（已有路径时保留原路径）
+pub fn with_path(err: io::Error, path: impl AsRef<Path>) -> io::Error {
+    let (kind, mut context) = into_context(err);
+    context.path.get_or_insert_with(|| path.as_ref().to_string_lossy().to_string());
+    io::Error::new(kind, context)
+}
+
+/// 为错误附加出错位置的字节偏移（已有偏移时保留原偏移）
+pub fn at_offset(err: io::Error, offset: u64) -> io::Error {
+    let (kind, mut context) = into_context(err);
+    context.offset.get_or_insert(offset);
+    io::Error::new(kind, context)
+}
+
+/// 包数据损坏的错误
+pub fn corrupt(message: impl Into<String>, offset: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, ErrorContext {
+        message: message.into(),
+        path: None,
+        offset: Some(offset),
+    })
+}
+
+#[derive(Serialize)]
+struct ErrorReport<'a> {
+    code: &'static str,
+    message: String,
+    path: Option<&'a str>,
+    offset: Option<u64>,
+}
+
+/// 供其他程序区分错误类型的错误码
+fn error_code(kind: io::ErrorKind) -> &'static str {
+    match kind {
+        io::ErrorKind::NotFound => "not_found",
+        io::ErrorKind::PermissionDenied => "permission_denied",
+        io::ErrorKind::AlreadyExists => "already_exists",
+        io::ErrorKind::InvalidInput => "invalid_input",
+        io::ErrorKind::InvalidData => "corrupt_data",
+        io::ErrorKind::UnexpectedEof => "truncated",
+        io::ErrorKind::Interrupted => "interrupted",
+        _ => "io_error",
+    }
+}
+
+/// 按指定格式将错误输出到标准错误
+pub fn print_error(err: &io::Error, format: ErrorFormat) {
+    let context = err.get_ref().and_then(|inner| inner.downcast_ref::<ErrorContext>());
+    let path = context.and_then(|c| c.path.as_deref());
+    let offset = context.and_then(|c| c.offset);
+
+    match format {
+        ErrorFormat::Json => {
+            let report = ErrorReport {
+                code: error_code(err.kind()),
+                message: err.to_string(),
+                path,
+                offset,
+            };
+            eprintln!("{}", serde_json::to_string(&report).unwrap());
+        }
+        ErrorFormat::Text => {
+            eprintln!("错误: {}", err);
+            if let Some(path) = path {
+                eprintln!("  文件: {}", path);
+            }
+            if let Some(offset) = offset {
+                eprintln!("  偏移: {} (0x{:X})", offset, offset);
+            }
+        }
+    }
+}
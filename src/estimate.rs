@@ -0,0 +1,74 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+use crate::common::{format_size, MAGIC_METADATA_END, MAGIC_NUMBER, MAX_ENTRY_SIZE, MB};
+use crate::compress::{compress, Compression};
+use crate::hash::{HashAlgo, Hasher};
+use crate::metadata::{FileInfo, XpakMetadata};
+use crate::pak::{collect_sources, PackOptions};
+
+/// 最多抽样的文件数
+const SAMPLE_FILES: usize = 64;
+/// 每个抽样文件读取的最大字节数
+const SAMPLE_SIZE: usize = MB;
+
+/// 估算打包结果，不写入任何文件
+pub fn estimate(inputs: &[String], compression: Option<Compression>, hash_algo: HashAlgo) -> io::Result<()> {
+    let options = PackOptions { hash_algo, ..Default::default() };
+    let files = collect_sources(inputs, &options, &mut Vec::new())?;
+    let total_size: u64 = files.iter().map(|f| f.size).sum();
+
+    // 均匀抽样部分文件，测量压缩率和处理速度
+    let step = files.len().div_ceil(SAMPLE_FILES).max(1);
+    let mut sampled = 0u64;
+    let mut compressed = 0u64;
+    let mut elapsed = Duration::ZERO;
+    let mut buffer = Vec::with_capacity(SAMPLE_SIZE);
+    for file in files.iter().step_by(step) {
+        buffer.clear();
+        File::open(&file.path)?.take(SAMPLE_SIZE as u64).read_to_end(&mut buffer)?;
+
+        let started = Instant::now();
+        let mut hasher = Hasher::new(hash_algo);
+        hasher.update(&buffer);
+        hasher.finalize_hex();
+        compressed += match compression {
+            Some(algo) => compress(algo, &buffer)?.len() as u64,
+            None => buffer.len() as u64,
+        };
+        elapsed += started.elapsed();
+        sampled += buffer.len() as u64;
+    }
+    let ratio = if sampled > 0 { compressed as f64 / sampled as f64 } else { 1.0 };
+    let data_size = (total_size as f64 * ratio) as u64;
+
+    // 使用占位摘要生成metadata，计算头部大小
+    let placeholder = "0".repeat(Hasher::new(hash_algo).finalize_hex().len());
+    let mut metadata = XpakMetadata::new(files.len() as u32, total_size);
+    metadata.files = files.iter()
+        .map(|f| FileInfo::new(&f.relative, f.size).with_hash(hash_algo, placeholder.clone()))
+        .collect();
+    let metadata_size = serde_json::to_vec(&metadata)?.len() as u64;
+    let entries_size: u64 = metadata.files.iter().map(|f| 8 + f.path.len() as u64).sum();
+    let archive_size = (MAGIC_NUMBER.len() + 4 + MAGIC_METADATA_END.len() + 4) as u64
+        + metadata_size + entries_size + data_size;
+
+    println!("文件数: {}", files.len());
+    println!("原始大小: {}", format_size(total_size));
+    if let Some(algo) = compression {
+        println!("预计压缩率 ({}): {:.1}%", algo, ratio * 100.0);
+    }
+    println!("Metadata大小: {}", format_size(metadata_size));
+    println!("预计包大小: {} ({} 字节)", format_size(archive_size), archive_size);
+    if sampled > 0 && !elapsed.is_zero() {
+        let throughput = sampled as f64 / elapsed.as_secs_f64();
+        println!("预计耗时: {:.1} 秒 (抽样速度 {}/s)", total_size as f64 / throughput, format_size(throughput as u64));
+    }
+
+    let oversized: Vec<_> = files.iter().filter(|f| f.size > MAX_ENTRY_SIZE).collect();
+    for file in &oversized {
+        println!("警告: {} 超过单个条目 4 GB 的上限，无法打包", file.relative.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use std::io::{self, BufRead};
+
+/// 显示包内的许可协议，并确认使用者已接受
+///
+/// 指定了`--accept-eula`时直接通过；否则在终端中询问，非交互环境下拒绝解包。
+pub fn require_acceptance(source: &str, text: &str, accepted: bool) -> io::Result<()> {
+    println!("{} 包含许可协议:", source);
+    println!("========================================");
+    println!("{}", text.trim_end());
+    println!("========================================");
+    if accepted {
+        println!("已通过 --accept-eula 接受许可协议");
+        return Ok(());
+    }
+
+    let term = console::Term::stderr();
+    if !term.is_term() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "需要先接受许可协议，确认接受后使用 --accept-eula 解包"
+        ));
+    }
+    term.write_str("是否接受以上许可协议？[y/N] ")?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "未接受许可协议，已取消解包"))
+    }
+}
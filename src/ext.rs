@@ -0,0 +1,122 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::common::format_size;
+use crate::trailer::{read_trailer, write_trailer, Trailer, TrailerRecord};
+
+/// 扩展数据的尾部记录标记
+pub const EXT_TAG: [u8; 8] = *b"EXTENSN_";
+/// 扩展类型名的最大长度
+const MAX_NAME_LEN: usize = u16::MAX as usize;
+
+/// 附加在数据区段之后的扩展数据，例如签名包或图标
+///
+/// 每个扩展是一条尾部记录，内容为`[类型名长度: u16][类型名][数据]`。扩展与
+/// 数据区段相互独立，`update`重写metadata和`repair`重建包时都会保留。
+pub struct Extension {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl Extension {
+    pub fn to_record(&self) -> TrailerRecord {
+        let mut data = Vec::with_capacity(2 + self.name.len() + self.data.len());
+        data.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        data.extend_from_slice(self.name.as_bytes());
+        data.extend_from_slice(&self.data);
+        TrailerRecord { tag: EXT_TAG, data }
+    }
+
+    pub fn from_record(record: &TrailerRecord) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "扩展记录已损坏");
+        let data = &record.data;
+        if data.len() < 2 {
+            return Err(invalid());
+        }
+        let name_len = u16::from_le_bytes([data[0], data[1]]) as usize;
+        let name = data.get(2..2 + name_len).ok_or_else(invalid)?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| invalid())?;
+        Ok(Self { name, data: data[2 + name_len..].to_vec() })
+    }
+}
+
+/// 包内的所有扩展，以及每个扩展的数据在文件中的偏移
+fn extensions(trailer: &Trailer) -> io::Result<Vec<(u64, Extension)>> {
+    let mut offset = trailer.offset;
+    let mut result = Vec::new();
+    for record in &trailer.records {
+        let data_offset = offset + 16;
+        offset = data_offset + record.data.len() as u64;
+        if record.tag == EXT_TAG {
+            let ext = Extension::from_record(record)?;
+            result.push((data_offset + 2 + ext.name.len() as u64, ext));
+        }
+    }
+    Ok(result)
+}
+
+fn open_trailer(input: &str) -> io::Result<Trailer> {
+    let mut file = fs::File::open(input)?;
+    Ok(read_trailer(&mut file)?.unwrap_or_default())
+}
+
+/// 添加扩展，`replace`为真时替换同名的扩展
+pub fn add(input: &str, name: &str, data_path: &str, replace: bool) -> io::Result<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "扩展类型名不能为空且不能超过65535字节"));
+    }
+    let data = fs::read(data_path)?;
+
+    let mut file = OpenOptions::new().read(true).write(true).open(input)?;
+    let file_len = file.seek(SeekFrom::End(0))?;
+    // 没有尾部区段的包从文件末尾开始写入
+    let trailer = read_trailer(&mut file)?.unwrap_or(Trailer { offset: file_len, records: Vec::new() });
+
+    let mut records = Vec::with_capacity(trailer.records.len() + 1);
+    for record in trailer.records {
+        if record.tag == EXT_TAG && Extension::from_record(&record)?.name == name {
+            if !replace {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("扩展 {} 已存在，使用 --replace 替换", name)
+                ));
+            }
+            continue;
+        }
+        records.push(record);
+    }
+    records.push(Extension { name: name.to_string(), data }.to_record());
+
+    file.set_len(trailer.offset)?;
+    file.seek(SeekFrom::Start(trailer.offset))?;
+    let mut writer = io::BufWriter::new(&mut file);
+    write_trailer(&mut writer, &records)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn list(input: &str) -> io::Result<()> {
+    let extensions = extensions(&open_trailer(input)?)?;
+    if extensions.is_empty() {
+        println!("包中没有扩展数据");
+        return Ok(());
+    }
+    println!("扩展数据 ({} 个):", extensions.len());
+    println!("----------------------------------------");
+    for (offset, ext) in &extensions {
+        println!("{}  偏移 {:#x}  {}", ext.name, offset, format_size(ext.data.len() as u64));
+    }
+    Ok(())
+}
+
+/// 取出扩展数据，没有指定输出文件时写到标准输出
+pub fn get(input: &str, name: &str, output: Option<&str>) -> io::Result<()> {
+    let extensions = extensions(&open_trailer(input)?)?;
+    let Some((_, ext)) = extensions.into_iter().find(|(_, e)| e.name == name) else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("包中没有扩展 {}", name)));
+    };
+    match output {
+        Some(path) => fs::write(path, &ext.data),
+        None => io::stdout().write_all(&ext.data),
+    }
+}
@@ -0,0 +1,110 @@
+//! 打包时读取源文件的描述符池
+//!
+//! 并行计算摘要时每个任务都打开一个源文件，在网络文件系统上可能遇到打开的文件过多
+//! （EMFILE）或暂时性的IO错误（EIO）。描述符池限制同时打开的源文件数，打开和读取遇到
+//! 暂时性错误时按指数退避重试。
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+/// 第一次重试前的等待时间，之后每次加倍
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+/// 两次重试之间最长的等待时间
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// 打开的文件过多（Unix的错误码）
+const EMFILE: i32 = 24;
+/// 系统打开的文件过多
+const ENFILE: i32 = 23;
+/// 底层IO错误，网络文件系统上常见于连接短暂中断
+const EIO: i32 = 5;
+
+/// 重试后可能成功的错误
+fn is_transient(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+        || (cfg!(unix) && matches!(e.raw_os_error(), Some(EMFILE | ENFILE | EIO)))
+}
+
+/// 源文件的描述符池
+#[derive(Debug, Default)]
+pub struct SourcePool {
+    limit: Option<usize>,
+    /// 暂时性错误的最大重试次数
+    retries: u32,
+    open: Mutex<usize>,
+    released: Condvar,
+}
+
+/// 从池中打开的文件，关闭时归还名额
+pub struct PooledFile<'a> {
+    file: File,
+    path: PathBuf,
+    pool: &'a SourcePool,
+}
+
+impl SourcePool {
+    /// `limit`为`None`时不限制同时打开的文件数
+    pub fn new(limit: Option<usize>, retries: u32) -> Self {
+        Self { limit, retries, ..Default::default() }
+    }
+
+    /// 打开文件，同时打开的文件数达到上限时等待其他文件关闭
+    pub fn open(&self, path: &Path) -> io::Result<PooledFile<'_>> {
+        {
+            let mut open = self.open.lock().unwrap();
+            if let Some(limit) = self.limit {
+                while *open >= limit {
+                    open = self.released.wait(open).unwrap();
+                }
+            }
+            *open += 1;
+        }
+        match self.retry(path, "打开", || File::open(path)) {
+            Ok(file) => Ok(PooledFile { file, path: path.to_path_buf(), pool: self }),
+            Err(e) => {
+                self.release();
+                Err(e)
+            }
+        }
+    }
+
+    /// 执行`operation`，遇到暂时性错误时等待并重试，至多重试`retries`次
+    fn retry<T>(&self, path: &Path, action: &str, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    attempt += 1;
+                    warn!(path = %path.display(), attempt, "{}文件时出错，{}ms 后重试: {}", action, delay.as_millis(), e);
+                    std::thread::sleep(delay);
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn release(&self) {
+        *self.open.lock().unwrap() -= 1;
+        self.released.notify_one();
+    }
+}
+
+impl Read for PooledFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // 读取失败时文件位置不变，可以直接重试
+        let file = &mut self.file;
+        self.pool.retry(&self.path, "读取", || file.read(buf))
+    }
+}
+
+impl Drop for PooledFile<'_> {
+    fn drop(&mut self) {
+        self.pool.release();
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::metadata::FileInfo;
+use crate::reader::XpakReader;
+
+/// 打包时通过`--file-meta`附加到条目上的属性
+#[derive(Debug, Clone)]
+pub enum FileMeta {
+    /// 条目只用于这些目标平台
+    Platform(Vec<String>),
+    /// 条目是这些语言的本地化内容
+    Lang(Vec<String>),
+}
+
+impl FileMeta {
+    /// 把属性记录到条目上，多条规则指定的取值合并在一起
+    pub fn apply(&self, info: &mut FileInfo) {
+        let (tags, values) = match self {
+            FileMeta::Platform(platforms) => (&mut info.platforms, platforms),
+            FileMeta::Lang(langs) => (&mut info.langs, langs),
+        };
+        for value in values {
+            if !tags.contains(value) {
+                tags.push(value.clone());
+            }
+        }
+    }
+}
+
+/// 解析`GLOB=KEY:VALUE`形式的条目属性规则，支持`platform:NAME[,NAME...]`和`lang:CODE[,CODE...]`
+pub fn parse_file_meta(spec: &str) -> Result<(String, FileMeta), String> {
+    let invalid = || format!("无效的条目属性 '{}'，应为 GLOB=KEY:VALUE", spec);
+    let (glob, meta) = spec.rsplit_once('=').filter(|(glob, _)| !glob.is_empty()).ok_or_else(invalid)?;
+    let (key, value) = meta.split_once(':').ok_or_else(invalid)?;
+    let values: Vec<String> = value.split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    let meta = match key {
+        "platform" if !values.is_empty() => FileMeta::Platform(values),
+        "lang" if !values.is_empty() => FileMeta::Lang(values),
+        "platform" | "lang" => return Err(format!("条目属性 '{}' 缺少取值", spec)),
+        _ => return Err(format!("不支持的条目属性 '{}'，支持 platform 和 lang", key)),
+    };
+    Ok((glob.to_string(), meta))
+}
+
+/// 条目是否用于`platform`，没有标记平台的条目用于所有平台
+pub fn for_platform(info: &FileInfo, platform: &str) -> bool {
+    info.platforms.is_empty() || info.platforms.iter().any(|p| p.eq_ignore_ascii_case(platform))
+}
+
+/// 条目是否属于`langs`中的某个语言，没有标记语言的条目总是选中
+pub fn for_langs(info: &FileInfo, langs: &[String]) -> bool {
+    info.langs.is_empty() || info.langs.iter().any(|l| langs.iter().any(|w| w.eq_ignore_ascii_case(l)))
+}
+
+/// 按语言统计包内的有效条目
+pub fn list_langs(input: &str) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut untagged = 0;
+    for file in reader.metadata.live_files() {
+        if file.langs.is_empty() {
+            untagged += 1;
+        }
+        for lang in &file.langs {
+            *counts.entry(lang).or_default() += 1;
+        }
+    }
+    if counts.is_empty() {
+        println!("包中没有标记语言的条目");
+    }
+    for (lang, count) in &counts {
+        println!("{}\t{} 个条目", lang, count);
+    }
+    if !counts.is_empty() && untagged > 0 {
+        println!("（另有 {} 个条目没有标记语言，解包时总是包含）", untagged);
+    }
+    Ok(())
+}
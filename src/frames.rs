@@ -0,0 +1,209 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::common::{BUFFER_SIZE, MB};
+use crate::metadata::XpakMetadata;
+use crate::parity::Parity;
+use crate::trailer::{write_trailer, TrailerRecord};
+
+/// 数据区段校验帧的默认大小
+pub const FRAME_SIZE: u32 = MB as u32;
+/// 帧校验表的尾部记录标记
+pub const FRAMES_TAG: [u8; 8] = *b"FRAMES__";
+
+/// 数据区段的分帧校验表
+///
+/// 数据区段（条目数量字段之后的所有条目）按固定大小分帧，每帧记录一个CRC32，
+/// 用于定位损坏的字节范围。偏移均相对于数据区段的起始位置。
+#[derive(Debug, Clone)]
+pub struct FrameTable {
+    pub frame_size: u32,
+    pub data_len: u64,
+    pub checksums: Vec<u32>,
+}
+
+impl FrameTable {
+    pub fn to_record(&self) -> TrailerRecord {
+        let mut data = Vec::with_capacity(12 + self.checksums.len() * 4);
+        data.extend_from_slice(&self.frame_size.to_le_bytes());
+        data.extend_from_slice(&self.data_len.to_le_bytes());
+        for checksum in &self.checksums {
+            data.extend_from_slice(&checksum.to_le_bytes());
+        }
+        TrailerRecord { tag: FRAMES_TAG, data }
+    }
+
+    pub fn from_record(record: &TrailerRecord) -> io::Result<Self> {
+        let data = &record.data;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "帧校验表已损坏");
+        if data.len() < 12 || !(data.len() - 12).is_multiple_of(4) {
+            return Err(invalid());
+        }
+        let frame_size = u32::from_le_bytes(data[..4].try_into().unwrap());
+        let data_len = u64::from_le_bytes(data[4..12].try_into().unwrap());
+        let checksums: Vec<u32> = data[12..].chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        if frame_size == 0 || data_len.div_ceil(frame_size as u64) != checksums.len() as u64 {
+            return Err(invalid());
+        }
+        Ok(Self { frame_size, data_len, checksums })
+    }
+
+    /// 第`index`帧覆盖的字节范围
+    pub fn frame_range(&self, index: usize) -> Range<u64> {
+        let start = index as u64 * self.frame_size as u64;
+        start..(start + self.frame_size as u64).min(self.data_len)
+    }
+
+    /// 重新计算数据区段各帧的校验值，返回损坏的帧
+    pub fn damaged_frames(&self, file: &mut File, data_offset: u64) -> io::Result<Vec<usize>> {
+        file.seek(SeekFrom::Start(data_offset))?;
+        let mut buffer = vec![0u8; self.frame_size as usize];
+        let mut damaged = Vec::new();
+        for (index, expected) in self.checksums.iter().enumerate() {
+            let range = self.frame_range(index);
+            let frame = &mut buffer[..(range.end - range.start) as usize];
+            let actual = match file.read_exact(frame) {
+                Ok(()) => crc32fast::hash(frame),
+                // 文件被截断时剩余的帧都视为损坏
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    damaged.extend(index..self.checksums.len());
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            if actual != *expected {
+                damaged.push(index);
+            }
+        }
+        Ok(damaged)
+    }
+}
+
+/// 计算每个条目（含头部）在数据区段中的字节范围
+///
+/// 只依赖metadata，条目头部损坏时仍然可以使用。
+pub fn entry_ranges(metadata: &XpakMetadata) -> Vec<Range<u64>> {
+    let path_length = metadata.path_length();
+    let mut offset = 0u64;
+    metadata.files.iter()
+        .map(|f| {
+            let len = path_length.field_len(f.path.len()) + f.path.len() as u64 + 4 + f.stored_size.unwrap_or(f.size);
+            let range = offset..offset + len;
+            offset += len;
+            range
+        })
+        .collect()
+}
+
+/// 写入数据区段时按帧计算校验值的写入器
+pub struct FrameWriter<W: Write> {
+    inner: W,
+    frame_size: u32,
+    hasher: crc32fast::Hasher,
+    in_frame: u64,
+    data_len: u64,
+    checksums: Vec<u32>,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(inner: W, frame_size: u32) -> Self {
+        Self {
+            inner,
+            frame_size,
+            hasher: crc32fast::Hasher::new(),
+            in_frame: 0,
+            data_len: 0,
+            checksums: Vec::new(),
+        }
+    }
+
+    /// 从第`checksums.len()`帧的起点继续写入，之前各帧的校验值保持不变
+    pub fn resume(inner: W, frame_size: u32, checksums: Vec<u32>) -> Self {
+        Self {
+            inner,
+            frame_size,
+            hasher: crc32fast::Hasher::new(),
+            in_frame: 0,
+            data_len: checksums.len() as u64 * frame_size as u64,
+            checksums,
+        }
+    }
+
+    /// 结束写入，返回内部写入器和校验表
+    pub fn finish(mut self) -> (W, FrameTable) {
+        if self.in_frame > 0 {
+            self.checksums.push(self.hasher.finalize());
+        }
+        let table = FrameTable {
+            frame_size: self.frame_size,
+            data_len: self.data_len,
+            checksums: self.checksums,
+        };
+        (self.inner, table)
+    }
+}
+
+impl<W: Write> Write for FrameWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let room = (self.frame_size as u64 - self.in_frame) as usize;
+        let n = self.inner.write(&buf[..buf.len().min(room)])?;
+        self.hasher.update(&buf[..n]);
+        self.in_frame += n as u64;
+        self.data_len += n as u64;
+        if self.in_frame == self.frame_size as u64 {
+            let hasher = std::mem::replace(&mut self.hasher, crc32fast::Hasher::new());
+            self.checksums.push(hasher.finalize());
+            self.in_frame = 0;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 创建输出的包文件，生成校验块时需要回读已写入的数据区段
+pub fn create_archive(path: impl AsRef<Path>) -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)
+}
+
+/// 数据区段长`data_len`时尾部区段的大小，不含`finish_data_section`的`extra_records`
+pub fn trailer_size(data_len: u64, frame_size: u32, parity_percent: Option<u32>, files_record: Option<&TrailerRecord>) -> u64 {
+    let frames = data_len.div_ceil(frame_size as u64);
+    let parity = parity_percent.map_or(0, |p| Parity::record_size(frames as usize, frame_size, p));
+    let files = files_record.map_or(0, |r| 16 + r.data.len() as u64);
+    16 + 12 + frames * 4 + parity + files + 16
+}
+
+/// 结束数据区段，写入帧校验表、可选的校验块以及其他尾部记录，返回包的总长度
+///
+/// 尾部区段紧接在数据区段之后写入，文件已预分配或是块设备时之后的内容不受影响。
+pub fn finish_data_section(
+    writer: FrameWriter<BufWriter<File>>,
+    data_offset: u64,
+    parity_percent: Option<u32>,
+    files_record: Option<TrailerRecord>,
+    extra_records: Vec<TrailerRecord>
+) -> io::Result<u64> {
+    let (writer, table) = writer.finish();
+    let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+
+    // 拆分出的条目列表的偏移在写入包头时已经确定，必须是第一条记录
+    let mut records: Vec<TrailerRecord> = files_record.into_iter().collect();
+    records.push(table.to_record());
+    if let Some(percent) = parity_percent {
+        records.push(Parity::compute(&mut file, data_offset, &table, percent)?);
+    }
+    file.seek(SeekFrom::Start(data_offset + table.data_len))?;
+    records.extend(extra_records);
+
+    let mut writer = BufWriter::with_capacity(BUFFER_SIZE, file);
+    write_trailer(&mut writer, &records)?;
+    writer.flush()?;
+    writer.stream_position()
+}
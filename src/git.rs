@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tempfile::TempDir;
+use tracing::{info, warn};
+
+/// 从git版本打包时记录的来源
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GitSource {
+    /// 命令行指定的版本
+    pub rev: String,
+    /// 解析后的提交
+    pub commit: String,
+    /// 打包时工作区所在的分支，分离HEAD时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// 打包时工作区是否有未提交的修改（不影响打包的内容）
+    pub dirty: bool,
+}
+
+/// 在`dir`中运行git命令并返回去掉首尾空白的输出
+pub fn run(dir: Option<&Path>, args: &[&str]) -> io::Result<String> {
+    let output = command(dir).args(args).output().map_err(unavailable)?;
+    if !output.status.success() {
+        return Err(unavailable(String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn command(dir: Option<&Path>) -> Command {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    command
+}
+
+fn unavailable(detail: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("无法读取git信息: {}", detail))
+}
+
+/// 树中的一个文件
+struct Blob {
+    mode: String,
+    kind: String,
+    oid: String,
+    path: String,
+}
+
+/// 列出`rev`中位于`dir`下的所有文件，路径相对于`dir`
+fn list_tree(dir: &Path, rev: &str) -> io::Result<Vec<Blob>> {
+    let output = command(Some(dir)).args(["ls-tree", "-r", "-z", rev]).output().map_err(unavailable)?;
+    if !output.status.success() {
+        return Err(unavailable(String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    output.stdout.split(|&b| b == 0)
+        .filter(|record| !record.is_empty())
+        .map(|record| {
+            let record = String::from_utf8_lossy(record);
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("无法解析 git ls-tree 的输出: {}", record));
+            let (header, path) = record.split_once('\t').ok_or_else(invalid)?;
+            let mut fields = header.split(' ');
+            let (Some(mode), Some(kind), Some(oid)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(invalid());
+            };
+            Ok(Blob { mode: mode.to_string(), kind: kind.to_string(), oid: oid.to_string(), path: path.to_string() })
+        })
+        .collect()
+}
+
+/// 把`repo`（仓库或其中的子目录）在`rev`时的文件树导出到临时目录
+///
+/// 只导出普通文件，符号链接和子模块会被跳过。导出在临时目录中进行，不影响工作区。
+pub fn export_tree(repo: &Path, rev: &str) -> io::Result<(TempDir, GitSource)> {
+    if !repo.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--git-rev 的输入 '{}' 不是目录", repo.display())));
+    }
+    let commit = run(Some(repo), &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, format!("在 '{}' 中找不到git版本 '{}'", repo.display(), rev)))?;
+    let branch = run(Some(repo), &["rev-parse", "--abbrev-ref", "HEAD"]).ok().filter(|b| b != "HEAD");
+    let dirty = !run(Some(repo), &["status", "--porcelain", "--untracked-files=no"])?.is_empty();
+
+    let mut blobs = Vec::new();
+    for blob in list_tree(repo, &commit)? {
+        match (blob.kind.as_str(), blob.mode.as_str()) {
+            ("blob", "100644" | "100755") => blobs.push(blob),
+            ("blob", "120000") => warn!(path = blob.path, "跳过符号链接"),
+            _ => warn!(path = blob.path, kind = blob.kind, "跳过子模块"),
+        }
+    }
+
+    // 用一个 git cat-file 进程依次读取所有文件，写入在单独的线程中进行以免管道阻塞
+    let staging = tempfile::tempdir()?;
+    let mut child = command(Some(repo)).args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(unavailable)?;
+    let mut stdin = child.stdin.take().unwrap();
+    let oids: Vec<String> = blobs.iter().map(|b| b.oid.clone()).collect();
+    let feeder = std::thread::spawn(move || -> io::Result<()> {
+        for oid in oids {
+            writeln!(stdin, "{}", oid)?;
+        }
+        Ok(())
+    });
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut header = String::new();
+    for blob in &blobs {
+        header.clear();
+        stdout.read_line(&mut header)?;
+        let size: u64 = header.trim_end().rsplit(' ').next()
+            .and_then(|size| size.parse().ok())
+            .filter(|_| header.starts_with(&blob.oid))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("无法读取 {}: {}", blob.path, header.trim_end())))?;
+        let target = staging.path().join(&blob.path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(&target)?;
+        let copied = io::copy(&mut (&mut stdout).take(size), &mut file)?;
+        if copied != size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} 的内容不完整", blob.path)));
+        }
+        // 每个对象的内容之后有一个换行
+        stdout.read_line(&mut header)?;
+    }
+    feeder.join().unwrap()?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(unavailable(format!("git cat-file 退出状态 {}", status)));
+    }
+
+    info!(rev, commit, files = blobs.len(), "已导出 git 版本");
+    Ok((staging, GitSource { rev: rev.to_string(), commit, branch, dirty }))
+}
@@ -0,0 +1,165 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::fs::File;
+
+use crate::common::{BUFFER_SIZE, MB};
+
+/// BLAKE3 每次交给rayon并行计算的数据块大小
+const BLAKE3_CHUNK_SIZE: usize = 4 * MB;
+
+/// 支持的哈希算法
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgo {
+    Md5,
+    Sha1,
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl fmt::Display for HashAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HashAlgo::Md5 => "md5",
+            HashAlgo::Sha1 => "sha1",
+            HashAlgo::Sha256 => "sha256",
+            HashAlgo::Blake3 => "blake3",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 对不同算法的统一封装
+pub enum Hasher {
+    Md5(md5::Md5),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algo: HashAlgo) -> Self {
+        match algo {
+            HashAlgo::Md5 => Hasher::Md5(md5::Md5::new()),
+            HashAlgo::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            HashAlgo::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            HashAlgo::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    /// 返回十六进制小写的摘要
+    pub fn finalize_hex(self) -> String {
+        match self {
+            Hasher::Md5(h) => to_hex(&h.finalize()),
+            Hasher::Sha1(h) => to_hex(&h.finalize()),
+            Hasher::Sha256(h) => to_hex(&h.finalize()),
+            Hasher::Blake3(h) => h.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// 读取`reader`的全部内容并计算摘要，BLAKE3 使用rayon多线程实现
+pub fn hash_reader(algo: HashAlgo, reader: &mut impl Read) -> io::Result<String> {
+    if algo == HashAlgo::Blake3 {
+        return hash_blake3(reader);
+    }
+
+    let mut hasher = Hasher::new(algo);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher.finalize_hex())
+}
+
+/// `hash_reader`使用的缓冲区大小
+pub fn buffer_size(algo: HashAlgo) -> usize {
+    if algo == HashAlgo::Blake3 { BLAKE3_CHUNK_SIZE } else { BUFFER_SIZE }
+}
+
+/// 计算文件的摘要
+pub fn hash_file(algo: HashAlgo, path: &Path) -> io::Result<String> {
+    hash_reader(algo, &mut File::open(path)?)
+}
+
+fn hash_blake3(reader: &mut impl Read) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; BLAKE3_CHUNK_SIZE];
+    loop {
+        // 尽量填满缓冲区，让每次并行计算的数据量足够大
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        hasher.update_rayon(&buffer[..filled]);
+        if filled < buffer.len() {
+            break;
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// 写入时同步计算摘要的Writer
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Option<Hasher>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W, algo: Option<HashAlgo>) -> Self {
+        Self { inner, hasher: algo.map(Hasher::new) }
+    }
+
+    /// 返回内部Writer和摘要（未指定算法时为`None`）
+    pub fn finish(self) -> (W, Option<String>) {
+        (self.inner, self.hasher.map(Hasher::finalize_hex))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
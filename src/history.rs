@@ -0,0 +1,150 @@
+//! 操作历史：记录修改或生成包的命令，供`history`命令查看
+//!
+//! 每条记录为一行JSON，追加到用户数据目录下的`xpak/history.jsonl`（Linux上为
+//! `~/.local/share/xpak/history.jsonl`）。路径可以通过`XPAK_HISTORY`环境变量指定，
+//! 设为空值时不记录。
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::common::format_size;
+
+/// 指定历史文件路径的环境变量
+const HISTORY_ENV: &str = "XPAK_HISTORY";
+
+/// 一次需要记录的操作
+pub struct Operation {
+    pub command: &'static str,
+    /// 操作的包，生成新包的命令为输出的包
+    pub archive: String,
+}
+
+/// 历史文件中的一条记录
+#[derive(Serialize, Deserialize)]
+struct Record {
+    time: DateTime<Utc>,
+    command: String,
+    archive: String,
+    duration_ms: u64,
+    /// 操作完成后包的大小，包不存在时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+    /// 失败时的错误信息，成功时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// 历史文件的路径，`XPAK_HISTORY`为空值时不记录
+fn history_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(HISTORY_ENV) {
+        return (!path.is_empty()).then(|| PathBuf::from(path));
+    }
+    let dir = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+    };
+    dir.map(|dir| dir.join("xpak").join("history.jsonl"))
+}
+
+/// 用绝对路径记录包，在不同目录下执行的命令也能按包查找
+fn absolute(path: &str) -> String {
+    let path = Path::new(path);
+    let absolute = fs::canonicalize(path)
+        .or_else(|_| std::env::current_dir().map(|dir| dir.join(path)))
+        .unwrap_or_else(|_| path.to_path_buf());
+    absolute.to_string_lossy().to_string()
+}
+
+/// 追加一条记录，无法写入历史文件时只记录警告，不影响命令本身的结果
+pub fn record(operation: &Operation, duration: Duration, result: &io::Result<()>) {
+    let Some(path) = history_path() else { return };
+    let archive = absolute(&operation.archive);
+    let record = Record {
+        time: Utc::now(),
+        command: operation.command.to_string(),
+        bytes: fs::metadata(&archive).ok().filter(|m| m.is_file()).map(|m| m.len()),
+        archive,
+        duration_ms: duration.as_millis() as u64,
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    if let Err(e) = append(&path, &record) {
+        warn!(path = %path.display(), "无法写入操作历史: {}", e);
+    }
+}
+
+fn append(path: &Path, record: &Record) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    // 整行一次写入，并发执行的命令不会交错
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(line.as_bytes())
+}
+
+/// 显示最近`limit`条记录，指定`archive`时只显示该包的记录，`json`时按原格式逐行输出
+pub fn show(archive: Option<&str>, limit: usize, json: bool) -> io::Result<()> {
+    let path = history_path().ok_or_else(|| io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("没有历史文件，{} 为空或无法确定用户数据目录", HISTORY_ENV)
+    ))?;
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if !json {
+                println!("还没有操作历史（{}）", path.display());
+            }
+            return Ok(());
+        }
+        Err(e) => return Err(io::Error::new(e.kind(), format!("无法读取操作历史 {}: {}", path.display(), e))),
+    };
+
+    let archive = archive.map(absolute);
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        // 写入中断留下的不完整行不影响其余记录
+        let Ok(record) = serde_json::from_str::<Record>(&line) else { continue };
+        if archive.as_ref().is_none_or(|a| *a == record.archive) {
+            records.push(record);
+        }
+    }
+    let records = &records[records.len().saturating_sub(limit)..];
+
+    let mut out = io::stdout().lock();
+    if json {
+        for record in records {
+            writeln!(out, "{}", serde_json::to_string(record)?)?;
+        }
+        return Ok(());
+    }
+    if records.is_empty() {
+        writeln!(out, "没有符合条件的记录")?;
+        return Ok(());
+    }
+    for record in records {
+        let result = match &record.error {
+            Some(error) => format!("失败: {}", error),
+            None => "成功".to_string(),
+        };
+        writeln!(
+            out,
+            "{}  {:<8} {:>10} {:>10}  {}  {}",
+            record.time.format("%Y-%m-%d %H:%M:%S UTC"),
+            record.command,
+            format!("{:.1}s", record.duration_ms as f64 / 1000.0),
+            record.bytes.map(format_size).unwrap_or_else(|| "-".to_string()),
+            record.archive,
+            result
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use rayon::prelude::*;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::progress::WorkerProgress;
+use crate::report::ReportSummary;
+
+/// 打包前钩子的输出
+pub struct HookOutput {
+    /// 钩子是否写出了转换后的内容
+    pub transformed: bool,
+    /// 钩子在标准输出第一行给出的新存储路径
+    pub renamed: Option<String>,
+}
+
+/// 通过系统shell执行命令
+pub fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+/// 解析`GLOB=COMMAND`形式的钩子规则
+pub fn parse_hook_rule(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((glob, command)) if !glob.is_empty() && !command.is_empty() => Ok((glob.to_string(), command.to_string())),
+        _ => Err(format!("无效的钩子规则 '{}'，应为 GLOB=COMMAND", spec)),
+    }
+}
+
+/// 对单个文件运行打包前钩子
+///
+/// 钩子通过环境变量`XPAK_SOURCE`、`XPAK_PATH`、`XPAK_MIME`获得源文件、存储路径和类型，
+/// 将转换后的内容写入`XPAK_OUTPUT`，并可以在标准输出的第一行给出新的存储路径。
+/// 既没有写出内容也没有改名时返回`None`，该文件按原样打包。
+pub fn run_pre_pack_hook(
+    command: &str,
+    source: &Path,
+    stored_path: &str,
+    mime: &str,
+    output: &Path
+) -> io::Result<Option<HookOutput>> {
+    let result = shell_command(command)
+        .env("XPAK_SOURCE", source)
+        .env("XPAK_PATH", stored_path)
+        .env("XPAK_MIME", mime)
+        .env("XPAK_OUTPUT", output)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !result.status.success() {
+        return Err(io::Error::other(format!("钩子处理 {} 失败 ({})", stored_path, result.status)));
+    }
+
+    let renamed = String::from_utf8_lossy(&result.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().trim_start_matches('/').to_string())
+        .filter(|line| !line.is_empty());
+    let transformed = output.exists();
+    if !transformed && renamed.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(HookOutput { transformed, renamed }))
+}
+
+/// 解包后钩子要处理的一个文件
+pub struct ExtractedFile {
+    /// 包内路径
+    pub path: String,
+    /// 解包后的文件路径
+    pub output: PathBuf,
+    pub size: u64,
+}
+
+/// 对每个解包出的文件运行解包后钩子，最多同时运行`parallel`个
+///
+/// 钩子通过环境变量`XPAK_EVENT=file`、`XPAK_PATH`、`XPAK_OUTPUT`、`XPAK_SIZE`获得文件信息。
+pub fn run_post_hooks(command: &str, files: &[ExtractedFile], parallel: usize) -> io::Result<()> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(parallel.max(1))
+        .build()
+        .map_err(io::Error::other)?;
+    pool.install(|| {
+        let progress = WorkerProgress::new(
+            files.len() as u64,
+            "{spinner:.green} 运行解包后钩子 [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}",
+            ""
+        );
+        let result = files.par_iter().try_for_each(|file| {
+            progress.start(&file.path);
+            let status = shell_command(command)
+                .env("XPAK_EVENT", "file")
+                .env("XPAK_PATH", &file.path)
+                .env("XPAK_OUTPUT", &file.output)
+                .env("XPAK_SIZE", file.size.to_string())
+                .stdin(Stdio::null())
+                .status()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("解包后钩子处理 {} 失败 ({})", file.path, status)));
+            }
+            progress.done(1);
+            Ok(())
+        });
+        progress.finish_and_clear();
+        result
+    })
+}
+
+/// 所有文件处理完后运行一次解包后钩子，通过`XPAK_EVENT=summary`和统计变量传入解包结果
+pub fn run_post_summary_hook(command: &str, output_dir: &str, summary: &ReportSummary) -> io::Result<()> {
+    let status = shell_command(command)
+        .env("XPAK_EVENT", "summary")
+        .env("XPAK_OUTPUT", output_dir)
+        .env("XPAK_EXTRACTED", summary.extracted.to_string())
+        .env("XPAK_SKIPPED", summary.skipped.to_string())
+        .env("XPAK_FAILED", summary.failed.to_string())
+        .env("XPAK_BYTES", summary.bytes_written.to_string())
+        .stdin(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("解包后钩子汇总调用失败 ({})", status)));
+    }
+    Ok(())
+}
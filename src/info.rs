@@ -0,0 +1,124 @@
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+use crate::bundle;
+use crate::common::format_size;
+use crate::deps;
+use crate::ext::{Extension, EXT_TAG};
+use crate::frames::{FrameTable, FRAMES_TAG};
+use crate::metadata::FileInfo;
+use crate::parity::{Parity, PARITY_TAG};
+use crate::progressive::describe;
+use crate::reader::XpakReader;
+
+/// 有无
+fn yes_no(value: bool) -> &'static str {
+    if value { "有" } else { "无" }
+}
+
+/// 以一屏内容概括包的版本、大小、压缩、加密和索引等信息
+pub fn info(input: &str) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    let trailer = reader.trailer()?.unwrap_or_default();
+    let metadata = &reader.metadata;
+    let files = &metadata.files;
+
+    println!("文件: {} ({})", input, format_size(fs::metadata(input)?.len()));
+    println!("格式版本: {}（由 xpak {} 创建）", metadata.format_version, metadata.version);
+    // 旧版本的包可能没有记录创建时间
+    if metadata.created_at != DateTime::<Utc>::UNIX_EPOCH {
+        println!("创建时间: {}", metadata.created_at.format("%Y-%m-%d %H:%M:%S UTC"));
+    }
+    if let Some(version) = &metadata.content_version {
+        println!("内容版本: {}", version);
+    }
+    if let Some(description) = metadata.description.as_deref().filter(|d| !d.is_empty()) {
+        println!("描述: {}", description);
+    }
+    if let Some(author) = metadata.common_text("author") {
+        println!("作者: {}", author);
+    }
+    if let Some(license) = metadata.common_text("license") {
+        println!("许可证: {}", license);
+    }
+    if let Some(git) = &metadata.git {
+        println!("来源: git {}{}", git.commit, if git.dirty { "（工作区有未提交的修改）" } else { "" });
+    }
+
+    // 大小
+    let stored: u64 = files.iter().map(|f| f.stored_size.unwrap_or(f.size)).sum();
+    println!();
+    let stale = metadata.live_entries().iter().filter(|live| !**live).count();
+    if stale > 0 {
+        println!("条目: {} 个（另有 {} 个被替换或删除的条目，可使用 optimize 清除）", metadata.files_count as usize - stale, stale);
+    } else {
+        println!("条目: {} 个", metadata.files_count);
+    }
+    if !metadata.skipped.is_empty() {
+        println!("打包时跳过: {} 个无法读取的文件", metadata.skipped.len());
+    }
+    println!("原始大小: {}", format_size(metadata.total_size));
+    if metadata.total_size > 0 {
+        println!("存储大小: {} ({:.1}%)", format_size(stored), stored as f64 / metadata.total_size as f64 * 100.0);
+    } else {
+        println!("存储大小: {}", format_size(stored));
+    }
+
+    // 压缩、加密和摘要，按算法统计条目数
+    let count_by = |key: &dyn Fn(&FileInfo) -> Option<String>| {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for file in files {
+            if let Some(key) = key(file) {
+                *counts.entry(key).or_default() += 1;
+            }
+        }
+        if counts.is_empty() {
+            "无".to_string()
+        } else {
+            counts.iter().map(|(k, n)| format!("{} ({} 个)", k, n)).collect::<Vec<_>>().join(", ")
+        }
+    };
+    println!("压缩: {}", count_by(&|f| f.compression.as_ref().map(|c| c.codec().to_string())));
+    println!("加密: {}", count_by(&|f| f.encryption.as_ref().map(|e| format!("密钥 {}", e.key_id))));
+    println!("摘要: {}", count_by(&|f| f.algo.map(|a| a.to_string())));
+    let windowed = files.iter().filter(|f| f.valid_from.is_some() || f.valid_until.is_some()).count();
+    if windowed > 0 {
+        println!("有效期: {} 个条目设置了有效期", windowed);
+    }
+    println!("分组: {}", bundle::summary(metadata));
+    println!("依赖: {}", deps::summary(metadata));
+    println!("许可协议: {}", if metadata.eula.is_some() { "解包前需要接受" } else { "无" });
+
+    // 索引和尾部记录
+    println!();
+    let frames = trailer.get(&FRAMES_TAG).map(FrameTable::from_record).transpose()?;
+    match &frames {
+        Some(table) => println!("帧校验表: 有（{} 帧，每帧 {}）", table.checksums.len(), format_size(table.frame_size as u64)),
+        None => println!("帧校验表: 无"),
+    }
+    match (trailer.get(&PARITY_TAG), &frames) {
+        (Some(record), Some(table)) => println!("校验块: 有（{}%）", Parity::from_record(record, table)?.percent()),
+        (record, _) => println!("校验块: {}", yes_no(record.is_some())),
+    }
+    println!("大小写查找表: {}", yes_no(metadata.lookup.is_some()));
+    if metadata.tiers.is_empty() {
+        println!("渐进下载: 无");
+    } else {
+        println!("渐进下载:");
+        for line in describe(&metadata.tiers, reader.data_offset) {
+            println!("  {}", line);
+        }
+    }
+    match &metadata.access_profile {
+        Some(profile) => println!("访问顺序: 按访问记录 {} 排列", profile),
+        None => println!("访问顺序: 无"),
+    }
+    let extensions = trailer.records.iter()
+        .filter(|r| r.tag == EXT_TAG)
+        .map(|r| Extension::from_record(r).map(|e| e.name))
+        .collect::<io::Result<Vec<_>>>()?;
+    println!("扩展数据: {}", if extensions.is_empty() { "无".to_string() } else { extensions.join(", ") });
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use keyring::Entry;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+/// 在系统凭据库中使用的服务名
+const SERVICE: &str = "xpak";
+/// 系统凭据库无法枚举条目，额外保存一份名称索引
+const INDEX_USER: &str = "__xpak_key_index__";
+
+fn entry(name: &str) -> io::Result<Entry> {
+    Entry::new(SERVICE, name).map_err(|e| keyring_error(name, e))
+}
+
+fn keyring_error(name: &str, e: keyring::Error) -> io::Error {
+    match e {
+        keyring::Error::NoEntry => io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("系统凭据库中没有密钥 {}", name)
+        ),
+        e => io::Error::other(format!("访问系统凭据库失败 ({}): {}", name, e)),
+    }
+}
+
+/// 读取已保存的密钥名称列表
+pub fn list_keys() -> io::Result<Vec<String>> {
+    match entry(INDEX_USER)?.get_password() {
+        Ok(index) => serde_json::from_str(&index)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("密钥索引已损坏: {}", e))),
+        Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+        Err(e) => Err(keyring_error(INDEX_USER, e)),
+    }
+}
+
+fn save_index(names: &[String]) -> io::Result<()> {
+    let index = serde_json::to_string(names)?;
+    entry(INDEX_USER)?.set_password(&index).map_err(|e| keyring_error(INDEX_USER, e))
+}
+
+pub fn store_key(name: &str, secret: &str) -> io::Result<()> {
+    if name == INDEX_USER {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "该名称为保留名称"));
+    }
+    entry(name)?.set_password(secret).map_err(|e| keyring_error(name, e))?;
+
+    let mut names = list_keys()?;
+    if !names.iter().any(|n| n == name) {
+        names.push(name.to_string());
+        names.sort();
+        save_index(&names)?;
+    }
+    Ok(())
+}
+
+pub fn load_key(name: &str) -> io::Result<String> {
+    entry(name)?.get_password().map_err(|e| keyring_error(name, e))
+}
+
+pub fn remove_key(name: &str) -> io::Result<()> {
+    entry(name)?.delete_credential().map_err(|e| keyring_error(name, e))?;
+
+    let mut names = list_keys()?;
+    names.retain(|n| n != name);
+    save_index(&names)
+}
+
+/// 交互式终端中隐藏输入读取口令，否则从标准输入读取一行
+pub fn read_secret(prompt: &str) -> io::Result<String> {
+    let term = console::Term::stderr();
+    if term.is_term() {
+        term.write_str(prompt)?;
+        return term.read_secure_line();
+    }
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// 解析`[KEY_ID=]SOURCE`形式的密钥来源，SOURCE 为`keyring:<name>`或`env:<VAR>`，
+/// 省略 KEY_ID 时使用来源中的名称
+pub fn resolve_key_source(spec: &str) -> io::Result<(String, String)> {
+    let (key_id, source) = match spec.split_once('=') {
+        Some((id, source)) => (Some(id), source),
+        None => (None, spec),
+    };
+
+    let (name, secret) = if let Some(name) = source.strip_prefix("keyring:") {
+        (name, load_key(name)?)
+    } else if let Some(var) = source.strip_prefix("env:") {
+        let secret = std::env::var(var).map_err(|_| io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("环境变量 {} 未设置", var)
+        ))?;
+        (var, secret)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("无效的密钥来源 '{}'，应为 keyring:<name> 或 env:<VAR>", spec)
+        ));
+    };
+
+    Ok((key_id.unwrap_or(name).to_string(), secret))
+}
+
+/// 合并命令行直接提供的密钥和从其他来源读取的密钥
+pub fn resolve_keys(keys: &[(String, String)], key_from: &[String]) -> io::Result<HashMap<String, String>> {
+    let mut resolved: HashMap<String, String> = keys.iter().cloned().collect();
+    for spec in key_from {
+        let (key_id, secret) = resolve_key_source(spec)?;
+        resolved.insert(key_id, secret);
+    }
+    Ok(resolved)
+}
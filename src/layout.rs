@@ -0,0 +1,314 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::common::{PathLength, BUFFER_SIZE, MAGIC_METADATA_END, MAGIC_NUMBER};
+use crate::migrate::{has_metadata_end, probe_version};
+use crate::trailer::{read_trailer, read_trailer_at};
+
+/// 包内一段字节的含义
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionKind {
+    Magic,
+    MetadataLength,
+    Metadata,
+    MetadataEnd,
+    EntryCount,
+    EntryPathLength { index: u32 },
+    EntryPath { index: u32, path: String },
+    EntrySize { index: u32, path: String },
+    EntryData { index: u32, path: String },
+    /// 最后一个条目之后、尾部区段之前多出的数据
+    TrailingData,
+    TrailerRecordHeader { tag: String },
+    TrailerRecordData { tag: String },
+    TrailerFooter,
+    /// 结构损坏后无法继续解析的数据
+    Unparsed,
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionKind::Magic => write!(f, "Magic Number"),
+            RegionKind::MetadataLength => write!(f, "Metadata长度字段"),
+            RegionKind::Metadata => write!(f, "Metadata内容"),
+            RegionKind::MetadataEnd => write!(f, "Metadata End标记"),
+            RegionKind::EntryCount => write!(f, "文件数量字段"),
+            RegionKind::EntryPathLength { index } => write!(f, "条目 #{} 的路径长度字段", index + 1),
+            RegionKind::EntryPath { index, path } if path.is_empty() => write!(f, "条目 #{} 的路径", index + 1),
+            RegionKind::EntryPath { index, path } => write!(f, "条目 #{} 的路径 ({})", index + 1, path),
+            RegionKind::EntrySize { index, path } => write!(f, "条目 #{} 的大小字段 ({})", index + 1, path),
+            RegionKind::EntryData { index, path } => write!(f, "条目 #{} 的内容 ({})", index + 1, path),
+            RegionKind::TrailingData => write!(f, "最后一个条目之后的多余数据"),
+            RegionKind::TrailerRecordHeader { tag } => write!(f, "尾部记录 {} 的头部", tag),
+            RegionKind::TrailerRecordData { tag } => write!(f, "尾部记录 {} 的内容", tag),
+            RegionKind::TrailerFooter => write!(f, "尾部区段结束标记"),
+            RegionKind::Unparsed => write!(f, "无法解析的数据"),
+        }
+    }
+}
+
+/// 一段连续的字节
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub range: Range<u64>,
+    pub kind: RegionKind,
+    /// 该区段存在的问题
+    pub problem: Option<String>,
+}
+
+/// 按字节扫描得到的包结构，结构损坏时尽量解析出前面完好的部分
+#[derive(Debug, Default)]
+pub struct Layout {
+    pub file_len: u64,
+    pub regions: Vec<Region>,
+    /// 数据区段的起始位置，头部无法解析时为`None`
+    pub data_offset: Option<u64>,
+    /// 文件数量字段的值
+    pub entry_count: Option<u32>,
+}
+
+impl Layout {
+    pub fn scan(input: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufReader::with_capacity(BUFFER_SIZE, File::open(input)?);
+        let file_len = file.seek(SeekFrom::End(0))?;
+        // 尾部区段损坏时按没有尾部区段处理，剩余数据会被标记为多余数据
+        let trailer = read_trailer(&mut file).ok().flatten();
+        let data_end = trailer.as_ref().map_or(file_len, |t| t.offset);
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut scanner = Scanner {
+            file,
+            position: 0,
+            limit: data_end,
+            path_length: PathLength::U32,
+            layout: Layout { file_len, ..Default::default() },
+        };
+        scanner.scan_archive();
+        let Scanner { mut file, mut layout, .. } = scanner;
+
+        // 文件末尾没有尾部区段时，尾部区段可能紧跟在最后一个条目之后，之后才是多余数据
+        let mut trailing = None;
+        let trailer = match trailer {
+            Some(trailer) => Some(trailer),
+            None => match layout.regions.last() {
+                Some(last) if last.kind == RegionKind::TrailingData => {
+                    let found = read_trailer_at(&mut file, last.range.start).ok().flatten();
+                    if let Some(trailer) = &found {
+                        layout.regions.pop();
+                        trailing = Some(trailer.end()..file_len);
+                    }
+                    found
+                }
+                _ => None,
+            },
+        };
+
+        if let Some(trailer) = trailer {
+            let mut position = trailer.offset;
+            for record in &trailer.records {
+                let tag = String::from_utf8_lossy(&record.tag).to_string();
+                layout.push(position..position + 16, RegionKind::TrailerRecordHeader { tag: tag.clone() }, None);
+                position += 16;
+                let end = position + record.data.len() as u64;
+                layout.push(position..end, RegionKind::TrailerRecordData { tag }, None);
+                position = end;
+            }
+            layout.push(position..position + 16, RegionKind::TrailerFooter, None);
+        }
+        if let Some(range) = trailing {
+            let problem = format!("{} 字节", range.end - range.start);
+            layout.push(range, RegionKind::TrailingData, Some(problem));
+        }
+        Ok(layout)
+    }
+
+    /// 包含`offset`的区段
+    pub fn find(&self, offset: u64) -> Option<&Region> {
+        self.regions.iter().find(|r| r.range.contains(&offset))
+    }
+
+    fn push(&mut self, range: Range<u64>, kind: RegionKind, problem: Option<String>) {
+        if !range.is_empty() {
+            self.regions.push(Region { range, kind, problem });
+        }
+    }
+}
+
+struct Scanner {
+    file: BufReader<File>,
+    position: u64,
+    /// 数据区段的结束位置（尾部区段的起始位置或文件末尾）
+    limit: u64,
+    /// 条目头部中路径长度的编码，读取metadata后确定
+    path_length: PathLength,
+    layout: Layout,
+}
+
+impl Scanner {
+    fn scan_archive(&mut self) {
+        let Some(magic) = self.field(4, RegionKind::Magic) else { return };
+        if magic != MAGIC_NUMBER {
+            self.mark_last("不是xpak文件");
+        }
+        let Some(meta_len) = self.field(4, RegionKind::MetadataLength) else { return };
+        let meta_len = u32::from_le_bytes(meta_len.try_into().unwrap()) as u64;
+        if self.limit - self.position < meta_len {
+            self.skip(meta_len, RegionKind::Metadata);
+            return;
+        }
+        let Some(metadata) = self.field(meta_len as usize, RegionKind::Metadata) else { return };
+        // 1.3之前的版本没有结束标记
+        let version = probe_version(&metadata);
+        self.path_length = PathLength::for_version(&version);
+        if has_metadata_end(&version) {
+            let Some(end) = self.field(8, RegionKind::MetadataEnd) else { return };
+            if end != MAGIC_METADATA_END {
+                self.mark_last("结束标记无效");
+            }
+        }
+        let Some(count) = self.field(4, RegionKind::EntryCount) else { return };
+        let count = u32::from_le_bytes(count.try_into().unwrap());
+        self.layout.data_offset = Some(self.position);
+        self.layout.entry_count = Some(count);
+
+        for index in 0..count {
+            if !self.scan_entry(index) {
+                return;
+            }
+        }
+        if self.position < self.limit {
+            let range = self.position..self.limit;
+            let problem = format!("{} 字节", range.end - range.start);
+            self.layout.push(range, RegionKind::TrailingData, Some(problem));
+            self.position = self.limit;
+        }
+    }
+
+    fn scan_entry(&mut self, index: u32) -> bool {
+        let start = self.position;
+        let (path_len, field_len) = match self.path_length.read(&mut self.file.by_ref().take(self.limit - start)) {
+            Ok((path_len, field_len)) => (path_len as u64, field_len),
+            Err(e) => {
+                let problem = if e.kind() == io::ErrorKind::UnexpectedEof { "数据被截断" } else { "路径长度字段无效" };
+                self.layout.push(start..self.limit, RegionKind::EntryPathLength { index }, Some(problem.to_string()));
+                self.position = self.limit;
+                return false;
+            }
+        };
+        self.position += field_len;
+        self.layout.push(start..self.position, RegionKind::EntryPathLength { index }, None);
+        if path_len > self.limit - self.position {
+            self.mark_last(&format!("路径长度 {} 超出文件范围", path_len));
+            self.rest();
+            return false;
+        }
+        let Some(path) = self.field(path_len as usize, RegionKind::EntryPath { index, path: String::new() }) else { return false };
+        let Ok(path) = String::from_utf8(path) else {
+            self.mark_last("路径不是有效的UTF-8");
+            self.rest();
+            return false;
+        };
+        if let Some(region) = self.layout.regions.last_mut() {
+            region.kind = RegionKind::EntryPath { index, path: path.clone() };
+        }
+        let Some(size) = self.field(4, RegionKind::EntrySize { index, path: path.clone() }) else { return false };
+        let size = u32::from_le_bytes(size.try_into().unwrap()) as u64;
+        self.skip(size, RegionKind::EntryData { index, path })
+    }
+
+    /// 读取一个定长字段，数据不足时记录到范围末尾并标记为截断
+    fn field(&mut self, len: usize, kind: RegionKind) -> Option<Vec<u8>> {
+        let start = self.position;
+        if self.limit - start < len as u64 {
+            self.layout.push(start..self.limit, kind, Some("数据被截断".to_string()));
+            self.position = self.limit;
+            return None;
+        }
+        let mut buffer = vec![0u8; len];
+        if self.file.read_exact(&mut buffer).is_err() {
+            self.rest();
+            return None;
+        }
+        self.position += len as u64;
+        self.layout.push(start..self.position, kind, None);
+        Some(buffer)
+    }
+
+    /// 跳过变长区段，超出范围时只记录到范围末尾
+    fn skip(&mut self, len: u64, kind: RegionKind) -> bool {
+        let start = self.position;
+        if self.limit - start < len {
+            let problem = format!("声明 {} 字节，实际只有 {} 字节", len, self.limit - start);
+            self.layout.push(start..self.limit, kind, Some(problem));
+            self.position = self.limit;
+            return false;
+        }
+        if self.file.seek_relative(len as i64).is_err() {
+            self.rest();
+            return false;
+        }
+        self.position += len;
+        self.layout.push(start..self.position, kind, None);
+        true
+    }
+
+    fn mark_last(&mut self, problem: &str) {
+        if let Some(region) = self.layout.regions.last_mut() {
+            region.problem = Some(problem.to_string());
+        }
+    }
+
+    fn rest(&mut self) {
+        self.layout.push(self.position..self.limit, RegionKind::Unparsed, None);
+        self.position = self.limit;
+    }
+}
+
+/// 解析十进制或`0x`开头的十六进制偏移
+pub fn parse_offset(value: &str) -> Result<u64, String> {
+    let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("无效的偏移 '{}'", value))
+}
+
+/// 说明偏移所属的结构，不指定偏移时列出完整的结构
+pub fn explain(input: &str, offset: Option<u64>) -> io::Result<()> {
+    let layout = Layout::scan(input)?;
+    let Some(offset) = offset else {
+        for region in &layout.regions {
+            print_region(region);
+        }
+        println!("文件大小: {} 字节", layout.file_len);
+        return Ok(());
+    };
+
+    let Some(region) = layout.find(offset) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("偏移 {:#x} 超出文件范围（文件大小 {} 字节）", offset, layout.file_len)
+        ));
+    };
+    println!("偏移 {:#x} ({}) 位于 {}", offset, offset, region.kind);
+    println!("  区段范围: {:#x}..{:#x} ({} 字节)", region.range.start, region.range.end, region.range.end - region.range.start);
+    println!("  区段内偏移: {}", offset - region.range.start);
+    if let Some(data_offset) = layout.data_offset.filter(|&start| offset >= start) {
+        if !matches!(region.kind, RegionKind::TrailerRecordHeader { .. } | RegionKind::TrailerRecordData { .. } | RegionKind::TrailerFooter) {
+            println!("  数据区段内偏移: {}", offset - data_offset);
+        }
+    }
+    if let Some(problem) = &region.problem {
+        println!("  问题: {}", problem);
+    }
+    Ok(())
+}
+
+fn print_region(region: &Region) {
+    let problem = region.problem.as_deref().map(|p| format!("  [{}]", p)).unwrap_or_default();
+    println!("{:#010x}..{:#010x}  {}{}", region.range.start, region.range.end, region.kind, problem);
+}
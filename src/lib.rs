@@ -0,0 +1,495 @@
+//! xpak包的只读核心
+//!
+//! 只依赖标准库，用于把读取xpak包的能力嵌入到内存受限的运行时中。核心只负责包的
+//! 二进制结构：定位并逐个读取条目、读取尾部区段；metadata按原始字节返回（JSON，
+//! 或以`BINARY_METADATA_TAG`开头的MessagePack），由使用者按需解析，条目摘要的校验
+//! 也由使用者完成。
+//!
+//! 使用`default-features = false, features = ["read-core"]`引入时不会带入命令行
+//! 工具的任何依赖。
+#![cfg(feature = "read-core")]
+
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+#[cfg(feature = "test-util")]
+pub mod testing;
+
+pub const MAGIC_NUMBER: &[u8] = b"XPAK";
+pub const MAGIC_METADATA_END: [u8; 8] = [0x4d, 0x45, 0x54, 0x41, 0x45, 0x4e, 0x44, 0x5f]; // METAEND_
+/// 尾部区段的结束标记
+pub const TRAILER_MAGIC: [u8; 8] = *b"XPAKTRL_";
+/// 结束标记和记录总长度字段的大小
+const FOOTER_SIZE: u64 = 16;
+/// 使用紧凑布局的格式版本
+pub const COMPACT_FORMAT_VERSION: &str = "2.0";
+/// 二进制metadata的第一个字节，其后为MessagePack编码的metadata；JSON总以`{`开头
+pub const BINARY_METADATA_TAG: u8 = 0x01;
+/// 条目路径的最大长度（字节）
+pub const MAX_PATH_LEN: usize = u16::MAX as usize;
+/// 按包内长度字段读取时一次预先分配的上限
+const PREALLOC_LIMIT: u64 = 1 << 20;
+
+/// 条目头部中路径长度的编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PathLength {
+    /// 固定4字节，2.0之前的版本
+    #[default]
+    U32,
+    /// LEB128变长整数，128字节以内的路径只占1字节
+    Varint,
+}
+
+impl PathLength {
+    /// 按格式版本选择编码，2.0及之后的版本使用变长整数
+    pub fn for_version(format_version: &str) -> Self {
+        let major = format_version.split('.').next().and_then(|m| m.parse::<u32>().ok());
+        if major.is_some_and(|m| m >= 2) { PathLength::Varint } else { PathLength::U32 }
+    }
+
+    /// 按metadata的原始字节选择编码，不解析metadata，只查找其中的`format_version`字段
+    pub fn for_metadata(metadata: &[u8]) -> Self {
+        probe_format_version(metadata).map_or(PathLength::U32, Self::for_version)
+    }
+
+    /// 编码后的长度字段
+    pub fn encode(self, len: usize) -> Vec<u8> {
+        match self {
+            PathLength::U32 => (len as u32).to_le_bytes().to_vec(),
+            PathLength::Varint => {
+                let mut bytes = Vec::with_capacity(3);
+                let mut value = len;
+                while value >= 0x80 {
+                    bytes.push(value as u8 | 0x80);
+                    value >>= 7;
+                }
+                bytes.push(value as u8);
+                bytes
+            }
+        }
+    }
+
+    /// 长度字段占用的字节数
+    pub fn field_len(self, len: usize) -> u64 {
+        match self {
+            PathLength::U32 => 4,
+            PathLength::Varint => (usize::BITS - len.max(1).leading_zeros()).div_ceil(7) as u64,
+        }
+    }
+
+    /// 读取长度字段，返回路径长度和字段占用的字节数
+    pub fn read(self, reader: &mut impl Read) -> io::Result<(usize, u64)> {
+        match self {
+            PathLength::U32 => {
+                let mut bytes = [0u8; 4];
+                reader.read_exact(&mut bytes)?;
+                Ok((u32::from_le_bytes(bytes) as usize, 4))
+            }
+            PathLength::Varint => {
+                let mut value = 0usize;
+                for n in 0..3 {
+                    let mut byte = [0u8; 1];
+                    reader.read_exact(&mut byte)?;
+                    value |= ((byte[0] & 0x7f) as usize) << (7 * n);
+                    if byte[0] & 0x80 == 0 {
+                        return Ok((value, n as u64 + 1));
+                    }
+                }
+                Err(io::Error::new(io::ErrorKind::InvalidData, "路径长度字段无效"))
+            }
+        }
+    }
+}
+
+/// 解析`主版本.次版本`形式的格式版本
+pub fn parse_format_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// 该版本的包头是否在metadata之后写有Metadata End标记
+pub fn has_metadata_end(format_version: &str) -> bool {
+    parse_format_version(format_version).is_none_or(|v| v >= (1, 3))
+}
+
+/// 在metadata的原始字节中查找`format_version`字段的值
+///
+/// metadata的字段按固定顺序写出，`format_version`总在用户数据之前，因此第一处匹配即为该字段。
+pub fn probe_format_version(metadata: &[u8]) -> Option<&str> {
+    if metadata.first() == Some(&BINARY_METADATA_TAG) {
+        // MessagePack中键和值都是短字符串：0xa0 | 长度，后跟内容
+        const KEY: &[u8] = b"\xaeformat_version";
+        let start = metadata.windows(KEY.len()).position(|w| w == KEY)? + KEY.len();
+        let marker = *metadata.get(start)?;
+        if marker & 0xe0 != 0xa0 {
+            return None;
+        }
+        let value = metadata.get(start + 1..start + 1 + (marker & 0x1f) as usize)?;
+        return std::str::from_utf8(value).ok();
+    }
+    const KEY: &[u8] = b"\"format_version\"";
+    let start = metadata.windows(KEY.len()).position(|w| w == KEY)? + KEY.len();
+    let rest = metadata[start..].trim_ascii_start().strip_prefix(b":")?;
+    let rest = rest.trim_ascii_start().strip_prefix(b"\"")?;
+    let end = rest.iter().position(|&b| b == b'"')?;
+    std::str::from_utf8(&rest[..end]).ok()
+}
+
+/// 数据区段之后的一条尾部记录
+///
+/// 尾部区段由若干`[tag: 8字节][长度: u64][内容]`形式的记录组成，最后是记录总长度
+/// 和结束标记，因此可以从文件末尾定位，不依赖数据区段在文件中的绝对位置。
+#[derive(Debug, Clone)]
+pub struct TrailerRecord {
+    pub tag: [u8; 8],
+    pub data: Vec<u8>,
+}
+
+/// 解析出的尾部区段
+#[derive(Debug, Default)]
+pub struct Trailer {
+    /// 尾部区段在文件中的起始位置，也就是数据区段的结束位置
+    pub offset: u64,
+    pub records: Vec<TrailerRecord>,
+}
+
+impl Trailer {
+    pub fn get(&self, tag: &[u8; 8]) -> Option<&TrailerRecord> {
+        self.records.iter().find(|r| &r.tag == tag)
+    }
+
+    /// 尾部区段（含结束标记）在文件中的结束位置
+    pub fn end(&self) -> u64 {
+        self.offset + self.records.iter().map(|r| 16 + r.data.len() as u64).sum::<u64>() + FOOTER_SIZE
+    }
+}
+
+/// 读取`len`字节，用于长度来自包内字段的内容
+///
+/// 损坏或构造出的长度字段可能远大于实际数据，`vec![0u8; len]`会在读取之前就分配全部内存；
+/// 这里按实际读到的数据增长缓冲区，数据不足`len`字节时返回`UnexpectedEof`。
+pub fn read_sized(reader: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(len.min(PREALLOC_LIMIT) as usize);
+    reader.by_ref().take(len).read_to_end(&mut data)?;
+    if (data.len() as u64) < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("数据不完整: 应为 {} 字节，只读到 {} 字节", len, data.len())
+        ));
+    }
+    Ok(data)
+}
+
+/// 读取metadata长度字段和metadata，`reader`位于Magic Number之后，`file_len`为整个包的大小
+///
+/// 长度字段超出包的剩余大小时直接报错，不按损坏的长度分配内存。
+pub fn read_metadata(reader: &mut impl Read, file_len: u64) -> io::Result<Vec<u8>> {
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes)?;
+    let len = u32::from_le_bytes(len_bytes) as u64;
+    let available = file_len.saturating_sub(8);
+    if len > available {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("metadata长度 {} 超出文件的剩余大小 {}", len, available)
+        ));
+    }
+    read_sized(reader, len)
+}
+
+/// 检查条目数量能否放进数据区段剩余的`available`字节，每个条目至少占用一个空路径的头部
+pub fn check_entry_count(count: u32, encoding: PathLength, available: u64) -> io::Result<()> {
+    let min_header = encoding.field_len(0) + 4;
+    if count as u64 * min_header > available {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("条目数量 {} 超出文件剩余的 {} 字节能容纳的上限", count, available)
+        ));
+    }
+    Ok(())
+}
+
+/// 从文件末尾读取尾部区段，没有尾部区段时返回`None`
+pub fn read_trailer(file: &mut (impl Read + Seek)) -> io::Result<Option<Trailer>> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    if file_len < FOOTER_SIZE {
+        return Ok(None);
+    }
+
+    let mut footer = [0u8; FOOTER_SIZE as usize];
+    file.seek(SeekFrom::Start(file_len - FOOTER_SIZE))?;
+    file.read_exact(&mut footer)?;
+    if footer[8..] != TRAILER_MAGIC {
+        return Ok(None);
+    }
+    let total = u64::from_le_bytes(footer[..8].try_into().unwrap());
+    // 尾部区段至少有一条记录，每条记录的头部为16字节
+    let offset = (file_len - FOOTER_SIZE).checked_sub(total)
+        .filter(|_| total >= 16)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "尾部区段长度无效"))?;
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut records = Vec::new();
+    let mut remaining = total;
+    while remaining > 0 {
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header)?;
+        let tag: [u8; 8] = header[..8].try_into().unwrap();
+        let len = u64::from_le_bytes(header[8..].try_into().unwrap());
+        if remaining.checked_sub(16).filter(|r| len <= *r).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "尾部记录长度无效"));
+        }
+        let data = read_sized(file, len)?;
+        remaining -= 16 + len;
+        records.push(TrailerRecord { tag, data });
+    }
+    Ok(Some(Trailer { offset, records }))
+}
+
+/// 从`offset`起按顺序解析尾部区段，用于尾部区段之后还有多余数据、无法从文件末尾定位的包
+///
+/// 解析到与已读记录总长度一致的结束标记为止，记录长度超出文件或没有找到结束标记时返回`None`。
+pub fn read_trailer_at(file: &mut (impl Read + Seek), offset: u64) -> io::Result<Option<Trailer>> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut position = offset;
+    let mut records = Vec::new();
+    while file_len.saturating_sub(position) >= FOOTER_SIZE {
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header)?;
+        let value = u64::from_le_bytes(header[..8].try_into().unwrap());
+        if header[8..] == TRAILER_MAGIC && value == position - offset {
+            return Ok(Some(Trailer { offset, records }));
+        }
+        let tag: [u8; 8] = header[..8].try_into().unwrap();
+        let len = u64::from_le_bytes(header[8..].try_into().unwrap());
+        if len > file_len - position - 16 {
+            return Ok(None);
+        }
+        let data = read_sized(file, len)?;
+        position += 16 + len;
+        records.push(TrailerRecord { tag, data });
+    }
+    Ok(None)
+}
+
+/// 读取条目头部，返回路径和包内存储的大小
+pub fn read_entry_header(reader: &mut impl Read, encoding: PathLength) -> io::Result<(String, u64)> {
+    // 读取文件路径
+    let (path_len, _) = encoding.read(reader)?;
+    if path_len > MAX_PATH_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("路径长度 {} 超出上限", path_len)));
+    }
+
+    let mut path_bytes = vec![0u8; path_len];
+    reader.read_exact(&mut path_bytes)?;
+    let path = String::from_utf8(path_bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("条目路径不是有效的UTF-8: {}", e.utf8_error())))?;
+
+    // 读取文件大小
+    let mut size_bytes = [0u8; 4];
+    reader.read_exact(&mut size_bytes)?;
+    let size = u32::from_le_bytes(size_bytes) as u64;
+    Ok((path, size))
+}
+
+/// 包内单个条目的位置
+#[derive(Debug, Clone)]
+pub struct RawEntry {
+    pub index: u32,
+    pub path: String,
+    /// 包内存储的大小，压缩或加密的条目与原始大小不同
+    pub size: u64,
+    /// 条目内容在包中的起始位置
+    pub offset: u64,
+}
+
+/// 顺序读取xpak包的最小读取器
+///
+/// 不解析metadata，通过`next_entry`逐个定位条目后用`Read`读取当前条目存储的内容，
+/// 未读完的内容会在定位下一个条目时跳过。
+pub struct RawArchive<R> {
+    inner: R,
+    metadata: Vec<u8>,
+    path_length: PathLength,
+    count: u32,
+    data_offset: u64,
+    next_index: u32,
+    position: u64,
+    remaining: u64,
+}
+
+impl<R: Read + Seek> RawArchive<R> {
+    pub fn open(mut inner: R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic)?;
+        if magic != MAGIC_NUMBER {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "无效的文件格式"));
+        }
+
+        let file_len = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(MAGIC_NUMBER.len() as u64))?;
+        let metadata = read_metadata(&mut inner, file_len)?;
+
+        // 1.3之前的版本没有metadata结束标记，不解析metadata中的版本而是直接检查标记
+        let mut position = 8 + metadata.len() as u64;
+        let mut marker = Vec::with_capacity(8);
+        inner.by_ref().take(8).read_to_end(&mut marker)?;
+        if marker == MAGIC_METADATA_END {
+            position += 8;
+        } else {
+            inner.seek(SeekFrom::Start(position))?;
+        }
+
+        let mut count_bytes = [0u8; 4];
+        inner.read_exact(&mut count_bytes)?;
+        position += 4;
+        let count = u32::from_le_bytes(count_bytes);
+        let path_length = PathLength::for_metadata(&metadata);
+        check_entry_count(count, path_length, file_len.saturating_sub(position))?;
+        Ok(Self {
+            inner,
+            path_length,
+            metadata,
+            count,
+            data_offset: position,
+            next_index: 0,
+            position,
+            remaining: 0,
+        })
+    }
+
+    /// metadata的原始JSON
+    pub fn metadata(&self) -> &[u8] {
+        &self.metadata
+    }
+
+    /// 条目数量
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// 数据区段（第一个条目）在包中的起始位置
+    pub fn data_offset(&self) -> u64 {
+        self.data_offset
+    }
+
+    /// 定位到下一个条目，所有条目读取完毕时返回`None`
+    pub fn next_entry(&mut self) -> io::Result<Option<RawEntry>> {
+        if self.remaining > 0 {
+            self.position += self.remaining;
+            self.inner.seek(SeekFrom::Start(self.position))?;
+            self.remaining = 0;
+        }
+        if self.next_index >= self.count {
+            return Ok(None);
+        }
+
+        let (path, size) = read_entry_header(&mut self.inner, self.path_length)?;
+        self.position += self.path_length.field_len(path.len()) + path.len() as u64 + 4;
+        let entry = RawEntry { index: self.next_index, path, size, offset: self.position };
+        self.next_index += 1;
+        self.remaining = size;
+        Ok(Some(entry))
+    }
+
+    /// 读取包的尾部区段，之后从第一个条目重新开始读取
+    pub fn trailer(&mut self) -> io::Result<Option<Trailer>> {
+        let trailer = read_trailer(&mut self.inner)?;
+        self.rewind()?;
+        Ok(trailer)
+    }
+
+    /// 回到第一个条目之前
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(self.data_offset))?;
+        self.position = self.data_offset;
+        self.next_index = 0;
+        self.remaining = 0;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<'a> RawArchive<Cursor<&'a [u8]>> {
+    /// 直接读取内存中的包
+    pub fn from_bytes(bytes: &'a [u8]) -> io::Result<Self> {
+        Self::open(Cursor::new(bytes))
+    }
+}
+
+impl<R: Read> Read for RawArchive<R> {
+    /// 读取当前条目存储的内容
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let max = self.remaining.min(buf.len() as u64) as usize;
+        let n = self.inner.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "条目内容不完整"));
+        }
+        self.position += n as u64;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一条尾部记录，`len`为头部中记录的长度，可以与`data`不一致
+    fn record(tag: &[u8; 8], len: u64, data: &[u8]) -> Vec<u8> {
+        let mut bytes = tag.to_vec();
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// 在4字节的数据之后写入`trailer`，结束标记中的总长度为`total`
+    fn archive(trailer: &[u8], total: u64) -> Cursor<Vec<u8>> {
+        let mut bytes = b"data".to_vec();
+        bytes.extend_from_slice(trailer);
+        bytes.extend_from_slice(&total.to_le_bytes());
+        bytes.extend_from_slice(&TRAILER_MAGIC);
+        Cursor::new(bytes)
+    }
+
+    #[test]
+    fn read_trailer_reads_records() {
+        let mut file = archive(&record(b"FRAMES__", 4, b"abcd"), 20);
+        let trailer = read_trailer(&mut file).unwrap().unwrap();
+        assert_eq!(trailer.offset, 4);
+        assert_eq!(trailer.get(b"FRAMES__").unwrap().data, b"abcd");
+    }
+
+    #[test]
+    fn read_trailer_rejects_total_shorter_than_a_record_header() {
+        for total in [0, 1, 15] {
+            let mut file = archive(&[0u8; 15], total);
+            let error = read_trailer(&mut file).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "total = {}", total);
+        }
+    }
+
+    #[test]
+    fn read_trailer_rejects_partial_record_header() {
+        // 最后一条记录之后只剩8字节，不足一个记录头部
+        let mut trailer = record(b"FRAMES__", 4, b"abcd");
+        trailer.extend_from_slice(&[0u8; 8]);
+        let mut file = archive(&trailer, 28);
+        let error = read_trailer(&mut file).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_trailer_rejects_record_longer_than_trailer() {
+        let mut file = archive(&record(b"FRAMES__", 100, b"abcd"), 20);
+        let error = read_trailer(&mut file).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,184 @@
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::common::{format_size, MB};
+use crate::compress::{compress, Compression};
+use crate::migrate::has_metadata_end;
+use crate::names::{check_path, printable, NameIssue};
+use crate::reader::XpakReader;
+
+/// 抽样检查压缩率的最小条目大小
+const COMPRESSIBLE_MIN_SIZE: u64 = 64 * 1024;
+/// 每个条目抽样的字节数
+const SAMPLE_SIZE: u64 = MB as u64;
+/// 压缩后不超过原大小的该比例时认为值得压缩
+const COMPRESSIBLE_RATIO: f64 = 0.8;
+/// metadata的建议上限
+const METADATA_LIMIT: u64 = MB as u64;
+
+/// 检查项
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// 未压缩但抽样可以明显压缩的条目
+    Compressible,
+    /// 内容完全相同的条目
+    DuplicateContent,
+    /// 含有可疑字符、保留名称或`..`的路径
+    SuspiciousPath,
+    /// 没有记录摘要的条目
+    MissingHash,
+    /// 过大的metadata
+    OversizedMetadata,
+    /// 使用反斜杠作为分隔符的路径
+    MixedSeparators,
+}
+
+impl Lint {
+    fn severity(self) -> Severity {
+        match self {
+            Lint::Compressible | Lint::DuplicateContent => Severity::Info,
+            Lint::SuspiciousPath | Lint::MissingHash | Lint::OversizedMetadata | Lint::MixedSeparators => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "提示",
+            Severity::Warning => "警告",
+            Severity::Error => "错误",
+        })
+    }
+}
+
+struct Finding {
+    lint: Lint,
+    path: Option<String>,
+    message: String,
+}
+
+impl Finding {
+    fn entry(lint: Lint, path: &str, message: impl Into<String>) -> Self {
+        Self { lint, path: Some(path.to_string()), message: message.into() }
+    }
+}
+
+/// 路径中的可疑之处，反斜杠由`mixed-separators`单独报告
+fn suspicious_path(path: &str) -> Vec<String> {
+    let mut problems: Vec<String> = check_path(path).into_iter()
+        .filter(|issue| !matches!(issue, NameIssue::ReservedChar('\\') | NameIssue::CaseCollision(_)))
+        .map(|issue| issue.to_string())
+        .collect();
+    if path.starts_with('/') {
+        problems.push("是绝对路径".to_string());
+    }
+    if path.split(['/', '\\']).any(|c| c == "..") {
+        problems.push("含有 .. 组件".to_string());
+    }
+    if path.split('/').any(|c| c.starts_with(char::is_whitespace)) {
+        problems.push("含有以空白开头的组件".to_string());
+    }
+    problems
+}
+
+/// 运行所有检查，`deny`中的检查项出现问题时视为错误，返回是否没有错误
+pub fn lint(input: &str, deny: &[Lint]) -> io::Result<bool> {
+    let mut reader = XpakReader::open(input)?;
+    // 条目信息与顺序读取使用的reader分开持有
+    let live = reader.metadata.live_entries();
+    let files = std::mem::take(&mut reader.metadata.files);
+    let mut findings = Vec::new();
+
+    // 头部固定字段之外的部分即为metadata
+    let end_len = if has_metadata_end(&reader.metadata.format_version) { 8 } else { 0 };
+    let metadata_len = reader.data_offset - 12 - end_len;
+    if metadata_len > METADATA_LIMIT {
+        findings.push(Finding {
+            lint: Lint::OversizedMetadata,
+            path: None,
+            message: format!("metadata 为 {}，超过 {}，每次打开包都需要完整读取和解析", format_size(metadata_len), format_size(METADATA_LIMIT)),
+        });
+    }
+
+    let mut first_by_hash: HashMap<(String, &str), &str> = HashMap::new();
+    for file in files.iter().zip(&live).filter(|(_, live)| **live).map(|(f, _)| f) {
+        for problem in suspicious_path(&file.path) {
+            findings.push(Finding::entry(Lint::SuspiciousPath, &file.path, problem));
+        }
+        if file.path.contains('\\') {
+            findings.push(Finding::entry(Lint::MixedSeparators, &file.path, "使用反斜杠作为分隔符，在非Windows平台上会成为文件名的一部分"));
+        }
+        match (file.algo, file.hash.as_deref()) {
+            (Some(algo), Some(hash)) if file.size > 0 => {
+                if let Some(first) = first_by_hash.get(&(algo.to_string(), hash)) {
+                    findings.push(Finding::entry(Lint::DuplicateContent, &file.path, format!("与 {} 内容相同（{}）", first, format_size(file.size))));
+                } else {
+                    first_by_hash.insert((algo.to_string(), hash), &file.path);
+                }
+            }
+            (Some(_), Some(_)) => {}
+            _ => findings.push(Finding::entry(Lint::MissingHash, &file.path, "没有记录摘要，无法校验内容")),
+        }
+    }
+
+    // 抽样未压缩条目的开头估算压缩率
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE as usize);
+    while let Some(entry) = reader.next_entry()? {
+        let Some(info) = files.get(entry.index as usize).filter(|_| live[entry.index as usize]) else {
+            continue;
+        };
+        if info.compression.is_some() || info.encryption.is_some() || info.size < COMPRESSIBLE_MIN_SIZE {
+            continue;
+        }
+        sample.clear();
+        (&mut reader).take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+        let ratio = compress(Compression::Zstd, &sample)?.len() as f64 / sample.len() as f64;
+        if ratio <= COMPRESSIBLE_RATIO {
+            findings.push(Finding::entry(
+                Lint::Compressible,
+                &info.path,
+                format!("未压缩，抽样压缩到 {:.0}%，可使用 pak --compress zstd", ratio * 100.0)
+            ));
+        }
+    }
+
+    let severity = |lint: Lint| if deny.contains(&lint) { Severity::Error } else { lint.severity() };
+    findings.sort_by_key(|f| std::cmp::Reverse(severity(f.lint)));
+    let mut counts: HashMap<Severity, usize> = HashMap::new();
+    for finding in &findings {
+        let severity = severity(finding.lint);
+        *counts.entry(severity).or_default() += 1;
+        match &finding.path {
+            Some(path) => println!("{}[{}] {}: {}", severity, finding.lint, printable(path), printable(&finding.message)),
+            None => println!("{}[{}] {}", severity, finding.lint, finding.message),
+        }
+    }
+
+    let count = |severity| counts.get(&severity).copied().unwrap_or(0);
+    if findings.is_empty() {
+        println!("没有发现问题");
+    } else {
+        println!("----------------------------------------");
+        println!(
+            "发现 {} 个问题：错误 {} 个，警告 {} 个，提示 {} 个",
+            findings.len(), count(Severity::Error), count(Severity::Warning), count(Severity::Info)
+        );
+    }
+    Ok(count(Severity::Error) == 0)
+}
@@ -0,0 +1,249 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use console::{measure_text_width, pad_str, Alignment};
+use std::io::{self, Write};
+use std::process::{Child, Stdio};
+
+use crate::common::format_size;
+use crate::hooks::shell_command;
+use crate::metadata::FileInfo;
+use crate::names::printable;
+use crate::validity::{availability, Availability};
+
+/// 摘要列显示的十六进制字符数
+const HASH_PREFIX_LEN: usize = 16;
+/// 路径列至少保留的宽度，终端再窄也不继续截断
+const MIN_PATH_WIDTH: usize = 16;
+
+/// 文件列表中可以显示的列
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// 包内路径
+    Path,
+    /// 原始大小
+    Size,
+    /// 包内存储的大小
+    Stored,
+    /// 摘要的前16个字符
+    Hash,
+    /// 打包时的修改时间
+    Mtime,
+    /// MIME类型
+    Mime,
+    /// 条目ID（十六进制）
+    Id,
+}
+
+impl Column {
+    fn title(self) -> &'static str {
+        match self {
+            Column::Path => "路径",
+            Column::Size => "大小",
+            Column::Stored => "存储大小",
+            Column::Hash => "摘要",
+            Column::Mtime => "修改时间",
+            Column::Mime => "类型",
+            Column::Id => "ID",
+        }
+    }
+
+    fn alignment(self) -> Alignment {
+        match self {
+            Column::Size | Column::Stored => Alignment::Right,
+            _ => Alignment::Left,
+        }
+    }
+}
+
+/// 文件列表的显示选项
+pub struct ListFormat {
+    pub columns: Vec<Column>,
+    /// 以字节数显示大小
+    pub bytes: bool,
+    /// 只输出以NUL分隔的路径，供`xargs -0`等使用
+    pub null: bool,
+}
+
+impl ListFormat {
+    pub fn new(columns: Vec<Column>, verbose: bool, bytes: bool, null: bool) -> Self {
+        let columns = if columns.is_empty() {
+            let mut columns = vec![Column::Path, Column::Size];
+            if verbose {
+                columns.push(Column::Mime);
+            }
+            columns
+        } else {
+            columns
+        };
+        Self { columns, bytes, null }
+    }
+
+    fn size(&self, size: u64) -> String {
+        if self.bytes { size.to_string() } else { format_size(size) }
+    }
+
+    fn cell(&self, column: Column, file: &FileInfo) -> String {
+        match column {
+            Column::Path => printable(&file.path),
+            Column::Size => self.size(file.size),
+            Column::Stored => self.size(file.stored_size.unwrap_or(file.size)),
+            Column::Hash => match (file.algo, file.hash.as_deref()) {
+                (Some(algo), Some(hash)) => format!("{}:{}", algo, &hash[..hash.len().min(HASH_PREFIX_LEN)]),
+                _ => "-".to_string(),
+            },
+            Column::Mtime => file.mtime.map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M:%S").to_string()),
+            Column::Mime => file.mime.clone().unwrap_or_else(|| "未知类型".to_string()),
+            Column::Id => format!("{:016x}", file.entry_id()),
+        }
+    }
+}
+
+/// 条目的压缩、加密和有效期标记
+fn flags(file: &FileInfo, now: DateTime<Utc>) -> String {
+    let mut flags = String::new();
+    if let Some(compression) = &file.compression {
+        flags += &format!(" [压缩: {}, {} 帧]", compression.codec(), compression.frames.len());
+    }
+    let availability = availability(file, now);
+    if availability != Availability::Valid {
+        flags += &format!(" [{}]", availability);
+    }
+    if let Some(encryption) = &file.encryption {
+        flags += &format!(" [加密: {}]", encryption.key_id);
+    }
+    if !file.platforms.is_empty() {
+        flags += &format!(" [平台: {}]", file.platforms.join(", "));
+    }
+    if !file.langs.is_empty() {
+        flags += &format!(" [语言: {}]", file.langs.join(", "));
+    }
+    if let Some(original) = file.debug.as_ref().and_then(|d| d.original_size) {
+        flags += &format!(" [已剥离调试信息，原大小 {}]", format_size(original));
+    }
+    if !file.attrs.is_empty() {
+        let attrs: Vec<String> = file.attrs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        flags += &format!(" [属性: {}]", attrs.join(", "));
+    }
+    flags
+}
+
+/// 保留末尾的部分使文本不超过`width`列，开头用`…`表示省略
+fn truncate_start(text: &str, width: usize) -> String {
+    if measure_text_width(text) <= width {
+        return text.to_string();
+    }
+    let mut kept = Vec::new();
+    let mut used = 1;
+    for c in text.chars().rev() {
+        let w = measure_text_width(c.encode_utf8(&mut [0; 4]));
+        if used + w > width {
+            break;
+        }
+        used += w;
+        kept.push(c);
+    }
+    std::iter::once('…').chain(kept.into_iter().rev()).collect()
+}
+
+/// 按列对齐输出文件列表，输出到终端时按终端宽度截断过长的路径
+pub fn write_table(out: &mut impl Write, files: &[&FileInfo], format: &ListFormat) -> io::Result<()> {
+    let now = Utc::now();
+    let index_width = files.len().to_string().len().max(4);
+    let mut rows: Vec<Vec<String>> = files.iter()
+        .map(|file| format.columns.iter().map(|&c| format.cell(c, file)).collect())
+        .collect();
+    let mut widths: Vec<usize> = format.columns.iter()
+        .map(|c| measure_text_width(c.title()))
+        .collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(measure_text_width(cell));
+        }
+    }
+
+    // 超出终端宽度时只截断路径列
+    if let (Some((_, term_width)), Some(path)) = (
+        console::Term::stdout().size_checked(),
+        format.columns.iter().position(|&c| c == Column::Path),
+    ) {
+        let others: usize = widths.iter().enumerate().filter(|&(i, _)| i != path).map(|(_, w)| w + 2).sum();
+        let available = (term_width as usize).saturating_sub(index_width + 2 + others).max(MIN_PATH_WIDTH);
+        if widths[path] > available {
+            widths[path] = available;
+            for row in &mut rows {
+                row[path] = truncate_start(&row[path], available);
+            }
+        }
+    }
+
+    let line = |cells: &mut dyn Iterator<Item = (Column, &str)>| {
+        cells.zip(&widths)
+            .map(|((column, cell), &width)| pad_str(cell, width, column.alignment(), None).into_owned())
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    let header = line(&mut format.columns.iter().map(|&c| (c, c.title())));
+    writeln!(out, "{:>index_width$}  {}", "#", header.trim_end())?;
+    for ((i, row), file) in rows.iter().enumerate().zip(files) {
+        let cells = line(&mut format.columns.iter().copied().zip(row.iter().map(String::as_str)));
+        writeln!(out, "{:>index_width$}  {}{}", i + 1, cells.trim_end(), flags(file, now))?;
+    }
+    Ok(())
+}
+
+/// 输出以NUL结尾的原始路径，不做任何转义
+pub fn write_paths_nul<'a>(out: &mut impl Write, paths: impl Iterator<Item = &'a str>) -> io::Result<()> {
+    for path in paths {
+        out.write_all(path.as_bytes())?;
+        out.write_all(b"\0")?;
+    }
+    out.flush()
+}
+
+/// 分页器或标准输出
+pub enum Output {
+    Stdout(io::Stdout),
+    Pager(Child),
+}
+
+impl Output {
+    /// `pager`为真且标准输出是终端时通过分页器输出
+    ///
+    /// 分页命令依次取自`XPAK_PAGER`和`PAGER`环境变量，默认为`less -FRX`（Windows上为`more`）。
+    pub fn open(pager: bool) -> io::Result<Self> {
+        if !pager || !console::Term::stdout().is_term() {
+            return Ok(Output::Stdout(io::stdout()));
+        }
+        let command = std::env::var("XPAK_PAGER")
+            .or_else(|_| std::env::var("PAGER"))
+            .unwrap_or_else(|_| if cfg!(windows) { "more" } else { "less -FRX" }.to_string());
+        let child = shell_command(&command).stdin(Stdio::piped()).spawn()?;
+        Ok(Output::Pager(child))
+    }
+
+    /// 等待分页器退出，提前退出分页器导致的管道关闭不视为错误
+    pub fn finish(self, result: io::Result<()>) -> io::Result<()> {
+        let result = result.or_else(|e| if e.kind() == io::ErrorKind::BrokenPipe { Ok(()) } else { Err(e) });
+        if let Output::Pager(mut child) = self {
+            drop(child.stdin.take());
+            child.wait()?;
+        }
+        result
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::Pager(child) => child.stdin.as_mut().unwrap().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::Pager(child) => child.stdin.as_mut().unwrap().flush(),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use clap::ValueEnum;
+use std::fs::File;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::level_filters::LevelFilter;
+
+/// 心跳日志的最小间隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 日志级别
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// 初始化日志输出：指定了日志文件时以JSON行格式写入文件，否则以文本格式写到标准错误
+pub fn init(level: LogLevel, log_file: Option<&str>) -> io::Result<()> {
+    let builder = tracing_subscriber::fmt().with_max_level(LevelFilter::from(level));
+    match log_file {
+        Some(path) => {
+            let file = File::create(path)?;
+            builder.json().with_ansi(false).with_writer(Mutex::new(file)).init();
+        }
+        None => builder.with_writer(io::stderr).init(),
+    }
+    Ok(())
+}
+
+/// 长时间操作的心跳，定期记录当前条目和吞吐量
+pub struct Heartbeat {
+    operation: &'static str,
+    started: Instant,
+    last: Instant,
+    bytes: u64,
+    entries: u64,
+}
+
+impl Heartbeat {
+    pub fn new(operation: &'static str) -> Self {
+        let now = Instant::now();
+        Self { operation, started: now, last: now, bytes: 0, entries: 0 }
+    }
+
+    /// 记录处理完的条目，距离上次心跳超过间隔时输出一次
+    pub fn tick(&mut self, entry: &str, bytes: u64) {
+        self.bytes += bytes;
+        self.entries += 1;
+        if self.last.elapsed() >= HEARTBEAT_INTERVAL {
+            self.last = Instant::now();
+            tracing::info!(
+                target: "heartbeat",
+                operation = self.operation,
+                entry,
+                entries = self.entries,
+                bytes = self.bytes,
+                throughput = self.throughput(),
+                "处理中"
+            );
+        }
+    }
+
+    /// 操作结束时输出汇总
+    pub fn finish(&self) {
+        tracing::info!(
+            operation = self.operation,
+            entries = self.entries,
+            bytes = self.bytes,
+            elapsed_ms = self.started.elapsed().as_millis() as u64,
+            throughput = self.throughput(),
+            "完成"
+        );
+    }
+
+    /// 平均吞吐量（字节/秒）
+    fn throughput(&self) -> u64 {
+        let secs = self.started.elapsed().as_secs_f64();
+        if secs > 0.0 { (self.bytes as f64 / secs) as u64 } else { 0 }
+    }
+}
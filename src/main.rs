@@ -3,18 +3,100 @@ mod unpak;
 mod metadata;
 mod common;
 mod view_pak_structure;
+mod report;
+mod reader;
+mod hash;
+mod checksums;
+mod crypto;
+mod keystore;
+mod compare;
+mod selection;
+mod compress;
+mod estimate;
+mod verify;
+mod logging;
+mod error;
+mod trailer;
+mod frames;
+mod repair;
+mod carve;
+mod history;
+mod metrics;
+mod parity;
+mod mime;
+mod hooks;
+mod layout;
+mod migrate;
+mod overlay;
+mod vpath;
+mod throttle;
+mod memory;
+mod fdpool;
+mod trace;
+mod ext;
+mod names;
+mod validity;
+mod eula;
+mod lint;
+mod template;
+mod git;
+mod progress;
+mod conflict;
+mod output_template;
+mod listing;
+mod info;
+mod archive_fs;
+mod backup;
+mod temp;
+mod cache;
+mod shared_file;
+mod device;
+mod sidecar;
+#[cfg(feature = "strip-debug")]
+mod strip;
+mod config;
+mod edit;
+mod porcelain;
+mod file_meta;
+mod progressive;
+mod bundle;
+mod deps;
+mod compat;
+mod changelog;
+mod catalog;
+mod pieces;
+mod preview;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use clap::{Parser, Subcommand};
 use std::sync::Arc;
 use std::io;
-use ctrlc;
+use std::time::{Duration, Instant};
+
+use crate::compress::{CompressMode, Compression};
+use crate::metadata::MetadataEncoding;
+use crate::hash::HashAlgo;
+use crate::logging::LogLevel;
+use crate::error::ErrorFormat;
+use crate::crypto::{parse_encrypt_rule, parse_key_spec};
+use crate::selection::{parse_entry_range, parse_transform, EntrySelector};
+use crate::vpath::PathPolicy;
+use crate::reader::Consistency;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// 日志级别
+    #[arg(long, global = true, value_enum, default_value_t = LogLevel::Warn)]
+    log_level: LogLevel,
+    /// 将日志以JSON行格式写入该文件（默认以文本格式输出到标准错误）
+    #[arg(long, global = true, value_name = "FILE")]
+    log_file: Option<String>,
+    /// 出错时错误信息的输出格式
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    error_format: ErrorFormat,
 }
 
 #[derive(Subcommand)]
@@ -22,16 +104,133 @@ enum Commands {
     /// 打包文件或目录
     #[command(arg_required_else_help = true)]
     Pak {
-        #[arg(value_name = "INPUT_DIR", help = "要打包的输入目录")]
-        input: String,
-        #[arg(value_name = "OUTPUT_FILE", help = "打包后的输出文件")]
-        output: String,
+        /// 要打包的输入目录或文件（可指定多个），最后是输出文件；只有一个参数时输出为当前目录下的
+        /// <输入名>.xpak，输出是已有目录时输出到该目录下
+        #[arg(value_name = "INPUT... [OUTPUT_FILE]", required_unless_present = "files_from", num_args = 1..)]
+        paths: Vec<String>,
+        /// 从文件读取要打包的路径列表（- 为标准输入），每行一个或以NUL分隔，此时只需指定输出文件
+        #[arg(long, value_name = "FILE", conflicts_with = "git_rev")]
+        files_from: Option<String>,
+        /// 允许覆盖已存在且不是xpak包的输出文件，或写入块设备（如分区）
+        #[arg(long)]
+        force: bool,
+        /// 写入前按计算出的包大小预分配磁盘空间，减少碎片
+        #[arg(long)]
+        preallocate: bool,
         #[arg(long, short, value_name = "FLAT", help = "是否扁平化打包（不保留目录结构）")]
         flat: bool,
-        #[arg(long, short, value_name = "DESCRIPTION", help = "描述信息")]
+        #[arg(long, short, value_name = "DESCRIPTION", help = "描述信息，可使用 {{date}}、{{git_sha}}、{{env.NAME}} 等模板变量")]
         description: Option<String>,
-        #[arg(long, short, value_name = "METADATA", help = "元数据信息（JSON或Base64编码的JSON）")]
+        /// 包内容的版本（semver），可用 compat 检查版本号是否符合内容的变化
+        #[arg(long, value_name = "VERSION", value_parser = compat::parse_content_version)]
+        content_version: Option<String>,
+        #[arg(long, short, value_name = "METADATA", help = "元数据信息（JSON或Base64编码的JSON），其中的字符串值可使用模板变量")]
         metadata: Option<String>,
+        /// 声明依赖的包，格式为 NAME=VERSION_REQ（如 base=^1.2），或已有包的路径（依赖其确切版本和文件摘要），可多次指定；包的名称和版本取自 --metadata 中的 name 和 version
+        #[arg(long, value_name = "SPEC")]
+        depends: Vec<String>,
+        /// 每个文件的完整性校验算法
+        #[arg(long, value_enum, default_value_t = HashAlgo::Sha256, value_name = "ALGO")]
+        hash: HashAlgo,
+        /// 按规则加密匹配的文件，格式为 GLOB=KEY_ID，可多次指定
+        #[arg(long, value_name = "GLOB=KEY_ID", value_parser = parse_encrypt_rule)]
+        encrypt: Vec<(String, String)>,
+        /// 加密使用的密钥，格式为 KEY_ID=PASSPHRASE，可多次指定
+        #[arg(long, value_name = "KEY_ID=PASSPHRASE", value_parser = parse_key_spec)]
+        key: Vec<(String, String)>,
+        /// 从其他来源读取密钥，格式为 [KEY_ID=]keyring:<name> 或 [KEY_ID=]env:<VAR>
+        #[arg(long, value_name = "SOURCE")]
+        key_from: Vec<String>,
+        /// 将未被其他规则加密的文件加密给age接收者，可多次指定
+        #[arg(long, value_name = "AGE_RECIPIENT")]
+        encrypt_to: Vec<String>,
+        /// 所有条目存放在该虚拟目录下
+        #[arg(long, value_name = "DIR")]
+        prefix: Option<String>,
+        /// 对存储路径进行正则替换，格式为 REGEX=REPLACEMENT，可多次指定并按顺序应用
+        #[arg(long, value_name = "REGEX=REPLACEMENT", value_parser = pak::parse_rename_rule)]
+        rename_rule: Vec<(regex::Regex, String)>,
+        /// 指定输入在包内的存放位置，格式为 SRC=DST
+        #[arg(long, value_name = "SRC=DST", value_parser = pak::parse_map)]
+        map: Vec<(String, String)>,
+        /// 跟随符号链接
+        #[arg(long)]
+        follow_symlinks: bool,
+        /// 不进入其他文件系统（如挂载在输入目录内的网络共享）
+        #[arg(long)]
+        same_filesystem: bool,
+        /// 最大遍历深度，输入目录下的文件深度为1
+        #[arg(long, value_name = "N")]
+        max_depth: Option<usize>,
+        /// 最小遍历深度，浅于该深度的文件不打包
+        #[arg(long, value_name = "N")]
+        min_depth: Option<usize>,
+        /// 存储路径冲突时的处理方式
+        #[arg(long, value_enum, default_value_t = pak::DuplicatePolicy::Error)]
+        on_duplicate: pak::DuplicatePolicy,
+        /// 文件无法读取时的处理方式：error、skip（记录在metadata中）或 retry[:N]
+        #[arg(long, value_name = "POLICY", default_value = "error", value_parser = pak::parse_read_error_policy)]
+        on_read_error: pak::ReadErrorPolicy,
+        /// 生成占数据该比例的 Reed-Solomon 校验块，用于 repair --use-parity
+        #[arg(long, value_name = "N%", value_parser = parity::parse_parity_percent)]
+        parity: Option<u32>,
+        /// 打包前对匹配的文件运行转换命令，格式为 GLOB=COMMAND，可多次指定
+        #[arg(long, value_name = "GLOB=COMMAND", value_parser = hooks::parse_hook_rule)]
+        hook: Vec<(String, String)>,
+        /// 在metadata中记录忽略大小写的查找表，供大小写不敏感的平台使用
+        #[arg(long)]
+        lookup_index: bool,
+        /// 限制读写速度（MB/s），读和写分别计算
+        #[arg(long, value_name = "MB/s", value_parser = throttle::parse_throttle)]
+        throttle: Option<f64>,
+        /// 并行处理时在途缓冲区的内存上限（MB），超出时等待其他任务完成
+        #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+        memory_limit: Option<u64>,
+        /// 同时打开的源文件数上限，在网络文件系统上避免打开的文件过多
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        max_open_files: Option<u32>,
+        /// 打开或读取源文件遇到暂时性错误（如EIO、EMFILE）时按指数退避重试的次数
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        io_retries: u32,
+        /// 按访问记录（每行一个路径）中的顺序排列条目，未记录的文件排在后面
+        #[arg(long, value_name = "TRACE_FILE")]
+        access_log: Option<String>,
+        /// 打包完成后把各阶段的耗时、文件数、大小和吞吐量写入该JSON文件，供构建监控读取
+        #[arg(long, value_name = "FILE")]
+        metrics_file: Option<String>,
+        /// 为条目附加属性的参数
+        #[command(flatten)]
+        attrs: Box<EntryAttrArgs>,
+        /// 去掉ELF可执行文件和库中的调试段后打包，并记录ELF、PE、Mach-O文件的build-id（需要 strip-debug 特性）
+        #[arg(long)]
+        strip_debug: bool,
+        /// 把剥离出的调试信息按 .build-id/xx/yyyy.debug 的布局打包到该文件
+        #[arg(long, value_name = "PAK_FILE", requires = "strip_debug")]
+        debug_archive: Option<String>,
+        /// 许可协议文本文件，解包前需要接受其中的条款
+        #[arg(long, value_name = "TEXT_FILE")]
+        eula: Option<String>,
+        /// 使用该算法分帧压缩文件，auto 按文件大小和类型逐个选择；已是压缩格式或压缩后没有变小的文件按原样存放
+        #[arg(long, value_enum)]
+        compress: Option<CompressMode>,
+        /// 每个压缩帧的大小（MB），各帧并行压缩，默认为4
+        #[arg(long, value_name = "MB", value_parser = compress::parse_frame_size, requires = "compress")]
+        frame_size: Option<u32>,
+        /// 打包输入目录在该git版本（提交、分支或标签）时的文件树，无需检出
+        #[arg(long, value_name = "REV")]
+        git_rev: Option<String>,
+        /// 描述和metadata中 {{KEY}} 模板的变量，格式为 KEY=VALUE，可多次指定
+        #[arg(long, value_name = "KEY=VALUE", value_parser = template::parse_var)]
+        var: Vec<(String, String)>,
+        /// 使用紧凑布局（格式 2.0）：路径长度为变长整数，metadata中每个目录只记录一次，适合条目很多的包
+        #[arg(long)]
+        compact: bool,
+        /// 把条目列表与其余metadata分开存放（格式 2.1，隐含 --compact），不需要条目列表的命令无需读取它
+        #[arg(long)]
+        split_metadata: bool,
+        /// metadata的编码，msgpack 体积更小、解析更快，需要较新的xpak读取
+        #[arg(long, value_enum, default_value_t = MetadataEncoding::Json)]
+        metadata_format: MetadataEncoding,
     },
     /// 解包文件
     #[command(arg_required_else_help = true)]
@@ -45,6 +244,102 @@ enum Commands {
         /// 要解包的文件路径列表，如果不指定则解包所有文件
         #[arg(long, short, num_args = 1.., value_name = "FILES")]
         files: Option<Vec<String>>,
+        /// 按 list 显示的序号选择条目，如 10..20,45（范围包含两端）
+        #[arg(long, value_name = "ENTRIES", value_delimiter = ',', value_parser = parse_entry_range)]
+        entries: Option<Vec<std::ops::RangeInclusive<u32>>>,
+        /// 解包这些分组（以逗号分隔）中的文件，可与 --files、--entries 同时使用
+        #[arg(long, value_name = "BUNDLES", value_delimiter = ',')]
+        bundle: Vec<String>,
+        /// 匹配 --files 时忽略大小写
+        #[arg(long)]
+        ignore_case: bool,
+        /// 把 --files 视为目录前缀，解包其下的所有文件
+        #[arg(long)]
+        prefix: bool,
+        /// 选择没有匹配任何文件时不报错
+        #[arg(long)]
+        ignore_missing: bool,
+        /// 去掉输出路径开头的N层目录，层级不足的文件被跳过
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        strip_components: usize,
+        /// 替换输出路径前缀，格式为 OLD_PREFIX=NEW_PREFIX，在 --strip-components 之后应用
+        #[arg(long, value_name = "OLD=NEW", value_parser = parse_transform)]
+        transform: Vec<(String, String)>,
+        /// 按模板生成输出路径，在 --transform 之后应用，如 '{dir}/{stem}_{hash8}{ext}'；
+        /// 可用变量: {path} {dir} {name} {stem} {ext} {hash} {hash8} {size} {index} {id} {mime}
+        #[arg(long, value_name = "TEMPLATE", value_parser = output_template::parse_output_template)]
+        output_template: Option<output_template::OutputTemplate>,
+        /// 扁平化解包：去掉目录，所有文件解包到输出目录下
+        #[arg(long, conflicts_with = "output_template")]
+        flat: bool,
+        /// 扁平化或按模板解包时不同条目的输出路径相同的处理方式
+        #[arg(long, value_enum, default_value_t = conflict::CollisionPolicy::Error)]
+        on_collision: conflict::CollisionPolicy,
+        /// 将解包报告（JSON）写入指定文件
+        #[arg(long, value_name = "REPORT_FILE")]
+        report: Option<String>,
+        /// 解密使用的密钥，格式为 KEY_ID=PASSPHRASE，可多次指定
+        #[arg(long, value_name = "KEY_ID=PASSPHRASE", value_parser = parse_key_spec)]
+        key: Vec<(String, String)>,
+        /// 从其他来源读取密钥，格式为 [KEY_ID=]keyring:<name> 或 [KEY_ID=]env:<VAR>
+        #[arg(long, value_name = "SOURCE")]
+        key_from: Vec<String>,
+        /// 解开age接收者密钥使用的身份文件，可多次指定
+        #[arg(long, short, value_name = "IDENTITY_FILE")]
+        identity: Vec<String>,
+        /// 解包后对每个文件运行的命令，通过 XPAK_PATH、XPAK_OUTPUT、XPAK_SIZE 获得文件信息，
+        /// 最后以 XPAK_EVENT=summary 再运行一次并传入统计结果
+        #[arg(long, value_name = "COMMAND")]
+        post_hook: Option<String>,
+        /// 同时运行的解包后钩子数量
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        parallel_hooks: usize,
+        /// 依次叠加在输入包之上的补丁包，同一路径以最后一层为准
+        #[arg(long, num_args = 1.., value_name = "PAK_FILE")]
+        layers: Vec<String>,
+        /// 叠加时判断路径是否相同的方式，ignore-case 同时使 --files 忽略大小写
+        #[arg(long, value_enum, default_value_t = PathPolicy::Exact)]
+        path_policy: PathPolicy,
+        /// 限制读写速度（MB/s），读和写分别计算
+        #[arg(long, value_name = "MB/s", value_parser = throttle::parse_throttle)]
+        throttle: Option<f64>,
+        /// 跳过不在有效期内的条目
+        #[arg(long)]
+        respect_validity: bool,
+        /// 只解包用于该平台的条目和没有标记平台的条目
+        #[arg(long, value_name = "PLATFORM")]
+        platform: Option<String>,
+        /// 只解包这些语言（以逗号分隔）的条目和没有标记语言的条目
+        #[arg(long, value_name = "LANGS", value_delimiter = ',')]
+        lang: Option<Vec<String>>,
+        /// 包尚未下载完整时只解包已经完整的条目，不报错
+        #[arg(long)]
+        available_only: bool,
+        /// 接受包内的许可协议，不再交互确认
+        #[arg(long)]
+        accept_eula: bool,
+        /// 输出文件已存在时的处理方式，默认在终端中询问
+        #[arg(long, value_enum, default_value_t = conflict::ExistingPolicy::Ask)]
+        on_existing: conflict::ExistingPolicy,
+        /// 条目头部的路径或大小与metadata不一致时报错（默认只警告）
+        #[arg(long)]
+        strict: bool,
+        /// 在每个文件旁写出附属文件（默认扩展名为 .meta.json），记录条目的属性、摘要和修改时间，可用 pak --sidecar-ext 重新读取
+        #[arg(long, value_name = "EXT", num_args = 0..=1, default_missing_value = ".meta.json", value_parser = sidecar::parse_sidecar_ext)]
+        write_sidecars: Option<String>,
+        /// 不在解包前检查输出目录所在磁盘的可用空间
+        #[arg(long)]
+        no_space_check: bool,
+        /// 解包写出的总大小上限，如 50G，下一个文件会超出时停止解包（可与 --report 一起使用）
+        #[arg(long, value_name = "SIZE", value_parser = common::parse_size)]
+        max_disk_usage: Option<u64>,
+    },
+    /// 概括包的版本、大小、压缩、加密和索引等信息
+    #[command(arg_required_else_help = true)]
+    Info {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
     },
     /// 查看元数据信息
     #[command(arg_required_else_help = true)]
@@ -54,6 +349,12 @@ enum Commands {
         input: String,
         #[arg(long, short, value_name = "FILES", help = "是否显示文件列表")]
         files: bool,
+        /// 不显示描述、作者和许可证横幅
+        #[arg(long)]
+        no_banner: bool,
+        /// 以稳定的制表符分隔格式输出，供脚本解析（目前只有 v1）
+        #[arg(long, value_enum, value_name = "VERSION", num_args = 0..=1, require_equals = true, default_missing_value = "v1", conflicts_with_all = ["files", "no_banner"])]
+        porcelain: Option<porcelain::Porcelain>,
     },
     /// 重新计算Metadata
     #[command(arg_required_else_help = true)]
@@ -63,11 +364,17 @@ enum Commands {
         input: String,
         #[arg(long, short, value_name = "DESCRIPTION", help = "更新描述信息")]
         description: Option<String>,
+        /// 更新包内容的版本（semver）
+        #[arg(long, value_name = "VERSION", value_parser = compat::parse_content_version)]
+        content_version: Option<String>,
         #[arg(long, short, value_name = "METADATA", help = "更新元数据信息（JSON或Base64编码的JSON）")]
         metadata: Option<String>,
         /// 重新生成所有元数据信息
         #[arg(long, short, help = "重新生成所有元数据信息")]
         all: bool,
+        /// 修改前把包备份为 <INPUT_FILE>.bak，可用 rollback 恢复
+        #[arg(long)]
+        backup: bool,
     },
     /// 列出包内文件
     #[command(arg_required_else_help = true)]
@@ -78,6 +385,439 @@ enum Commands {
         /// 重新扫描文件内容而不是使用metadata
         #[arg(long, short)]
         recheck: bool,
+        /// 显示每个文件的MIME类型
+        #[arg(long, short)]
+        verbose: bool,
+        /// 不显示描述、作者和许可证横幅
+        #[arg(long)]
+        no_banner: bool,
+        /// 要显示的列，以逗号分隔，默认为 path,size
+        #[arg(long, value_enum, value_delimiter = ',', value_name = "COLUMNS")]
+        columns: Vec<listing::Column>,
+        /// 以字节数显示大小
+        #[arg(long)]
+        bytes: bool,
+        /// 输出到终端时通过分页器显示（XPAK_PAGER 或 PAGER，默认 less）
+        #[arg(long)]
+        pager: bool,
+        /// 只输出以NUL分隔的路径，不带任何修饰，可用于 xargs -0
+        #[arg(short = '0', long = "null", conflicts_with_all = ["columns", "bytes", "pager", "verbose"])]
+        null: bool,
+        /// 以稳定的制表符分隔格式输出，供脚本解析（目前只有 v1）
+        #[arg(long, value_enum, value_name = "VERSION", num_args = 0..=1, require_equals = true, default_missing_value = "v1", conflicts_with_all = ["recheck", "verbose", "no_banner", "columns", "bytes", "pager", "null"])]
+        porcelain: Option<porcelain::Porcelain>,
+        /// 只列出包中有哪些语言及各语言的条目数
+        #[arg(long, conflicts_with_all = ["recheck", "verbose", "no_banner", "columns", "bytes", "pager", "null", "porcelain"])]
+        langs: bool,
+        /// 在第一列显示条目ID，可用于 cat --id 和按ID查找条目
+        #[arg(long, conflicts_with_all = ["null", "porcelain", "langs"])]
+        ids: bool,
+    },
+    /// 预览包内的文本或图片条目：文本显示开头和结尾，图片在终端中显示或输出格式和尺寸
+    #[command(arg_required_else_help = true)]
+    Preview {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 包内文件的路径
+        #[arg(value_name = "PATH")]
+        path: String,
+        /// 文本较长时开头和结尾各显示的行数
+        #[arg(long, short = 'n', value_name = "N", default_value_t = 20)]
+        lines: usize,
+        /// 显示图片使用的终端协议
+        #[arg(long, value_enum, default_value_t = preview::ImageProtocol::Auto)]
+        protocol: preview::ImageProtocol,
+        /// 图片显示的宽度（终端列数），默认为终端宽度
+        #[arg(long, value_name = "COLUMNS")]
+        width: Option<u32>,
+    },
+    /// 像目录一样列出包内某一层的文件和子目录
+    #[command(arg_required_else_help = true)]
+    Ls {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 包内目录，默认为根目录
+        #[arg(value_name = "DIR", default_value = "")]
+        dir: String,
+    },
+    /// 读取包内某个文件的一段内容，压缩的文件只解压需要的帧
+    #[command(arg_required_else_help = true)]
+    Cat {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 包内文件的路径
+        #[arg(value_name = "PATH", required_unless_present = "id")]
+        path: Option<String>,
+        /// 按条目ID（list --ids 显示的十六进制值）而不是路径查找条目
+        #[arg(long, value_name = "ID", value_parser = vpath::parse_entry_id, conflicts_with = "path")]
+        id: Option<u64>,
+        /// 起始偏移，支持十进制或 0x 开头的十六进制
+        #[arg(long, value_name = "OFFSET", value_parser = layout::parse_offset, default_value = "0")]
+        offset: u64,
+        /// 读取的字节数，不指定时读到文件末尾
+        #[arg(long, value_name = "BYTES", value_parser = layout::parse_offset)]
+        length: Option<u64>,
+        /// 输出文件，不指定时写到标准输出
+        #[arg(short, long, value_name = "OUTPUT_FILE")]
+        output: Option<String>,
+    },
+    /// 把包内的文件解压到临时文件并用外部程序打开，程序退出后删除临时文件
+    ///
+    /// 命令参数中的 {} 替换为临时文件的路径，没有 {} 时路径追加在最后
+    #[command(arg_required_else_help = true)]
+    Open {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 包内文件的路径
+        #[arg(value_name = "PATH")]
+        path: String,
+        /// 打开文件的程序及其参数
+        #[arg(value_name = "COMMAND", required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// 输出sha256sum兼容格式的校验和清单
+    #[command(arg_required_else_help = true)]
+    Checksums {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 哈希算法
+        #[arg(long, value_enum, default_value_t = HashAlgo::Sha256)]
+        algo: HashAlgo,
+        /// 同时计算的线程数，每个线程使用独立的读取句柄
+        #[arg(long, short = 'j', value_name = "N", default_value_t = 1)]
+        jobs: usize,
+    },
+    /// 校验包的结构与摘要（发现问题时退出码为1）
+    #[command(arg_required_else_help = true)]
+    Verify {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE", required_unless_present = "daemon")]
+        input: Option<String>,
+        /// 持续监视 --watch-dir 中新出现的包并逐个校验，直到按 Ctrl-C
+        #[arg(long, requires = "watch_dir", conflicts_with = "input")]
+        daemon: bool,
+        /// 监视的目录
+        #[arg(long, value_name = "DIR", requires = "daemon")]
+        watch_dir: Option<String>,
+        /// 校验结果以JSON POST到的地址，未指定时取自配置文件中的 verify.webhook
+        #[arg(long, value_name = "URL", requires = "daemon")]
+        webhook: Option<String>,
+        /// 轮询目录的间隔（秒）
+        #[arg(long, value_name = "SECS", default_value_t = 5, requires = "daemon")]
+        interval: u64,
+        /// 以稳定的制表符分隔格式输出，供脚本解析（目前只有 v1）
+        #[arg(long, value_enum, value_name = "VERSION", num_args = 0..=1, require_equals = true, default_missing_value = "v1", conflicts_with = "daemon")]
+        porcelain: Option<porcelain::Porcelain>,
+    },
+    /// 丢弃损坏的条目，将其余条目恢复到新的包中
+    #[command(arg_required_else_help = true)]
+    Repair {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 恢复后的输出文件
+        #[arg(value_name = "OUTPUT_FILE")]
+        output: String,
+        /// 先使用包内的 Reed-Solomon 校验块恢复损坏的帧
+        #[arg(long)]
+        use_parity: bool,
+    },
+    /// 条目头部无法读取时，按启发式规则搜索条目边界并提取能找回的文件，报告每个命中的可信度
+    #[command(arg_required_else_help = true)]
+    Carve {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 提取文件的目录
+        #[arg(value_name = "OUTPUT_DIR")]
+        output: String,
+        /// 可信度低的命中也提取
+        #[arg(long)]
+        all: bool,
+    },
+    /// 用本地文件替换包内一个条目的内容，只重写该条目之后的部分
+    #[command(arg_required_else_help = true)]
+    Touch {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 要替换的条目在包内的路径
+        path: String,
+        /// 新内容所在的本地文件
+        #[arg(long, value_name = "LOCAL_FILE")]
+        from: String,
+        /// 把新内容追加到包的末尾，旧的条目留在原处，之后用 optimize 清除
+        #[arg(long)]
+        append: bool,
+        /// 修改前把包备份为 <INPUT_FILE>.bak，可用 rollback 恢复
+        #[arg(long)]
+        backup: bool,
+    },
+    /// 把本地文件作为新条目追加到包的末尾
+    #[command(arg_required_else_help = true)]
+    Add {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 新条目在包内的路径
+        path: String,
+        /// 条目内容所在的本地文件
+        #[arg(long, value_name = "LOCAL_FILE")]
+        from: String,
+        /// 修改前把包备份为 <INPUT_FILE>.bak，可用 rollback 恢复
+        #[arg(long)]
+        backup: bool,
+    },
+    /// 在包的末尾追加删除标记来删除条目，之后用 optimize 清除
+    #[command(arg_required_else_help = true)]
+    Remove {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 要删除的条目在包内的路径
+        path: String,
+        /// 修改前把包备份为 <INPUT_FILE>.bak，可用 rollback 恢复
+        #[arg(long)]
+        backup: bool,
+    },
+    /// 把另一个包中的条目原样复制到包的末尾，压缩和加密的条目不解压也不解密
+    #[command(arg_required_else_help = true)]
+    Copy {
+        /// 来源包的路径
+        #[arg(value_name = "SOURCE_FILE")]
+        source: String,
+        /// 目标包的路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 要复制的条目，可以使用glob，如 'textures/**' 'audio/*.ogg'
+        #[arg(long, short, num_args = 1.., required = true, value_name = "GLOBS")]
+        files: Vec<String>,
+        /// 目标包中已有同路径的条目时用复制的条目取代，旧的条目之后用 optimize 清除
+        #[arg(long)]
+        replace: bool,
+        /// 修改前把包备份为 <INPUT_FILE>.bak，可用 rollback 恢复
+        #[arg(long)]
+        backup: bool,
+    },
+    /// 清除被替换的条目、删除标记和中断写入留下的多余数据，重写整个包
+    #[command(arg_required_else_help = true)]
+    Optimize {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 不丢弃多余数据，保存为名为 trailing-data 的扩展（可用 ext get 导出）
+        #[arg(long)]
+        keep_trailing: bool,
+        /// 修改前把包备份为 <INPUT_FILE>.bak，可用 rollback 恢复
+        #[arg(long)]
+        backup: bool,
+    },
+    /// 把用旧密钥加密的条目改用新密钥加密，条目内容逐块解密后立即重新加密，不解出到磁盘
+    #[command(arg_required_else_help = true)]
+    Rekey {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 当前的密钥，格式为 KEY_ID=PASSPHRASE
+        #[arg(long, value_name = "KEY_ID=PASSPHRASE", value_parser = parse_key_spec)]
+        old_key: (String, String),
+        /// 新的密钥，格式为 KEY_ID=PASSPHRASE，KEY_ID 可以与旧密钥相同
+        #[arg(long, value_name = "KEY_ID=PASSPHRASE", value_parser = parse_key_spec)]
+        new_key: (String, String),
+        /// 修改前把包备份为 <INPUT_FILE>.bak，可用 rollback 恢复
+        #[arg(long)]
+        backup: bool,
+    },
+    /// 用修改前保存的 <INPUT_FILE>.bak 恢复包
+    #[command(arg_required_else_help = true)]
+    Rollback {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+    },
+    /// 清除目录下重写包时中断留下的临时文件，跳过其他进程正在写入的临时文件
+    #[command(arg_required_else_help = true)]
+    CleanTemp {
+        /// 要清理的目录
+        #[arg(value_name = "DIR")]
+        dir: String,
+        /// 只列出找到的临时文件，不删除
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 查看打包、解包和修改包等操作的历史，包括耗时、包的大小和结果
+    History {
+        /// 只显示该包的记录
+        #[arg(value_name = "INPUT_FILE")]
+        input: Option<String>,
+        /// 显示的记录数
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+        /// 按JSON行格式输出
+        #[arg(long)]
+        json: bool,
+    },
+    /// 按固定大小切分整个包并计算每块的摘要，生成与包的布局对齐的清单，可同时生成.torrent文件
+    #[command(arg_required_else_help = true)]
+    Pieces {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 每块的大小，支持 K 和 M 后缀，必须是2的幂
+        #[arg(long, value_name = "SIZE", default_value = "4M", value_parser = pieces::parse_piece_size)]
+        piece_size: u64,
+        /// 清单中每块摘要使用的算法
+        #[arg(long, value_enum, default_value_t = HashAlgo::Sha256)]
+        hash: HashAlgo,
+        /// 清单（JSON）的输出文件，默认输出到标准输出
+        #[arg(long, short, value_name = "MANIFEST_FILE")]
+        output: Option<String>,
+        /// 同时生成.torrent文件（块摘要为BT协议要求的SHA-1）
+        #[arg(long, value_name = "TORRENT_FILE")]
+        torrent: Option<String>,
+        /// 写入.torrent的tracker地址，可多次指定
+        #[arg(long, value_name = "URL", requires = "torrent")]
+        tracker: Vec<String>,
+    },
+    /// 估算打包后的大小和耗时，不写入文件
+    #[command(arg_required_else_help = true)]
+    Estimate {
+        /// 要打包的输入目录或文件
+        #[arg(value_name = "INPUT", required = true, num_args = 1..)]
+        inputs: Vec<String>,
+        /// 按该压缩算法抽样估算压缩率
+        #[arg(long, value_enum)]
+        compress: Option<Compression>,
+        /// 哈希算法
+        #[arg(long, value_enum, default_value_t = HashAlgo::Sha256)]
+        hash: HashAlgo,
+    },
+    /// 比较目录内容是否与包一致（不一致时退出码为1）
+    #[command(arg_required_else_help = true)]
+    Compare {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 要比较的目录
+        #[arg(value_name = "DIR")]
+        dir: String,
+        /// 以JSON格式输出结果
+        #[arg(long)]
+        json: bool,
+    },
+    /// 比较两个版本的包，判断新包是新增、修改还是删除了文件，并建议内容版本号的增量（版本号不足时退出码为1）
+    #[command(arg_required_else_help = true)]
+    Compat {
+        /// 旧版本的包
+        #[arg(value_name = "OLD_FILE")]
+        old: String,
+        /// 新版本的包
+        #[arg(value_name = "NEW_FILE")]
+        new: String,
+        /// 以JSON格式输出结果
+        #[arg(long)]
+        json: bool,
+    },
+    /// 生成两个版本的包之间的变更记录（新增、修改、删除的文件及大小），用于发布说明
+    #[command(arg_required_else_help = true)]
+    Changelog {
+        /// 旧版本的包
+        #[arg(value_name = "OLD_FILE")]
+        old: String,
+        /// 新版本的包
+        #[arg(value_name = "NEW_FILE")]
+        new: String,
+        /// 输出格式
+        #[arg(long, value_enum, default_value_t = changelog::ChangelogFormat::Md)]
+        format: changelog::ChangelogFormat,
+        /// 写入该文件，不指定时写到标准输出
+        #[arg(short, long, value_name = "OUTPUT_FILE")]
+        output: Option<String>,
+    },
+    /// 检查包内路径在Windows、macOS和Linux上解压时是否会出错（有问题时退出码为1）
+    #[command(arg_required_else_help = true)]
+    CheckNames {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 把需要改名的条目及建议的新路径写入该JSON文件
+        #[arg(long, value_name = "PLAN_FILE")]
+        plan: Option<String>,
+    },
+    /// 检查包的质量问题，例如可压缩的数据、重复内容和可疑路径（有错误时退出码为1）
+    #[command(arg_required_else_help = true)]
+    Lint {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 将该检查项的问题视为错误，可多次指定
+        #[arg(long, value_enum, value_name = "ID")]
+        deny: Vec<lint::Lint>,
+    },
+    /// 管理系统凭据库中的密钥
+    #[command(arg_required_else_help = true)]
+    Key {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+    /// 管理包尾部的扩展数据
+    #[command(arg_required_else_help = true)]
+    Ext {
+        #[command(subcommand)]
+        command: ExtCommands,
+    },
+    /// 为一批包建立目录，查找文件所在的包
+    #[command(arg_required_else_help = true)]
+    Catalog {
+        #[command(subcommand)]
+        command: CatalogCommands,
+    },
+    /// 检查包之间的依赖
+    #[command(arg_required_else_help = true)]
+    Deps {
+        #[command(subcommand)]
+        command: DepsCommands,
+    },
+    /// 比较访问记录与包内条目的排列，估算寻道开销
+    #[command(arg_required_else_help = true)]
+    AnalyzeTrace {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 访问记录文件，每行一个路径
+        #[arg(value_name = "TRACE_FILE")]
+        trace: String,
+        /// 按访问记录实际读取一遍，统计该大小（MB）的解压结果缓存的命中率
+        #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+        cache: Option<u64>,
+    },
+    /// 按叠加顺序查找路径最终来自哪个包
+    #[command(arg_required_else_help = true)]
+    Resolve {
+        /// 从下到上依次叠加的包，同一路径以最后一层为准
+        #[arg(long, num_args = 1.., required = true, value_name = "PAK_FILE")]
+        layers: Vec<String>,
+        /// 要查找的路径，也可以直接写在 --layers 的最后
+        #[arg(value_name = "PATH")]
+        path: Option<String>,
+        /// 判断路径是否相同的方式
+        #[arg(long, value_enum, default_value_t = PathPolicy::Exact)]
+        path_policy: PathPolicy,
+    },
+    /// 说明某个字节偏移属于包的哪个结构，不指定偏移时列出完整结构
+    #[command(arg_required_else_help = true)]
+    Explain {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 要说明的偏移，支持十进制或 0x 开头的十六进制
+        #[arg(long, value_name = "OFFSET", value_parser = layout::parse_offset)]
+        offset: Option<u64>,
     },
     /// 查看pak结构
     #[command(arg_required_else_help = true, name = "view")]
@@ -85,11 +825,194 @@ enum Commands {
         /// 输入文件路径
         #[arg(value_name = "INPUT_FILE")]
         input: String,
+        /// 以十六进制显示指定区段：header、metadata、trailer 或 entry:N
+        #[arg(long, value_name = "SECTION", value_parser = view_pak_structure::parse_hex_section)]
+        hex: Option<view_pak_structure::HexSection>,
+    },
+}
+
+impl Commands {
+    /// 输出是否供其他程序解析（此时不打印版本信息）
+    fn machine_output(&self) -> bool {
+        matches!(
+            self,
+            Commands::Checksums { .. }
+                | Commands::List { null: true, .. }
+                | Commands::List { porcelain: Some(_), .. }
+                | Commands::Metadata { porcelain: Some(_), .. }
+                | Commands::Verify { porcelain: Some(_), .. }
+                | Commands::Cat { output: None, .. }
+                | Commands::Open { .. }
+                | Commands::Pieces { output: None, .. }
+                | Commands::Compare { json: true, .. }
+                | Commands::Compat { json: true, .. }
+                | Commands::Changelog { output: None, .. }
+                | Commands::Catalog { command: CatalogCommands::Find { json: true, .. } }
+                | Commands::Ext { command: ExtCommands::Get { output: None, .. } }
+                | Commands::History { json: true, .. }
+        )
+    }
+
+    /// 需要记入操作历史的命令，只记录生成或修改包的命令，以及解包和恢复文件
+    fn operation(&self) -> Option<history::Operation> {
+        let (command, archive) = match self {
+            Commands::Pak { paths, files_from, .. } => ("pak", pak::output_path(paths, files_from.is_some())?),
+            Commands::Unpak { input, .. } => ("unpak", input.clone()),
+            Commands::Update { input, .. } => ("update", input.clone()),
+            Commands::Repair { output, .. } => ("repair", output.clone()),
+            Commands::Carve { input, .. } => ("carve", input.clone()),
+            Commands::Touch { input, .. } => ("touch", input.clone()),
+            Commands::Add { input, .. } => ("add", input.clone()),
+            Commands::Remove { input, .. } => ("remove", input.clone()),
+            Commands::Copy { input, .. } => ("copy", input.clone()),
+            Commands::Optimize { input, .. } => ("optimize", input.clone()),
+            Commands::Rekey { input, .. } => ("rekey", input.clone()),
+            Commands::Rollback { input } => ("rollback", input.clone()),
+            Commands::Ext { command: ExtCommands::Add { input, .. } } => ("ext add", input.clone()),
+            _ => return None,
+        };
+        Some(history::Operation { command, archive })
+    }
+}
+
+/// `pak`中为条目附加属性的参数
+#[derive(clap::Args)]
+struct EntryAttrArgs {
+    /// 有效期清单，每行一条 GLOB=FROM..UNTIL 规则，时间为 RFC 3339 或 YYYY-MM-DD
+    #[arg(long, value_name = "MANIFEST_FILE")]
+    validity: Option<String>,
+    /// 为匹配的文件附加属性，格式为 GLOB=KEY:VALUE，可多次指定；目前支持 platform:NAME[,NAME...] 标记条目只用于这些平台，lang:CODE[,CODE...] 标记条目的语言
+    #[arg(long, value_name = "GLOB=KEY:VALUE", value_parser = file_meta::parse_file_meta)]
+    file_meta: Vec<(String, file_meta::FileMeta)>,
+    /// 从与文件相邻、带有该扩展名的JSON附属文件（如 foo.png.meta.json）读取条目属性，附属文件本身不打包
+    #[arg(long, value_name = "EXT", value_parser = sidecar::parse_sidecar_ext)]
+    sidecar_ext: Option<String>,
+    /// 附属文件本身也打包
+    #[arg(long, requires = "sidecar_ext")]
+    keep_sidecars: bool,
+    /// 优先级清单，每行一条 GLOB=PRIORITY 规则，条目按优先级从高到低排列
+    #[arg(long, value_name = "MANIFEST_FILE")]
+    priority: Option<String>,
+    /// 分组清单，每行一条 GLOB=BUNDLE 规则，条目属于所有匹配规则的分组，可用 unpak --bundle 按分组解包
+    #[arg(long, value_name = "MANIFEST_FILE")]
+    bundles: Option<String>,
+    /// 记录各优先级段的位置，下载未完成的包可以用 unpak --available-only 解包已完整的部分
+    #[arg(long, conflicts_with = "split_metadata")]
+    progressive: bool,
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    /// 保存密钥（口令从终端或标准输入读取）
+    Set {
+        /// 密钥名称
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// 列出已保存的密钥名称
+    List,
+    /// 删除密钥
+    Remove {
+        /// 密钥名称
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExtCommands {
+    /// 添加扩展数据，重写metadata后仍会保留
+    Add {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 扩展类型名
+        #[arg(value_name = "TYPE")]
+        name: String,
+        /// 扩展数据所在的文件
+        #[arg(value_name = "DATA_FILE")]
+        data: String,
+        /// 替换已存在的同名扩展
+        #[arg(long)]
+        replace: bool,
+    },
+    /// 列出扩展数据及其偏移
+    List {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+    },
+    /// 取出扩展数据
+    Get {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 扩展类型名
+        #[arg(value_name = "TYPE")]
+        name: String,
+        /// 输出文件，不指定时写到标准输出
+        #[arg(short, long, value_name = "OUTPUT_FILE")]
+        output: Option<String>,
     },
 }
 
-fn main() -> io::Result<()> {
-    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));  
+#[derive(Subcommand)]
+enum CatalogCommands {
+    /// 扫描目录（包括子目录）中的包，记录每个包的名称、摘要、metadata概要和文件列表
+    Build {
+        /// 存放包的目录
+        #[arg(value_name = "DIR")]
+        dir: String,
+        /// 输出的目录文件
+        #[arg(value_name = "CATALOG_FILE")]
+        output: String,
+    },
+    /// 查找包含该文件的包（没有找到时退出码为1），路径中可以使用 * 等glob通配符
+    Find {
+        /// 包内的文件路径或glob
+        #[arg(value_name = "PATH")]
+        path: String,
+        /// 目录文件
+        #[arg(long, short, value_name = "CATALOG_FILE", default_value = "catalog.json")]
+        catalog: String,
+        /// 比较路径的方式
+        #[arg(long, value_enum, default_value_t = PathPolicy::Exact)]
+        path_policy: PathPolicy,
+        /// 以JSON格式输出结果
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DepsCommands {
+    /// 检查包声明的依赖是否都能找到，且版本和摘要符合要求
+    Check {
+        /// 输入文件路径
+        #[arg(value_name = "INPUT_FILE")]
+        input: String,
+        /// 查找依赖包的目录（包括子目录），可多次指定，默认为输入文件所在的目录
+        #[arg(long, value_name = "DIR")]
+        search_dir: Vec<String>,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let error_format = cli.error_format;
+    if let Err(e) = run(cli) {
+        error::print_error(&e, error_format);
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> io::Result<()> {
+    logging::init(cli.log_level, cli.log_file.as_deref())?;
+
+    // 机器可读的输出需要保持干净，便于重定向给其他工具
+    if !cli.command.machine_output() {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    }
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
 
@@ -98,28 +1021,312 @@ fn main() -> io::Result<()> {
         println!("\n操作已取消");
     }).expect("无法设置 Ctrl-C 处理器");
 
-    let cli = Cli::parse();
+    let operation = cli.command.operation();
+    let started = Instant::now();
+    let result = execute(cli.command, running);
+    if let Some(operation) = &operation {
+        history::record(operation, started.elapsed(), &result);
+    }
+    result
+}
 
-    match cli.command {
-        Commands::Pak { input, output, flat, description, metadata } => {
-            pak::pack_files(&input, &output, flat, description.as_deref(), metadata.as_deref(), running)?;
+fn execute(command: Commands, running: Arc<AtomicBool>) -> io::Result<()> {
+    match command {
+        Commands::Pak {
+            paths, files_from, force, preallocate, flat, description, content_version, metadata, depends, hash, encrypt, key, key_from, encrypt_to,
+            prefix, rename_rule, map, follow_symlinks, same_filesystem, max_depth, min_depth,
+            on_duplicate, on_read_error, parity, hook, lookup_index, throttle, memory_limit, max_open_files, io_retries, access_log, metrics_file, attrs, strip_debug, debug_archive, eula, compress, frame_size, git_rev, var, compact, split_metadata, metadata_format
+        } => {
+            let options = pak::PackOptions {
+                flat,
+                description,
+                content_version,
+                metadata,
+                hash_algo: hash,
+                dependencies: deps::resolve_specs(&depends)?,
+                encrypt,
+                keys: keystore::resolve_keys(&key, &key_from)?,
+                encrypt_to,
+                prefix,
+                rename_rules: rename_rule,
+                maps: map,
+                follow_symlinks,
+                same_filesystem,
+                max_depth,
+                min_depth,
+                on_duplicate,
+                on_read_error,
+                parity,
+                hooks: hook,
+                lookup_index,
+                throttle,
+                memory_limit,
+                max_open_files,
+                io_retries,
+                access_log,
+                metrics_file,
+                validity: match attrs.validity {
+                    Some(path) => validity::load_manifest(&path)?,
+                    None => Vec::new(),
+                },
+                file_meta: attrs.file_meta,
+                priorities: match attrs.priority {
+                    Some(path) => progressive::load_manifest(&path)?,
+                    None => Vec::new(),
+                },
+                bundles: match attrs.bundles {
+                    Some(path) => bundle::load_manifest(&path)?,
+                    None => Vec::new(),
+                },
+                progressive: attrs.progressive,
+                eula,
+                compress,
+                frame_size_mb: frame_size,
+                git_rev,
+                vars: var,
+                force,
+                preallocate,
+                files_from,
+                sidecar_ext: attrs.sidecar_ext,
+                keep_sidecars: attrs.keep_sidecars,
+                strip_debug,
+                debug_archive,
+                compact,
+                split_metadata,
+                metadata_encoding: metadata_format,
+            };
+            let (inputs, output) = pak::split_output(paths, options.files_from.is_some())?;
+            pak::pack_files(&inputs, &output, &options, running)?;
             println!("操作已完成");
         }
-        Commands::Unpak { input, output, files } => {
-            unpak::unpack_files(&input, &output, files.as_deref(), running)?;
+        Commands::Unpak {
+            input, output, files, entries, bundle, ignore_case, prefix, ignore_missing,
+            strip_components, transform, output_template, flat, on_collision, report, key, key_from, identity, post_hook, parallel_hooks, layers, path_policy, throttle,
+            respect_validity, platform, lang, available_only, accept_eula, on_existing, strict, write_sidecars,
+            no_space_check, max_disk_usage
+        } => {
+            let options = unpak::UnpackOptions {
+                selector: EntrySelector {
+                    files,
+                    entries,
+                    ignore_case: ignore_case || path_policy == PathPolicy::IgnoreCase,
+                    prefix,
+                },
+                bundles: bundle,
+                ignore_missing,
+                strip_components,
+                transforms: transform,
+                output_template,
+                flat,
+                on_collision,
+                report,
+                keys: keystore::resolve_keys(&key, &key_from)?,
+                identities: identity,
+                post_hook,
+                parallel_hooks,
+                layers,
+                path_policy,
+                throttle,
+                respect_validity,
+                platform,
+                langs: lang,
+                available_only,
+                accept_eula,
+                on_existing,
+                consistency: if strict { Consistency::Strict } else { Consistency::Warn },
+                write_sidecars,
+                no_space_check,
+                max_disk_usage,
+            };
+            unpak::unpack_files(&input, &output, &options, running)?;
             println!("操作已完成");
         }
-        Commands::Metadata { input, files } => {
-            metadata::display_metadata(&input, files)?;
+        Commands::Info { input } => {
+            info::info(&input)?;
+        }
+        Commands::Metadata { input, porcelain: Some(version), .. } => {
+            porcelain::metadata(&input, version)?;
+        }
+        Commands::Metadata { input, files, no_banner, porcelain: None } => {
+            metadata::display_metadata(&input, files, !no_banner)?;
+        }
+        Commands::List { input, porcelain: Some(version), .. } => {
+            porcelain::list(&input, version)?;
+        }
+        Commands::List { input, langs: true, .. } => {
+            file_meta::list_langs(&input)?;
+        }
+        Commands::List { input, recheck, verbose, no_banner, columns, bytes, pager, null, ids, .. } => {
+            let mut format = listing::ListFormat::new(columns, verbose, bytes, null);
+            if ids && !format.columns.contains(&listing::Column::Id) {
+                format.columns.insert(0, listing::Column::Id);
+            }
+            unpak::list_files(&input, recheck, &format, !no_banner, pager)?;
+        }
+        Commands::Preview { input, path, lines, protocol, width } => {
+            preview::preview(&input, &path, &preview::PreviewOptions { lines, protocol, width })?;
+        }
+        Commands::Ls { input, dir } => {
+            archive_fs::ls(&input, &dir)?;
+        }
+        Commands::Cat { input, path, id, offset, length, output } => {
+            unpak::cat_entry(&input, path.as_deref(), id, offset, length, output.as_deref())?;
+        }
+        Commands::Open { input, path, command } => {
+            let code = unpak::open_entry_with(&input, &path, &command)?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Checksums { input, algo, jobs } => {
+            checksums::print_checksums(&input, algo, jobs)?;
+        }
+        Commands::Verify { input, daemon, watch_dir, webhook, interval, porcelain } => {
+            if daemon {
+                let webhook = match webhook {
+                    Some(url) => Some(url),
+                    None => config::Config::load()?.verify.webhook,
+                };
+                let dir = watch_dir.unwrap_or_default();
+                verify::watch(&dir, webhook.as_deref(), Duration::from_secs(interval), &running)?;
+            } else if let Some(version) = porcelain {
+                if !porcelain::verify(&input.unwrap_or_default(), version)? {
+                    std::process::exit(1);
+                }
+            } else if !verify::verify(&input.unwrap_or_default())? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Repair { input, output, use_parity } => {
+            repair::repair(&input, &output, use_parity)?;
+        }
+        Commands::Carve { input, output, all } => {
+            carve::carve(&input, &output, all)?;
+        }
+        Commands::Touch { input, path, from, append, backup } => {
+            backup::prepare(&input, backup, true)?;
+            edit::touch(&input, &path, &from, append)?;
+        }
+        Commands::Add { input, path, from, backup } => {
+            backup::prepare(&input, backup, true)?;
+            edit::add(&input, &path, &from)?;
+        }
+        Commands::Remove { input, path, backup } => {
+            backup::prepare(&input, backup, true)?;
+            edit::remove(&input, &path)?;
+        }
+        Commands::Copy { source, input, files, replace, backup } => {
+            backup::prepare(&input, backup, true)?;
+            edit::copy(&source, &input, &files, replace)?;
+        }
+        Commands::Optimize { input, keep_trailing, backup } => {
+            backup::prepare(&input, backup, false)?;
+            edit::optimize(&input, keep_trailing)?;
+        }
+        Commands::CleanTemp { dir, dry_run } => {
+            temp::clean_temp(&dir, dry_run)?;
+        }
+        Commands::History { input, limit, json } => {
+            history::show(input.as_deref(), limit, json)?;
+        }
+        Commands::Rollback { input } => {
+            backup::rollback(&input)?;
+        }
+        Commands::Pieces { input, piece_size, hash, output, torrent, tracker } => {
+            let options = pieces::PieceOptions { piece_size, algo: hash, output, torrent, trackers: tracker };
+            pieces::pieces(&input, &options)?;
+        }
+        Commands::Rekey { input, old_key, new_key, backup } => {
+            backup::prepare(&input, backup, false)?;
+            edit::rekey(&input, &old_key, &new_key)?;
+        }
+        Commands::Estimate { inputs, compress, hash } => {
+            estimate::estimate(&inputs, compress, hash)?;
+        }
+        Commands::Compare { input, dir, json } => {
+            if !compare::compare(&input, &dir, json)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Compat { old, new, json } => {
+            if !compat::compat(&old, &new, json)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Changelog { old, new, format, output } => {
+            changelog::changelog(&old, &new, format, output.as_deref())?;
+        }
+        Commands::CheckNames { input, plan } => {
+            if !names::check_names(&input, plan.as_deref())? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Lint { input, deny } => {
+            if !lint::lint(&input, &deny)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Key { command } => match command {
+            KeyCommands::Set { name } => {
+                let secret = keystore::read_secret(&format!("请输入密钥 {} 的口令: ", name))?;
+                keystore::store_key(&name, &secret)?;
+                println!("密钥 {} 已保存", name);
+            }
+            KeyCommands::List => {
+                for name in keystore::list_keys()? {
+                    println!("{}", name);
+                }
+            }
+            KeyCommands::Remove { name } => {
+                keystore::remove_key(&name)?;
+                println!("密钥 {} 已删除", name);
+            }
+        },
+        Commands::Ext { command } => match command {
+            ExtCommands::Add { input, name, data, replace } => {
+                ext::add(&input, &name, &data, replace)?;
+                println!("扩展 {} 已写入", name);
+            }
+            ExtCommands::List { input } => {
+                ext::list(&input)?;
+            }
+            ExtCommands::Get { input, name, output } => {
+                ext::get(&input, &name, output.as_deref())?;
+            }
+        },
+        Commands::Catalog { command } => match command {
+            CatalogCommands::Build { dir, output } => {
+                catalog::build(&dir, &output)?;
+            }
+            CatalogCommands::Find { path, catalog, path_policy, json } => {
+                if !catalog::find(&catalog, &path, path_policy, json)? {
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Deps { command: DepsCommands::Check { input, search_dir } } => {
+            deps::check(&input, &search_dir)?;
+        }
+        Commands::AnalyzeTrace { input, trace, cache } => {
+            trace::analyze_trace(&input, &trace, cache)?;
+        }
+        Commands::Resolve { mut layers, path, path_policy } => {
+            let path = match path {
+                Some(path) => path,
+                None if layers.len() > 1 => layers.pop().unwrap(),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "缺少要查找的路径")),
+            };
+            overlay::resolve(&layers, &path, path_policy)?;
         }
-        Commands::List { input, recheck } => {
-            unpak::list_files(&input, recheck)?;
+        Commands::Explain { input, offset } => {
+            layout::explain(&input, offset)?;
         }
-        Commands::ViewStructure { input } => {
-            view_pak_structure::view_structure(&input)?;
+        Commands::ViewStructure { input, hex } => {
+            view_pak_structure::view_structure(&input, hex)?;
         }
-        Commands::Update { input, description, metadata, all } => {
-            metadata::update_metadata(&input, description.as_deref(), metadata.as_deref(), all)?;
+        Commands::Update { input, description, content_version, metadata, all, backup } => {
+            backup::prepare(&input, backup, false)?;
+            metadata::update_metadata(&input, description.as_deref(), content_version.as_deref(), metadata.as_deref(), all)?;
             println!("元数据更新完成");
         }
     }
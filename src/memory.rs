@@ -0,0 +1,64 @@
+use std::sync::{Condvar, Mutex};
+
+use crate::common::{format_size, MB};
+
+/// 并行处理时在途缓冲区的内存上限
+///
+/// 每个任务在分配缓冲区前申请额度，额度不足时阻塞等待其他任务释放，
+/// 从而限制同时进行的任务数量。
+#[derive(Debug, Default)]
+pub struct MemoryBudget {
+    limit: Option<u64>,
+    usage: Mutex<Usage>,
+    released: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    in_use: u64,
+    peak: u64,
+}
+
+/// 申请到的额度，释放时唤醒等待的任务
+pub struct Lease<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    /// `limit_mb`为`None`时不限制，只统计峰值
+    pub fn new(limit_mb: Option<u64>) -> Self {
+        Self { limit: limit_mb.map(|mb| mb * MB as u64), ..Default::default() }
+    }
+
+    /// 申请`bytes`字节的额度。单个申请超过上限时，等到没有其他任务占用额度后放行，避免死锁
+    pub fn acquire(&self, bytes: usize) -> Lease<'_> {
+        let bytes = bytes as u64;
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(limit) = self.limit {
+            while usage.in_use > 0 && usage.in_use + bytes > limit {
+                usage = self.released.wait(usage).unwrap();
+            }
+        }
+        usage.in_use += bytes;
+        usage.peak = usage.peak.max(usage.in_use);
+        Lease { budget: self, bytes }
+    }
+
+    /// 在途缓冲区的峰值和上限的说明
+    pub fn summary(&self) -> String {
+        let peak = format_size(self.usage.lock().unwrap().peak);
+        match self.limit {
+            Some(limit) => format!("缓冲区峰值 {}，上限 {}", peak, format_size(limit)),
+            None => format!("缓冲区峰值 {}", peak),
+        }
+    }
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        let mut usage = self.budget.usage.lock().unwrap();
+        usage.in_use -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
@@ -1,33 +1,162 @@
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use chrono::{DateTime, Utc};
 use std::io::{self, Read, Write, Seek, SeekFrom};
 use serde_json::Value;
 use std::path::Path;
 use std::fs::File;
 use indicatif::{ProgressBar, ProgressStyle};
+use tracing::{debug, info, warn};
 
-use crate::common::{FORMAT_VERSION, MAGIC_NUMBER, MAGIC_METADATA_END};
+use crate::common::{PathLength, BINARY_METADATA_TAG, COMPACT_FORMAT_VERSION, FORMAT_VERSION, MAGIC_NUMBER, MAGIC_METADATA_END, SPLIT_FORMAT_VERSION};
+use crate::hash::HashAlgo;
+use crate::compress::EntryCompression;
+use crate::crypto::{EntryEncryption, KeyInfo};
+use crate::git::GitSource;
+use crate::progressive::PriorityTier;
+use crate::deps::Dependency;
+use crate::migrate::{has_metadata_end, parse_metadata, probe_version};
+use crate::temp::TempFile;
+use crate::vpath::entry_id;
+use xpak::{read_entry_header, read_metadata, read_sized, TrailerRecord};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// 尾部区段中条目列表的标记
+pub const FILES_TAG: [u8; 8] = *b"FILES___";
+/// 条目列表拆分到尾部区段时包头中metadata之后预留的空白，追加更新时metadata略微变长也能原地写入
+pub const METADATA_SLACK: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileInfo {
     pub path: String,
+    /// 紧凑布局中所在目录在`XpakMetadata::dirs`中的序号，此时`path`只是文件名，读取后还原为完整路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<u32>,
     pub size: u64,
+    /// 文件内容的摘要（十六进制）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// 摘要使用的算法
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algo: Option<HashAlgo>,
+    /// 包内实际存储的大小（与`size`不同时记录）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_size: Option<u64>,
+    /// 条目加密信息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EntryEncryption>,
+    /// 分帧压缩信息，加密条目先压缩再加密
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<EntryCompression>,
+    /// 因路径冲突被重命名时的原始路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_path: Option<String>,
+    /// 打包时嗅探出的MIME类型
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// 经过打包前钩子转换时记录的来源
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook: Option<HookInfo>,
+    /// 有效期的开始时间，之前的时刻不应解包
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<DateTime<Utc>>,
+    /// 有效期的结束时间，之后的时刻不应解包
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<DateTime<Utc>>,
+    /// 打包时源文件的修改时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<DateTime<Utc>>,
+    /// 删除标记：追加更新删除条目时写入的空条目，之前同一路径的条目都视为已删除
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub removed: bool,
+    /// 条目只用于这些目标平台，为空时用于所有平台
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+    /// 条目是这些语言的本地化内容，为空时与语言无关
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub langs: Vec<String>,
+    /// 渐进下载时的优先级，数值大的排在前面
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// 由路径算出的条目ID，见`vpath::entry_id`；旧版本的包没有记录，按路径计算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// 打包时从附属文件读取的自定义属性
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub attrs: serde_json::Map<String, serde_json::Value>,
+    /// 可执行文件的格式和调试信息标识，打包时剥离了调试信息的条目同时记录剥离前的大小
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<DebugInfo>,
+}
+
+/// 打包前钩子转换的来源信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HookInfo {
+    pub original_path: String,
+    pub original_mime: String,
+    pub command: String,
+}
+
+/// 可执行文件的调试信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DebugInfo {
+    /// `elf`、`pe`或`macho`
+    pub format: String,
+    /// ELF的build-id、Mach-O的UUID或PE对应PDB的GUID和age（十六进制）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+    /// 剥离调试信息前的大小，没有剥离时不记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_size: Option<u64>,
+    /// 分离出的调试信息在调试信息包中的路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_path: Option<String>,
 }
 
 impl FileInfo {
     pub fn new(path: impl AsRef<Path>, size: u64) -> Self {
+        let path = path.as_ref()
+            .to_string_lossy()
+            .replace('\\', "/")
+            .to_string();
         Self {
-            path: path.as_ref()
-                .to_string_lossy()
-                .replace('\\', "/")
-                .to_string(),
-            size
+            id: Some(entry_id(&path)),
+            path,
+            dir: None,
+            size,
+            hash: None,
+            algo: None,
+            stored_size: None,
+            encryption: None,
+            compression: None,
+            original_path: None,
+            mime: None,
+            hook: None,
+            valid_from: None,
+            valid_until: None,
+            mtime: None,
+            removed: false,
+            platforms: Vec::new(),
+            langs: Vec::new(),
+            priority: None,
+            attrs: serde_json::Map::new(),
+            debug: None,
         }
     }
+
+    pub fn with_hash(mut self, algo: HashAlgo, hash: String) -> Self {
+        self.hash = Some(hash);
+        self.algo = Some(algo);
+        self
+    }
+
+    /// 条目ID，没有记录时按路径计算
+    pub fn entry_id(&self) -> u64 {
+        self.id.unwrap_or_else(|| entry_id(&self.path))
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct XpakMetadata {
     pub version: String,
     pub format_version: String,
@@ -36,9 +165,91 @@ pub struct XpakMetadata {
     pub total_size: u64,
     #[serde(default)]
     pub description: Option<String>,
+    /// 包内容的版本（semver），与创建包的xpak版本`version`无关
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_version: Option<String>,
     #[serde(default)]
     pub common: HashMap<String, Value>,
+    /// 加密条目使用的密钥参数，key_id -> 派生参数
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub keys: HashMap<String, KeyInfo>,
     pub files: Vec<FileInfo>,
+    /// 忽略大小写的查找表，大小写折叠后的路径 -> 实际路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lookup: Option<HashMap<String, String>>,
+    /// 条目按访问记录排列时记录的标识
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_profile: Option<String>,
+    /// 解包前需要接受的许可协议全文
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eula: Option<String>,
+    /// 从git版本打包时记录的提交、分支和工作区状态
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitSource>,
+    /// 紧凑布局中条目所在的目录，每个目录只记录一次，读取后清空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dirs: Option<Vec<String>>,
+    /// 条目列表拆分到尾部区段时的位置，此时包头中的`files`为空，需要时通过`load_files`读取
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files_table: Option<FilesTable>,
+    /// 使用`pak --progressive`打包时记录的各优先级段的位置
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiers: Vec<PriorityTier>,
+    /// 打包时因无法读取而跳过的文件（`pak --on-read-error skip`）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<SkippedFile>,
+    /// 命名的分组，分组名 -> 条目路径，可以用`unpak --bundle`按分组解包
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub bundles: BTreeMap<String, Vec<String>>,
+    /// 依赖的其他包，可以用`deps check`检查
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<Dependency>,
+    /// 写入包头时使用的编码，读取时按包内的编码设置
+    #[serde(skip)]
+    pub encoding: MetadataEncoding,
+}
+
+/// 打包时跳过的文件
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkippedFile {
+    /// 本应使用的存储路径
+    pub path: String,
+    pub error: String,
+}
+
+/// metadata在包头中的编码
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetadataEncoding {
+    /// JSON文本，所有版本都可以读取
+    #[default]
+    Json,
+    /// MessagePack，条目很多时体积更小、解析更快
+    Msgpack,
+}
+
+/// 拆分出的条目列表在包内的位置
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct FilesTable {
+    /// 相对数据区段起点的偏移，条目列表是尾部区段的第一条记录
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl FilesTable {
+    /// 读取条目列表的原始内容，`data_offset`为数据区段在包内的起点
+    pub fn read_bytes(&self, reader: &mut (impl Read + Seek), data_offset: u64) -> io::Result<Vec<u8>> {
+        reader.seek(SeekFrom::Start(data_offset + self.offset))?;
+        read_sized(reader, self.len)
+            .map_err(|e| io::Error::new(e.kind(), format!("无法读取条目列表: {}", e)))
+    }
+}
+
+/// 尾部区段中的条目列表，编码与包头的metadata相同
+#[derive(Serialize, Deserialize)]
+struct FileList {
+    files: Vec<FileInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dirs: Option<Vec<String>>,
 }
 
 impl Default for XpakMetadata {
@@ -50,8 +261,21 @@ impl Default for XpakMetadata {
             files_count: 0,
             total_size: 0,
             description: None,
+            content_version: None,
             common: HashMap::new(),
+            keys: HashMap::new(),
             files: Vec::new(),
+            lookup: None,
+            access_profile: None,
+            eula: None,
+            git: None,
+            dirs: None,
+            files_table: None,
+            tiers: Vec::new(),
+            skipped: Vec::new(),
+            bundles: BTreeMap::new(),
+            dependencies: Vec::new(),
+            encoding: MetadataEncoding::Json,
         }
     }
 }
@@ -65,13 +289,123 @@ impl XpakMetadata {
             files_count,
             total_size,
             description: None,
+            content_version: None,
             common: HashMap::new(),
+            keys: HashMap::new(),
             files: Vec::new(),
+            lookup: None,
+            access_profile: None,
+            eula: None,
+            git: None,
+            dirs: None,
+            files_table: None,
+            tiers: Vec::new(),
+            skipped: Vec::new(),
+            bundles: BTreeMap::new(),
+            dependencies: Vec::new(),
+            encoding: MetadataEncoding::Json,
         }
     }
 
+    /// 条目头部中路径长度的编码，由格式版本决定
+    pub fn path_length(&self) -> PathLength {
+        PathLength::for_version(&self.format_version)
+    }
+
+    /// 序列化为写入包头的metadata
+    ///
+    /// 紧凑布局的包中，条目路径拆成目录序号和文件名，每个目录只在`dirs`中记录一次。
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        if self.path_length() == PathLength::U32 {
+            return encode(self.encoding, self);
+        }
+        let (files, dirs) = compact_paths(&self.files);
+        let compact = Self { files, dirs: Some(dirs), ..self.clone_header() };
+        encode(self.encoding, &compact)
+    }
+
+    /// 把条目列表移到尾部区段，返回要写在尾部区段最前面的记录
+    ///
+    /// `data_len`为数据区段的长度，用于计算条目列表的偏移。之后`to_bytes`只包含其余字段。
+    pub fn split_files(&mut self, data_len: u64) -> io::Result<TrailerRecord> {
+        let files = std::mem::take(&mut self.files);
+        let list = match self.path_length() {
+            PathLength::U32 => FileList { files, dirs: None },
+            PathLength::Varint => {
+                let (files, dirs) = compact_paths(&files);
+                FileList { files, dirs: Some(dirs) }
+            }
+        };
+        let data = encode(self.encoding, &list)?;
+        // 尾部记录的标记和长度共16字节
+        self.files_table = Some(FilesTable { offset: data_len + 16, len: data.len() as u64 });
+        Ok(TrailerRecord { tag: FILES_TAG, data })
+    }
+
+    /// 从尾部区段读取拆分出的条目列表，条目列表没有拆分时不做任何事
+    pub fn load_files(&mut self, reader: &mut (impl Read + Seek), data_offset: u64) -> io::Result<()> {
+        let Some(table) = self.files_table else {
+            return Ok(());
+        };
+        let bytes = table.read_bytes(reader, data_offset)?;
+        let list: FileList = match bytes.split_first() {
+            Some((&BINARY_METADATA_TAG, bytes)) => rmp_serde::from_slice(bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("无法解析条目列表: {}", e)))?,
+            _ => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("无法解析条目列表: {}", e)))?,
+        };
+        self.files = list.files;
+        self.dirs = list.dirs;
+        self.expand_paths()
+    }
+
+    /// 复制除条目列表以外的字段
+    fn clone_header(&self) -> Self {
+        Self {
+            version: self.version.clone(),
+            format_version: self.format_version.clone(),
+            created_at: self.created_at,
+            files_count: self.files_count,
+            total_size: self.total_size,
+            description: self.description.clone(),
+            content_version: self.content_version.clone(),
+            common: self.common.clone(),
+            keys: self.keys.clone(),
+            files: Vec::new(),
+            lookup: self.lookup.clone(),
+            access_profile: self.access_profile.clone(),
+            eula: self.eula.clone(),
+            git: self.git.clone(),
+            dirs: None,
+            files_table: self.files_table,
+            tiers: self.tiers.clone(),
+            skipped: self.skipped.clone(),
+            bundles: self.bundles.clone(),
+            dependencies: self.dependencies.clone(),
+            encoding: self.encoding,
+        }
+    }
+
+    /// 把紧凑布局中的目录序号和文件名还原为完整路径
+    pub fn expand_paths(&mut self) -> io::Result<()> {
+        let Some(dirs) = self.dirs.take() else {
+            return Ok(());
+        };
+        for file in &mut self.files {
+            let Some(id) = file.dir.take() else { continue };
+            let dir = dirs.get(id as usize).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("条目 {} 的目录序号 {} 超出范围", file.path, id)
+            ))?;
+            if !dir.is_empty() {
+                file.path = format!("{}/{}", dir, file.path);
+            }
+        }
+        Ok(())
+    }
+
     pub fn merge_user_metadata(&mut self, user_meta: &str) -> Result<(), String> {
-        println!("Raw metadata: {:?}", user_meta);
+        debug!("原始用户metadata: {:?}", user_meta);
         
         match serde_json::from_str::<serde_json::Value>(user_meta) {
             Ok(user_value) => {
@@ -89,53 +423,153 @@ impl XpakMetadata {
             }
         }
     }
+
+    /// 每个条目是否仍然有效
+    ///
+    /// 追加更新时新的条目写在包的末尾，路径重复时以最后一个条目为准；删除标记和被替换的
+    /// 条目都无效，由`optimize`清除。
+    pub fn live_entries(&self) -> Vec<bool> {
+        let mut seen = std::collections::HashSet::new();
+        let mut live: Vec<bool> = self.files.iter().rev()
+            .map(|f| seen.insert(f.path.as_str()) && !f.removed)
+            .collect();
+        live.reverse();
+        live
+    }
+
+    /// 仍然有效的条目
+    pub fn live_files(&self) -> Vec<&FileInfo> {
+        self.files.iter().zip(self.live_entries()).filter(|(_, live)| *live).map(|(f, _)| f).collect()
+    }
+
+    /// 读取用户metadata中的文本字段，例如`author`和`license`
+    pub fn common_text(&self, key: &str) -> Option<String> {
+        match self.common.get(key)? {
+            Value::Null => None,
+            Value::String(s) => Some(s.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+/// 按`encoding`序列化，二进制编码以`BINARY_METADATA_TAG`开头
+fn encode(encoding: MetadataEncoding, value: &impl Serialize) -> io::Result<Vec<u8>> {
+    match encoding {
+        MetadataEncoding::Json => Ok(serde_json::to_vec(value)?),
+        MetadataEncoding::Msgpack => {
+            let mut bytes = vec![BINARY_METADATA_TAG];
+            rmp_serde::encode::write_named(&mut bytes, value)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("序列化metadata失败: {}", e)))?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// 把条目路径拆成目录序号和文件名，返回拆分后的条目和目录表
+fn compact_paths(files: &[FileInfo]) -> (Vec<FileInfo>, Vec<String>) {
+    let mut compact = Vec::with_capacity(files.len());
+    let mut dirs: Vec<String> = Vec::new();
+    let mut index: HashMap<&str, u32> = HashMap::new();
+    for file in files {
+        let (dir, name) = file.path.rsplit_once('/').unwrap_or(("", &file.path));
+        let id = *index.entry(dir).or_insert_with(|| {
+            dirs.push(dir.to_string());
+            dirs.len() as u32 - 1
+        });
+        compact.push(FileInfo { path: name.to_string(), dir: Some(id), ..file.clone() });
+    }
+    (compact, dirs)
+}
+
+/// 在文件列表之前显示包的描述、作者、许可证和创建时间
+pub fn write_banner(out: &mut impl Write, metadata: &XpakMetadata) -> io::Result<()> {
+    writeln!(out, "========================================")?;
+    if let Some(description) = metadata.description.as_deref().filter(|d| !d.is_empty()) {
+        writeln!(out, " {}", description)?;
+    }
+    if let Some(author) = metadata.common_text("author") {
+        writeln!(out, " 作者: {}", author)?;
+    }
+    if let Some(license) = metadata.common_text("license") {
+        writeln!(out, " 许可证: {}", license)?;
+    }
+    if let Some(git) = &metadata.git {
+        let branch = git.branch.as_deref().map(|b| format!(" ({})", b)).unwrap_or_default();
+        let dirty = if git.dirty { "，打包时工作区有未提交的修改" } else { "" };
+        writeln!(out, " 来源: git {}{}{}", git.commit, branch, dirty)?;
+    }
+    if metadata.eula.is_some() {
+        writeln!(out, " 许可协议: 解包前需要接受（可用 metadata 命令查看全文）")?;
+    }
+    // 旧版本的包可能没有记录创建时间
+    if metadata.created_at != DateTime::<Utc>::UNIX_EPOCH {
+        writeln!(out, " 创建时间: {}", metadata.created_at.format("%Y-%m-%d %H:%M:%S UTC"))?;
+    }
+    writeln!(out, "========================================")
 }
 
-pub fn display_metadata(input: &str, show_files: bool) -> io::Result<()> {
+pub fn display_metadata(input: &str, show_files: bool, banner: bool) -> io::Result<()> {
     let mut file = File::open(input)?;
 
     // 读取并验证Magic Number
     let mut magic = [0u8; 4];
     file.read_exact(&mut magic)?;
-    if &magic != MAGIC_NUMBER {
+    if magic != MAGIC_NUMBER {
         return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid file format"));
     }
 
-    // 读取metadata长度
-    let mut meta_len_bytes = [0u8; 4];
-    file.read_exact(&mut meta_len_bytes)?;
-    let meta_len = u32::from_le_bytes(meta_len_bytes) as usize;
+    // 读取metadata
+    let file_len = file.metadata()?.len();
+    let metadata_bytes = read_metadata(&mut file, file_len)?;
+    let meta_len = metadata_bytes.len();
 
     if meta_len == 0 {
         println!("No metadata found");
         return Ok(());
     }
 
-    // 读取metadata内容
-    let mut metadata_bytes = vec![0u8; meta_len];
-    file.read_exact(&mut metadata_bytes)?;
+    let metadata = parse_metadata(&metadata_bytes)?;
+    if banner {
+        write_banner(&mut io::stdout(), &metadata)?;
+    }
 
-    match serde_json::from_slice::<Value>(&metadata_bytes) {
-        Ok(mut json) => {
-            if !show_files {
-                // 把files变为...
-                json.get_mut("files").map(|v| *v = Value::String("...".to_string()));
+    // 二进制metadata同样以JSON的形式显示
+    let mut json = to_json_value(&metadata_bytes)?;
+    if !show_files {
+        // 把files和紧凑布局的dirs变为...
+        for key in ["files", "dirs"] {
+            if let Some(v) = json.get_mut(key) {
+                *v = Value::String("...".to_string());
             }
-            print_json_tree("", &json);
         }
-        Err(e) => {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse metadata: {}", e)));
+    } else if let (Some(table), Value::Object(map)) = (metadata.files_table, &mut json) {
+        // 条目列表拆分到尾部区段时，只在需要显示时读取
+        let mut data_offset = 8 + meta_len as u64 + 4;
+        if has_metadata_end(&metadata.format_version) {
+            data_offset += MAGIC_METADATA_END.len() as u64;
+        }
+        if let Value::Object(list) = to_json_value(&table.read_bytes(&mut file, data_offset)?)? {
+            map.extend(list);
         }
     }
+    print_json_tree("", &json);
 
     Ok(())
 }
 
+/// 把JSON或二进制编码的内容解析为JSON值
+fn to_json_value(bytes: &[u8]) -> io::Result<Value> {
+    match bytes.split_first() {
+        Some((&BINARY_METADATA_TAG, bytes)) => rmp_serde::from_slice::<Value>(bytes).map_err(|e| e.to_string()),
+        _ => serde_json::from_slice::<Value>(bytes).map_err(|e| e.to_string()),
+    }.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse metadata: {}", e)))
+}
+
 fn print_json_tree(prefix: &str, value: &Value) {
     match value {
         Value::Object(map) => {
             let mut entries: Vec<_> = map.iter().collect();
-            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries.sort_by_key(|(k, _)| *k);
 
             // 去除没有值的键
             entries.retain(|(_, val)| !val.is_null());
@@ -177,117 +611,131 @@ fn print_json_tree(prefix: &str, value: &Value) {
     }
 } 
 
-pub fn update_metadata(input: &str, description: Option<&str>, metadata: Option<&str>, all: bool) -> io::Result<()> {
+pub fn update_metadata(
+    input: &str,
+    description: Option<&str>,
+    content_version: Option<&str>,
+    metadata: Option<&str>,
+    all: bool
+) -> io::Result<()> {
     let mut file = File::open(input)?;
     
     // 读取并验证Magic Number
-    println!("读取并验证Magic Number");
+    debug!("读取并验证Magic Number");
     let mut magic = [0u8; 4];
     file.read_exact(&mut magic)?;
-    if &magic != MAGIC_NUMBER {
+    if magic != MAGIC_NUMBER {
         return Err(io::Error::new(io::ErrorKind::InvalidData, "无效的文件格式"));
     }
 
     // 读取metadata长度
-    println!("读取metadata长度");
-    let mut meta_len_bytes = [0u8; 4];
-    file.read_exact(&mut meta_len_bytes)?;
-    let meta_len = u32::from_le_bytes(meta_len_bytes) as usize;
-
-    let mut metadata_bytes = vec![0u8; meta_len];
-    file.read_exact(&mut metadata_bytes)?;
+    debug!("读取metadata长度");
+    let file_len = file.metadata()?.len();
+    let metadata_bytes = read_metadata(&mut file, file_len)?;
+    let meta_len = metadata_bytes.len();
     
-    // 读取并验证metadata结束标志
-    println!("读取并验证metadata结束标志");
-    let mut end_magic = [0u8; 8];
-    file.read_exact(&mut end_magic)?;
-    if end_magic != MAGIC_METADATA_END {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "无效的metadata结束标志"));
+    // 读取并验证metadata结束标志，1.3之前的版本没有该标志
+    debug!("读取并验证metadata结束标志");
+    let mut header_len = 8 + meta_len as u64; // magic(4) + len(4) + metadata
+    let version = probe_version(&metadata_bytes);
+    let path_length = PathLength::for_version(&version);
+    if has_metadata_end(&version) {
+        let mut end_magic = [0u8; 8];
+        file.read_exact(&mut end_magic)?;
+        if end_magic != MAGIC_METADATA_END {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "无效的metadata结束标志"));
+        }
+        header_len += 8;
     }
 
     let mut xpak_meta: XpakMetadata = if all {
         // 如果是全部重新生成，创建新的metadata
-        let data_offset = 8 + meta_len as u64 + 8; // magic(4) + len(4) + metadata + end_magic(8)
-        file.seek(SeekFrom::Start(data_offset))?;
-        
-        // 计算文件总大小
-        println!("计算文件总大小");
-        let mut total_size = 0u64;
+        file.seek(SeekFrom::Start(header_len))?;
+
+        // 删除标记在条目头部中与空文件相同，只能从原有的metadata中按序号取得
+        let previous = parse_metadata(&metadata_bytes).ok();
+        if previous.is_none() {
+            warn!("原有的metadata无法解析，重新生成后删除标记不会保留");
+        }
         let mut files = Vec::new();
         
         // 读取文件头部信息
-        println!("读取文件头部信息");
-        loop {
-            let mut name_len_bytes = [0u8; 4];
-            if file.read_exact(&mut name_len_bytes).is_err() {
-                break;
-            }
-            let name_len = u32::from_le_bytes(name_len_bytes) as usize;
-            
-            let mut name_bytes = vec![0u8; name_len];
-            file.read_exact(&mut name_bytes)?;
-            let name = String::from_utf8_lossy(&name_bytes).to_string();
-            
-            let mut size_bytes = [0u8; 8];
-            file.read_exact(&mut size_bytes)?;
-            let size = u64::from_le_bytes(size_bytes);
-            
-            total_size += size;
-            files.push(FileInfo::new(name, size));
-            
+        debug!("读取文件头部信息");
+        let mut count_bytes = [0u8; 4];
+        file.read_exact(&mut count_bytes)?;
+        for index in 0..u32::from_le_bytes(count_bytes) as usize {
+            let (name, size) = read_entry_header(&mut file, path_length)?;
+            let mut info = FileInfo::new(name, size);
+            info.removed = previous.as_ref()
+                .and_then(|m| m.files.get(index))
+                .is_some_and(|f| f.removed && f.path == info.path);
+            files.push(info);
+
             // 跳过文件内容
             file.seek(SeekFrom::Current(size as i64))?;
         }
-        
-        let mut new_meta = XpakMetadata::new(files.len() as u32, total_size);
+
+        // 被替换的条目和删除标记不计入总大小
+        debug!("计算文件总大小");
+        let mut new_meta = XpakMetadata::new(files.len() as u32, 0);
         new_meta.files = files;
+        new_meta.total_size = new_meta.live_files().iter().map(|f| f.size).sum();
         new_meta
     } else {
-        serde_json::from_slice(&metadata_bytes)?
+        parse_metadata(&metadata_bytes)?
     };
+    // 重写后的包头使用当前格式，条目头部按原样复制，紧凑布局的包保持紧凑布局
+    xpak_meta.format_version = match path_length {
+        PathLength::U32 => FORMAT_VERSION,
+        PathLength::Varint if xpak_meta.files_table.is_some() => SPLIT_FORMAT_VERSION,
+        PathLength::Varint => COMPACT_FORMAT_VERSION,
+    }.to_string();
 
     // 更新描述信息
-    println!("更新描述信息");
+    debug!("更新描述信息");
     if let Some(desc) = description {
         xpak_meta.description = Some(desc.to_string());
     }
+    if let Some(version) = content_version {
+        xpak_meta.content_version = Some(version.to_string());
+    }
 
     // 更新用户自定义metadata
-    println!("更新用户自定义metadata");
+    debug!("更新用户自定义metadata");
     if let Some(meta_str) = metadata {
         xpak_meta.merge_user_metadata(meta_str)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
     }
 
     // 将更新后的metadata写回文件
-    println!("将更新后的metadata写回文件");
-    let new_metadata = serde_json::to_vec(&xpak_meta)
+    debug!("将更新后的metadata写回文件");
+    let new_metadata = xpak_meta.to_bytes()
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("序列化metadata失败: {}", e)))?;
 
     // 创建临时文件
-    println!("创建临时文件");
-    let temp_path = format!("{}.tmp", input);
-    let mut temp_file = File::create(&temp_path)?;
+    debug!("创建临时文件");
+    let temp = TempFile::new(input)?;
+    let mut temp_file = File::create(temp.path())?;
 
     // 写入Magic Number
-    println!("写入Magic Number");
+    debug!("写入Magic Number");
     temp_file.write_all(MAGIC_NUMBER)?;
 
     // 写入新的metadata长度
-    println!("写入新的metadata长度");
+    debug!("写入新的metadata长度");
     temp_file.write_all(&(new_metadata.len() as u32).to_le_bytes())?;
 
     // 写入新的metadata
-    println!("写入新的metadata");
+    debug!("写入新的metadata");
     temp_file.write_all(&new_metadata)?;
     
     // 写入metadata结束标志
-    println!("写入metadata结束标志");
+    debug!("写入metadata结束标志");
     temp_file.write_all(&MAGIC_METADATA_END)?;
 
     // 复制剩余的文件数据（从数据区域开始）
-    println!("复制剩余的文件数据（从数据区域开始）");
-    file.seek(SeekFrom::Start(8 + meta_len as u64 + 8))?; // 跳过原始metadata部分和结束标志
+    debug!("复制剩余的文件数据（从数据区域开始）");
+    file.seek(SeekFrom::Start(header_len))?; // 跳过原始metadata部分和结束标志
     
     // 获取剩余需要复制的数据大小
     let remaining_size = file.metadata()?.len() - file.stream_position()?;
@@ -321,8 +769,9 @@ pub fn update_metadata(input: &str, description: Option<&str>, metadata: Option<
     drop(temp_file);
 
     // 替换原文件
-    println!("替换原文件");
-    std::fs::rename(temp_path, input)?;
+    debug!("替换原文件");
+    temp.commit(input)?;
+    info!(input, metadata_len = new_metadata.len(), "metadata已更新");
 
     Ok(())
 } 
\ No newline at end of file
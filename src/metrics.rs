@@ -0,0 +1,84 @@
+//! 打包过程的指标，供构建监控读取，不需要解析日志
+//!
+//! `pak --metrics-file`在打包完成后写出一个JSON文件，记录各阶段的耗时、文件数、
+//! 输入和输出的大小以及吞吐量。
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+/// 打包的各阶段，按执行顺序排列
+#[derive(Serialize, Debug, Default)]
+pub struct PackPhases {
+    /// 遍历输入并收集文件信息
+    pub walk_ms: f64,
+    /// 剥离调试信息，未启用时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_ms: Option<f64>,
+    /// 分帧压缩，未启用时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compress_ms: Option<f64>,
+    /// 计算摘要和识别文件类型
+    pub hash_ms: f64,
+    /// 写入metadata、条目和尾部区段
+    pub write_ms: f64,
+}
+
+/// 一次打包的指标
+#[derive(Serialize, Debug)]
+pub struct PackMetrics {
+    pub command: &'static str,
+    pub output: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: f64,
+    pub files: usize,
+    /// 输入文件的总大小
+    pub input_bytes: u64,
+    /// 写出的包的大小
+    pub archive_bytes: u64,
+    /// 按输入大小和总耗时计算
+    pub throughput_bytes_per_sec: f64,
+    pub phases: PackPhases,
+    #[serde(skip)]
+    timer: Instant,
+}
+
+/// 以毫秒为单位的耗时，与解包报告一致
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl PackMetrics {
+    pub fn new(output: &str) -> Self {
+        Self {
+            command: "pak",
+            output: output.to_string(),
+            started_at: Utc::now(),
+            duration_ms: 0.0,
+            files: 0,
+            input_bytes: 0,
+            archive_bytes: 0,
+            throughput_bytes_per_sec: 0.0,
+            phases: PackPhases::default(),
+            timer: Instant::now(),
+        }
+    }
+
+    /// 记录总耗时和大小，写出到`path`
+    pub fn finish(mut self, files: usize, input_bytes: u64, archive_bytes: u64, path: &str) -> io::Result<()> {
+        let elapsed = self.timer.elapsed();
+        self.duration_ms = millis(elapsed);
+        self.files = files;
+        self.input_bytes = input_bytes;
+        self.archive_bytes = archive_bytes;
+        if !elapsed.is_zero() {
+            self.throughput_bytes_per_sec = input_bytes as f64 / elapsed.as_secs_f64();
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use tracing::info;
+
+use crate::common::{BINARY_METADATA_TAG, COMPACT_FORMAT_VERSION, FORMAT_VERSION};
+use crate::metadata::{FileInfo, MetadataEncoding, XpakMetadata};
+pub use xpak::has_metadata_end;
+use xpak::parse_format_version;
+
+/// 只读取版本号，用于选择反序列化使用的结构
+#[derive(Deserialize)]
+struct VersionProbe {
+    #[serde(default)]
+    format_version: Option<String>,
+}
+
+/// 1.0 到 1.2 版本的metadata
+///
+/// 这些版本的统计字段和创建时间可能缺失，条目只记录路径和大小，
+/// 包头中也没有Metadata End标记。
+#[derive(Deserialize)]
+struct MetadataV1_0 {
+    #[serde(default)]
+    version: Option<String>,
+    format_version: String,
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    files_count: Option<u32>,
+    #[serde(default)]
+    total_size: Option<u64>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    common: HashMap<String, Value>,
+    #[serde(default)]
+    files: Vec<FileInfoV1_0>,
+}
+
+#[derive(Deserialize)]
+struct FileInfoV1_0 {
+    path: String,
+    size: u64,
+}
+
+impl From<MetadataV1_0> for XpakMetadata {
+    fn from(old: MetadataV1_0) -> Self {
+        let files: Vec<FileInfo> = old.files.into_iter()
+            .map(|f| FileInfo::new(f.path, f.size))
+            .collect();
+        Self {
+            version: old.version.unwrap_or_default(),
+            format_version: old.format_version,
+            created_at: old.created_at.unwrap_or(DateTime::<Utc>::UNIX_EPOCH),
+            files_count: old.files_count.unwrap_or(files.len() as u32),
+            total_size: old.total_size.unwrap_or_else(|| files.iter().map(|f| f.size).sum()),
+            description: old.description,
+            content_version: None,
+            common: old.common,
+            keys: HashMap::new(),
+            files,
+            lookup: None,
+            access_profile: None,
+            eula: None,
+            git: None,
+            dirs: None,
+            files_table: None,
+            tiers: Vec::new(),
+            skipped: Vec::new(),
+            bundles: BTreeMap::new(),
+            dependencies: Vec::new(),
+            encoding: MetadataEncoding::Json,
+        }
+    }
+}
+
+/// 从metadata内容中读取格式版本，无法读取时视为当前版本
+pub fn probe_version(bytes: &[u8]) -> String {
+    if bytes.first() == Some(&BINARY_METADATA_TAG) {
+        return xpak::probe_format_version(bytes).unwrap_or(FORMAT_VERSION).to_string();
+    }
+    serde_json::from_slice::<VersionProbe>(bytes)
+        .ok()
+        .and_then(|p| p.format_version)
+        .unwrap_or_else(|| FORMAT_VERSION.to_string())
+}
+
+/// 按格式版本解析metadata，旧版本转换为当前的结构
+///
+/// `format_version`保留包内记录的原始版本。
+pub fn parse_metadata(bytes: &[u8]) -> io::Result<XpakMetadata> {
+    let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, format!("无法解析metadata: {}", e));
+    let version = probe_version(bytes);
+    // 能读取的最新版本是紧凑布局
+    let current = parse_format_version(COMPACT_FORMAT_VERSION).unwrap();
+
+    match parse_format_version(&version) {
+        Some(v) if v.0 > current.0 => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("不支持的格式版本 {}，请升级xpak", version)
+        )),
+        Some(v) if v < (1, 3) => {
+            info!(format_version = version, "按旧版本格式读取metadata");
+            serde_json::from_slice::<MetadataV1_0>(bytes).map(XpakMetadata::from).map_err(invalid)
+        }
+        _ => {
+            let mut metadata: XpakMetadata = match bytes.split_first() {
+                Some((&BINARY_METADATA_TAG, bytes)) => {
+                    let mut metadata: XpakMetadata = rmp_serde::from_slice(bytes)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("无法解析metadata: {}", e)))?;
+                    metadata.encoding = MetadataEncoding::Msgpack;
+                    metadata
+                }
+                _ => serde_json::from_slice(bytes).map_err(invalid)?,
+            };
+            metadata.expand_paths()?;
+            Ok(metadata)
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::common::KB;
+
+/// 嗅探类型时读取的文件头部长度
+const SNIFF_LEN: u64 = 8 * KB as u64;
+
+/// 根据文件头部的魔数和扩展名判断文件的MIME类型
+pub fn sniff_file(path: &Path) -> io::Result<String> {
+    let mut head = Vec::with_capacity(SNIFF_LEN as usize);
+    File::open(path)?.take(SNIFF_LEN).read_to_end(&mut head)?;
+    Ok(sniff(path, &head))
+}
+
+/// 优先使用魔数，其次使用扩展名，都无法判断时区分文本和二进制
+pub fn sniff(path: &Path, head: &[u8]) -> String {
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_string();
+    }
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        return mime.essence_str().to_string();
+    }
+
+    // 头部可能在多字节字符中间截断，末尾不完整的字符不算错误
+    let is_text = !head.contains(&0) && match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if is_text { "text/plain" } else { "application/octet-stream" }.to_string()
+}
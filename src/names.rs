@@ -0,0 +1,224 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+
+use crate::reader::XpakReader;
+use crate::vpath::{lookup_key, PathPolicy};
+
+/// 单个路径组件的最大字节数（ext4、APFS和NTFS的上限都约为255）
+const MAX_COMPONENT_LEN: usize = 255;
+/// Windows不允许出现在文件名中的字符
+const WINDOWS_RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+/// Windows的设备名，带扩展名时同样不可用
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// 路径在目标平台上的问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameIssue {
+    /// 含有替换字符或Unicode非字符，原文件名的编码已丢失，无法在Windows上原样还原
+    Encoding,
+    /// 含有Windows保留的字符或控制字符
+    ReservedChar(char),
+    /// 使用了Windows的设备名
+    ReservedName(String),
+    /// 以点或空格结尾，Windows会去掉结尾的点和空格
+    TrailingDotOrSpace(String),
+    /// 路径组件超过255字节
+    TooLong { component: String, bytes: usize },
+    /// 在大小写不敏感的文件系统上与另一个条目冲突
+    CaseCollision(String),
+}
+
+impl NameIssue {
+    /// 受影响的平台
+    pub fn platforms(&self) -> &'static str {
+        match self {
+            NameIssue::Encoding | NameIssue::ReservedChar(_) | NameIssue::ReservedName(_) | NameIssue::TrailingDotOrSpace(_) => "Windows",
+            NameIssue::TooLong { .. } => "Windows/macOS/Linux",
+            NameIssue::CaseCollision(_) => "Windows/macOS",
+        }
+    }
+}
+
+impl fmt::Display for NameIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameIssue::Encoding => write!(f, "含有替换字符或Unicode非字符，原文件名的编码已丢失"),
+            NameIssue::ReservedChar(c) if c.is_control() => write!(f, "含有控制字符 U+{:04X}", *c as u32),
+            NameIssue::ReservedChar(c) => write!(f, "含有保留字符 '{}'", c),
+            NameIssue::ReservedName(name) => write!(f, "使用了保留名称 {}", name),
+            NameIssue::TrailingDotOrSpace(component) => write!(f, "'{}' 以点或空格结尾", component),
+            NameIssue::TooLong { component, bytes } => {
+                write!(f, "'{}' 长度为 {} 字节，超过 {} 字节", component, bytes, MAX_COMPONENT_LEN)
+            }
+            NameIssue::CaseCollision(other) => write!(f, "与 {} 只有大小写不同", other),
+        }
+    }
+}
+
+fn is_noncharacter(c: char) -> bool {
+    let c = c as u32;
+    (0xFDD0..=0xFDEF).contains(&c) || (c & 0xFFFE) == 0xFFFE
+}
+
+fn is_reserved_char(c: char) -> bool {
+    c.is_control() || WINDOWS_RESERVED_CHARS.contains(&c)
+}
+
+/// 去掉扩展名后是否为Windows设备名
+fn reserved_name(component: &str) -> Option<&'static str> {
+    let stem = component.split('.').next().unwrap_or_default().trim_end();
+    WINDOWS_RESERVED_NAMES.iter().copied().find(|name| name.eq_ignore_ascii_case(stem))
+}
+
+/// 检查单个路径，不包括条目之间的大小写冲突
+pub fn check_path(path: &str) -> Vec<NameIssue> {
+    let mut issues = Vec::new();
+    if path.chars().any(|c| c == char::REPLACEMENT_CHARACTER || is_noncharacter(c)) {
+        issues.push(NameIssue::Encoding);
+    }
+    let mut reserved_chars: Vec<char> = path.chars().filter(|&c| is_reserved_char(c)).collect();
+    reserved_chars.dedup();
+    issues.extend(reserved_chars.into_iter().map(NameIssue::ReservedChar));
+
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        if let Some(name) = reserved_name(component) {
+            issues.push(NameIssue::ReservedName(name.to_string()));
+        }
+        if component.ends_with(['.', ' ']) && component != "." && component != ".." {
+            issues.push(NameIssue::TrailingDotOrSpace(component.to_string()));
+        }
+        if component.len() > MAX_COMPONENT_LEN {
+            issues.push(NameIssue::TooLong { component: component.to_string(), bytes: component.len() });
+        }
+    }
+    issues
+}
+
+/// 在字符边界处截断到不超过`max`字节
+fn truncate_bytes(s: &str, max: usize) -> &str {
+    let mut end = max.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// 生成在所有平台上都可用的路径组件
+fn sanitize_component(component: &str) -> String {
+    let mut name: String = component.chars()
+        .map(|c| if is_reserved_char(c) || c == char::REPLACEMENT_CHARACTER || is_noncharacter(c) { '_' } else { c })
+        .collect();
+    let trimmed = name.trim_end_matches(['.', ' ']).len();
+    if trimmed < name.len() && name != "." && name != ".." {
+        name.truncate(trimmed);
+        name.push('_');
+    }
+    if let Some(reserved) = reserved_name(&name) {
+        name.insert(reserved.len(), '_');
+    }
+    if name.len() > MAX_COMPONENT_LEN {
+        // 尽量保留扩展名
+        let ext = name.rfind('.')
+            .map(|i| name[i..].to_string())
+            .filter(|ext| ext.len() < MAX_COMPONENT_LEN / 2)
+            .unwrap_or_default();
+        let stem = truncate_bytes(&name[..name.len() - ext.len()], MAX_COMPONENT_LEN - ext.len());
+        name = format!("{}{}", stem, ext);
+    }
+    name
+}
+
+/// 把控制字符转义后再输出到终端
+pub fn printable(s: &str) -> String {
+    s.chars().map(|c| if c.is_control() { c.escape_unicode().to_string() } else { c.to_string() }).collect()
+}
+
+fn sanitize_path(path: &str) -> String {
+    path.split('/').map(sanitize_component).collect::<Vec<_>>().join("/")
+}
+
+/// 在`name (N).ext`中找一个大小写折叠后未被使用的名称
+fn unique_name(path: &str, used: &HashSet<String>) -> String {
+    if !used.contains(&lookup_key(path, PathPolicy::IgnoreCase)) {
+        return path.to_string();
+    }
+    let (dir, file) = path.rsplit_once('/').map_or(("", path), |(d, f)| (d, f));
+    let (stem, ext) = match file.rfind('.') {
+        Some(i) if i > 0 => (&file[..i], &file[i..]),
+        _ => (file, ""),
+    };
+    (1..)
+        .map(|n| {
+            let file = format!("{} ({}){}", stem, n, ext);
+            if dir.is_empty() { file } else { format!("{}/{}", dir, file) }
+        })
+        .find(|p| !used.contains(&lookup_key(p, PathPolicy::IgnoreCase)))
+        .unwrap()
+}
+
+/// 检查包内的路径，返回是否所有路径都没有问题
+///
+/// 指定`plan`时把需要改名的条目写成JSON（原路径 -> 新路径），新路径在所有平台上都可用，
+/// 且互相之间不会因大小写冲突。
+pub fn check_names(input: &str, plan: Option<&str>) -> io::Result<bool> {
+    let reader = XpakReader::open(input)?;
+    let paths: Vec<&str> = reader.metadata.live_files().into_iter().map(|f| f.path.as_str()).collect();
+
+    let mut issues: Vec<Vec<NameIssue>> = paths.iter().map(|p| check_path(p)).collect();
+    let mut first_seen: BTreeMap<String, &str> = BTreeMap::new();
+    for (i, path) in paths.iter().enumerate() {
+        match first_seen.get(&lookup_key(path, PathPolicy::IgnoreCase)) {
+            Some(other) => issues[i].push(NameIssue::CaseCollision(other.to_string())),
+            None => {
+                first_seen.insert(lookup_key(path, PathPolicy::IgnoreCase), path);
+            }
+        }
+    }
+
+    println!("检查 {} 个条目的路径:", paths.len());
+    println!("----------------------------------------");
+    let mut problems = 0;
+    for (path, issues) in paths.iter().zip(&issues) {
+        if issues.is_empty() {
+            continue;
+        }
+        problems += 1;
+        println!("{}", printable(path));
+        for issue in issues {
+            println!("  [{}] {}", issue.platforms(), printable(&issue.to_string()));
+        }
+    }
+    if problems == 0 {
+        println!("所有路径在 Windows、macOS 和 Linux 上都可用");
+    } else {
+        println!("----------------------------------------");
+        println!("{} 个路径在部分平台上解压时会出错", problems);
+    }
+
+    if let Some(plan_path) = plan {
+        // 先占用不需要改名的路径，改名后的路径不能与它们冲突
+        let mut used: HashSet<String> = paths.iter().zip(&issues)
+            .filter(|(_, issues)| issues.is_empty())
+            .map(|(path, _)| lookup_key(path, PathPolicy::IgnoreCase))
+            .collect();
+        let mut renames: BTreeMap<&str, String> = BTreeMap::new();
+        for (path, issues) in paths.iter().zip(&issues) {
+            if issues.is_empty() {
+                continue;
+            }
+            let renamed = unique_name(&sanitize_path(path), &used);
+            used.insert(lookup_key(&renamed, PathPolicy::IgnoreCase));
+            renames.insert(path, renamed);
+        }
+        let json = serde_json::to_string_pretty(&renames).map_err(io::Error::other)?;
+        fs::write(plan_path, json + "\n")?;
+        println!("重命名计划已写入 {} ({} 个条目)", plan_path, renames.len());
+    }
+    Ok(problems == 0)
+}
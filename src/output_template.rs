@@ -0,0 +1,121 @@
+//! 解包时按模板生成输出路径，如`{dir}/{stem}_{hash8}{ext}`
+//!
+//! 模板中的变量取自条目的路径（应用`--strip-components`和`--transform`之后）和metadata，
+//! `{{`和`}}`表示字面的花括号。展开后的路径按`/`分隔，空的目录层级被去掉。
+
+use std::io;
+
+use crate::metadata::FileInfo;
+use crate::reader::EntryHeader;
+use crate::vpath::entry_id;
+
+/// 模板中可用的变量
+const VARIABLES: &[&str] = &["path", "dir", "name", "stem", "ext", "hash", "hash8", "size", "index", "id", "mime"];
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Var(&'static str),
+}
+
+/// 解析后的输出路径模板
+#[derive(Debug, Clone)]
+pub struct OutputTemplate {
+    parts: Vec<Part>,
+}
+
+/// 解析`--output-template`，未知的变量和不成对的花括号报错
+pub fn parse_output_template(value: &str) -> Result<OutputTemplate, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("模板中有不成对的 '{'，字面的花括号请写作 '{{'".to_string()),
+                    }
+                }
+                let var = VARIABLES.iter().find(|v| **v == name.trim()).ok_or_else(|| format!(
+                    "模板中的变量 {{{}}} 无效，可用的变量: {}",
+                    name,
+                    VARIABLES.iter().map(|v| format!("{{{}}}", v)).collect::<Vec<_>>().join(" ")
+                ))?;
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(Part::Var(var));
+            }
+            '}' => return Err("模板中有不成对的 '}'，字面的花括号请写作 '}}'".to_string()),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    if !parts.iter().any(|p| matches!(p, Part::Var(_))) {
+        return Err("模板中没有变量，所有条目会写到同一个文件".to_string());
+    }
+    Ok(OutputTemplate { parts })
+}
+
+impl OutputTemplate {
+    /// 展开条目的输出路径，`path`为应用`--strip-components`和`--transform`之后的路径
+    ///
+    /// `{index}`从1开始，与`list`的输出一致；`{id}`按条目在包内的原始路径计算。
+    /// 条目没有记录模板用到的摘要或MIME类型时报错；展开结果为空或含有`.`、`..`时同样
+    /// 报错，避免写到输出目录之外。
+    pub fn render(&self, entry: &EntryHeader, path: &str, info: Option<&FileInfo>) -> io::Result<String> {
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let (stem, ext) = match name.rfind('.') {
+            Some(i) if i > 0 => (&name[..i], &name[i..]),
+            _ => (name, ""),
+        };
+        let missing = |var: &str| io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("条目 {} 没有记录{}，无法展开模板中的 {{{}}}", entry.path, if var == "mime" { "MIME类型" } else { "摘要" }, var)
+        );
+
+        let mut rendered = String::new();
+        for part in &self.parts {
+            let value = match part {
+                Part::Text(text) => text.clone(),
+                Part::Var("path") => path.to_string(),
+                Part::Var("dir") => dir.to_string(),
+                Part::Var("name") => name.to_string(),
+                Part::Var("stem") => stem.to_string(),
+                Part::Var("ext") => ext.to_string(),
+                Part::Var(var @ ("hash" | "hash8")) => {
+                    let hash = info.and_then(|f| f.hash.as_deref()).ok_or_else(|| missing(var))?;
+                    if *var == "hash8" { hash.chars().take(8).collect() } else { hash.to_string() }
+                }
+                Part::Var("size") => info.map_or(entry.size, |f| f.size).to_string(),
+                Part::Var("index") => (entry.index + 1).to_string(),
+                Part::Var("id") => format!("{:016x}", entry_id(&entry.path)),
+                Part::Var(var) => info.and_then(|f| f.mime.clone()).ok_or_else(|| missing(var))?,
+            };
+            rendered.push_str(&value);
+        }
+
+        let components: Vec<&str> = rendered.split('/').filter(|c| !c.is_empty()).collect();
+        if components.is_empty() || components.iter().any(|c| *c == "." || *c == "..") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("条目 {} 按模板展开的输出路径 '{}' 无效", entry.path, rendered)
+            ));
+        }
+        Ok(components.join("/"))
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use tracing::warn;
+
+use crate::metadata::FileInfo;
+use crate::reader::XpakReader;
+use crate::vpath::{lookup_key, PathPolicy};
+
+/// 某一层包内的条目
+#[derive(Debug, Clone)]
+pub struct OverlayEntry {
+    /// 所在层的序号，0为最底层
+    pub layer: usize,
+    /// 条目在该层包内的位置
+    pub index: u32,
+    pub info: FileInfo,
+}
+
+/// 按顺序叠加多个包，同一路径以最后一层为准
+///
+/// 与运行时加载补丁包的方式一致：后面的包覆盖前面包中的同名文件。
+pub struct OverlayReader {
+    layers: Vec<String>,
+    policy: PathPolicy,
+    /// 查找键 -> 包含该路径的各层条目，按层的顺序排列
+    entries: HashMap<String, Vec<OverlayEntry>>,
+}
+
+impl OverlayReader {
+    pub fn open(layers: &[String], policy: PathPolicy) -> io::Result<Self> {
+        let mut entries: HashMap<String, Vec<OverlayEntry>> = HashMap::new();
+        for (layer, path) in layers.iter().enumerate() {
+            let reader = XpakReader::open(path)?;
+            let live = reader.metadata.live_entries();
+            // 打包时生成了查找表的包保证没有只差大小写的路径，无需再检查
+            let check_case = policy == PathPolicy::IgnoreCase && reader.metadata.lookup.is_none();
+            let mut seen = HashSet::new();
+            for (index, info) in reader.metadata.files.into_iter().enumerate().filter(|(i, _)| live[*i]) {
+                let key = lookup_key(&info.path, policy);
+                if check_case && !seen.insert(key.clone()) {
+                    warn!(layer = path, path = info.path, "包内有只差大小写的路径，以后出现的为准");
+                }
+                entries.entry(key).or_default().push(OverlayEntry {
+                    layer,
+                    index: index as u32,
+                    info,
+                });
+            }
+        }
+        Ok(Self { layers: layers.to_vec(), policy, entries })
+    }
+
+    pub fn layer_name(&self, layer: usize) -> &str {
+        &self.layers[layer]
+    }
+
+    /// 查找路径最终生效的条目
+    pub fn resolve(&self, path: &str) -> Option<&OverlayEntry> {
+        self.versions(path).last()
+    }
+
+    /// 包含该路径的所有条目，最后一个为生效的条目
+    pub fn versions(&self, path: &str) -> &[OverlayEntry] {
+        self.entries.get(&lookup_key(path, self.policy)).map_or(&[], Vec::as_slice)
+    }
+
+    /// 条目被更上层的包覆盖时返回覆盖它的层
+    pub fn shadowed_by(&self, layer: usize, path: &str) -> Option<usize> {
+        self.resolve(path).map(|e| e.layer).filter(|&top| top != layer)
+    }
+}
+
+/// 输出路径在叠加后的来源
+pub fn resolve(layers: &[String], path: &str, policy: PathPolicy) -> io::Result<()> {
+    let overlay = OverlayReader::open(layers, policy)?;
+    let versions = overlay.versions(path);
+    let Some(entry) = versions.last() else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("所有层中都没有 {}", path)));
+    };
+
+    println!("{} -> {}", path, describe(&overlay, entry));
+    for shadowed in versions[..versions.len() - 1].iter().rev() {
+        println!("  覆盖了 {}", describe(&overlay, shadowed));
+    }
+    Ok(())
+}
+
+fn describe(overlay: &OverlayReader, entry: &OverlayEntry) -> String {
+    format!(
+        "{}: {} (条目 #{}, {} 字节)",
+        overlay.layer_name(entry.layer), entry.info.path, entry.index + 1, entry.info.size
+    )
+}
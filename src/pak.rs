@@ -2,52 +2,382 @@ use base64::{Engine as _, engine::general_purpose::STANDARD};
 use std::io::{self, Read, Write, BufReader, BufWriter};
 use std::sync::atomic::{AtomicBool, Ordering};
 use indicatif::{ProgressBar, ProgressStyle};
-use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use rayon::prelude::*;
+use std::path::{Component, Path, PathBuf};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use walkdir::WalkDir;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::fs::File;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 
-use crate::common::{BUFFER_SIZE, FORMAT_VERSION, MAGIC_METADATA_END, MAGIC_NUMBER};
-use crate::metadata::{XpakMetadata, FileInfo};
+use crate::common::{format_size, ARCHIVE_EXTENSION, BUFFER_SIZE, COMPACT_FORMAT_VERSION, FORMAT_VERSION, MAX_PATH_LEN, MAGIC_METADATA_END, MAGIC_NUMBER, MAX_ENTRY_SIZE, SPLIT_FORMAT_VERSION};
+use crate::metadata::{XpakMetadata, FileInfo, DebugInfo, HookInfo, MetadataEncoding, SkippedFile, METADATA_SLACK};
+use crate::hash::{buffer_size, hash_reader, HashAlgo};
+use crate::memory::MemoryBudget;
+use crate::fdpool::{PooledFile, SourcePool};
+use crate::trace::{apply_order, AccessTrace};
+use crate::throttle::{Throttle, Throttled};
+use crate::logging::Heartbeat;
+use crate::metrics::{millis, PackMetrics};
+use crate::progress::WorkerProgress;
+use crate::mime::sniff_file;
+use crate::hooks::run_pre_pack_hook;
+use crate::error;
+use crate::deps::Dependency;
+use crate::validity::ValidityWindow;
+use crate::file_meta::FileMeta;
+use crate::sidecar::{self, Sidecar};
+#[cfg(feature = "strip-debug")]
+use crate::strip;
+use crate::progressive::{priority_order, tiers};
+use crate::template::Template;
+use crate::git;
+use crate::vpath::{build_lookup_index, check_entry_ids};
+use crate::frames::{create_archive, entry_ranges, finish_data_section, trailer_size, FrameWriter, FRAME_SIZE};
+use crate::compress::{compress_frames, is_incompressible, Codec, CompressMode, EntryCompression, DEFAULT_FRAME_MB};
+use crate::config::Config;
+use crate::device::{is_block_device, preallocate};
+use crate::crypto::{encrypt_stream, encrypted_size, generate_nonce, EntryEncryption, EntryKey, RECIPIENT_KEY_ID};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use clap::ValueEnum;
+use tempfile::TempDir;
+use tracing::{debug, info, warn};
+
+/// 打包选项
+#[derive(Default)]
+pub struct PackOptions {
+    /// 是否扁平化打包（不保留目录结构）
+    pub flat: bool,
+    pub description: Option<String>,
+    /// 包内容的版本（semver）
+    pub content_version: Option<String>,
+    /// 用户元数据（JSON或Base64编码的JSON）
+    pub metadata: Option<String>,
+    pub hash_algo: HashAlgo,
+    /// 依赖的其他包
+    pub dependencies: Vec<Dependency>,
+    /// 加密规则 (glob, key_id)，按顺序匹配第一条
+    pub encrypt: Vec<(String, String)>,
+    /// key_id -> 口令
+    pub keys: HashMap<String, String>,
+    /// age接收者公钥，未被其他规则加密的条目都加密给这些接收者
+    pub encrypt_to: Vec<String>,
+    /// 所有条目存放在该虚拟目录下
+    pub prefix: Option<String>,
+    /// 按顺序应用到存储路径的正则替换规则
+    pub rename_rules: Vec<(Regex, String)>,
+    /// 输入路径在包内的存放位置 (src, dst)
+    pub maps: Vec<(String, String)>,
+    /// 跟随符号链接
+    pub follow_symlinks: bool,
+    /// 不跨越文件系统（挂载点）
+    pub same_filesystem: bool,
+    /// 目录遍历的最大、最小深度，输入目录下的文件深度为1
+    pub max_depth: Option<usize>,
+    pub min_depth: Option<usize>,
+    /// 存储路径冲突时的处理方式
+    pub on_duplicate: DuplicatePolicy,
+    /// 文件无法读取时的处理方式
+    pub on_read_error: ReadErrorPolicy,
+    /// Reed-Solomon 校验块占数据的百分比
+    pub parity: Option<u32>,
+    /// 打包前钩子 (glob, command)，按顺序匹配第一条
+    pub hooks: Vec<(String, String)>,
+    /// 在metadata中记录忽略大小写的查找表
+    pub lookup_index: bool,
+    /// 读写速度上限（MB/s）
+    pub throttle: Option<f64>,
+    /// 并行计算摘要时在途缓冲区的上限（MB）
+    pub memory_limit: Option<u64>,
+    /// 同时打开的源文件数上限
+    pub max_open_files: Option<u32>,
+    /// 打开或读取源文件遇到暂时性错误时的重试次数
+    pub io_retries: u32,
+    /// 按其中的访问顺序排列条目的访问记录文件
+    pub access_log: Option<String>,
+    /// 有效期规则 (glob, 有效期)，按顺序匹配第一条
+    pub validity: Vec<(String, ValidityWindow)>,
+    /// 条目属性规则 (glob, 属性)，匹配的规则都会应用
+    pub file_meta: Vec<(String, FileMeta)>,
+    /// 优先级规则 (glob, 优先级)，按顺序匹配第一条，条目按优先级从高到低排列
+    pub priorities: Vec<(String, i32)>,
+    /// 分组规则 (glob, 分组名)，条目属于所有匹配规则的分组
+    pub bundles: Vec<(String, String)>,
+    /// 在metadata中记录各优先级段的位置，下载未完成的包也可以解包完整的部分
+    pub progressive: bool,
+    /// 许可协议文本文件，解包前需要接受
+    pub eula: Option<String>,
+    /// 分帧压缩的方式
+    pub compress: Option<CompressMode>,
+    /// 压缩帧的大小（MB），未指定时使用默认值
+    pub frame_size_mb: Option<u32>,
+    /// 打包输入目录在该git版本时的文件树，而不是工作区中的文件
+    pub git_rev: Option<String>,
+    /// 描述和metadata中可用的模板变量 (key, value)
+    pub vars: Vec<(String, String)>,
+    /// 使用紧凑布局（格式2.0）：路径长度为变长整数，metadata中每个目录只记录一次
+    pub compact: bool,
+    /// 把条目列表放在尾部区段（格式2.1），包头只保留其余字段，隐含`compact`
+    pub split_metadata: bool,
+    /// metadata的编码
+    pub metadata_encoding: MetadataEncoding,
+    /// 允许覆盖不是xpak包的已有文件或块设备
+    pub force: bool,
+    /// 写入前按计算出的包大小预分配磁盘空间
+    pub preallocate: bool,
+    /// 从该文件（`-`为标准输入）读取要打包的文件列表，代替遍历输入目录
+    pub files_from: Option<String>,
+    /// 附属文件的扩展名，如`.meta.json`，文件的属性从与它相邻的附属文件中读取
+    pub sidecar_ext: Option<String>,
+    /// 附属文件本身也打包
+    pub keep_sidecars: bool,
+    /// 剥离ELF可执行文件中的调试信息（需要`strip-debug`特性）
+    pub strip_debug: bool,
+    /// 把剥离出的调试信息打包到该文件
+    pub debug_archive: Option<String>,
+    /// 打包完成后把各阶段耗时等指标写入该JSON文件
+    pub metrics_file: Option<String>,
+}
+
+/// 存储路径冲突时的处理方式
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// 报错并停止打包
+    #[default]
+    Error,
+    /// 只保留排序后的第一个文件（存储路径相同的文件按原路径排序）
+    Skip,
+    /// 为后出现的文件添加序号后缀，原始路径记录在metadata中
+    Rename,
+    /// 以相同路径保存所有文件，解包时后面的文件覆盖前面的
+    KeepBoth,
+}
+
+/// 文件在收集之后消失或没有读取权限时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadErrorPolicy {
+    /// 报错并停止打包
+    #[default]
+    Error,
+    /// 不打包该文件，记录在metadata的`skipped`中
+    Skip,
+    /// 间隔一段时间重试打开文件，最多重试N次，仍然失败时报错
+    Retry(u32),
+}
+
+/// 未指定次数时的重试次数
+const DEFAULT_READ_RETRIES: u32 = 3;
+/// 第一次重试前的等待时间，之后每次加倍
+const READ_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// 解析`error`、`skip`、`retry`或`retry:N`
+pub fn parse_read_error_policy(value: &str) -> Result<ReadErrorPolicy, String> {
+    let invalid = || format!("无效的处理方式 '{}'，应为 error、skip、retry 或 retry:N", value);
+    match value.trim().to_ascii_lowercase().as_str() {
+        "error" => Ok(ReadErrorPolicy::Error),
+        "skip" => Ok(ReadErrorPolicy::Skip),
+        "retry" => Ok(ReadErrorPolicy::Retry(DEFAULT_READ_RETRIES)),
+        other => other.strip_prefix("retry:")
+            .and_then(|n| n.trim().parse::<u32>().ok())
+            .filter(|&n| n > 0)
+            .map(ReadErrorPolicy::Retry)
+            .ok_or_else(invalid),
+    }
+}
+
+/// 从描述符池中打开待打包的文件，`Retry`时失败后等待并重试
+fn open_source<'a>(pool: &'a SourcePool, path: &Path, policy: ReadErrorPolicy) -> io::Result<PooledFile<'a>> {
+    let retries = match policy {
+        ReadErrorPolicy::Retry(n) => n,
+        _ => 0,
+    };
+    let mut delay = READ_RETRY_DELAY;
+    for attempt in 1..=retries {
+        match pool.open(path) {
+            Ok(file) => return Ok(file),
+            Err(e) => {
+                warn!(path = %path.display(), attempt, "无法打开文件，{}ms 后重试: {}", delay.as_millis(), e);
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+    pool.open(path).map_err(|e| error::with_path(e, path))
+}
+
+/// 无法读取文件时按处理方式报错，`Skip`时记录路径和原因后继续
+fn skip_unreadable(path: &Path, e: io::Error, policy: ReadErrorPolicy, skipped: &mut Vec<SkippedFile>) -> io::Result<()> {
+    if policy != ReadErrorPolicy::Skip {
+        return Err(error::with_path(e, path));
+    }
+    warn!(path = %path.display(), "无法读取文件，跳过: {}", e);
+    skipped.push(SkippedFile { path: portable_path(path), error: e.to_string() });
+    Ok(())
+}
+
+/// 在读取内容之前检查每个文件能否打开，`Skip`时把无法打开的文件从待打包的文件中去掉
+///
+/// 之后的阶段仍可能遇到刚被删除的文件，此时按`Retry`重试，不再跳过。
+fn check_readable(
+    pool: &SourcePool,
+    files: &mut Vec<SourceFile>,
+    stored_paths: &mut Vec<PathBuf>,
+    policy: ReadErrorPolicy,
+    skipped: &mut Vec<SkippedFile>
+) -> io::Result<()> {
+    let mut kept = Vec::with_capacity(files.len());
+    for (index, (file, stored_path)) in files.iter().zip(stored_paths.iter()).enumerate() {
+        match open_source(pool, &file.path, policy) {
+            Ok(_) => kept.push(index),
+            Err(e) => skip_unreadable(stored_path, e, policy, skipped)?,
+        }
+    }
+    if kept.len() < files.len() {
+        apply_order(files, &kept);
+        apply_order(stored_paths, &kept);
+    }
+    Ok(())
+}
+
+/// 待打包的源文件
+pub struct SourceFile {
+    /// 磁盘上的路径
+    pub path: PathBuf,
+    /// 包内的相对路径（应用替换规则之前）
+    pub relative: PathBuf,
+    pub size: u64,
+    /// 源文件的修改时间
+    pub mtime: Option<DateTime<Utc>>,
+    /// 经过打包前钩子转换时的来源信息，此时`path`指向暂存的转换结果
+    pub hook: Option<HookInfo>,
+    /// 从附属文件读取的条目属性
+    pub sidecar: Option<Sidecar>,
+    /// 可执行文件的调试信息，剥离调试信息后`path`指向暂存的剥离结果
+    pub debug: Option<DebugInfo>,
+}
 
 pub fn pack_files(
-    input: &str, 
+    inputs: &[String],
     output: &str, 
-    flat: bool, 
-    description: Option<&str>,
-    metadata: Option<&str>,
+    options: &PackOptions,
     running: Arc<AtomicBool>
 ) -> io::Result<()> {
-    let input_path = Path::new(input);
-    
-    if !input_path.exists() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("Input path '{}' does not exist", input)
-        ));
+    let description = options.description.as_deref();
+    let metadata = options.metadata.as_deref();
+    let hash_algo = options.hash_algo;
+    check_output(Path::new(output), options.force)?;
+    if let Some(debug_archive) = options.debug_archive.as_deref() {
+        check_output(Path::new(debug_archive), options.force)?;
+    }
+    #[cfg(not(feature = "strip-debug"))]
+    if options.strip_debug {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "剥离调试信息需要在编译时启用 strip-debug 特性"));
     }
 
-    // 收集文件信息
-    let files: Vec<_> = WalkDir::new(input)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
+    let mut metrics = PackMetrics::new(output);
+    let phase = Instant::now();
+
+    // 从git版本打包时先把该版本的文件树导出到临时目录，再按普通目录打包
+    let (checkout, git_source) = match options.git_rev.as_deref() {
+        Some(rev) => {
+            let [input] = inputs else {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "--git-rev 只能指定一个输入目录"));
+            };
+            let (checkout, source) = git::export_tree(Path::new(input), rev)?;
+            (Some(checkout), Some(source))
+        }
+        None => (None, None),
+    };
+    let checkout_input = checkout.as_ref().map(|dir| vec![dir.path().to_string_lossy().to_string()]);
+    let inputs = checkout_input.as_deref().unwrap_or(inputs);
+
+    // 收集文件信息，输出文件位于输入目录中时不打包已有的旧包
+    let mut skipped = Vec::new();
+    let mut files = collect_sources(inputs, options, &mut skipped)?;
+    if let Ok(existing) = Path::new(output).canonicalize() {
+        files.retain(|f| f.path.canonicalize().map_or(true, |p| p != existing));
+    }
+    if git_source.is_some() {
+        // 导出的文件树没有有意义的修改时间
+        files.iter_mut().for_each(|f| f.mtime = None);
+    }
+    metrics.phases.walk_ms = millis(phase.elapsed());
+
+    // 存储路径
+    let mut stored_paths: Vec<PathBuf> = files.iter()
+        .map(|f| rewrite_stored_path(&f.relative, options))
+        .collect();
+
+    // 收集之后消失或没有读取权限的文件
+    let pool = SourcePool::new(options.max_open_files.map(|n| n as usize), options.io_retries);
+    check_readable(&pool, &mut files, &mut stored_paths, options.on_read_error, &mut skipped)?;
+
+    // 运行打包前钩子，转换结果所在的临时目录在打包结束后删除
+    let _staging = apply_pre_pack_hooks(&mut files, &mut stored_paths, &options.hooks)?;
+
+    // 剥离可执行文件中的调试信息，剥离结果和分离出的调试信息在打包结束后删除
+    #[cfg(feature = "strip-debug")]
+    let stripped = if options.strip_debug {
+        let phase = Instant::now();
+        let stripped = strip::strip_sources(&mut files, &stored_paths, options.debug_archive.is_some())?;
+        metrics.phases.strip_ms = Some(millis(phase.elapsed()));
+        Some(stripped)
+    } else {
+        None
+    };
+
+    // 处理路径冲突
+    let mut original_paths = resolve_duplicates(&mut files, &mut stored_paths, options.on_duplicate)?;
+
+    // 按运行时的访问顺序排列条目，提高加载时的读取局部性
+    let access_trace = options.access_log.as_deref()
+        .map(|path| AccessTrace::load(path).map_err(|e| error::with_path(e, path)))
+        .transpose()?;
+    if let Some(trace) = &access_trace {
+        let keys: Vec<String> = stored_paths.iter().map(|p| p.to_string_lossy().replace('\\', "/")).collect();
+        let order = trace.order(&keys);
+        apply_order(&mut files, &order);
+        apply_order(&mut stored_paths, &order);
+        apply_order(&mut original_paths, &order);
+        info!(profile = trace.id, "按访问记录排列条目");
+    }
+
+    // 按优先级排列条目，同一优先级内保持访问记录的顺序
+    let priority_rules = build_glob_rules(&options.priorities)?;
+    let mut priorities: Vec<Option<i32>> = files.iter().zip(&stored_paths)
+        .map(|(f, p)| f.sidecar.as_ref().and_then(Sidecar::priority)
+            .or_else(|| priority_rules.matches(p).first().map(|&rule| options.priorities[rule].1)))
         .collect();
+    if priorities.iter().any(Option::is_some) {
+        let order = priority_order(&priorities.iter().map(|p| p.unwrap_or(0)).collect::<Vec<_>>());
+        apply_order(&mut files, &order);
+        apply_order(&mut stored_paths, &order);
+        apply_order(&mut original_paths, &order);
+        apply_order(&mut priorities, &order);
+    }
     
     // 计算总大小
-    let total_size: u64 = files.iter()
-        .filter_map(|entry| entry.metadata().ok())
-        .map(|meta| meta.len())
-        .sum();
+    let total_size: u64 = files.iter().map(|f| f.size).sum();
+    info!(output, files = files.len(), total_size, "开始打包");
 
     // 创建基础metadata
     let mut xpak_meta = XpakMetadata::new(files.len() as u32, total_size);
 
+    // 展开描述和metadata中的模板变量
+    let mut vars = Vec::new();
+    if let Some(source) = &git_source {
+        // 模板中的git信息以打包的版本为准
+        vars.push(("git_sha".to_string(), source.commit.clone()));
+        vars.push(("git_short_sha".to_string(), source.commit.chars().take(7).collect()));
+    }
+    vars.extend(options.vars.iter().cloned());
+    let template = Template::new(&vars, files.len(), total_size);
+
     // 如果有提供的描述，设置描述
     if let Some(desc) = description {
-        xpak_meta.description = Some(desc.to_string());
+        xpak_meta.description = Some(template.expand(desc)?);
     }
 
     // 如果有提供的metadata，验证并合并它
@@ -66,8 +396,7 @@ pub fn pack_files(
                 },
                 Err(e) => {
                     // 如果不是有效的 base64，就使用原始字符串
-                    println!("Invalid base64 metadata: {}", e);
-                    println!("Using original metadata: {}", meta);
+                    warn!("metadata不是有效的Base64 ({})，按原始字符串处理", e);
                     meta.to_string()
                 }
             }
@@ -82,10 +411,137 @@ pub fn pack_files(
                 format!("Metadata error: {}", e)
             ));
         }
+        for value in xpak_meta.common.values_mut() {
+            template.expand_value(value)?;
+        }
+    }
+
+    // 许可协议全文，在写入前读取以便尽早报告错误
+    let eula = options.eula.as_deref()
+        .map(|path| std::fs::read_to_string(path).map_err(|e| error::with_path(e, path)))
+        .transpose()?;
+
+    // 有效期规则在写入metadata时匹配
+    let validity_rules = build_glob_rules(&options.validity)?;
+    let file_meta_rules = build_glob_rules(&options.file_meta)?;
+
+    // 按条目在包内的顺序记录各分组的路径
+    let bundle_rules = build_glob_rules(&options.bundles)?;
+    let mut bundles: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for path in &stored_paths {
+        for rule in bundle_rules.matches(path) {
+            bundles.entry(options.bundles[rule].1.clone()).or_default().push(portable_path(path));
+        }
+    }
+    bundles.values_mut().for_each(Vec::dedup);
+
+    // 按规则确定需要加密的条目，并为用到的密钥派生参数
+    let encrypt_rules = build_glob_rules(&options.encrypt)?;
+    let mut entry_keys: HashMap<String, EntryKey> = HashMap::new();
+    let mut key_infos = HashMap::new();
+    let mut encryptions = Vec::with_capacity(files.len());
+    let recipient_key_id = RECIPIENT_KEY_ID.to_string();
+    if !options.encrypt_to.is_empty() {
+        let (key, info) = EntryKey::generate_for_recipients(&options.encrypt_to)?;
+        entry_keys.insert(recipient_key_id.clone(), key);
+        key_infos.insert(recipient_key_id.clone(), info);
+    }
+    for stored_path in &stored_paths {
+        let key_id = encrypt_rules.matches(stored_path).first().map(|&i| &options.encrypt[i].1)
+            .or_else(|| (!options.encrypt_to.is_empty()).then_some(&recipient_key_id));
+        let encryption = match key_id {
+            Some(key_id) => {
+                if !entry_keys.contains_key(key_id) {
+                    let passphrase = options.keys.get(key_id).ok_or_else(|| io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("未提供密钥 {}", key_id)
+                    ))?;
+                    let (key, info) = EntryKey::generate(passphrase)?;
+                    entry_keys.insert(key_id.clone(), key);
+                    key_infos.insert(key_id.clone(), info);
+                }
+                Some(EntryEncryption { key_id: key_id.clone(), nonce: generate_nonce() })
+            }
+            None => None,
+        };
+        encryptions.push(encryption);
+    }
+
+    // 读和写分别限速，并行计算摘要的线程共享读取的额度
+    let read_throttle = options.throttle.map(Throttle::new);
+    let write_throttle = options.throttle.map(Throttle::new);
+
+    // 分帧压缩，压缩结果暂存在临时目录中，打包结束后删除
+    let (compressions, _compressed) = match options.compress {
+        Some(mode) => {
+            let frame_mb = options.frame_size_mb.unwrap_or(DEFAULT_FRAME_MB);
+            let phase = Instant::now();
+            let (compressions, staging) = compress_sources(&files, mode, frame_mb, (&pool, options.on_read_error), read_throttle.as_ref(), &running)?;
+            metrics.phases.compress_ms = Some(millis(phase.elapsed()));
+            (compressions, Some(staging))
+        }
+        None => (files.iter().map(|_| None).collect(), None),
+    };
+
+    // 条目大小字段只有32位，超出时直接拒绝，避免写入被截断的大小
+    for ((entry, encryption), compression) in files.iter().zip(&encryptions).zip(&compressions) {
+        let size = compression.as_ref().map_or(entry.size, |c| c.compression.compressed_size());
+        let stored_size = if encryption.is_some() { encrypted_size(size) } else { size };
+        entry_size_field(&entry.path, stored_size)?;
+    }
+    if let Some(path) = stored_paths.iter().find(|p| p.as_os_str().len() > MAX_PATH_LEN) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("存储路径 '{}' 超过 {} 字节的上限", path.display(), MAX_PATH_LEN)
+        ));
+    }
+
+    // 条目ID冲突和忽略大小写的查找表中只差大小写的路径都在写入前报错
+    let paths: Vec<String> = stored_paths.iter().map(|p| p.to_string_lossy().replace('\\', "/")).collect();
+    check_entry_ids(paths.iter().map(String::as_str))?;
+    let lookup = if options.lookup_index {
+        Some(build_lookup_index(paths.iter().map(String::as_str))?)
+    } else {
+        None
+    };
+
+    // 并行计算每个文件的摘要，每个任务的缓冲区占用内存额度
+    let budget = MemoryBudget::new(options.memory_limit);
+    let phase = Instant::now();
+    let hash_progress = WorkerProgress::new(
+        total_size,
+        "{spinner:.green} 计算{msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})",
+        hash_algo.to_string()
+    );
+    let (hashes, mimes): (Vec<_>, Vec<_>) = files.par_iter()
+        .map(|entry| {
+            if !running.load(Ordering::SeqCst) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "操作被用户取消"));
+            }
+            let _lease = budget.acquire(buffer_size(hash_algo));
+            hash_progress.start(entry.path.to_string_lossy());
+            let file = open_source(&pool, &entry.path, options.on_read_error)?;
+            let hash = hash_reader(hash_algo, &mut Throttled::new(file, read_throttle.as_ref()))
+                .map_err(|e| error::with_path(e, &entry.path))?;
+            if let Some(e) = source_changed(&entry.path, entry.size) {
+                return Err(e);
+            }
+            let mime = sniff_file(&entry.path).map_err(|e| error::with_path(e, &entry.path))?;
+            hash_progress.done(entry.size);
+            Ok((hash, mime))
+        })
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    hash_progress.finish_and_clear();
+    metrics.phases.hash_ms = millis(phase.elapsed());
+    if options.memory_limit.is_some() {
+        println!("计算摘要: {}", budget.summary());
     }
 
     // 开始写入文件
-    let mut pak_file = BufWriter::with_capacity(BUFFER_SIZE, File::create(output)?);
+    let phase = Instant::now();
+    let mut pak_file = BufWriter::with_capacity(BUFFER_SIZE, create_archive(output)?);
 
     // 写入Magic Number
     pak_file.write_all(MAGIC_NUMBER)?;
@@ -93,36 +549,77 @@ pub fn pack_files(
     // 序列化 metadata 处理并写入
     let mut metadata_content = XpakMetadata {
         version: env!("CARGO_PKG_VERSION").to_string(),
-        format_version: FORMAT_VERSION.to_string(),
+        format_version: if options.split_metadata {
+            SPLIT_FORMAT_VERSION
+        } else if options.compact {
+            COMPACT_FORMAT_VERSION
+        } else {
+            FORMAT_VERSION
+        }.to_string(),
         created_at: Utc::now(),
         files_count: files.len() as u32,
         total_size,
-        description: metadata.map(|s| s.to_string()),
-        common: HashMap::new(),
-        files: files.iter().map(|entry| {
-            let path = entry.path();
-            let relative_path = path.strip_prefix(input_path).unwrap();
-            let file_path = if flat {
-                PathBuf::from(path.file_name().unwrap())
-            } else {
-                relative_path.to_path_buf()
-            };
-            
-            FileInfo::new(file_path, entry.metadata().unwrap().len())
+        description: xpak_meta.description,
+        content_version: options.content_version.clone(),
+        common: xpak_meta.common,
+        keys: key_infos,
+        skipped: skipped.clone(),
+        bundles,
+        dependencies: options.dependencies.clone(),
+        files: files.iter().zip(&stored_paths).zip(hashes).zip(&encryptions).zip(&compressions).zip(original_paths).zip(mimes).zip(priorities).map(|(((((((entry, file_path), hash), encryption), compression), original_path), mime), priority)| {
+            let size = entry.size;
+            let mut info = FileInfo::new(file_path, size).with_hash(hash_algo, hash);
+            info.original_path = original_path;
+            info.priority = priority;
+            info.mime = Some(mime);
+            info.hook = entry.hook.clone();
+            info.debug = entry.debug.clone();
+            info.mtime = entry.mtime;
+            if let Some(&rule) = validity_rules.matches(file_path).first() {
+                let window = options.validity[rule].1;
+                info.valid_from = window.from;
+                info.valid_until = window.until;
+            }
+            for rule in file_meta_rules.matches(file_path) {
+                options.file_meta[rule].1.apply(&mut info);
+            }
+            if let Some(sidecar) = &entry.sidecar {
+                sidecar.apply(&mut info);
+            }
+            if let Some(compressed) = compression {
+                info.stored_size = Some(compressed.compression.compressed_size());
+                info.compression = Some(compressed.compression.clone());
+            }
+            if let Some(encryption) = encryption {
+                info.stored_size = Some(encrypted_size(info.stored_size.unwrap_or(size)));
+                info.encryption = Some(encryption.clone());
+            }
+            info
         }).collect(),
+        lookup,
+        access_profile: access_trace.map(|t| t.id),
+        eula,
+        git: git_source,
+        dirs: None,
+        files_table: None,
+        tiers: Vec::new(),
+        encoding: options.metadata_encoding,
     };
-
-    // 合并metadata
-    if let Some(meta_str) = metadata {
-        if let Err(e) = metadata_content.merge_user_metadata(meta_str) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Metadata error: {}", e)
-            ));
-        }
+    if options.progressive {
+        metadata_content.tiers = tiers(&metadata_content);
     }
 
-    let metadata_bytes = serde_json::to_vec(&metadata_content)?;
+    let data_len = entry_ranges(&metadata_content).last().map_or(0, |r| r.end);
+    let files_record = if options.split_metadata {
+        Some(metadata_content.split_files(data_len)?)
+    } else {
+        None
+    };
+    let mut metadata_bytes = metadata_content.to_bytes()?;
+    if options.split_metadata {
+        metadata_bytes.resize(metadata_bytes.len() + METADATA_SLACK, b' ');
+    }
+    let path_length = metadata_content.path_length();
     pak_file.write_all(&(metadata_bytes.len() as u32).to_le_bytes())?;
     pak_file.write_all(&metadata_bytes)?;
 
@@ -132,6 +629,18 @@ pub fn pack_files(
     // 写入文件数量
     pak_file.write_all(&(files.len() as u32).to_le_bytes())?;
 
+    // 条目的存储大小此时都已确定，可以算出整个包的大小
+    let data_offset = (MAGIC_NUMBER.len() + 4 + metadata_bytes.len() + MAGIC_METADATA_END.len() + 4) as u64;
+    let device = is_block_device(Path::new(output));
+    if options.preallocate && !device {
+        let archive_size = data_offset + data_len + trailer_size(data_len, FRAME_SIZE, options.parity, files_record.as_ref());
+        preallocate(pak_file.get_ref(), archive_size)?;
+        println!("已预分配 {}", format_size(archive_size));
+    }
+
+    // 数据区段按帧计算校验值，写在尾部区段中
+    let mut pak_file = Throttled::new(FrameWriter::new(pak_file, FRAME_SIZE), write_throttle.as_ref());
+
     // 预分配缓冲区
     let mut buffer = vec![0u8; BUFFER_SIZE];
 
@@ -143,55 +652,659 @@ pub fn pack_files(
         .progress_chars("#>-"));
     
     // 写入文件内容
-    for entry in files {
+    let mut heartbeat = Heartbeat::new("pak");
+    for (((entry, file_path), encryption), compression) in files.iter().zip(&stored_paths).zip(&encryptions).zip(&compressions) {
         if !running.load(Ordering::SeqCst) {
             drop(pak_file);
-            if Path::new(output).exists() {
+            if !device && Path::new(output).exists() {
                 std::fs::remove_file(output)?;
             }
             return Err(io::Error::new(io::ErrorKind::Interrupted, "操作被用户取消"));
         }
 
-        let path = entry.path();
-        let relative_path = path.strip_prefix(input_path).unwrap();
-        let file_path = if flat {
-            PathBuf::from(path.file_name().unwrap())
-        } else {
-            relative_path.to_path_buf()
-        };
+        let path = &entry.path;
 
         // 写入文件路径
         let path_str = file_path.to_string_lossy();
-        pak_file.write_all(&(path_str.len() as u32).to_le_bytes())?;
+        pak_file.write_all(&path_length.encode(path_str.len()))?;
         pak_file.write_all(path_str.as_bytes())?;
 
-        // 优化文件内容写入
-        let mut file = BufReader::with_capacity(BUFFER_SIZE, File::open(path)?);
-        let file_size = file.get_ref().metadata()?.len() as usize;
-
-        pak_file.write_all(&(file_size as u32).to_le_bytes())?;
+        // 优化文件内容写入，压缩的文件写入暂存的压缩结果
+        // 写入的大小以metadata中记录的为准，不按打开时的文件大小，读取期间的变化在写完后检查
+        let (file, file_size) = match compression {
+            Some(compressed) => (
+                pool.open(&compressed.staged).map_err(|e| error::with_path(e, path))?,
+                compressed.compression.compressed_size() as usize
+            ),
+            None => (open_source(&pool, path, options.on_read_error)?, entry.size as usize),
+        };
+        let mut file = BufReader::with_capacity(BUFFER_SIZE, Throttled::new(file, read_throttle.as_ref()));
 
-        if file_size >= BUFFER_SIZE {
-            // 大文件使用 io::copy
-            io::copy(&mut file, &mut pak_file)?;
+        let written = if let Some(encryption) = encryption {
+            // 加密条目写入的是密文大小
+            let key = &entry_keys[&encryption.key_id];
+            pak_file.write_all(&entry_size_field(path, encrypted_size(file_size as u64))?.to_le_bytes())?;
+            encrypt_stream(&mut file, &mut pak_file, key, &encryption.nonce, file_size as u64).map(|_| ())
         } else {
-            // 小文件使用缓冲区
-            let mut remaining = file_size;
-            while remaining > 0 {
-                let to_read = remaining.min(BUFFER_SIZE);
-                let buf = &mut buffer[..to_read];
-                file.read_exact(buf)?;
-                pak_file.write_all(buf)?;
-                remaining -= to_read;
+            pak_file.write_all(&entry_size_field(path, file_size as u64)?.to_le_bytes())?;
+            copy_entry(&mut file, &mut pak_file, file_size, &mut buffer)
+        };
+
+        // metadata和之前条目的帧校验值已经写出，无法只改写这个条目的头部，变化的文件只能停止打包
+        let changed = compression.is_none().then(|| source_changed(path, entry.size)).flatten();
+        if let Some(e) = changed.or(written.err()) {
+            drop(pak_file);
+            if !device && Path::new(output).exists() {
+                std::fs::remove_file(output)?;
             }
+            return Err(e);
         }
-        
+
         progress.inc(file_size as u64);
+        heartbeat.tick(&path_str, file_size as u64);
     }
 
-    // 确保所有数据都写入磁盘
-    pak_file.flush()?;
+    // 写入尾部区段，确保所有数据都写入磁盘
     progress.finish();
+    let archive_size = finish_data_section(pak_file.into_inner(), data_offset, options.parity, files_record, Vec::new())?;
+    if device {
+        // 块设备上包之后的内容保持不变，确保数据写入设备后再返回
+        File::options().write(true).open(output)?.sync_all()?;
+        println!("已写入块设备 {}（{}）", output, format_size(archive_size));
+    } else if options.preallocate {
+        // 预分配的大小是按尾部区段的预计大小算出的，截断到实际长度
+        File::options().write(true).open(output)?.set_len(archive_size)?;
+    }
+    heartbeat.finish();
+    metrics.phases.write_ms = millis(phase.elapsed());
+
+    // 分离出的调试信息另外打包，包内按build-id存放
+    #[cfg(feature = "strip-debug")]
+    if let (Some(stripped), Some(debug_archive)) = (&stripped, options.debug_archive.as_deref()) {
+        if stripped.debug_files == 0 {
+            println!("没有分离出调试信息，不生成 {}", debug_archive);
+        } else {
+            let debug_options = PackOptions {
+                hash_algo,
+                force: options.force,
+                metadata_encoding: options.metadata_encoding,
+                ..Default::default()
+            };
+            let debug_dir = stripped.debug_dir().to_string_lossy().to_string();
+            pack_files(&[debug_dir], debug_archive, &debug_options, running.clone())?;
+            println!("已将 {} 个文件的调试信息打包到 {}", stripped.debug_files, debug_archive);
+        }
+    }
+
+    if !skipped.is_empty() {
+        println!("跳过了 {} 个无法读取的文件:", skipped.len());
+        for file in &skipped {
+            println!("  {}: {}", file.path, file.error);
+        }
+    }
+
+    if let Some(path) = options.metrics_file.as_deref() {
+        metrics.finish(files.len(), total_size, archive_size, path).map_err(|e| error::with_path(e, path))?;
+        println!("打包指标已写入 {}", path);
+    }
+    Ok(())
+}
+
+/// 复制`size`字节的条目内容，文件提前结束时返回错误
+fn copy_entry(file: &mut impl Read, writer: &mut impl Write, size: usize, buffer: &mut [u8]) -> io::Result<()> {
+    if size >= BUFFER_SIZE {
+        // 大文件使用 io::copy
+        let copied = io::copy(&mut file.take(size as u64), writer)?;
+        if copied < size as u64 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("只读到 {} 字节，应为 {} 字节", copied, size)));
+        }
+    } else {
+        // 小文件使用缓冲区
+        let buf = &mut buffer[..size];
+        file.read_exact(buf)?;
+        writer.write_all(buf)?;
+    }
+    Ok(())
+}
+
+/// 读取之后文件的大小与收集时记录的不同时返回错误，此时写入的条目与metadata不符
+fn source_changed(path: &Path, expected: u64) -> Option<io::Error> {
+    let actual = std::fs::metadata(path).ok()?.len();
+    (actual != expected).then(|| error::with_path(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("文件在打包过程中被修改，大小从 {} 字节变为 {} 字节", expected, actual)
+    ), path))
+}
+
+/// 暂存的压缩结果
+struct CompressedSource {
+    staged: PathBuf,
+    compression: EntryCompression,
+}
+
+/// 依次对每个文件分帧压缩，帧之间并行
+///
+/// 已经是压缩格式的文件不再压缩，和压缩后没有变小的文件一样直接存储，结果为`None`。
+/// 自动选择算法时按配置文件中的阈值为每个文件选择算法和级别。
+fn compress_sources(
+    files: &[SourceFile],
+    mode: CompressMode,
+    frame_mb: u32,
+    (pool, on_read_error): (&SourcePool, ReadErrorPolicy),
+    throttle: Option<&Arc<Throttle>>,
+    running: &AtomicBool
+) -> io::Result<(Vec<Option<CompressedSource>>, TempDir)> {
+    let staging = tempfile::tempdir()?;
+    let frame_size = frame_mb as usize * 1024 * 1024;
+    let total_size: u64 = files.iter().map(|f| f.size).sum();
+    let progress = ProgressBar::new(total_size);
+    progress.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} 压缩{msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+        .unwrap()
+        .progress_chars("#>-"));
+    progress.set_message(mode.to_string());
+    let auto = match mode {
+        CompressMode::Auto => Config::load()?.auto_compress,
+        _ => Default::default(),
+    };
+
+    let mut results = Vec::with_capacity(files.len());
+    let mut compressed_size = 0u64;
+    let mut skipped = 0;
+    for (index, entry) in files.iter().enumerate() {
+        if !running.load(Ordering::SeqCst) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "操作被用户取消"));
+        }
+        if is_incompressible(&entry.path).map_err(|e| error::with_path(e, &entry.path))? {
+            debug!(path = %entry.relative.display(), "已是压缩格式，直接存储");
+            progress.inc(entry.size);
+            compressed_size += entry.size;
+            skipped += 1;
+            results.push(None);
+            continue;
+        }
+        let codec = Codec::choose(mode, &entry.path, entry.size, &auto).map_err(|e| error::with_path(e, &entry.path))?;
+        debug!(path = %entry.relative.display(), codec = %codec, "选择压缩算法");
+        let staged = staging.path().join(format!("{}.{}", index, codec.algo));
+        let compression = open_source(pool, &entry.path, on_read_error)
+            .and_then(|file| {
+                let mut writer = BufWriter::with_capacity(BUFFER_SIZE, File::create(&staged)?);
+                let compression = compress_frames(codec, frame_size, &mut Throttled::new(file, throttle), &mut writer)?;
+                writer.flush()?;
+                Ok(compression)
+            })
+            .map_err(|e| error::with_path(e, &entry.path))?;
+        if let Some(e) = source_changed(&entry.path, entry.size) {
+            return Err(e);
+        }
+        progress.inc(entry.size);
+
+        if compression.compressed_size() < entry.size {
+            compressed_size += compression.compressed_size();
+            results.push(Some(CompressedSource { staged, compression }));
+        } else {
+            compressed_size += entry.size;
+            std::fs::remove_file(&staged)?;
+            results.push(None);
+        }
+    }
+    progress.finish_and_clear();
+
+    let count = results.iter().filter(|r| r.is_some()).count();
+    info!(mode = %mode, frame_mb, files = count, skipped, total_size, compressed_size, "分帧压缩完成");
+    println!(
+        "压缩了 {} 个文件 ({})，{} 字节 -> {} 字节",
+        count, mode, total_size, compressed_size
+    );
+    if skipped > 0 {
+        println!("{} 个文件已是压缩格式，直接存储", skipped);
+    }
+    Ok((results, staging))
+}
+
+/// 对匹配的文件运行打包前钩子，用转换结果替换源文件并更新存储路径，
+/// 返回暂存转换结果的临时目录
+fn apply_pre_pack_hooks(
+    files: &mut [SourceFile],
+    stored_paths: &mut [PathBuf],
+    hooks: &[(String, String)]
+) -> io::Result<Option<TempDir>> {
+    if hooks.is_empty() {
+        return Ok(None);
+    }
+    let rules = build_glob_rules(hooks)?;
+    let staging = tempfile::tempdir()?;
+    let progress = WorkerProgress::new(
+        files.len() as u64,
+        "{spinner:.green} 运行打包前钩子 [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}",
+        ""
+    );
+    files.par_iter_mut().zip(stored_paths.par_iter_mut()).enumerate()
+        .try_for_each(|(index, (file, stored_path))| -> io::Result<()> {
+            let Some(&rule) = rules.matches(&*stored_path).first() else {
+                progress.done(1);
+                return Ok(());
+            };
+            progress.start(file.path.to_string_lossy());
+            let command = &hooks[rule].1;
+            let stored = stored_path.to_string_lossy().replace('\\', "/");
+            let mime = sniff_file(&file.path).map_err(|e| error::with_path(e, &file.path))?;
+            let staged = staging.path().join(index.to_string());
+            let Some(output) = run_pre_pack_hook(command, &file.path, &stored, &mime, &staged)
+                .map_err(|e| error::with_path(e, &file.path))? else {
+                progress.done(1);
+                return Ok(());
+            };
+
+            if output.transformed {
+                file.size = staged.metadata()?.len();
+                file.path = staged;
+            }
+            if let Some(renamed) = output.renamed {
+                *stored_path = PathBuf::from(renamed);
+            }
+            file.hook = Some(HookInfo { original_path: stored, original_mime: mime, command: command.clone() });
+            progress.done(1);
+            Ok(())
+        })?;
+    progress.finish_and_clear();
+    Ok(Some(staging))
+}
+
+/// 按策略处理存储路径相同的文件，`files`和`stored_paths`中只留下要打包的文件，
+/// 返回被重命名文件的原始路径
+fn resolve_duplicates(
+    files: &mut Vec<SourceFile>,
+    stored_paths: &mut Vec<PathBuf>,
+    policy: DuplicatePolicy
+) -> io::Result<Vec<Option<String>>> {
+    let key = |path: &Path| path.to_string_lossy().replace('\\', "/");
+    let mut used: HashSet<String> = HashSet::new();
+    let mut kept_files = Vec::with_capacity(files.len());
+    let mut kept_paths = Vec::with_capacity(files.len());
+    let mut original_paths = Vec::with_capacity(files.len());
+
+    for (file, stored_path) in std::mem::take(files).into_iter().zip(std::mem::take(stored_paths)) {
+        if used.insert(key(&stored_path)) {
+            kept_files.push(file);
+            kept_paths.push(stored_path);
+            original_paths.push(None);
+            continue;
+        }
+
+        match policy {
+            DuplicatePolicy::Error => {
+                return Err(error::with_path(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("存储路径冲突: '{}' ({})，可使用 --on-duplicate 指定处理方式", key(&stored_path), file.path.display())
+                ), &file.path));
+            }
+            DuplicatePolicy::Skip => {
+                println!("跳过重复路径: {} ({})", key(&stored_path), file.path.display());
+            }
+            DuplicatePolicy::Rename => {
+                let stem = stored_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                let ext = stored_path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+                let renamed = (1..)
+                    .map(|n| stored_path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+                    .find(|p| !used.contains(&key(p)))
+                    .unwrap();
+                println!("重命名重复路径: {} -> {}", key(&stored_path), key(&renamed));
+                used.insert(key(&renamed));
+                kept_files.push(file);
+                kept_paths.push(renamed);
+                original_paths.push(Some(key(&stored_path)));
+            }
+            DuplicatePolicy::KeepBoth => {
+                kept_files.push(file);
+                kept_paths.push(stored_path);
+                original_paths.push(None);
+            }
+        }
+    }
+    *files = kept_files;
+    *stored_paths = kept_paths;
+    Ok(original_paths)
+}
+
+/// 检查条目大小能否写入32位的大小字段
+fn entry_size_field(path: &Path, size: u64) -> io::Result<u32> {
+    u32::try_from(size).map_err(|_| error::with_path(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("文件 '{}' 存储大小为 {} 字节，超过单个条目 {} 字节的上限", path.display(), size, MAX_ENTRY_SIZE)
+    ), path))
+}
+
+/// 收集所有输入中的文件
+///
+/// 只有一个目录输入时，目录内容直接放在包的根目录下；多个输入时每个输入以自身的名称
+/// 存放，也可以通过`--map`指定存放位置。
+pub fn collect_sources(inputs: &[String], options: &PackOptions, skipped: &mut Vec<SkippedFile>) -> io::Result<Vec<SourceFile>> {
+    let mut sources = match options.files_from.as_deref() {
+        Some(list) => collect_listed(list, options, skipped)?,
+        None => collect_inputs(inputs, options, skipped)?,
+    };
+    if let Some(ext) = options.sidecar_ext.as_deref() {
+        sidecar::attach(&mut sources, ext, options.keep_sidecars)?;
+    }
+    Ok(sources)
+}
+
+fn collect_inputs(inputs: &[String], options: &PackOptions, skipped: &mut Vec<SkippedFile>) -> io::Result<Vec<SourceFile>> {
+    for (src, _) in &options.maps {
+        if !inputs.iter().any(|input| Path::new(input) == Path::new(src)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--map 中的 '{}' 不是输入路径", src)
+            ));
+        }
+    }
+
+    let mut sources = Vec::new();
+    for input in inputs {
+        let input_path = Path::new(input);
+        if !input_path.exists() {
+            return Err(error::with_path(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Input path '{}' does not exist", input)
+            ), input_path));
+        }
+
+        let mapped = options.maps.iter()
+            .find(|(src, _)| Path::new(src) == input_path)
+            .map(|(_, dst)| PathBuf::from(dst.trim_matches('/')));
+        let name = input_path.file_name().map(PathBuf::from).unwrap_or_default();
+
+        if input_path.is_file() {
+            let relative = mapped.filter(|m| !m.as_os_str().is_empty()).unwrap_or_else(|| name.clone());
+            let metadata = input_path.metadata().map_err(|e| error::with_path(e, input_path))?;
+            sources.push(SourceFile {
+                path: input_path.to_path_buf(),
+                relative: if options.flat { name } else { relative },
+                size: metadata.len(),
+                mtime: metadata.modified().ok().map(DateTime::from),
+                hook: None,
+                sidecar: None,
+                debug: None,
+            });
+            continue;
+        }
+
+        let base = match mapped {
+            Some(dst) => dst,
+            None if inputs.len() == 1 => PathBuf::new(),
+            None => name,
+        };
+        let mut walker = WalkDir::new(input_path)
+            .follow_links(options.follow_symlinks)
+            .same_file_system(options.same_filesystem);
+        if let Some(depth) = options.max_depth {
+            walker = walker.max_depth(depth);
+        }
+        if let Some(depth) = options.min_depth {
+            walker = walker.min_depth(depth);
+        }
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let path = e.path().unwrap_or(input_path).to_path_buf();
+                    skip_unreadable(&path, e.into(), options.on_read_error, skipped)?;
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path();
+            let relative = if options.flat {
+                PathBuf::from(entry.file_name())
+            } else {
+                base.join(path.strip_prefix(input_path).unwrap_or(path))
+            };
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    skip_unreadable(path, e.into(), options.on_read_error, skipped)?;
+                    continue;
+                }
+            };
+            sources.push(SourceFile {
+                path: path.to_path_buf(),
+                relative,
+                size: metadata.len(),
+                mtime: metadata.modified().ok().map(DateTime::from),
+                hook: None,
+                sidecar: None,
+                debug: None,
+            });
+        }
+    }
+    // 遍历顺序取决于平台和文件系统，按存储路径对所有输入统一排序，使相同的文件树生成相同的包，
+    // 与输入的先后无关；存储路径相同的文件再按原路径排序
+    sources.sort_by_cached_key(|source| (portable_path(&source.relative), portable_path(&source.path)));
+    Ok(sources)
+}
+
+/// 以`/`分隔的存储路径，按字节比较时与平台和区域设置无关
+fn portable_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// 读取文件列表，`-`表示标准输入；内容含有NUL时按NUL分隔（如`find -print0`），否则按行分隔
+fn read_file_list(source: &str) -> io::Result<Vec<PathBuf>> {
+    let mut data = Vec::new();
+    if source == "-" {
+        io::stdin().lock().read_to_end(&mut data)?;
+    } else {
+        File::open(source).and_then(|mut file| file.read_to_end(&mut data)).map_err(|e| error::with_path(e, source))?;
+    }
+    let separator = if data.contains(&0) { 0 } else { b'\n' };
+    data.split(|&b| b == separator)
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty())
+        .map(|line| String::from_utf8(line.to_vec()).map(PathBuf::from).map_err(|_| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("文件列表中的路径不是有效的UTF-8: {}", String::from_utf8_lossy(line))
+        )))
+        .collect()
+}
+
+/// 按文件列表收集源文件，包内路径为列表中的路径去掉开头的`./`和根目录
+///
+/// 列表中的目录被忽略，只打包文件；不跟随符号链接时也忽略符号链接。
+fn collect_listed(source: &str, options: &PackOptions, skipped: &mut Vec<SkippedFile>) -> io::Result<Vec<SourceFile>> {
+    if !options.maps.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--map 不能与 --files-from 一起使用"));
+    }
+    let mut sources = Vec::new();
+    for path in read_file_list(source)? {
+        let metadata = match if options.follow_symlinks { path.metadata() } else { path.symlink_metadata() } {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                skip_unreadable(&path, e, options.on_read_error, skipped)?;
+                continue;
+            }
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        let mut relative = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => relative.push(name),
+                Component::ParentDir => {
+                    return Err(error::with_path(
+                        io::Error::new(io::ErrorKind::InvalidInput, "文件列表中的路径不能包含 .."),
+                        &path
+                    ));
+                }
+                Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            }
+        }
+        if let (true, Some(name)) = (options.flat, path.file_name()) {
+            relative = PathBuf::from(name);
+        }
+        sources.push(SourceFile {
+            path,
+            relative,
+            size: metadata.len(),
+            mtime: metadata.modified().ok().map(DateTime::from),
+            hook: None,
+            sidecar: None,
+            debug: None,
+        });
+    }
+    info!(source, files = sources.len(), "已读取文件列表");
+    Ok(sources)
+}
+
+/// 从命令行的位置参数中分出输入和输出文件
+///
+/// 只有一个参数时它是输入，输出为当前目录下的`<输入名>.xpak`；否则最后一个参数是输出，
+/// 它是已存在的目录时输出到该目录下的`<第一个输入名>.xpak`。`files_from`为真时输入来自
+/// 文件列表，参数最多只有输出一个，包名取自当前目录。
+pub fn split_output(mut paths: Vec<String>, files_from: bool) -> io::Result<(Vec<String>, String)> {
+    let (inputs, output, derived) = resolve_output(&paths, files_from)?;
+    if derived {
+        println!("输出文件: {}", output);
+    }
+    paths.truncate(inputs);
+    Ok((paths, output))
+}
 
+/// 与`split_output`相同的规则得到输出文件，不输出任何内容，参数无效时返回`None`
+pub fn output_path(paths: &[String], files_from: bool) -> Option<String> {
+    resolve_output(paths, files_from).ok().map(|(_, output, _)| output)
+}
+
+/// 返回输入参数的个数、输出文件和输出文件是否由输入名推导
+fn resolve_output(paths: &[String], files_from: bool) -> io::Result<(usize, String, bool)> {
+    let output = match paths.len() {
+        0 if !files_from => return Err(io::Error::new(io::ErrorKind::InvalidInput, "缺少要打包的输入")),
+        1 if !files_from => None,
+        n if files_from && n > 1 => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "使用 --files-from 时只能指定输出文件"));
+        }
+        _ => paths.last(),
+    };
+    let inputs = paths.len() - usize::from(output.is_some());
+    let dir = match output {
+        Some(output) if !Path::new(output).is_dir() => return Ok((inputs, output.clone(), false)),
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::new(),
+    };
+    let name = paths[..inputs].first().map_or(Path::new("."), Path::new);
+    let output = dir.join(format!("{}.{}", archive_stem(name)?, ARCHIVE_EXTENSION));
+    Ok((inputs, output.to_string_lossy().to_string(), true))
+}
+
+/// 由输入路径得到包名：目录使用目录名，文件去掉扩展名
+fn archive_stem(input: &Path) -> io::Result<String> {
+    let path = if input.file_name().is_some() { input.to_path_buf() } else { input.canonicalize()? };
+    let stem = if path.is_dir() { path.file_name() } else { path.file_stem() };
+    stem.map(|s| s.to_string_lossy().to_string())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("无法由 '{}' 推断输出文件名，请指定 OUTPUT_FILE", input.display())
+        ))
+}
+
+/// 检查输出文件：扩展名不是`.xpak`时给出警告，已存在的文件不是xpak包时除非`force`否则拒绝覆盖
+///
+/// 块设备的大小总是0，除非其中已经是xpak包，否则也需要`force`。
+fn check_output(output: &Path, force: bool) -> io::Result<()> {
+    if is_block_device(output) {
+        let mut magic = [0u8; 4];
+        let is_archive = File::open(output)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .is_ok() && magic == MAGIC_NUMBER;
+        if !is_archive && !force {
+            return Err(error::with_path(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "输出是块设备，写入会覆盖其中的数据，确认请使用 --force"
+            ), output));
+        }
+        return Ok(());
+    }
+    let extension = output.extension().and_then(|e| e.to_str());
+    if !extension.is_some_and(|e| e.eq_ignore_ascii_case(ARCHIVE_EXTENSION)) {
+        println!("警告：输出文件 {} 没有使用 .{} 扩展名", output.display(), ARCHIVE_EXTENSION);
+        warn!(output = %output.display(), "输出文件的扩展名不是 .{}", ARCHIVE_EXTENSION);
+    }
+
+    let mut magic = [0u8; 4];
+    let is_archive = match File::open(output) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && magic == MAGIC_NUMBER,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(error::with_path(e, output)),
+    };
+    let empty = output.metadata()?.len() == 0;
+    if is_archive || empty {
+        return Ok(());
+    }
+    if !force {
+        return Err(error::with_path(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "输出文件已存在且不是xpak包，确认覆盖请使用 --force"
+        ), output));
+    }
+    println!("警告：覆盖不是xpak包的文件 {}", output.display());
     Ok(())
 }
+
+/// 解析`SRC=DST`形式的输入映射
+pub fn parse_map(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((src, dst)) if !src.is_empty() => Ok((src.to_string(), dst.to_string())),
+        _ => Err(format!("无效的映射 '{}'，应为 SRC=DST", spec)),
+    }
+}
+
+/// 对存储路径依次应用替换规则和虚拟目录前缀
+fn rewrite_stored_path(file_path: &Path, options: &PackOptions) -> PathBuf {
+    let mut stored = file_path.to_string_lossy().replace('\\', "/");
+    for (regex, replacement) in &options.rename_rules {
+        stored = regex.replace_all(&stored, replacement.as_str()).into_owned();
+    }
+    if let Some(prefix) = options.prefix.as_deref().map(|p| p.trim_matches('/')).filter(|p| !p.is_empty()) {
+        stored = format!("{}/{}", prefix, stored.trim_start_matches('/'));
+    }
+    PathBuf::from(stored)
+}
+
+/// 解析`REGEX=REPLACEMENT`形式的路径替换规则
+pub fn parse_rename_rule(spec: &str) -> Result<(Regex, String), String> {
+    let (pattern, replacement) = spec.split_once('=')
+        .ok_or_else(|| format!("无效的替换规则 '{}'，应为 REGEX=REPLACEMENT", spec))?;
+    let regex = Regex::new(pattern)
+        .map_err(|e| format!("无效的正则表达式 '{}': {}", pattern, e))?;
+    Ok((regex, replacement.to_string()))
+}
+
+fn build_glob_rules<T>(rules: &[(String, T)]) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for (pattern, _) in rules {
+        let glob = Glob::new(pattern)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的匹配规则 '{}': {}", pattern, e)))?;
+        builder.add(glob);
+    }
+    builder.build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn portable_path_orders_independent_of_separator() {
+        let mut windows = ["b\\c.txt", "a\\z.txt", "a\\b\\c.txt", "a.txt"].map(|p| portable_path(Path::new(p)));
+        let mut unix = ["a/z.txt", "a.txt", "b/c.txt", "a/b/c.txt"].map(|p| portable_path(Path::new(p)));
+        windows.sort();
+        unix.sort();
+        assert_eq!(windows, unix);
+        assert_eq!(unix, ["a.txt", "a/b/c.txt", "a/z.txt", "b/c.txt"]);
+    }
+}
@@ -0,0 +1,173 @@
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::frames::FrameTable;
+use crate::trailer::TrailerRecord;
+
+/// Reed-Solomon 校验块的尾部记录标记
+pub const PARITY_TAG: [u8; 8] = *b"PARITY__";
+/// 每个条带包含的数据帧数
+const STRIPE_FRAMES: usize = 64;
+
+/// 数据区段的 Reed-Solomon 校验块
+///
+/// 数据帧每`STRIPE_FRAMES`个组成一个条带，每个条带按比例生成若干与帧等长的校验块，
+/// 条带内损坏的帧数不超过可用校验块数时可以完整恢复。
+pub struct Parity {
+    stripe_frames: usize,
+    percent: u32,
+    frame_size: usize,
+    checksums: Vec<u32>,
+    shards: Vec<u8>,
+}
+
+/// 解析`N%`或`N`形式的校验块比例
+pub fn parse_parity_percent(spec: &str) -> Result<u32, String> {
+    let percent: u32 = spec.trim_end_matches('%').parse()
+        .map_err(|_| format!("无效的校验比例 '{}'，应为 1% 到 100%", spec))?;
+    if !(1..=100).contains(&percent) {
+        return Err(format!("无效的校验比例 '{}'，应为 1% 到 100%", spec));
+    }
+    Ok(percent)
+}
+
+fn parity_shards(data_shards: usize, percent: u32) -> usize {
+    (data_shards * percent as usize).div_ceil(100).max(1)
+}
+
+fn rs_error(e: reed_solomon_erasure::Error) -> io::Error {
+    io::Error::other(format!("Reed-Solomon 计算失败: {:?}", e))
+}
+
+/// 读取第`index`帧，不足帧长的部分补零
+fn read_frame(file: &mut File, data_offset: u64, table: &FrameTable, index: usize) -> io::Result<Vec<u8>> {
+    let range = table.frame_range(index);
+    let mut frame = vec![0u8; table.frame_size as usize];
+    file.seek(SeekFrom::Start(data_offset + range.start))?;
+    file.read_exact(&mut frame[..(range.end - range.start) as usize])?;
+    Ok(frame)
+}
+
+impl Parity {
+    /// 为数据区段生成校验块
+    pub fn compute(file: &mut File, data_offset: u64, table: &FrameTable, percent: u32) -> io::Result<TrailerRecord> {
+        let frames = table.checksums.len();
+        let mut checksums = Vec::new();
+        let mut shards = Vec::new();
+        for stripe_start in (0..frames).step_by(STRIPE_FRAMES) {
+            let stripe = stripe_start..(stripe_start + STRIPE_FRAMES).min(frames);
+            let parity_count = parity_shards(stripe.len(), percent);
+            let mut stripe_shards = stripe.clone()
+                .map(|index| read_frame(file, data_offset, table, index))
+                .collect::<io::Result<Vec<_>>>()?;
+            stripe_shards.resize(stripe.len() + parity_count, vec![0u8; table.frame_size as usize]);
+
+            ReedSolomon::new(stripe.len(), parity_count).map_err(rs_error)?
+                .encode(&mut stripe_shards).map_err(rs_error)?;
+            for shard in &stripe_shards[stripe.len()..] {
+                checksums.push(crc32fast::hash(shard));
+                shards.extend_from_slice(shard);
+            }
+        }
+
+        let mut data = Vec::with_capacity(12 + checksums.len() * 4 + shards.len());
+        data.extend_from_slice(&(STRIPE_FRAMES as u32).to_le_bytes());
+        data.extend_from_slice(&percent.to_le_bytes());
+        data.extend_from_slice(&(checksums.len() as u32).to_le_bytes());
+        for checksum in &checksums {
+            data.extend_from_slice(&checksum.to_le_bytes());
+        }
+        data.extend_from_slice(&shards);
+        Ok(TrailerRecord { tag: PARITY_TAG, data })
+    }
+
+    /// `frames`个帧生成的校验块记录的大小（含记录头）
+    pub fn record_size(frames: usize, frame_size: u32, percent: u32) -> u64 {
+        let count: usize = (0..frames).step_by(STRIPE_FRAMES)
+            .map(|start| parity_shards((frames - start).min(STRIPE_FRAMES), percent))
+            .sum();
+        16 + 12 + count as u64 * (4 + frame_size as u64)
+    }
+
+    pub fn from_record(record: &TrailerRecord, table: &FrameTable) -> io::Result<Self> {
+        let data = &record.data;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "校验块记录已损坏");
+        if data.len() < 12 {
+            return Err(invalid());
+        }
+        let stripe_frames = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        let percent = u32::from_le_bytes(data[4..8].try_into().unwrap());
+        let count = u32::from_le_bytes(data[8..12].try_into().unwrap()) as usize;
+        let frame_size = table.frame_size as usize;
+        let shards_offset = 12 + count * 4;
+        if stripe_frames == 0 || data.len() != shards_offset + count * frame_size {
+            return Err(invalid());
+        }
+        let checksums = data[12..shards_offset].chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        Ok(Self {
+            stripe_frames,
+            percent,
+            frame_size,
+            checksums,
+            shards: data[shards_offset..].to_vec(),
+        })
+    }
+
+    pub fn percent(&self) -> u32 {
+        self.percent
+    }
+
+    /// 使用校验块恢复损坏的帧，返回成功恢复的帧内容（已去掉补零）
+    pub fn reconstruct(
+        &self,
+        file: &mut File,
+        data_offset: u64,
+        table: &FrameTable,
+        damaged: &[usize]
+    ) -> io::Result<HashMap<usize, Vec<u8>>> {
+        let frames = table.checksums.len();
+        let mut restored = HashMap::new();
+        let mut parity_start = 0;
+        for stripe_start in (0..frames).step_by(self.stripe_frames) {
+            let stripe = stripe_start..(stripe_start + self.stripe_frames).min(frames);
+            let parity_count = parity_shards(stripe.len(), self.percent);
+            let parity_range = parity_start..parity_start + parity_count;
+            parity_start += parity_count;
+            if !damaged.iter().any(|i| stripe.contains(i)) {
+                continue;
+            }
+
+            let mut shards: Vec<Option<Vec<u8>>> = Vec::with_capacity(stripe.len() + parity_count);
+            for index in stripe.clone() {
+                let frame = (!damaged.contains(&index))
+                    .then(|| read_frame(file, data_offset, table, index))
+                    .transpose()?;
+                shards.push(frame);
+            }
+            for index in parity_range {
+                let shard = self.shards.get(index * self.frame_size..(index + 1) * self.frame_size);
+                let intact = shard.filter(|s| self.checksums.get(index) == Some(&crc32fast::hash(s)));
+                shards.push(intact.map(|s| s.to_vec()));
+            }
+
+            let rs = ReedSolomon::new(stripe.len(), parity_count).map_err(rs_error)?;
+            if rs.reconstruct_data(&mut shards).is_err() {
+                // 损坏的块超过校验块数量，该条带无法恢复
+                continue;
+            }
+            for index in stripe.filter(|i| damaged.contains(i)) {
+                let range = table.frame_range(index);
+                let mut frame = shards[index - stripe_start].take().unwrap();
+                frame.truncate((range.end - range.start) as usize);
+                if crc32fast::hash(&frame) == table.checksums[index] {
+                    restored.insert(index, frame);
+                }
+            }
+        }
+        Ok(restored)
+    }
+}
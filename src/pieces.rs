@@ -0,0 +1,201 @@
+//! 按固定大小切分整个包并计算每一块的摘要，供分块下载时逐块校验
+
+use chrono::Utc;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::common::{format_size, BUFFER_SIZE, KB, MB};
+use crate::frames::entry_ranges;
+use crate::hash::{HashAlgo, Hasher};
+use crate::reader::XpakReader;
+
+/// 块大小的下限和上限，与常见BT客户端的限制一致
+const MIN_PIECE_SIZE: u64 = 16 * KB as u64;
+const MAX_PIECE_SIZE: u64 = 64 * MB as u64;
+
+/// 分块摘要清单
+#[derive(Serialize)]
+struct PieceManifest {
+    archive: String,
+    size: u64,
+    piece_size: u64,
+    algo: HashAlgo,
+    pieces: Vec<String>,
+    /// 包头（含metadata）和尾部区段所在的块
+    header: PieceSpan,
+    trailer: PieceSpan,
+    entries: Vec<EntryPieces>,
+}
+
+/// 文件中的一段字节范围及覆盖它的块
+#[derive(Serialize)]
+struct PieceSpan {
+    start: u64,
+    end: u64,
+    first_piece: u64,
+    /// 范围为空时与`first_piece`相同
+    last_piece: u64,
+}
+
+impl PieceSpan {
+    fn new(start: u64, end: u64, piece_size: u64) -> Self {
+        Self {
+            start,
+            end,
+            first_piece: start / piece_size,
+            last_piece: end.saturating_sub(1).max(start) / piece_size,
+        }
+    }
+}
+
+/// 条目（含头部）在文件中的位置，下载并校验这些块后即可解包该条目
+#[derive(Serialize)]
+struct EntryPieces {
+    path: String,
+    #[serde(flatten)]
+    span: PieceSpan,
+}
+
+/// 解析块大小，支持`K`和`M`后缀，必须是2的幂
+pub fn parse_piece_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    let (digits, unit) = if let Some(n) = upper.strip_suffix('M').or_else(|| upper.strip_suffix("MB")) {
+        (n, MB as u64)
+    } else if let Some(n) = upper.strip_suffix('K').or_else(|| upper.strip_suffix("KB")) {
+        (n, KB as u64)
+    } else {
+        (upper.as_str(), 1)
+    };
+    let size = digits.trim().parse::<u64>().ok().and_then(|n| n.checked_mul(unit));
+    match size {
+        Some(size) if size.is_power_of_two() && (MIN_PIECE_SIZE..=MAX_PIECE_SIZE).contains(&size) => Ok(size),
+        _ => Err(format!(
+            "无效的块大小 '{}'，应为 {} 到 {} 之间的2的幂，如 4M",
+            value, format_size(MIN_PIECE_SIZE), format_size(MAX_PIECE_SIZE)
+        )),
+    }
+}
+
+/// `pieces`命令的选项
+pub struct PieceOptions {
+    pub piece_size: u64,
+    pub algo: HashAlgo,
+    /// 清单的输出文件，未指定时输出到标准输出
+    pub output: Option<String>,
+    /// 同时生成的.torrent文件
+    pub torrent: Option<String>,
+    /// 写入.torrent的tracker地址
+    pub trackers: Vec<String>,
+}
+
+/// 生成与包的布局对齐的分块摘要清单，可选同时生成.torrent文件
+pub fn pieces(input: &str, options: &PieceOptions) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    let size = fs::metadata(input)?.len();
+    let piece_size = options.piece_size;
+
+    let ranges = entry_ranges(&reader.metadata);
+    let data_end = reader.data_offset + ranges.last().map_or(0, |r| r.end);
+    let live = reader.metadata.live_entries();
+    let entries = reader.metadata.files.iter().zip(&ranges).zip(live)
+        .filter(|(_, live)| *live)
+        .map(|((file, range), _)| EntryPieces {
+            path: file.path.clone(),
+            span: PieceSpan::new(reader.data_offset + range.start, reader.data_offset + range.end, piece_size),
+        })
+        .collect();
+
+    // 清单使用的摘要和BT协议要求的SHA-1在一次读取中同时计算
+    let mut file = BufReader::with_capacity(BUFFER_SIZE, File::open(input)?);
+    let mut pieces = Vec::new();
+    let mut torrent_pieces = Vec::new();
+    let mut buffer = vec![0u8; piece_size as usize];
+    let mut remaining = size;
+    while remaining > 0 {
+        let len = remaining.min(piece_size) as usize;
+        file.read_exact(&mut buffer[..len])?;
+        remaining -= len as u64;
+        let mut hasher = Hasher::new(options.algo);
+        hasher.update(&buffer[..len]);
+        pieces.push(hasher.finalize_hex());
+        if options.torrent.is_some() {
+            torrent_pieces.extend_from_slice(&Sha1::digest(&buffer[..len]));
+        }
+    }
+
+    let name = Path::new(input).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    if let Some(path) = options.torrent.as_deref() {
+        fs::write(path, torrent(&name, size, piece_size, &torrent_pieces, &options.trackers))?;
+        eprintln!("已生成 {}", path);
+    }
+
+    let manifest = PieceManifest {
+        archive: name,
+        size,
+        piece_size,
+        algo: options.algo,
+        header: PieceSpan::new(0, reader.data_offset, piece_size),
+        trailer: PieceSpan::new(data_end, size, piece_size),
+        pieces,
+        entries,
+    };
+    let json = serde_json::to_string_pretty(&manifest)?;
+    match options.output.as_deref() {
+        Some(path) => {
+            fs::write(path, json + "\n")?;
+            eprintln!("已生成 {}（{} 块，每块 {}）", path, manifest.pieces.len(), format_size(piece_size));
+        }
+        None => {
+            let mut out = io::stdout().lock();
+            writeln!(out, "{}", json)?;
+        }
+    }
+    Ok(())
+}
+
+/// 按BitTorrent v1的格式编码单文件种子，字典的键按字节序排列
+fn torrent(name: &str, size: u64, piece_size: u64, pieces: &[u8], trackers: &[String]) -> Vec<u8> {
+    fn bytes(out: &mut Vec<u8>, value: &[u8]) {
+        out.extend_from_slice(format!("{}:", value.len()).as_bytes());
+        out.extend_from_slice(value);
+    }
+    fn int(out: &mut Vec<u8>, value: i64) {
+        out.extend_from_slice(format!("i{}e", value).as_bytes());
+    }
+
+    let mut out = b"d".to_vec();
+    if let Some(first) = trackers.first() {
+        bytes(&mut out, b"announce");
+        bytes(&mut out, first.as_bytes());
+    }
+    if trackers.len() > 1 {
+        bytes(&mut out, b"announce-list");
+        out.push(b'l');
+        for tracker in trackers {
+            out.push(b'l');
+            bytes(&mut out, tracker.as_bytes());
+            out.push(b'e');
+        }
+        out.push(b'e');
+    }
+    bytes(&mut out, b"created by");
+    bytes(&mut out, format!("xpak {}", env!("CARGO_PKG_VERSION")).as_bytes());
+    bytes(&mut out, b"creation date");
+    int(&mut out, Utc::now().timestamp());
+    bytes(&mut out, b"info");
+    out.push(b'd');
+    bytes(&mut out, b"length");
+    int(&mut out, size as i64);
+    bytes(&mut out, b"name");
+    bytes(&mut out, name.as_bytes());
+    bytes(&mut out, b"piece length");
+    int(&mut out, piece_size as i64);
+    bytes(&mut out, b"pieces");
+    bytes(&mut out, pieces);
+    out.extend_from_slice(b"ee");
+    out
+}
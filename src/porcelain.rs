@@ -0,0 +1,126 @@
+//! 供脚本和其他程序解析的稳定输出格式
+//!
+//! 与给人看的输出分开实现，人看的输出可以随时调整，同一版本的porcelain格式不会改变。
+//! 每行一条记录，字段以制表符分隔；字段中的`\`、制表符、换行和回车转义为`\\`、`\t`、
+//! `\n`和`\r`，没有值的字段为`-`。以后只会在行尾增加字段或增加新的记录类型，
+//! 解析时应忽略不认识的记录和多余的字段。
+//!
+//! v1 格式：
+//!
+//! - `list`：每个有效条目一行，`路径 大小 存储大小 摘要 压缩 加密密钥 修改时间 MIME类型`。
+//!   大小以字节为单位，摘要为`算法:十六进制`，压缩为`算法`或`算法-级别`，修改时间为RFC 3339。
+//! - `metadata`：每个字段一行，`字段名 值`。依次为`format_version`、`version`、
+//!   `created_at`、`files_count`、`total_size`、`description`，之后是用户metadata，
+//!   字段名为`common.<键>`，值为JSON。
+//! - `verify`：每个问题一行`problem 描述`，每个提示一行`note 描述`，最后一行为
+//!   `result ok|failed 校验通过的条目数 问题数`。
+
+use clap::ValueEnum;
+use serde_json::Value;
+use std::io::{self, BufWriter, Write};
+
+use crate::listing::Output;
+use crate::metadata::{FileInfo, XpakMetadata};
+use crate::reader::XpakReader;
+use crate::verify::{check, Verification};
+
+/// porcelain输出格式的版本
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Porcelain {
+    V1,
+}
+
+/// 转义字段中的分隔符，没有值时为`-`
+fn field(value: Option<&str>) -> String {
+    let Some(value) = value else {
+        return "-".to_string();
+    };
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 逐行输出记录，读取端提前关闭管道不视为错误
+fn write_records(records: impl IntoIterator<Item = Vec<String>>) -> io::Result<()> {
+    let output = Output::open(false)?;
+    let mut out = BufWriter::new(output);
+    let result = records.into_iter()
+        .try_for_each(|fields| writeln!(out, "{}", fields.join("\t")))
+        .and_then(|_| out.flush());
+    let (output, _) = out.into_parts();
+    output.finish(result)
+}
+
+fn list_record(file: &FileInfo) -> Vec<String> {
+    vec![
+        field(Some(&file.path)),
+        file.size.to_string(),
+        file.stored_size.unwrap_or(file.size).to_string(),
+        field(file.algo.zip(file.hash.as_deref()).map(|(algo, hash)| format!("{}:{}", algo, hash)).as_deref()),
+        field(file.compression.as_ref().map(|c| c.codec().to_string()).as_deref()),
+        field(file.encryption.as_ref().map(|e| e.key_id.as_str())),
+        field(file.mtime.map(|t| t.to_rfc3339()).as_deref()),
+        field(file.mime.as_deref()),
+    ]
+}
+
+/// 按`list`的格式输出有效条目
+pub fn list(input: &str, _version: Porcelain) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    write_records(reader.metadata.live_files().into_iter().map(list_record))
+}
+
+fn metadata_records(metadata: &XpakMetadata) -> Vec<Vec<String>> {
+    let mut records = vec![
+        vec!["format_version".to_string(), field(Some(&metadata.format_version))],
+        vec!["version".to_string(), field(Some(&metadata.version))],
+        vec!["created_at".to_string(), metadata.created_at.to_rfc3339()],
+        vec!["files_count".to_string(), metadata.files_count.to_string()],
+        vec!["total_size".to_string(), metadata.total_size.to_string()],
+        vec!["description".to_string(), field(metadata.description.as_deref())],
+    ];
+    let mut common: Vec<(&String, &Value)> = metadata.common.iter().collect();
+    common.sort_by_key(|(k, _)| *k);
+    for (key, value) in common {
+        records.push(vec![field(Some(&format!("common.{}", key))), field(Some(&value.to_string()))]);
+    }
+    records
+}
+
+/// 按`metadata`的格式输出包头中的字段
+pub fn metadata(input: &str, _version: Porcelain) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    write_records(metadata_records(&reader.metadata))
+}
+
+fn verify_records(result: &Verification) -> Vec<Vec<String>> {
+    let mut records: Vec<Vec<String>> = Vec::new();
+    for problem in &result.problems {
+        records.push(vec!["problem".to_string(), field(Some(problem))]);
+    }
+    for note in &result.notes {
+        records.push(vec!["note".to_string(), field(Some(note))]);
+    }
+    records.push(vec![
+        "result".to_string(),
+        if result.problems.is_empty() { "ok" } else { "failed" }.to_string(),
+        result.verified.to_string(),
+        result.problems.len().to_string(),
+    ]);
+    records
+}
+
+/// 按`verify`的格式输出校验结果，返回是否没有发现问题
+pub fn verify(input: &str, _version: Porcelain) -> io::Result<bool> {
+    let result = check(input)?;
+    write_records(verify_records(&result))?;
+    Ok(result.problems.is_empty())
+}
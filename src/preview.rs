@@ -0,0 +1,313 @@
+//! 在终端中快速查看包内的文本和图片条目，不解包
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use clap::ValueEnum;
+use console::{measure_text_width, style, Term};
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageReader};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::common::{format_size, KB, MB};
+use crate::mime::sniff;
+use crate::reader::XpakReader;
+
+/// 文本条目小于该大小时全部读入，否则只读取开头和结尾
+const TEXT_READ_LIMIT: u64 = 256 * KB as u64;
+/// 图片条目的大小上限，超出时只显示格式和尺寸
+const IMAGE_READ_LIMIT: u64 = 64 * MB as u64;
+/// 截断位置向前后寻找语法边界的最大行数
+const BOUNDARY_SEARCH: usize = 8;
+/// 终端宽度未知时每行最多显示的列数
+const DEFAULT_WIDTH: usize = 120;
+/// 估算的终端字符宽度（像素），用于缩放sixel图片
+const CELL_WIDTH_PX: u32 = 10;
+/// kitty图形协议每段base64数据的最大长度
+const KITTY_CHUNK: usize = 4096;
+
+/// 在终端中显示图片使用的协议
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    /// 按环境变量判断终端支持的协议，无法判断时只显示格式和尺寸
+    Auto,
+    Kitty,
+    Iterm,
+    Sixel,
+    /// 只显示格式和尺寸
+    None,
+}
+
+/// preview命令的选项
+pub struct PreviewOptions {
+    /// 文本被截断时开头和结尾各显示的行数
+    pub lines: usize,
+    pub protocol: ImageProtocol,
+    /// 图片显示的宽度（终端列数），默认为终端宽度
+    pub width: Option<u32>,
+}
+
+/// 显示包内一个条目的预览：文本显示开头和结尾，图片在终端中显示或输出格式和尺寸
+pub fn preview(input: &str, path: &str, options: &PreviewOptions) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    let mut entry = reader.open_entry(path)?;
+    let size = entry.len();
+    let mut head = Vec::new();
+    (&mut entry).take(TEXT_READ_LIMIT.min(size)).read_to_end(&mut head)?;
+    let mime = entry.info().mime.clone().unwrap_or_else(|| sniff(Path::new(path), &head));
+    println!("{} ({}, {})", style(path).bold(), mime, format_size(size));
+
+    if mime.starts_with("image/") {
+        if size > IMAGE_READ_LIMIT {
+            println!("图片超过 {}，不显示预览", format_size(IMAGE_READ_LIMIT));
+            return Ok(());
+        }
+        let mut bytes = head;
+        entry.read_to_end(&mut bytes)?;
+        return preview_image(&bytes, options);
+    }
+
+    let Some(text) = decode_text(&head, size > TEXT_READ_LIMIT) else {
+        println!("二进制内容，不显示预览；可使用 cat 读取原始内容");
+        return Ok(());
+    };
+    if size <= TEXT_READ_LIMIT {
+        let lines: Vec<&str> = text.lines().collect();
+        return print_truncated(&lines, options.lines, None);
+    }
+
+    // 较大的条目只读取开头和结尾，两段各自丢弃可能不完整的首尾行
+    entry.seek(SeekFrom::Start(size - TEXT_READ_LIMIT))?;
+    let mut tail = Vec::new();
+    entry.read_to_end(&mut tail)?;
+    let tail_text = String::from_utf8_lossy(&tail);
+    let mut head_lines: Vec<&str> = text.lines().collect();
+    head_lines.pop();
+    let tail_lines: Vec<&str> = tail_text.lines().skip(1).collect();
+    let shown_head = &head_lines[..head_lines.len().min(options.lines * 2)];
+    let shown_tail = &tail_lines[tail_lines.len().saturating_sub(options.lines * 2)..];
+    print_truncated(&[shown_head, shown_tail].concat(), options.lines, Some(shown_head.len()))
+}
+
+/// 按BOM解码文本，没有BOM时要求是UTF-8，`partial`为真时允许末尾的字符不完整
+fn decode_text(bytes: &[u8], partial: bool) -> Option<String> {
+    if let Some(rest) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        return Some(String::from_utf8_lossy(rest).into_owned());
+    }
+    let utf16 = |rest: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = rest.chunks_exact(2).map(|c| from([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&units)
+    };
+    if let Some(rest) = bytes.strip_prefix(&[0xff, 0xfe]) {
+        return Some(utf16(rest, u16::from_le_bytes));
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        return Some(utf16(rest, u16::from_be_bytes));
+    }
+    if bytes.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(text.to_string()),
+        Err(e) if partial && e.error_len().is_none() => Some(String::from_utf8_lossy(&bytes[..e.valid_up_to()]).into_owned()),
+        Err(_) => None,
+    }
+}
+
+/// 截断位置是否适合作为分界：空行，或顶层的块结束行（如`}`、`]`、`end`）之后
+fn is_boundary(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty()
+        || (!line.starts_with(char::is_whitespace)
+            && (trimmed.starts_with(['}', ']', ')']) || trimmed == "end" || trimmed.starts_with("</")))
+}
+
+/// 显示开头和结尾各约`keep`行，截断位置就近移到语法边界，避免从块的中间截断
+///
+/// `gap`为已知的省略位置（只读取了开头和结尾时），此时中间省略的行数未知。
+fn print_truncated(lines: &[&str], keep: usize, gap: Option<usize>) -> io::Result<()> {
+    let width = Term::stdout().size_checked().map_or(DEFAULT_WIDTH, |(_, w)| w as usize);
+    let mut out = io::stdout().lock();
+    let number_width = lines.len().to_string().len();
+    let print = |out: &mut io::StdoutLock, number: Option<usize>, line: &str| -> io::Result<()> {
+        let line = clip(line, width.saturating_sub(number_width + 3));
+        match number {
+            Some(n) => writeln!(out, "{} {}", style(format!("{:>number_width$} │", n)).dim(), line),
+            None => writeln!(out, "{} {}", style(format!("{:>number_width$} │", "")).dim(), line),
+        }
+    };
+
+    if gap.is_none() && lines.len() <= keep * 2 {
+        for (i, line) in lines.iter().enumerate() {
+            print(&mut out, Some(i + 1), line)?;
+        }
+        return Ok(());
+    }
+
+    // 开头在`keep`行附近的边界之后结束，结尾从倒数`keep`行附近的边界开始
+    let head_limit = gap.unwrap_or(lines.len()).min(keep);
+    let head_end = (head_limit.saturating_sub(BOUNDARY_SEARCH)..head_limit).rev()
+        .find(|&i| i > 0 && is_boundary(lines[i - 1]))
+        .unwrap_or(head_limit);
+    let tail_limit = lines.len().saturating_sub(keep).max(gap.unwrap_or(head_end));
+    let tail_start = (tail_limit..(tail_limit + BOUNDARY_SEARCH).min(lines.len()))
+        .find(|&i| i > 0 && is_boundary(lines[i - 1]))
+        .unwrap_or(tail_limit);
+
+    for (i, line) in lines[..head_end].iter().enumerate() {
+        print(&mut out, gap.is_none().then_some(i + 1), line)?;
+    }
+    match gap {
+        Some(_) => writeln!(out, "{}", style("… 省略中间部分 …").yellow())?,
+        None => writeln!(out, "{}", style(format!("… 省略 {} 行 …", tail_start - head_end)).yellow())?,
+    }
+    for (i, line) in lines.iter().enumerate().skip(tail_start) {
+        print(&mut out, gap.is_none().then_some(i + 1), line)?;
+    }
+    Ok(())
+}
+
+/// 过长的行截断到`width`列
+fn clip(line: &str, width: usize) -> String {
+    if measure_text_width(line) <= width {
+        return line.to_string();
+    }
+    let mut clipped = String::new();
+    let mut used = 1;
+    for c in line.chars() {
+        let w = measure_text_width(c.encode_utf8(&mut [0; 4]));
+        if used + w > width {
+            break;
+        }
+        used += w;
+        clipped.push(c);
+    }
+    clipped + "…"
+}
+
+/// 显示图片的格式和尺寸，终端支持时显示图片
+fn preview_image(bytes: &[u8], options: &PreviewOptions) -> io::Result<()> {
+    let invalid = |e: image::ImageError| io::Error::new(io::ErrorKind::InvalidData, format!("无法解码图片: {}", e));
+    let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let Some(format) = reader.format() else {
+        println!("不支持的图片格式，不显示预览");
+        return Ok(());
+    };
+    let (width, height) = reader.into_dimensions().map_err(invalid)?;
+    println!("图片: {:?} {}x{}", format, width, height);
+
+    let term = Term::stdout();
+    let protocol = match options.protocol {
+        ImageProtocol::Auto if term.is_term() => detect_protocol(),
+        ImageProtocol::Auto => ImageProtocol::None,
+        protocol => protocol,
+    };
+    if protocol == ImageProtocol::None {
+        return Ok(());
+    }
+    let columns = options.width
+        .unwrap_or_else(|| term.size_checked().map_or(80, |(_, w)| w as u32))
+        .max(1);
+
+    let mut out = io::stdout().lock();
+    match protocol {
+        ImageProtocol::Iterm => {
+            // iTerm2 自己解码原始数据
+            write!(out, "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:{}\x07", bytes.len(), columns, STANDARD.encode(bytes))?;
+        }
+        ImageProtocol::Kitty => {
+            // kitty 只接受PNG或原始像素，其他格式先转换为PNG
+            let png = if format == ImageFormat::Png {
+                bytes.to_vec()
+            } else {
+                let image = image::load_from_memory_with_format(bytes, format).map_err(invalid)?;
+                let mut png = Vec::new();
+                image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).map_err(invalid)?;
+                png
+            };
+            let encoded = STANDARD.encode(png);
+            let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = u8::from(i + 1 < chunks.len());
+                if i == 0 {
+                    write!(out, "\x1b_Ga=T,f=100,c={},m={};", columns, more)?;
+                } else {
+                    write!(out, "\x1b_Gm={};", more)?;
+                }
+                out.write_all(chunk)?;
+                write!(out, "\x1b\\")?;
+            }
+        }
+        ImageProtocol::Sixel => {
+            let image = image::load_from_memory_with_format(bytes, format).map_err(invalid)?;
+            let max_width = columns * CELL_WIDTH_PX;
+            let image = if image.width() > max_width { image.resize(max_width, u32::MAX, image::imageops::FilterType::Triangle) } else { image };
+            write_sixel(&mut out, &image)?;
+        }
+        ImageProtocol::Auto | ImageProtocol::None => {}
+    }
+    writeln!(out)?;
+    out.flush()
+}
+
+/// 按终端设置的环境变量判断支持的图片协议
+fn detect_protocol() -> ImageProtocol {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    if !var("KITTY_WINDOW_ID").is_empty() || var("TERM") == "xterm-kitty" || var("TERM_PROGRAM") == "ghostty" {
+        ImageProtocol::Kitty
+    } else if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm") || var("LC_TERMINAL") == "iTerm2" {
+        ImageProtocol::Iterm
+    } else if var("TERM").contains("sixel") || var("TERM") == "mlterm" || var("TERM_PROGRAM") == "foot" {
+        ImageProtocol::Sixel
+    } else {
+        ImageProtocol::None
+    }
+}
+
+/// 以sixel格式输出图片，颜色量化为6x6x6的调色板，透明像素按黑色显示
+fn write_sixel(out: &mut impl Write, image: &DynamicImage) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let rgb = image.to_rgb8();
+    let level = |v: u8| (v as u16 * 5 + 127) / 255;
+    let color = |x: u32, y: u32| {
+        let [r, g, b] = rgb.get_pixel(x, y).0;
+        (level(r) * 36 + level(g) * 6 + level(b)) as usize
+    };
+
+    write!(out, "\x1bPq\"1;1;{};{}", width, height)?;
+    for i in 0..216u16 {
+        let percent = |v: u16| v * 100 / 5;
+        write!(out, "#{};2;{};{};{}", i, percent(i / 36), percent(i / 6 % 6), percent(i % 6))?;
+    }
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        // 每种颜色在这一带中每列的sixel位
+        let mut bits = vec![vec![0u8; width as usize]; 216];
+        let mut used = [false; 216];
+        for x in 0..width {
+            for dy in 0..rows {
+                let c = color(x, band + dy);
+                bits[c][x as usize] |= 1 << dy;
+                used[c] = true;
+            }
+        }
+        for (c, columns) in bits.iter().enumerate().filter(|(c, _)| used[*c]) {
+            write!(out, "#{}", c)?;
+            let mut x = 0;
+            while x < columns.len() {
+                let value = columns[x];
+                let run = columns[x..].iter().take_while(|&&v| v == value).count();
+                let ch = (b'?' + value) as char;
+                if run > 3 {
+                    write!(out, "!{}{}", run, ch)?;
+                } else {
+                    for _ in 0..run {
+                        write!(out, "{}", ch)?;
+                    }
+                }
+                x += run;
+            }
+            write!(out, "$")?;
+        }
+        write!(out, "-")?;
+    }
+    write!(out, "\x1b\\")
+}
@@ -0,0 +1,69 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+/// 最多显示的工作线程行数，线程更多时按序号共用
+const MAX_WORKER_LINES: usize = 8;
+
+/// 并行任务的进度显示
+///
+/// 在终端中显示总进度条，下方每个工作线程一行显示正在处理的文件；标准错误不是终端时
+/// 只保留总进度。需要在执行任务的rayon线程池中创建，以便确定工作线程的数量。
+pub struct WorkerProgress {
+    total: ProgressBar,
+    workers: Vec<ProgressBar>,
+    _multi: Option<MultiProgress>,
+}
+
+impl WorkerProgress {
+    /// `template`为总进度条的样式，其中的`{msg}`显示`message`
+    pub fn new(total: u64, template: &str, message: impl Into<String>) -> Self {
+        let bar = ProgressBar::new(total);
+        bar.set_style(ProgressStyle::default_bar()
+            .template(template)
+            .unwrap()
+            .progress_chars("#>-"));
+        bar.set_message(message.into());
+
+        if !console::Term::stderr().is_term() {
+            return Self { total: bar, workers: Vec::new(), _multi: None };
+        }
+        let multi = MultiProgress::new();
+        let total = multi.add(bar);
+        let style = ProgressStyle::default_bar().template("  {prefix:.dim} {wide_msg}").unwrap();
+        let workers = (0..rayon::current_num_threads().min(MAX_WORKER_LINES))
+            .map(|index| {
+                let worker = multi.add(ProgressBar::new_spinner());
+                worker.set_style(style.clone());
+                worker.set_prefix(format!("#{}", index + 1));
+                worker
+            })
+            .collect();
+        Self { total, workers, _multi: Some(multi) }
+    }
+
+    fn worker(&self) -> Option<&ProgressBar> {
+        let index = rayon::current_thread_index()?;
+        self.workers.get(index % self.workers.len().max(1))
+    }
+
+    /// 当前工作线程开始处理`name`
+    pub fn start(&self, name: impl Into<String>) {
+        if let Some(worker) = self.worker() {
+            worker.set_message(name.into());
+        }
+    }
+
+    /// 当前工作线程处理完一个文件，总进度增加`amount`
+    pub fn done(&self, amount: u64) {
+        if let Some(worker) = self.worker() {
+            worker.set_message("");
+        }
+        self.total.inc(amount);
+    }
+
+    pub fn finish_and_clear(&self) {
+        for worker in &self.workers {
+            worker.finish_and_clear();
+        }
+        self.total.finish_and_clear();
+    }
+}
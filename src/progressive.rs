@@ -0,0 +1,87 @@
+//! 渐进下载：条目按优先级排列，下载到一半的包也可以解包已经完整的部分
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+
+use crate::common::format_size;
+use crate::frames::entry_ranges;
+use crate::metadata::XpakMetadata;
+
+/// 同一优先级的一段连续条目，按数据区段中的顺序记录
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct PriorityTier {
+    pub priority: i32,
+    /// 这一段中的条目数
+    pub entries: u32,
+    /// 这一段在数据区段中结束的位置，下载到这里即可解包这一段及之前的条目
+    pub end: u64,
+}
+
+/// 读取优先级清单，每行一条`GLOB=PRIORITY`规则，忽略空行和`#`开头的注释
+///
+/// 条目按顺序使用第一条匹配的规则，数值大的排在前面，没有匹配的条目优先级为0。
+pub fn load_manifest(path: &str) -> io::Result<Vec<(String, i32)>> {
+    let content = fs::read_to_string(path)?;
+    let mut rules = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let rule = line.rsplit_once('=')
+            .ok_or_else(|| "应为 GLOB=PRIORITY".to_string())
+            .and_then(|(glob, priority)| {
+                let priority = priority.trim().parse::<i32>()
+                    .map_err(|_| format!("无效的优先级 '{}'", priority.trim()))?;
+                Ok((glob.trim().to_string(), priority))
+            })
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{} 第 {} 行: {}", path, number + 1, e)))?;
+        rules.push(rule);
+    }
+    Ok(rules)
+}
+
+/// 按优先级从高到低排列的顺序，优先级相同的条目保持原来的顺序
+pub fn priority_order(priorities: &[i32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..priorities.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(priorities[i]));
+    order
+}
+
+/// 按条目在数据区段中的顺序划分优先级段
+pub fn tiers(metadata: &XpakMetadata) -> Vec<PriorityTier> {
+    let mut tiers: Vec<PriorityTier> = Vec::new();
+    for (file, range) in metadata.files.iter().zip(entry_ranges(metadata)) {
+        let priority = file.priority.unwrap_or(0);
+        match tiers.last_mut() {
+            Some(tier) if tier.priority == priority => {
+                tier.entries += 1;
+                tier.end = range.end;
+            }
+            _ => tiers.push(PriorityTier { priority, entries: 1, end: range.end }),
+        }
+    }
+    tiers
+}
+
+/// 前`complete`个条目完整时，已经完整的最低优先级
+pub fn complete_priority(tiers: &[PriorityTier], complete: u32) -> Option<i32> {
+    let mut entries = 0;
+    let mut priority = None;
+    for tier in tiers {
+        entries += tier.entries;
+        if entries > complete {
+            break;
+        }
+        priority = Some(tier.priority);
+    }
+    priority
+}
+
+/// 各优先级段需要下载的大小，`data_offset`为数据区段在包内的起点
+pub fn describe(tiers: &[PriorityTier], data_offset: u64) -> Vec<String> {
+    tiers.iter()
+        .map(|t| format!("优先级 {}：{} 个条目，下载前 {} 即可解包", t.priority, t.entries, format_size(data_offset + t.end)))
+        .collect()
+}
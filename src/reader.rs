@@ -0,0 +1,482 @@
+use std::io::{self, Read, BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::NamedTempFile;
+use tracing::warn;
+
+use crate::archive_fs::ArchiveFs;
+use crate::shared_file::SharedFile;
+use crate::cache::{CacheStats, EntryCache};
+use crate::common::{BUFFER_SIZE, MAGIC_METADATA_END, MAGIC_NUMBER};
+use crate::frames::entry_ranges;
+use crate::metadata::{FileInfo, XpakMetadata};
+use crate::migrate::{has_metadata_end, parse_metadata};
+use crate::error;
+use crate::trailer::{read_trailer, read_trailer_at, Trailer};
+use xpak::{read_entry_header, read_metadata};
+use crate::vpath::{lookup_key, PathPolicy};
+
+/// 包内单个条目的头部信息
+#[derive(Debug, Clone)]
+pub struct EntryHeader {
+    pub index: u32,
+    pub path: String,
+    pub size: u64,
+}
+
+/// 顺序读取时条目头部与metadata中的记录不一致的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Consistency {
+    /// 不检查，由调用方自行比较
+    Off,
+    /// 记录警告，继续按头部读取
+    #[default]
+    Warn,
+    /// 返回错误
+    Strict,
+}
+
+/// 顺序读取xpak包的读取器
+///
+/// 通过`next_entry`逐个定位条目，定位后可以通过`Read`读取当前条目的内容，
+/// 未读完的内容会在定位下一个条目时自动跳过。
+///
+/// `clone`得到独立的读取句柄：与原读取器共享文件句柄和解压结果缓存，读取位置各自独立，
+/// 可以交给其他线程同时读取，读取时不需要加锁。
+pub struct XpakReader {
+    file: BufReader<SharedFile>,
+    pub metadata: XpakMetadata,
+    pub count: u32,
+    /// 数据区段（第一个条目）在文件中的起始位置
+    pub data_offset: u64,
+    /// 每个条目是否仍然有效，见`XpakMetadata::live_entries`
+    live: Arc<Vec<bool>>,
+    /// 有效条目的ID -> 序号
+    ids: Arc<HashMap<u64, usize>>,
+    /// `read`使用的解压结果缓存，通过`with_cache`启用
+    cache: Option<Arc<Mutex<EntryCache>>>,
+    /// `next_entry`比较头部与metadata的方式
+    consistency: Consistency,
+    next_index: u32,
+    remaining: u64,
+}
+
+impl XpakReader {
+    pub fn open(input: impl AsRef<Path>) -> io::Result<Self> {
+        let input = input.as_ref();
+        Self::open_file(input).map_err(|e| error::with_path(e, input))
+    }
+
+    fn open_file(input: &Path) -> io::Result<Self> {
+        let mut file = BufReader::with_capacity(BUFFER_SIZE, SharedFile::open(input)?);
+
+        // 验证Magic Number
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if magic != MAGIC_NUMBER {
+            return Err(error::corrupt("无效的文件格式", 0));
+        }
+
+        // 读取metadata
+        let file_len = file.get_ref().len();
+        let metadata_bytes = read_metadata(&mut file, file_len).map_err(|e| error::at_offset(e, 4))?;
+        let meta_len = metadata_bytes.len();
+
+        let mut metadata = parse_metadata(&metadata_bytes).map_err(|e| error::at_offset(e, 8))?;
+
+        // 验证metadata结束标记，1.3之前的版本没有该标记
+        let mut data_offset = 8 + meta_len as u64;
+        if has_metadata_end(&metadata.format_version) {
+            let mut metadata_end = [0u8; 8];
+            file.read_exact(&mut metadata_end).map_err(|e| error::at_offset(e, data_offset))?;
+            if metadata_end != MAGIC_METADATA_END {
+                return Err(error::corrupt("无效的metadata结束标记", data_offset));
+            }
+            data_offset += 8;
+        }
+
+        // 读取文件数量
+        let mut count_bytes = [0u8; 4];
+        file.read_exact(&mut count_bytes)?;
+        let count = u32::from_le_bytes(count_bytes);
+
+        // 条目列表拆分到尾部区段时读取后回到数据区段的起点
+        if metadata.files_table.is_some() {
+            metadata.load_files(&mut file, data_offset + 4)?;
+            file.seek(SeekFrom::Start(data_offset + 4))?;
+        }
+
+        let live = metadata.live_entries();
+        let ids = metadata.files.iter().enumerate()
+            .filter(|&(i, _)| live[i])
+            .map(|(i, f)| (f.entry_id(), i))
+            .collect();
+        Ok(Self {
+            file,
+            live: Arc::new(live),
+            ids: Arc::new(ids),
+            cache: None,
+            consistency: Consistency::default(),
+            metadata,
+            count,
+            data_offset: data_offset + 4,
+            next_index: 0,
+            remaining: 0,
+        })
+    }
+
+    /// 读取包的尾部区段
+    ///
+    /// 文件末尾没有尾部区段时从最后一个条目之后解析，尾部区段之后有多余数据的包也能读取。
+    /// 通过独立的读取位置读取，不影响`next_entry`的顺序读取。
+    pub fn trailer(&self) -> io::Result<Option<Trailer>> {
+        let mut file = BufReader::with_capacity(BUFFER_SIZE, self.file.get_ref().at(0));
+        match read_trailer(&mut file)? {
+            Some(trailer) => Ok(Some(trailer)),
+            None => read_trailer_at(&mut file, self.data_end()),
+        }
+    }
+
+    /// 最后一个条目在文件中的结束位置
+    pub fn data_end(&self) -> u64 {
+        self.data_offset + entry_ranges(&self.metadata).last().map_or(0, |r| r.end)
+    }
+
+    /// 打开指定路径的条目，用于随机读取其中的任意范围
+    ///
+    /// 返回的读取器使用独立的文件句柄，不影响`next_entry`的顺序读取，也可以同时打开多个。
+    /// 压缩条目按帧表只解压读取位置所在的帧。路径重复时打开最后一个条目。
+    pub fn open_entry(&self, path: &str) -> io::Result<EntryReader> {
+        let index = self.find_live(path)?;
+        self.open_index(index)
+    }
+
+    /// 启用`read`的解压结果缓存，缓存的总大小不超过`capacity`字节
+    ///
+    /// 适合反复读取同一批小条目的场景，超过`capacity`的条目不缓存。
+    pub fn with_cache(mut self, capacity: u64) -> Self {
+        self.cache = Some(Arc::new(Mutex::new(EntryCache::new(capacity))));
+        self
+    }
+
+    /// 设置`next_entry`比较条目头部与metadata的方式，默认为`Consistency::Warn`
+    pub fn with_consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// 缓存的命中统计，没有启用缓存时返回`None`
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.lock().unwrap().stats())
+    }
+
+    /// 读取条目解压后的全部内容，启用缓存时优先从缓存中读取
+    pub fn read(&self, path: &str) -> io::Result<Arc<[u8]>> {
+        let index = self.find_live(path)?;
+        if let Some(data) = self.cache.as_ref().and_then(|cache| cache.lock().unwrap().get(index)) {
+            return Ok(data);
+        }
+        let mut entry = self.open_index(index)?;
+        let mut data = Vec::with_capacity(entry.len() as usize);
+        entry.read_to_end(&mut data)?;
+        let data: Arc<[u8]> = data.into();
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().insert(index, data.clone());
+        }
+        Ok(data)
+    }
+
+    /// 按条目ID（见`vpath::entry_id`）打开条目，与`open_entry`相同但不需要比较路径
+    pub fn by_id(&self, id: u64) -> io::Result<EntryReader> {
+        match self.ids.get(&id) {
+            Some(&index) => self.open_index(index),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("包中没有ID为 {:016x} 的条目", id))),
+        }
+    }
+
+    /// 路径为`path`的最后一个有效条目的序号
+    fn find_live(&self, path: &str) -> io::Result<usize> {
+        let key = lookup_key(path, PathPolicy::Exact);
+        self.metadata.files.iter().enumerate()
+            .rposition(|(i, f)| self.live[i] && lookup_key(&f.path, PathPolicy::Exact) == key)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("包中没有 {}", path)))
+    }
+
+    fn open_index(&self, index: usize) -> io::Result<EntryReader> {
+        let info = self.metadata.files[index].clone();
+        if info.encryption.is_some() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} 已加密，不支持随机读取", info.path)));
+        }
+        // 帧表来自metadata，读取时按帧大小定位，先确认它与条目大小一致
+        if let Some(compression) = &info.compression {
+            let frame_size = compression.frame_size as u64;
+            if frame_size == 0 || compression.frames.len() as u64 != info.size.div_ceil(frame_size) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("条目 {} 的帧表与大小不符（帧大小 {}，{} 帧）", info.path, frame_size, compression.frames.len())
+                ));
+            }
+        }
+
+        // 按metadata定位条目，并确认头部与metadata一致
+        let header_offset = self.data_offset + entry_ranges(&self.metadata)[index].start;
+        let mut file = BufReader::with_capacity(BUFFER_SIZE, self.file.get_ref().at(header_offset));
+        let (path_len, _) = self.metadata.path_length().read(&mut file).map_err(|e| error::at_offset(e, header_offset))?;
+        // 先比较长度再分配，损坏的长度字段不会导致分配过多内存
+        let mut path_bytes = vec![0u8; info.path.len()];
+        let mut size_bytes = [0u8; 4];
+        if path_len != info.path.len()
+            || file.read_exact(&mut path_bytes).is_err()
+            || path_bytes != info.path.as_bytes()
+            || file.read_exact(&mut size_bytes).is_err()
+            || u32::from_le_bytes(size_bytes) as u64 != info.stored_size.unwrap_or(info.size)
+        {
+            return Err(error::corrupt(format!("条目 {} 的头部与metadata不一致", info.path), header_offset));
+        }
+
+        let start = file.stream_position()?;
+        let frame_offsets = info.compression.as_ref().map(|c| c.frame_offsets()).unwrap_or_default();
+        Ok(EntryReader { file: file.into_inner(), start, info, position: 0, frame: None, frame_offsets })
+    }
+
+    /// 把条目解压到临时文件，供只能按路径打开文件的第三方库使用
+    ///
+    /// Linux上优先放在内存文件系统`/dev/shm`中。临时文件保留条目的扩展名，以便按扩展名
+    /// 识别格式的程序使用，返回值被丢弃时删除。
+    pub fn extract_temp(&self, path: &str) -> io::Result<TempExtractedFile> {
+        let mut entry = self.open_entry(path)?;
+        let name = Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let suffix = match name.rfind('.') {
+            Some(i) if i > 0 => &name[i..],
+            _ => "",
+        };
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("xpak-").suffix(suffix);
+        let shm = Path::new("/dev/shm");
+        let mut file = if cfg!(target_os = "linux") && shm.is_dir() {
+            builder.tempfile_in(shm).or_else(|_| builder.tempfile())?
+        } else {
+            builder.tempfile()?
+        };
+        let mut writer = io::BufWriter::with_capacity(BUFFER_SIZE, file.as_file_mut());
+        io::copy(&mut entry, &mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        Ok(TempExtractedFile { file })
+    }
+
+    /// 得到把包作为只读文件系统使用的对象，可以交给`vfs::VfsPath::new`
+    ///
+    /// 返回的对象使用独立的读取句柄，不影响当前读取器的顺序读取。
+    pub fn to_vfs(&self) -> io::Result<ArchiveFs> {
+        Ok(ArchiveFs::new(self.clone()))
+    }
+
+    /// 只读取文件中已经完整的条目，返回不完整的条目数，用于下载未完成的包
+    pub fn limit_to_complete(&mut self) -> io::Result<u32> {
+        let available = self.file.get_ref().len().saturating_sub(self.data_offset);
+        let complete = entry_ranges(&self.metadata).iter().take_while(|r| r.end <= available).count() as u32;
+        let missing = self.count.saturating_sub(complete);
+        self.count = self.count.min(complete);
+        Ok(missing)
+    }
+
+    /// 条目是否仍然有效，被之后的条目替换的条目和删除标记无效
+    pub fn is_live(&self, index: u32) -> bool {
+        self.live.get(index as usize).copied().unwrap_or(true)
+    }
+
+    /// 定位到下一个条目，所有条目读取完毕时返回`None`
+    pub fn next_entry(&mut self) -> io::Result<Option<EntryHeader>> {
+        if self.remaining > 0 {
+            self.file.seek_relative(self.remaining as i64)?;
+            self.remaining = 0;
+        }
+        if self.next_index >= self.count {
+            return Ok(None);
+        }
+
+        let offset = self.file.stream_position()?;
+        let (path, size) = read_entry_header(&mut self.file, self.metadata.path_length())
+            .map_err(|e| error::at_offset(e, offset))?;
+
+        let header = EntryHeader { index: self.next_index, path, size };
+        if self.consistency != Consistency::Off {
+            if let Some(problem) = self.header_mismatch(&header) {
+                if self.consistency == Consistency::Strict {
+                    return Err(error::corrupt(problem, offset));
+                }
+                warn!(offset, "{}", problem);
+            }
+        }
+        self.next_index += 1;
+        self.remaining = size;
+        Ok(Some(header))
+    }
+
+    /// 条目头部与metadata中同一序号的记录不一致时返回说明
+    fn header_mismatch(&self, header: &EntryHeader) -> Option<String> {
+        let number = header.index + 1;
+        let Some(info) = self.metadata.files.get(header.index as usize) else {
+            return Some(format!("条目 #{} {} 在metadata中没有对应的记录", number, header.path));
+        };
+        if info.path != header.path {
+            return Some(format!("条目 #{} 路径不一致: 头部为 {}，metadata 为 {}", number, header.path, info.path));
+        }
+        let expected = info.stored_size.unwrap_or(info.size);
+        if header.size != expected {
+            return Some(format!(
+                "条目 #{} {} 大小不一致: 头部 {} 字节，metadata {} 字节",
+                number, header.path, header.size, expected
+            ));
+        }
+        None
+    }
+}
+
+impl Clone for XpakReader {
+    /// 得到读取位置与当前读取器相同的独立句柄
+    fn clone(&self) -> Self {
+        // 缓冲区中还没有读取的部分不属于新句柄，按逻辑位置重新开始缓冲
+        let position = self.file.get_ref().position() - self.file.buffer().len() as u64;
+        Self {
+            file: BufReader::with_capacity(BUFFER_SIZE, self.file.get_ref().at(position)),
+            metadata: self.metadata.clone(),
+            count: self.count,
+            data_offset: self.data_offset,
+            live: self.live.clone(),
+            ids: self.ids.clone(),
+            cache: self.cache.clone(),
+            consistency: self.consistency,
+            next_index: self.next_index,
+            remaining: self.remaining,
+        }
+    }
+}
+
+impl Read for XpakReader {
+    /// 读取当前条目的内容
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let max = self.remaining.min(buf.len() as u64) as usize;
+        let n = self.file.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "条目内容不完整"));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// 解压到临时文件的条目，被丢弃时删除临时文件
+pub struct TempExtractedFile {
+    file: NamedTempFile,
+}
+
+impl TempExtractedFile {
+    /// 临时文件的路径
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
+
+/// 可随机读取的单个条目，读取的是解压后的内容
+///
+/// 每次读取都按位置从文件中读取，不依赖文件句柄的读取位置，定位只改变`position`。
+pub struct EntryReader {
+    file: SharedFile,
+    /// 条目内容在文件中的起始位置
+    start: u64,
+    info: FileInfo,
+    /// 解压后内容中的读取位置
+    position: u64,
+    /// 最近解压的帧 (序号, 内容)
+    frame: Option<(usize, Vec<u8>)>,
+    frame_offsets: Vec<u64>,
+}
+
+impl EntryReader {
+    /// 解压后的大小
+    pub fn len(&self) -> u64 {
+        self.info.size
+    }
+
+    /// 条目在metadata中的记录
+    pub fn info(&self) -> &FileInfo {
+        &self.info
+    }
+
+    /// 读取未压缩条目的内容
+    fn read_stored(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = (self.info.size - self.position).min(buf.len() as u64) as usize;
+        let n = self.file.read_at(&mut buf[..max], self.start + self.position)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "条目内容不完整"));
+        }
+        Ok(n)
+    }
+
+    /// 从读取位置所在的帧中复制内容，需要时先解压该帧
+    fn read_frame(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(compression) = self.info.compression.as_ref() else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "条目没有压缩信息"));
+        };
+        let frame_size = compression.frame_size as u64;
+        let index = (self.position / frame_size) as usize;
+        if self.frame.as_ref().is_none_or(|(i, _)| *i != index) {
+            let Some(&len) = compression.frames.get(index) else {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "读取位置超出帧表的范围"));
+            };
+            // 帧长来自metadata，超出条目存储大小时说明metadata已损坏，不按其分配内存
+            if self.frame_offsets[index] + len as u64 > self.info.stored_size.unwrap_or(self.info.size) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "帧长度超出条目的存储大小"));
+            }
+            let mut data = vec![0u8; len as usize];
+            self.file.read_exact_at(&mut data, self.start + self.frame_offsets[index])?;
+            self.frame = Some((index, compression.decode_frame(index, &data)?));
+        }
+
+        let (_, data) = self.frame.as_ref().unwrap();
+        let offset = (self.position % frame_size) as usize;
+        if offset >= data.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "压缩数据不完整"));
+        }
+        let n = (data.len() - offset).min(buf.len());
+        buf[..n].copy_from_slice(&data[offset..offset + n]);
+        Ok(n)
+    }
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.info.size {
+            return Ok(0);
+        }
+        let n = if self.info.compression.is_some() {
+            self.read_frame(buf)?
+        } else {
+            self.read_stored(buf)?
+        };
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for EntryReader {
+    /// 按解压后的位置定位，可以超出条目末尾，此时读取不到内容
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.info.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        let Some(position) = position else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "定位到条目开头之前"));
+        };
+        self.position = position;
+        Ok(position)
+    }
+}
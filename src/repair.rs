@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use crate::common::{BUFFER_SIZE, MAGIC_METADATA_END, MAGIC_NUMBER};
+use crate::frames::{create_archive, entry_ranges, finish_data_section, FrameTable, FrameWriter, FRAMES_TAG};
+use crate::metadata::FILES_TAG;
+use crate::parity::{Parity, PARITY_TAG};
+use crate::reader::XpakReader;
+
+/// 丢弃落在损坏帧中的条目，把其余条目复制到新的包中
+///
+/// `use_parity`时先用校验块恢复损坏的帧，只丢弃无法恢复的条目。
+pub fn repair(input: &str, output: &str, use_parity: bool) -> io::Result<()> {
+    let mut reader = XpakReader::open(input)?;
+    let trailer = reader.trailer()?.unwrap_or_default();
+    let table = trailer.get(&FRAMES_TAG)
+        .map(FrameTable::from_record)
+        .transpose()?
+        .ok_or_else(|| io::Error::new(
+            io::ErrorKind::Unsupported,
+            "包中没有帧校验信息，无法定位损坏的位置"
+        ))?;
+
+    let data_offset = reader.data_offset;
+    let mut source = File::open(input)?;
+    let mut damaged_frames = table.damaged_frames(&mut source, data_offset)?;
+    let parity = trailer.get(&PARITY_TAG)
+        .map(|record| Parity::from_record(record, &table))
+        .transpose()?;
+
+    let mut restored = HashMap::new();
+    if use_parity && !damaged_frames.is_empty() {
+        let parity = parity.as_ref().ok_or_else(|| io::Error::new(
+            io::ErrorKind::Unsupported,
+            "包中没有校验块，无法恢复损坏的帧"
+        ))?;
+        restored = parity.reconstruct(&mut source, data_offset, &table, &damaged_frames)?;
+        println!("使用校验块恢复了 {}/{} 个损坏的帧", restored.len(), damaged_frames.len());
+        damaged_frames.retain(|index| !restored.contains_key(index));
+    }
+    let damaged: Vec<Range<u64>> = damaged_frames.iter()
+        .map(|&index| table.frame_range(index))
+        .collect();
+
+    // 与损坏帧重叠或超出数据区段的条目无法恢复
+    let mut metadata = std::mem::take(&mut reader.metadata);
+    let ranges = entry_ranges(&metadata);
+    let mut kept = Vec::new();
+    let mut kept_ranges = Vec::new();
+    let mut lost = Vec::new();
+    for (file, range) in metadata.files.drain(..).zip(ranges) {
+        let intact = range.end <= table.data_len
+            && !damaged.iter().any(|d| d.start < range.end && range.start < d.end);
+        if intact {
+            kept.push(file);
+            kept_ranges.push(range);
+        } else {
+            lost.push(file.path);
+        }
+    }
+    metadata.files_count = kept.len() as u32;
+    metadata.total_size = kept.iter().map(|f| f.size).sum();
+    metadata.files = kept;
+    let files_record = match metadata.files_table {
+        Some(_) => Some(metadata.split_files(kept_ranges.iter().map(|r| r.end - r.start).sum())?),
+        None => None,
+    };
+
+    let mut pak_file = BufWriter::with_capacity(BUFFER_SIZE, create_archive(output)?);
+    let metadata_bytes = metadata.to_bytes()?;
+    pak_file.write_all(MAGIC_NUMBER)?;
+    pak_file.write_all(&(metadata_bytes.len() as u32).to_le_bytes())?;
+    pak_file.write_all(&metadata_bytes)?;
+    pak_file.write_all(&MAGIC_METADATA_END)?;
+    pak_file.write_all(&metadata.files_count.to_le_bytes())?;
+
+    // 条目头部和内容按原样复制，已恢复的帧使用恢复后的内容
+    let mut pak_file = FrameWriter::new(pak_file, table.frame_size);
+    for range in &kept_ranges {
+        copy_range(&mut source, data_offset, &table, &restored, range.clone(), &mut pak_file)?;
+    }
+
+    // 数据区段可能已改变，重新生成帧校验表和校验块
+    let new_data_offset = (MAGIC_NUMBER.len() + 4 + metadata_bytes.len() + MAGIC_METADATA_END.len() + 4) as u64;
+    let extra_records = trailer.records.into_iter()
+        .filter(|r| r.tag != FRAMES_TAG && r.tag != PARITY_TAG && r.tag != FILES_TAG)
+        .collect();
+    finish_data_section(pak_file, new_data_offset, parity.map(|p| p.percent()), files_record, extra_records)?;
+
+    if damaged.is_empty() && restored.is_empty() {
+        println!("没有发现损坏的帧");
+    } else if !damaged.is_empty() {
+        println!("{} 个损坏的帧无法恢复", damaged.len());
+    }
+    for path in &lost {
+        println!("丢弃: {}", path);
+    }
+    println!("已恢复 {} 个条目到 {}，丢弃 {} 个", metadata.files_count, output, lost.len());
+    Ok(())
+}
+
+/// 复制数据区段中的一段字节，落在已恢复帧中的部分取恢复后的内容
+fn copy_range(
+    source: &mut File,
+    data_offset: u64,
+    table: &FrameTable,
+    restored: &HashMap<usize, Vec<u8>>,
+    range: Range<u64>,
+    writer: &mut impl Write
+) -> io::Result<()> {
+    let frame_size = table.frame_size as u64;
+    let mut position = range.start;
+    while position < range.end {
+        let index = (position / frame_size) as usize;
+        let frame_end = ((index as u64 + 1) * frame_size).min(range.end);
+        match restored.get(&index) {
+            Some(frame) => {
+                let start = (position - index as u64 * frame_size) as usize;
+                let end = (frame_end - index as u64 * frame_size) as usize;
+                writer.write_all(&frame[start..end])?;
+            }
+            None => {
+                source.seek(SeekFrom::Start(data_offset + position))?;
+                io::copy(&mut (&mut *source).take(frame_end - position), writer)?;
+            }
+        }
+        position = frame_end;
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use std::io::{self, BufWriter};
+use std::time::Instant;
+use std::fs::File;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryStatus {
+    Extracted,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EntryReport {
+    pub index: u32,
+    pub path: String,
+    pub status: EntryStatus,
+    pub output_path: Option<String>,
+    pub bytes_written: u64,
+    /// 哈希校验结果，包内没有记录哈希时为null
+    pub hash_verified: Option<bool>,
+    pub duration_ms: f64,
+    pub error: Option<String>,
+    /// 跳过的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl EntryReport {
+    /// 未解包的条目
+    pub fn skipped(index: u32, path: String, started: Instant, reason: Option<String>) -> Self {
+        Self {
+            index,
+            path,
+            status: EntryStatus::Skipped,
+            output_path: None,
+            bytes_written: 0,
+            hash_verified: None,
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+            error: None,
+            reason,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ReportSummary {
+    pub total: u32,
+    pub extracted: u32,
+    pub skipped: u32,
+    pub failed: u32,
+    pub bytes_written: u64,
+    pub completed: bool,
+    pub error: Option<String>,
+}
+
+/// 解包报告，记录每个条目的处理结果
+#[derive(Serialize, Debug)]
+pub struct ExtractionReport {
+    pub input: String,
+    pub output: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: f64,
+    pub summary: ReportSummary,
+    pub entries: Vec<EntryReport>,
+    #[serde(skip)]
+    timer: Option<Instant>,
+}
+
+impl ExtractionReport {
+    pub fn new(input: &str, output: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            output: output.to_string(),
+            started_at: Utc::now(),
+            finished_at: None,
+            duration_ms: 0.0,
+            summary: ReportSummary::default(),
+            entries: Vec::new(),
+            timer: Some(Instant::now()),
+        }
+    }
+
+    pub fn push(&mut self, entry: EntryReport) {
+        self.summary.total += 1;
+        match entry.status {
+            EntryStatus::Extracted => self.summary.extracted += 1,
+            EntryStatus::Skipped => self.summary.skipped += 1,
+            EntryStatus::Failed => self.summary.failed += 1,
+        }
+        self.summary.bytes_written += entry.bytes_written;
+        self.entries.push(entry);
+    }
+
+    /// 结束记录，`error`为整个操作失败的原因
+    pub fn finish(&mut self, error: Option<&io::Error>) {
+        self.finished_at = Some(Utc::now());
+        if let Some(timer) = self.timer.take() {
+            self.duration_ms = timer.elapsed().as_secs_f64() * 1000.0;
+        }
+        self.summary.completed = error.is_none();
+        self.summary.error = error.map(|e| e.to_string());
+    }
+
+    pub fn write_to(&self, path: &str) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::ops::RangeInclusive;
+
+/// 解包时选择条目的规则
+///
+/// 同时指定路径和序号时，满足任意一个即被选中；都未指定时选择全部条目。
+#[derive(Default, Clone)]
+pub struct EntrySelector {
+    pub files: Option<Vec<String>>,
+    /// 序号范围，序号从1开始，与`list`的输出一致
+    pub entries: Option<Vec<RangeInclusive<u32>>>,
+    /// 路径比较时忽略大小写
+    pub ignore_case: bool,
+    /// 把`files`视为目录前缀，选择其下的所有条目
+    pub prefix: bool,
+}
+
+impl EntrySelector {
+    /// 在`files`之外再选择`paths`中的条目，如分组中的条目
+    pub fn with_files(&self, paths: Vec<String>) -> Self {
+        let mut selector = self.clone();
+        selector.files.get_or_insert_with(Vec::new).extend(paths);
+        selector
+    }
+
+    /// 创建记录各选择项是否命中的跟踪器
+    pub fn tracker(&self) -> SelectionTracker<'_> {
+        SelectionTracker {
+            selector: self,
+            files_hit: vec![false; self.files.as_ref().map_or(0, Vec::len)],
+            entries_hit: vec![false; self.entries.as_ref().map_or(0, Vec::len)],
+        }
+    }
+
+    fn path_matches(&self, selected: &str, path: &str) -> bool {
+        let (selected, path) = if self.ignore_case {
+            (selected.to_lowercase(), path.to_lowercase())
+        } else {
+            (selected.to_string(), path.to_string())
+        };
+
+        if !self.prefix {
+            return selected == path;
+        }
+
+        // 按目录边界匹配前缀，`assets/ui`不会匹配`assets/uix/...`
+        let selected = selected.trim_end_matches('/');
+        selected.is_empty()
+            || path == selected
+            || path.strip_prefix(selected).is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// 记录每个选择项是否命中过条目
+pub struct SelectionTracker<'a> {
+    selector: &'a EntrySelector,
+    files_hit: Vec<bool>,
+    entries_hit: Vec<bool>,
+}
+
+impl SelectionTracker<'_> {
+    /// 判断条目是否被选中，同时记录命中的选择项。`index`为条目在包内的位置（从0开始）
+    pub fn matches(&mut self, index: u32, path: &str) -> bool {
+        if self.selector.files.is_none() && self.selector.entries.is_none() {
+            return true;
+        }
+
+        let mut hit = false;
+        if let Some(files) = &self.selector.files {
+            for (i, f) in files.iter().enumerate() {
+                if self.selector.path_matches(f, path) {
+                    self.files_hit[i] = true;
+                    hit = true;
+                }
+            }
+        }
+        if let Some(ranges) = &self.selector.entries {
+            for (i, r) in ranges.iter().enumerate() {
+                if r.contains(&(index + 1)) {
+                    self.entries_hit[i] = true;
+                    hit = true;
+                }
+            }
+        }
+        hit
+    }
+
+    /// 没有命中任何条目的选择项
+    pub fn unmatched(&self) -> Vec<String> {
+        let files = self.selector.files.iter().flatten()
+            .zip(&self.files_hit)
+            .filter(|(_, hit)| !**hit)
+            .map(|(f, _)| f.clone());
+        let entries = self.selector.entries.iter().flatten()
+            .zip(&self.entries_hit)
+            .filter(|(_, hit)| !**hit)
+            .map(|r| if r.0.start() == r.0.end() {
+                format!("#{}", r.0.start())
+            } else {
+                format!("#{}..{}", r.0.start(), r.0.end())
+            });
+        files.chain(entries).collect()
+    }
+}
+
+/// 解析单个序号或`10..20`形式的范围，范围包含两端
+pub fn parse_entry_range(spec: &str) -> Result<RangeInclusive<u32>, String> {
+    let parse_number = |s: &str| s.trim().parse::<u32>()
+        .map_err(|_| format!("无效的序号 '{}'", s.trim()));
+
+    let range = match spec.split_once("..") {
+        Some((start, end)) => {
+            let end = end.strip_prefix('=').unwrap_or(end);
+            parse_number(start)?..=parse_number(end)?
+        }
+        None => {
+            let n = parse_number(spec)?;
+            n..=n
+        }
+    };
+    if range.is_empty() || *range.start() == 0 {
+        return Err(format!("无效的序号范围 '{}'，序号从1开始", spec.trim()));
+    }
+    Ok(range)
+}
+
+/// 解析`old-prefix=new-prefix`形式的路径替换规则
+pub fn parse_transform(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((old, new)) if !old.is_empty() => Ok((old.to_string(), new.to_string())),
+        _ => Err(format!("无效的替换规则 '{}'，应为 OLD_PREFIX=NEW_PREFIX", spec)),
+    }
+}
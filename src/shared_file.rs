@@ -0,0 +1,82 @@
+//! 多个读取器共享的文件句柄
+//!
+//! 每个`SharedFile`记录自己的读取位置，按位置读取（Unix上为pread，Windows上为`seek_read`），
+//! 不依赖也不改变句柄的文件指针，因此多个线程可以同时通过同一个句柄读取不同位置而不需要加锁。
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+pub struct SharedFile {
+    file: Arc<File>,
+    /// 打开时的文件长度，块设备的长度不能从metadata得到，由定位到末尾得到
+    len: u64,
+    position: u64,
+}
+
+impl SharedFile {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.seek(SeekFrom::End(0))?;
+        Ok(Self { file: Arc::new(file), len, position: 0 })
+    }
+
+    /// 共享同一个句柄、从`position`开始读取的新读取器
+    pub fn at(&self, position: u64) -> Self {
+        Self { file: self.file.clone(), len: self.len, position }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// 从`offset`处读取，不使用也不改变读取位置
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        #[cfg(unix)]
+        return std::os::unix::fs::FileExt::read_at(&*self.file, buf, offset);
+        #[cfg(windows)]
+        return std::os::windows::fs::FileExt::seek_read(&*self.file, buf, offset);
+    }
+
+    /// 从`offset`处读满`buf`，不使用也不改变读取位置
+    pub fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "文件内容不完整")),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for SharedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.read_at(buf, self.position)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SharedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "定位到文件开头之前"))?;
+        Ok(self.position)
+    }
+}
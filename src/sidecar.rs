@@ -0,0 +1,159 @@
+//! 打包时从与文件相邻的附属文件（如`foo.png.meta.json`）读取条目属性，解包时写出同样格式的附属文件
+
+use chrono::{DateTime, Utc};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::error;
+use crate::hash::HashAlgo;
+use crate::metadata::FileInfo;
+use crate::pak::SourceFile;
+
+/// 附属文件的内容，已知的键写入条目的对应字段，其余的键原样记录为自定义属性
+#[derive(Deserialize, Debug, Clone)]
+pub struct Sidecar {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    langs: Vec<String>,
+    priority: Option<i32>,
+    valid_from: Option<DateTime<Utc>>,
+    valid_until: Option<DateTime<Utc>>,
+    mime: Option<String>,
+    /// 解包时写出的、由文件内容决定的字段，重新打包时重新计算，不作为自定义属性
+    #[serde(default, rename = "path")]
+    _path: Option<IgnoredAny>,
+    #[serde(default, rename = "size")]
+    _size: Option<IgnoredAny>,
+    #[serde(default, rename = "algo")]
+    _algo: Option<IgnoredAny>,
+    #[serde(default, rename = "hash")]
+    _hash: Option<IgnoredAny>,
+    #[serde(default, rename = "mtime")]
+    _mtime: Option<IgnoredAny>,
+    #[serde(flatten)]
+    attrs: Map<String, Value>,
+}
+
+/// 解包时写出的附属文件，键与`Sidecar`一致，重新打包时可以直接读取
+#[derive(Serialize)]
+struct SidecarExport<'a> {
+    path: &'a str,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    algo: Option<HashAlgo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtime: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    valid_from: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    valid_until: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    platforms: &'a [String],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    langs: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<i32>,
+    #[serde(flatten)]
+    attrs: &'a Map<String, Value>,
+}
+
+impl Sidecar {
+    /// 附属文件指定的优先级，优先于`--priority`清单中的规则
+    pub fn priority(&self) -> Option<i32> {
+        self.priority
+    }
+
+    /// 把属性记录到条目上，平台和语言与`--file-meta`指定的合并，其余字段覆盖规则得到的值
+    pub fn apply(&self, info: &mut FileInfo) {
+        for (tags, values) in [(&mut info.platforms, &self.platforms), (&mut info.langs, &self.langs)] {
+            for value in values {
+                if !tags.contains(value) {
+                    tags.push(value.clone());
+                }
+            }
+        }
+        if self.valid_from.is_some() || self.valid_until.is_some() {
+            info.valid_from = self.valid_from;
+            info.valid_until = self.valid_until;
+        }
+        if let Some(mime) = &self.mime {
+            info.mime = Some(mime.clone());
+        }
+        info.attrs.extend(self.attrs.clone());
+    }
+}
+
+/// 规范化附属文件的扩展名，缺少开头的`.`时补上
+pub fn parse_sidecar_ext(value: &str) -> Result<String, String> {
+    let ext = value.trim();
+    if ext.trim_start_matches('.').is_empty() {
+        return Err(format!("无效的附属文件扩展名 '{}'", value));
+    }
+    Ok(if ext.starts_with('.') { ext.to_string() } else { format!(".{}", ext) })
+}
+
+/// 读取每个文件的附属文件，`keep`为假时把附属文件本身从待打包的文件中去掉
+///
+/// 附属文件与对应文件的磁盘路径只差扩展名`ext`；没有对应文件的附属文件同样不打包，只记录警告。
+pub fn attach(sources: &mut Vec<SourceFile>, ext: &str, keep: bool) -> io::Result<()> {
+    let is_sidecar = |source: &SourceFile| source.path.as_os_str().to_string_lossy().ends_with(ext);
+    let mut used = HashSet::new();
+    for source in sources.iter_mut().filter(|s| !is_sidecar(s)) {
+        let mut path = source.path.clone().into_os_string();
+        path.push(ext);
+        let path = PathBuf::from(path);
+        if !path.is_file() {
+            continue;
+        }
+        let content = fs::read(&path).map_err(|e| error::with_path(e, &path))?;
+        let sidecar = serde_json::from_slice(&content)
+            .map_err(|e| error::with_path(io::Error::new(io::ErrorKind::InvalidData, format!("无效的附属文件: {}", e)), &path))?;
+        source.sidecar = Some(sidecar);
+        used.insert(path);
+    }
+    if !keep {
+        sources.retain(|source| {
+            if !is_sidecar(source) {
+                return true;
+            }
+            if !used.contains(&source.path) {
+                warn!(path = %source.path.display(), "附属文件没有对应的文件，不打包");
+            }
+            false
+        });
+    }
+    Ok(())
+}
+
+/// 在解包出的文件`file_path`旁写出附属文件，记录条目的属性、摘要和打包时的修改时间
+pub fn write_sidecar(file_path: &Path, ext: &str, info: &FileInfo) -> io::Result<()> {
+    let export = SidecarExport {
+        path: &info.path,
+        size: info.size,
+        algo: info.algo,
+        hash: info.hash.as_deref(),
+        mtime: info.mtime,
+        mime: info.mime.as_deref(),
+        valid_from: info.valid_from,
+        valid_until: info.valid_until,
+        platforms: &info.platforms,
+        langs: &info.langs,
+        priority: info.priority,
+        attrs: &info.attrs,
+    };
+    let mut path = file_path.as_os_str().to_owned();
+    path.push(ext);
+    let json = serde_json::to_string_pretty(&export)?;
+    fs::write(&path, json + "\n").map_err(|e| error::with_path(e, Path::new(&path)))
+}
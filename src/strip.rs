@@ -0,0 +1,169 @@
+//! 打包时剥离可执行文件中的调试信息（`strip-debug`特性）
+//!
+//! 识别ELF、PE和Mach-O文件并记录它们的调试信息标识。ELF文件中的`.debug_*`段被去掉后
+//! 打包，分离出的调试信息可以另外打包，按GDB的`.build-id/xx/yyyy.debug`布局存放。
+//! PE的调试信息通常在单独的PDB文件中，Mach-O在dSYM中，这两种格式只记录标识。
+
+use object::build::elf::{Builder, SectionData};
+use object::{elf, BinaryFormat, Object};
+use rayon::prelude::*;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tracing::warn;
+
+use crate::error;
+use crate::metadata::DebugInfo;
+use crate::pak::SourceFile;
+use crate::progress::WorkerProgress;
+
+/// 剥离结果，被丢弃时删除暂存的文件
+pub struct Stripped {
+    staging: TempDir,
+    /// 分离出的调试信息文件数
+    pub debug_files: usize,
+}
+
+impl Stripped {
+    /// 分离出的调试信息所在的目录，按调试信息包内的路径存放
+    pub fn debug_dir(&self) -> PathBuf {
+        self.staging.path().join("debug")
+    }
+}
+
+/// 识别可执行文件并剥离ELF文件的调试信息，`separate`为真时同时保存分离出的调试信息
+pub fn strip_sources(files: &mut [SourceFile], stored_paths: &[PathBuf], separate: bool) -> io::Result<Stripped> {
+    let staging = tempfile::tempdir()?;
+    let debug_dir = staging.path().join("debug");
+    let progress = WorkerProgress::new(
+        files.len() as u64,
+        "{spinner:.green} 剥离调试信息 [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}",
+        ""
+    );
+    let debug_files = files.par_iter_mut().zip(stored_paths).enumerate()
+        .map(|(index, (file, stored_path))| -> io::Result<usize> {
+            progress.start(file.path.to_string_lossy());
+            let staged = staging.path().join(index.to_string());
+            let stored = stored_path.to_string_lossy().replace('\\', "/");
+            let result = strip_file(file, &stored, &staged, separate.then_some(debug_dir.as_path()))
+                .map_err(|e| error::with_path(e, &file.path));
+            progress.done(1);
+            result
+        })
+        .sum::<io::Result<usize>>()?;
+    progress.finish_and_clear();
+    Ok(Stripped { staging, debug_files })
+}
+
+/// 文件开头是否为可执行文件格式的标记，避免把所有文件读入内存
+fn looks_executable(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; 4];
+    let n = fs::File::open(path)?.take(4).read(&mut magic)?;
+    Ok(n == 4 && (
+        magic == *b"\x7fELF"
+            || magic[..2] == *b"MZ"
+            || matches!(u32::from_be_bytes(magic), 0xfeedface | 0xfeedfacf | 0xcefaedfe | 0xcffaedfe)
+    ))
+}
+
+/// 处理单个文件，返回分离出的调试信息文件数
+fn strip_file(file: &mut SourceFile, stored: &str, staged: &Path, debug_dir: Option<&Path>) -> io::Result<usize> {
+    if !looks_executable(&file.path)? {
+        return Ok(0);
+    }
+    let data = fs::read(&file.path)?;
+    let Ok(object) = object::File::parse(&*data) else {
+        return Ok(0);
+    };
+    let (format, build_id) = match object.format() {
+        BinaryFormat::Elf => ("elf", object.build_id().ok().flatten().map(hex)),
+        BinaryFormat::MachO => ("macho", object.mach_uuid().ok().flatten().map(|id| hex(&id))),
+        BinaryFormat::Pe => ("pe", object.pdb_info().ok().flatten().map(|pdb| format!("{}{:x}", hex(&pdb.guid()), pdb.age()))),
+        _ => return Ok(0),
+    };
+    let mut info = DebugInfo { format: format.to_string(), build_id, original_size: None, debug_path: None };
+    if format != "elf" {
+        if object.has_debug_symbols() {
+            warn!(path = stored, format, "不支持剥离该格式中的调试信息，按原样打包");
+        }
+        file.debug = Some(info);
+        return Ok(0);
+    }
+
+    let Some(stripped) = remove_debug_sections(&data).map_err(invalid)? else {
+        file.debug = Some(info);
+        return Ok(0);
+    };
+    fs::write(staged, &stripped)?;
+    info.original_size = Some(file.size);
+    file.size = stripped.len() as u64;
+    file.path = staged.to_path_buf();
+
+    let mut separated = 0;
+    if let Some(debug_dir) = debug_dir {
+        let debug_path = match &info.build_id {
+            Some(id) if id.len() > 2 => format!(".build-id/{}/{}.debug", &id[..2], &id[2..]),
+            _ => format!("{}.debug", stored),
+        };
+        let output = debug_dir.join(&debug_path);
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&output, only_keep_debug(&data).map_err(invalid)?)?;
+        info.debug_path = Some(debug_path);
+        separated = 1;
+    }
+    file.debug = Some(info);
+    Ok(separated)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn invalid(e: object::build::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("无法处理ELF文件: {}", e))
+}
+
+fn is_debug_section(name: &[u8]) -> bool {
+    name.starts_with(b".debug") || name.starts_with(b".zdebug")
+}
+
+/// 去掉调试段和作用于它们的重定位段，没有调试段时返回`None`
+fn remove_debug_sections(data: &[u8]) -> object::build::Result<Option<Vec<u8>>> {
+    let mut builder = Builder::read(data)?;
+    let debug: Vec<_> = builder.sections.iter()
+        .filter(|s| is_debug_section(&s.name))
+        .map(|s| s.id())
+        .collect();
+    if debug.is_empty() {
+        return Ok(None);
+    }
+    for section in &mut builder.sections {
+        if debug.contains(&section.id()) || section.sh_info_section.is_some_and(|id| debug.contains(&id)) {
+            section.delete = true;
+        }
+    }
+    builder.delete_orphans();
+    let mut output = Vec::new();
+    builder.write(&mut output)?;
+    Ok(Some(output))
+}
+
+/// 只保留调试信息，与`objcopy --only-keep-debug`相同，其余载入内存的段只保留段头
+fn only_keep_debug(data: &[u8]) -> object::build::Result<Vec<u8>> {
+    let mut builder = Builder::read(data)?;
+    for section in &mut builder.sections {
+        if section.is_alloc() && section.sh_type != elf::SHT_NOTE {
+            section.sh_type = elf::SHT_NOBITS;
+            section.data = SectionData::UninitializedData(section.sh_size);
+        }
+    }
+    for symbol in &mut builder.dynamic_symbols {
+        symbol.delete = true;
+    }
+    let mut output = Vec::new();
+    builder.write(&mut output)?;
+    Ok(output)
+}
@@ -0,0 +1,119 @@
+//! 重写包时使用的临时文件
+//!
+//! 临时文件与包在同一目录下，名为`.<包名>.<随机字符>.xpak-tmp`，多个进程同时重写也不会冲突。
+//! 写入期间持有文件锁，写完并同步到磁盘后才替换原文件；出错时自动删除，进程崩溃留下的
+//! 临时文件在下次重写同一个包时或由`clean-temp`清除。
+
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tempfile::TempPath;
+
+use crate::common::{format_size, MAGIC_NUMBER};
+
+/// 临时文件名的后缀
+const TEMP_SUFFIX: &str = ".xpak-tmp";
+/// 旧版本使用的临时文件名后缀，只有内容以xpak的magic开头时才视为临时文件
+const LEGACY_SUFFIX: &str = ".tmp";
+
+/// 包所在的目录
+fn parent_dir(path: &Path) -> &Path {
+    path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."))
+}
+
+/// 包`target`对应的临时文件名前缀
+fn temp_prefix(target: &Path) -> String {
+    format!(".{}.", target.file_name().map(|n| n.to_string_lossy()).unwrap_or_default())
+}
+
+/// 写入中的临时文件，没有调用`commit`就被丢弃时删除
+pub struct TempFile {
+    path: TempPath,
+    /// 持有锁的句柄，`clean-temp`据此跳过其他进程正在写入的临时文件
+    lock: File,
+}
+
+impl TempFile {
+    /// 在`target`所在目录下创建临时文件，先清除之前重写同一个包时留下的临时文件
+    pub fn new(target: impl AsRef<Path>) -> io::Result<Self> {
+        let target = target.as_ref();
+        let dir = parent_dir(target);
+        let prefix = temp_prefix(target);
+        for (orphan, _) in orphans(dir)? {
+            if orphan.file_name().is_some_and(|n| n.to_string_lossy().starts_with(&prefix)) {
+                fs::remove_file(&orphan)?;
+                println!("已清除上次中断留下的临时文件 {}", orphan.display());
+            }
+        }
+        let file = tempfile::Builder::new().prefix(&prefix).suffix(TEMP_SUFFIX).tempfile_in(dir)?;
+        let (lock, path) = file.into_parts();
+        lock.lock()?;
+        Ok(Self { path, lock })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 把临时文件同步到磁盘后替换`target`
+    pub fn commit(self, target: impl AsRef<Path>) -> io::Result<()> {
+        let target = target.as_ref();
+        self.lock.sync_all()?;
+        self.path.persist(target).map_err(|e| e.error)?;
+        // 同步目录，确保rename本身也已写入磁盘；Windows不支持打开目录
+        #[cfg(unix)]
+        File::open(parent_dir(target))?.sync_all()?;
+        Ok(())
+    }
+}
+
+/// 文件是否是没有进程在写入的xpak临时文件
+fn is_orphan(path: &Path) -> io::Result<bool> {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let current = name.starts_with('.') && name.ends_with(TEMP_SUFFIX);
+    let legacy = !current && name.ends_with(LEGACY_SUFFIX);
+    if !current && !legacy {
+        return Ok(false);
+    }
+    let mut file = File::open(path)?;
+    if legacy {
+        let mut magic = [0u8; 4];
+        if file.read_exact(&mut magic).is_err() || magic != MAGIC_NUMBER {
+            return Ok(false);
+        }
+    }
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+/// `dir`下中断留下的临时文件及其大小
+fn orphans(dir: &Path) -> io::Result<Vec<(PathBuf, u64)>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && is_orphan(&entry.path())? {
+            found.push((entry.path(), entry.metadata()?.len()));
+        }
+    }
+    Ok(found)
+}
+
+/// 清除`dir`下重写包时中断留下的临时文件，跳过其他进程正在写入的临时文件
+pub fn clean_temp(dir: &str, dry_run: bool) -> io::Result<()> {
+    let found = orphans(Path::new(dir))?;
+    let total: u64 = found.iter().map(|(_, size)| size).sum();
+    for (path, size) in &found {
+        if !dry_run {
+            fs::remove_file(path)?;
+        }
+        println!("{}（{}）", path.display(), format_size(*size));
+    }
+    match dry_run {
+        true => println!("共 {} 个临时文件（{}），未删除", found.len(), format_size(total)),
+        false => println!("已清除 {} 个临时文件，释放 {}", found.len(), format_size(total)),
+    }
+    Ok(())
+}
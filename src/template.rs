@@ -0,0 +1,91 @@
+use chrono::Utc;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+
+use crate::git;
+
+/// 打包时展开描述和metadata中的`{{变量}}`
+///
+/// 内置变量有`date`、`datetime`、`version`、`file_count`、`total_size`、
+/// `git_sha`、`git_short_sha`和`git_branch`，`env.NAME`读取环境变量，
+/// `--var`指定的变量优先于内置变量。
+pub struct Template {
+    vars: HashMap<String, String>,
+    pattern: Regex,
+}
+
+impl Template {
+    pub fn new(user_vars: &[(String, String)], file_count: usize, total_size: u64) -> Self {
+        let now = Utc::now();
+        let mut vars = HashMap::from([
+            ("date".to_string(), now.format("%Y-%m-%d").to_string()),
+            ("datetime".to_string(), now.to_rfc3339()),
+            ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+            ("file_count".to_string(), file_count.to_string()),
+            ("total_size".to_string(), total_size.to_string()),
+        ]);
+        vars.extend(user_vars.iter().cloned());
+        Self { vars, pattern: Regex::new(r"\{\{\s*([A-Za-z0-9_.\-]+)\s*\}\}").unwrap() }
+    }
+
+    fn resolve(&self, name: &str) -> io::Result<String> {
+        if let Some(value) = self.vars.get(name) {
+            return Ok(value.clone());
+        }
+        if let Some(var) = name.strip_prefix("env.") {
+            return std::env::var(var).map_err(|_| io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("模板中的环境变量 {} 未设置", var)
+            ));
+        }
+        match name {
+            "git_sha" => git::run(None, &["rev-parse", "HEAD"]),
+            "git_short_sha" => git::run(None, &["rev-parse", "--short", "HEAD"]),
+            "git_branch" => git::run(None, &["rev-parse", "--abbrev-ref", "HEAD"]),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("未知的模板变量 {{{{{}}}}}", name))),
+        }
+    }
+
+    /// 展开字符串中的所有变量
+    pub fn expand(&self, text: &str) -> io::Result<String> {
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+        for captures in self.pattern.captures_iter(text) {
+            let whole = captures.get(0).unwrap();
+            result.push_str(&text[last..whole.start()]);
+            result.push_str(&self.resolve(&captures[1])?);
+            last = whole.end();
+        }
+        result.push_str(&text[last..]);
+        Ok(result)
+    }
+
+    /// 展开JSON中所有字符串值里的变量，键保持不变
+    pub fn expand_value(&self, value: &mut Value) -> io::Result<()> {
+        match value {
+            Value::String(s) => *s = self.expand(s)?,
+            Value::Array(items) => {
+                for item in items {
+                    self.expand_value(item)?;
+                }
+            }
+            Value::Object(map) => {
+                for item in map.values_mut() {
+                    self.expand_value(item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// 解析`KEY=VALUE`形式的模板变量
+pub fn parse_var(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("无效的变量 '{}'，应为 KEY=VALUE", spec)),
+    }
+}
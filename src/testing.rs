@@ -0,0 +1,241 @@
+//! 编写测试用的工具（`test-util`特性）
+//!
+//! 在内存中构造xpak包，按需要破坏其中的特定结构，再检查读取器的行为。构造出的包可以
+//! 直接交给`RawArchive`读取，也可以写成文件交给命令行工具，metadata的内容与`pak`写出的
+//! 一致（不记录摘要）。破坏结构的方法与`assert_entries`、`assert_read_fails`配合使用，
+//! 检查读取器能否读出预期的条目，或能否发现包已损坏。
+
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::{has_metadata_end, PathLength, RawArchive, MAGIC_METADATA_END, MAGIC_NUMBER};
+
+/// 测试包默认使用的格式版本，与`pak`默认写出的版本相同
+pub const DEFAULT_FORMAT_VERSION: &str = "1.4";
+
+/// 在内存中构造xpak包
+#[derive(Debug, Clone)]
+pub struct ArchiveBuilder {
+    format_version: String,
+    description: Option<String>,
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for ArchiveBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ArchiveBuilder {
+    pub fn new() -> Self {
+        Self { format_version: DEFAULT_FORMAT_VERSION.to_string(), description: None, entries: Vec::new() }
+    }
+
+    /// 追加一个条目，路径相同的条目按顺序都会写入
+    pub fn entry(mut self, path: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        self.entries.push((path.into(), data.into()));
+        self
+    }
+
+    /// 写入metadata的格式版本，决定条目头部中路径长度的编码和是否写入metadata结束标记
+    pub fn format_version(mut self, version: impl Into<String>) -> Self {
+        self.format_version = version.into();
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn build(&self) -> TestArchive {
+        let total_size: usize = self.entries.iter().map(|(_, data)| data.len()).sum();
+        let files: Vec<String> = self.entries.iter()
+            .map(|(path, data)| format!("{{\"path\":{},\"size\":{}}}", json_string(path), data.len()))
+            .collect();
+        let metadata = format!(
+            "{{\"version\":{},\"format_version\":{},\"created_at\":\"1970-01-01T00:00:00Z\",\"files_count\":{},\"total_size\":{},\"description\":{},\"common\":{{}},\"files\":[{}]}}",
+            json_string(env!("CARGO_PKG_VERSION")),
+            json_string(&self.format_version),
+            self.entries.len(),
+            total_size,
+            self.description.as_deref().map_or("null".to_string(), json_string),
+            files.join(",")
+        );
+
+        let mut bytes = MAGIC_NUMBER.to_vec();
+        bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        let metadata_range = bytes.len()..bytes.len() + metadata.len();
+        bytes.extend_from_slice(metadata.as_bytes());
+        let metadata_end = has_metadata_end(&self.format_version).then(|| {
+            bytes.extend_from_slice(&MAGIC_METADATA_END);
+            bytes.len() - MAGIC_METADATA_END.len()..bytes.len()
+        });
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+
+        let path_length = PathLength::for_version(&self.format_version);
+        let mut entries = Vec::with_capacity(self.entries.len());
+        for (path, data) in &self.entries {
+            let header = bytes.len();
+            bytes.extend_from_slice(&path_length.encode(path.len()));
+            bytes.extend_from_slice(path.as_bytes());
+            let size = bytes.len();
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            let start = bytes.len();
+            bytes.extend_from_slice(data);
+            entries.push(EntryLayout { header, size, data: start..bytes.len() });
+        }
+        TestArchive { bytes, metadata: metadata_range, metadata_end, entries }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// 条目在包中的位置
+#[derive(Debug, Clone)]
+struct EntryLayout {
+    /// 条目头部的起始位置
+    header: usize,
+    /// 头部中大小字段的位置
+    size: usize,
+    /// 条目内容的范围
+    data: Range<usize>,
+}
+
+/// 构造出的包，破坏结构的方法都返回新的包，便于在同一个包上尝试多种破坏方式
+#[derive(Debug, Clone)]
+pub struct TestArchive {
+    bytes: Vec<u8>,
+    metadata: Range<usize>,
+    metadata_end: Option<Range<usize>>,
+    entries: Vec<EntryLayout>,
+}
+
+impl TestArchive {
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// 可以交给`RawArchive::open`的读取器
+    pub fn cursor(&self) -> Cursor<&[u8]> {
+        Cursor::new(&self.bytes)
+    }
+
+    /// 写成文件，供按路径打开包的读取器和命令行工具使用
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, &self.bytes)
+    }
+
+    /// 条目`index`的头部在包中的起始位置
+    pub fn entry_offset(&self, index: usize) -> u64 {
+        self.entry(index).header as u64
+    }
+
+    fn entry(&self, index: usize) -> &EntryLayout {
+        self.entries.get(index)
+            .unwrap_or_else(|| panic!("测试包中只有 {} 个条目，没有条目 #{}", self.entries.len(), index))
+    }
+
+    /// 翻转包开头的Magic Number
+    pub fn flip_magic(mut self) -> Self {
+        self.bytes[0] ^= 0xff;
+        self
+    }
+
+    /// 翻转metadata结束标记，格式版本没有该标记时不改变
+    pub fn flip_metadata_end(mut self) -> Self {
+        if let Some(range) = self.metadata_end.clone() {
+            self.bytes[range.start] ^= 0xff;
+        }
+        self
+    }
+
+    /// 用无法解析的内容覆盖metadata，长度不变
+    pub fn garble_metadata(mut self) -> Self {
+        let range = self.metadata.clone();
+        self.bytes[range].fill(b'#');
+        self
+    }
+
+    /// 在条目`index`的内容中间截断，内容为空时截断在头部中间
+    pub fn truncate_entry(mut self, index: usize) -> Self {
+        let entry = self.entry(index);
+        let len = if entry.data.is_empty() {
+            entry.header + (entry.size - entry.header).div_ceil(2)
+        } else {
+            entry.data.start + entry.data.len() / 2
+        };
+        self.bytes.truncate(len);
+        self.entries.truncate(index + 1);
+        self
+    }
+
+    /// 改写条目`index`头部中的大小字段，使其与metadata和实际内容不一致
+    pub fn set_entry_size(mut self, index: usize, size: u32) -> Self {
+        let offset = self.entry(index).size;
+        self.bytes[offset..offset + 4].copy_from_slice(&size.to_le_bytes());
+        self
+    }
+
+    /// 翻转条目`index`内容中第`offset`个字节，用于检查摘要校验
+    pub fn flip_entry_byte(mut self, index: usize, offset: usize) -> Self {
+        let entry = self.entry(index);
+        assert!(offset < entry.data.len(), "条目 #{} 只有 {} 字节", index, entry.data.len());
+        let position = entry.data.start + offset;
+        self.bytes[position] ^= 0xff;
+        self
+    }
+}
+
+/// 用`RawArchive`读取包中的所有条目，返回路径和内容
+pub fn read_entries(bytes: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = RawArchive::from_bytes(bytes)?;
+    let mut entries = Vec::new();
+    while let Some(entry) = archive.next_entry()? {
+        let mut data = Vec::with_capacity(entry.size as usize);
+        archive.read_to_end(&mut data)?;
+        entries.push((entry.path, data));
+    }
+    Ok(entries)
+}
+
+/// 断言包可以完整读取，且条目的路径和内容与`expected`一致
+#[track_caller]
+pub fn assert_entries(bytes: &[u8], expected: &[(&str, &[u8])]) {
+    let entries = read_entries(bytes).unwrap_or_else(|e| panic!("读取测试包失败: {}", e));
+    let paths: Vec<&str> = entries.iter().map(|(path, _)| path.as_str()).collect();
+    let expected_paths: Vec<&str> = expected.iter().map(|(path, _)| *path).collect();
+    assert_eq!(paths, expected_paths, "条目路径不一致");
+    for ((path, data), (_, expected)) in entries.iter().zip(expected) {
+        assert!(data == expected, "条目 {} 的内容不一致: 读到 {} 字节，应为 {} 字节", path, data.len(), expected.len());
+    }
+}
+
+/// 断言读取包时报错，返回读取器给出的错误
+#[track_caller]
+pub fn assert_read_fails(bytes: &[u8]) -> io::Error {
+    match read_entries(bytes) {
+        Ok(entries) => panic!("读取测试包应当失败，但读到了 {} 个条目", entries.len()),
+        Err(e) => e,
+    }
+}
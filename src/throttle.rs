@@ -0,0 +1,92 @@
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::common::MB;
+
+/// 空闲超过该时间后重新计时，避免长时间空闲后瞬间突发
+const IDLE_RESET: Duration = Duration::from_secs(1);
+
+/// 按字节数限制IO速度，可以在多个线程间共享
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_sec: f64,
+    /// 计时起点和此后消耗的字节数
+    state: Mutex<(Instant, u64)>,
+}
+
+impl Throttle {
+    pub fn new(mb_per_sec: f64) -> Arc<Self> {
+        Arc::new(Self {
+            bytes_per_sec: mb_per_sec * MB as f64,
+            state: Mutex::new((Instant::now(), 0)),
+        })
+    }
+
+    /// 记录消耗的字节数，超出速度时休眠
+    pub fn consume(&self, bytes: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let elapsed = state.0.elapsed();
+            let expected = Duration::from_secs_f64(state.1 as f64 / self.bytes_per_sec);
+            if elapsed > expected + IDLE_RESET {
+                *state = (Instant::now(), 0);
+            }
+            state.1 += bytes as u64;
+            Duration::from_secs_f64(state.1 as f64 / self.bytes_per_sec).saturating_sub(state.0.elapsed())
+        };
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// 解析`--throttle`的值（MB/s）
+pub fn parse_throttle(value: &str) -> Result<f64, String> {
+    let value = value.trim().trim_end_matches("MB/s").trim();
+    match value.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("无效的速度 '{}'，应为大于0的 MB/s 数值", value)),
+    }
+}
+
+/// 经过限速器的Reader/Writer，未指定限速器时直接透传
+pub struct Throttled<T> {
+    inner: T,
+    throttle: Option<Arc<Throttle>>,
+}
+
+impl<T> Throttled<T> {
+    pub fn new(inner: T, throttle: Option<&Arc<Throttle>>) -> Self {
+        Self { inner, throttle: throttle.cloned() }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(throttle) = &self.throttle {
+            throttle.consume(n);
+        }
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Throttled<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(throttle) = &self.throttle {
+            throttle.consume(n);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,172 @@
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::time::Instant;
+
+use crate::common::{format_size, MB};
+use crate::frames::entry_ranges;
+use crate::hash::to_hex;
+use crate::reader::XpakReader;
+use crate::vpath::{lookup_key, PathPolicy};
+
+/// 估算寻道耗时使用的单次寻道时间（毫秒）
+const HDD_SEEK_MS: f64 = 10.0;
+const SSD_SEEK_MS: f64 = 0.1;
+
+/// 运行时记录的文件访问顺序
+pub struct AccessTrace {
+    /// 按访问顺序排列的路径，可能重复
+    pub paths: Vec<String>,
+    /// 由记录内容计算的标识，写入metadata用于判断包按哪份记录排列
+    pub id: String,
+}
+
+impl AccessTrace {
+    /// 读取每行一个路径的访问记录，忽略空行和`#`开头的注释
+    pub fn load(path: &str) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let paths: Vec<String> = content.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| lookup_key(line, PathPolicy::Exact))
+            .collect();
+        let mut hasher = Sha256::new();
+        for path in &paths {
+            hasher.update(path.as_bytes());
+            hasher.update(b"\n");
+        }
+        let id = to_hex(&hasher.finalize()[..8]);
+        Ok(Self { paths, id })
+    }
+
+    /// 按首次访问的顺序排列`paths`，未被访问的保持原有顺序排在后面，返回新顺序对应的原序号
+    pub fn order(&self, paths: &[String]) -> Vec<usize> {
+        let positions: HashMap<String, usize> = paths.iter().enumerate()
+            .map(|(i, p)| (lookup_key(p, PathPolicy::Exact), i))
+            .collect();
+        let mut placed = vec![false; paths.len()];
+        let mut order = Vec::with_capacity(paths.len());
+        for path in &self.paths {
+            if let Some(&i) = positions.get(path) {
+                if !placed[i] {
+                    placed[i] = true;
+                    order.push(i);
+                }
+            }
+        }
+        order.extend((0..paths.len()).filter(|&i| !placed[i]));
+        order
+    }
+}
+
+/// 按`order`给出的原序号重新排列
+pub fn apply_order<T>(items: &mut Vec<T>, order: &[usize]) {
+    let mut slots: Vec<Option<T>> = items.drain(..).map(Some).collect();
+    items.extend(order.iter().map(|&i| slots[i].take().unwrap()));
+}
+
+/// 按访问顺序读取时的寻道统计
+#[derive(Default)]
+struct SeekStats {
+    sequential: u32,
+    seeks: u32,
+    backward: u32,
+    distance: u64,
+}
+
+fn simulate(trace: &AccessTrace, ranges: &HashMap<&str, Range<u64>>) -> SeekStats {
+    let mut stats = SeekStats::default();
+    let mut position: Option<u64> = None;
+    for range in trace.paths.iter().filter_map(|p| ranges.get(p.as_str())) {
+        match position {
+            Some(end) if end == range.start => stats.sequential += 1,
+            Some(end) => {
+                stats.seeks += 1;
+                stats.distance += end.abs_diff(range.start);
+                if range.start < end {
+                    stats.backward += 1;
+                }
+            }
+            // 第一次访问总是需要定位
+            None => stats.seeks += 1,
+        }
+        position = Some(range.end);
+    }
+    stats
+}
+
+fn print_stats(title: &str, stats: &SeekStats) {
+    println!("{}:", title);
+    println!("  顺序读取 {} 次，寻道 {} 次（向后 {} 次），跨越 {}", stats.sequential, stats.seeks, stats.backward, format_size(stats.distance));
+    println!(
+        "  估计寻道耗时: 机械硬盘约 {:.1} ms，固态硬盘约 {:.1} ms",
+        stats.seeks as f64 * HDD_SEEK_MS, stats.seeks as f64 * SSD_SEEK_MS
+    );
+}
+
+/// 按访问记录实际读取条目，统计`capacity`字节的解压结果缓存的命中情况
+fn replay_with_cache(input: &str, trace: &AccessTrace, capacity: u64) -> io::Result<()> {
+    let reader = XpakReader::open(input)?.with_cache(capacity);
+    let started = Instant::now();
+    let mut skipped = 0;
+    for path in &trace.paths {
+        match reader.read(path) {
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::Unsupported) => skipped += 1,
+            Err(e) => return Err(e),
+        }
+    }
+    let elapsed = started.elapsed();
+    let stats = reader.cache_stats().unwrap_or_default();
+    println!(
+        "缓存 {}: 命中 {} 次，未命中 {} 次，命中率 {:.1}%，淘汰 {} 个条目，读取耗时 {:.1} ms",
+        format_size(capacity), stats.hits, stats.misses, stats.hit_rate() * 100.0, stats.evictions,
+        elapsed.as_secs_f64() * 1000.0
+    );
+    println!("  结束时缓存了 {} 个条目，共 {}", stats.entries, format_size(stats.bytes));
+    if skipped > 0 {
+        println!("  {} 次访问的文件不在包内或已加密，没有读取", skipped);
+    }
+    Ok(())
+}
+
+/// 比较访问记录与包内条目的排列，估算读取时的寻道开销
+///
+/// 指定`cache_mb`时还按记录实际读取一遍，统计该大小（MB）的解压结果缓存的命中率。
+pub fn analyze_trace(input: &str, trace_path: &str, cache_mb: Option<u64>) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    let metadata = &reader.metadata;
+    let trace = AccessTrace::load(trace_path)?;
+
+    let paths: Vec<String> = metadata.files.iter().map(|f| f.path.clone()).collect();
+    let current: HashMap<&str, Range<u64>> = paths.iter().map(String::as_str).zip(entry_ranges(metadata)).collect();
+    let distinct: HashSet<&str> = trace.paths.iter().map(String::as_str).collect();
+    let missing = distinct.iter().filter(|p| !current.contains_key(*p)).count();
+
+    println!("访问记录 {}: {} 次访问，{} 个不同文件，{} 个不在包内", trace.id, trace.paths.len(), distinct.len(), missing);
+    match metadata.access_profile.as_deref() {
+        Some(id) if id == trace.id => println!("包已按此访问记录排列"),
+        Some(id) => println!("包按另一份访问记录 {} 排列", id),
+        None => println!("包没有按访问记录排列"),
+    }
+    print_stats("当前排列", &simulate(&trace, &current));
+
+    // 按访问记录重新排列后的条目范围
+    let order = trace.order(&paths);
+    let mut offset = 0u64;
+    let optimized: HashMap<&str, Range<u64>> = order.iter()
+        .map(|&i| {
+            let len = current[paths[i].as_str()].end - current[paths[i].as_str()].start;
+            let range = offset..offset + len;
+            offset += len;
+            (paths[i].as_str(), range)
+        })
+        .collect();
+    print_stats("按访问记录排列后", &simulate(&trace, &optimized));
+    if let Some(mb) = cache_mb {
+        replay_with_cache(input, &trace, mb * MB as u64)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+pub use xpak::{read_trailer, read_trailer_at, Trailer, TrailerRecord, TRAILER_MAGIC};
+
+pub fn write_trailer(writer: &mut impl Write, records: &[TrailerRecord]) -> io::Result<()> {
+    let mut total = 0u64;
+    for record in records {
+        writer.write_all(&record.tag)?;
+        writer.write_all(&(record.data.len() as u64).to_le_bytes())?;
+        writer.write_all(&record.data)?;
+        total += 16 + record.data.len() as u64;
+    }
+    writer.write_all(&total.to_le_bytes())?;
+    writer.write_all(&TRAILER_MAGIC)?;
+    Ok(())
+}
+
+/// 数据区段和尾部区段都没有用到的字节范围，`data_end`为最后一个条目的结束位置
+///
+/// 中断的写入可能在数据区段和尾部区段之间或尾部区段之后留下多余数据。
+pub fn unaccounted(data_end: u64, trailer: Option<&Trailer>, file_len: u64) -> Vec<Range<u64>> {
+    let ranges = match trailer {
+        Some(trailer) => [Some(data_end..trailer.offset), Some(trailer.end()..file_len)],
+        None => [Some(data_end..file_len), None],
+    };
+    ranges.into_iter().flatten().filter(|r| r.start < r.end).collect()
+}
@@ -1,63 +1,317 @@
-use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::io::{self, BufRead, Read, Write, Seek, SeekFrom, BufReader, BufWriter};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::fs::{self, File};
 use std::path::Path;
 use std::sync::Arc;
-use serde_json;
+use std::time::Instant;
+use std::collections::{BTreeMap, HashMap};
+use std::process::Command;
+use chrono::Utc;
 use indicatif::{ProgressBar, ProgressStyle};
+use tracing::{error, info, warn};
 
-use crate::common::{BUFFER_SIZE, GB, KB, MAGIC_METADATA_END, MAGIC_NUMBER, MB};
-use crate::metadata::XpakMetadata;
+use crate::common::{format_size, PathLength, BUFFER_SIZE, MAGIC_METADATA_END, MAGIC_NUMBER};
+use xpak::{check_entry_count, read_entry_header, read_metadata};
+use crate::metadata::{write_banner, FileInfo, XpakMetadata};
+use crate::listing::{write_paths_nul, write_table, ListFormat, Output};
+use crate::report::{EntryReport, EntryStatus, ExtractionReport};
+use crate::hash::HashingWriter;
+use crate::compress::FrameDecoder;
+use crate::eula::require_acceptance;
+use crate::conflict::{CollisionPolicy, ConflictResolver, ExistingPolicy, Resolution};
+use crate::validity::{availability, Availability};
+use crate::file_meta::{for_langs, for_platform};
+use crate::progressive::complete_priority;
+use crate::crypto::{decrypt_stream, load_identities, EntryKey};
+use crate::reader::{Consistency, XpakReader};
+use crate::sidecar::write_sidecar;
+use crate::selection::{EntrySelector, SelectionTracker};
+use crate::overlay::OverlayReader;
+use crate::output_template::OutputTemplate;
+use crate::vpath::PathPolicy;
+use crate::throttle::{Throttle, Throttled};
+use crate::logging::Heartbeat;
+use crate::migrate::{has_metadata_end, parse_metadata, probe_version};
+use crate::hooks::{run_post_hooks, run_post_summary_hook, ExtractedFile};
+use crate::error;
+use crate::bundle;
+use crate::device::available_space;
+
+/// 解包选项
+#[derive(Default)]
+pub struct UnpackOptions {
+    /// 要解包的条目
+    pub selector: EntrySelector,
+    /// 解包这些分组中的条目，与`selector`中的选择合并
+    pub bundles: Vec<String>,
+    /// 选择没有匹配任何文件时不报错
+    pub ignore_missing: bool,
+    /// 去掉输出路径开头的N层目录
+    pub strip_components: usize,
+    /// 输出路径的前缀替换规则 (old, new)，按顺序使用第一条匹配的规则
+    pub transforms: Vec<(String, String)>,
+    /// 输出路径模板，在`strip_components`和`transforms`之后应用
+    pub output_template: Option<OutputTemplate>,
+    /// 去掉输出路径中的目录，所有文件解包到输出目录下
+    pub flat: bool,
+    /// 扁平化或按模板解包时输出路径相同的处理方式
+    pub on_collision: CollisionPolicy,
+    /// 解包报告的输出路径
+    pub report: Option<String>,
+    /// key_id -> 口令
+    pub keys: HashMap<String, String>,
+    /// age身份文件路径
+    pub identities: Vec<String>,
+    /// 解包后对每个文件和最终结果运行的命令
+    pub post_hook: Option<String>,
+    /// 同时运行的解包后钩子数量
+    pub parallel_hooks: usize,
+    /// 依次叠加在输入包之上的包，同一路径以最后一层为准
+    pub layers: Vec<String>,
+    /// 叠加时判断路径是否相同的方式
+    pub path_policy: PathPolicy,
+    /// 读写速度上限（MB/s）
+    pub throttle: Option<f64>,
+    /// 跳过不在有效期内的条目
+    pub respect_validity: bool,
+    /// 只解包用于该平台的条目和没有标记平台的条目
+    pub platform: Option<String>,
+    /// 只解包这些语言的条目和没有标记语言的条目
+    pub langs: Option<Vec<String>>,
+    /// 包尚未下载完整时只解包已经完整的条目
+    pub available_only: bool,
+    /// 已接受包内的许可协议
+    pub accept_eula: bool,
+    /// 输出文件已存在时的处理方式
+    pub on_existing: ExistingPolicy,
+    /// 条目头部与metadata不一致时的处理方式
+    pub consistency: Consistency,
+    /// 在每个解包出的文件旁写出带有该扩展名的附属文件，记录条目的metadata
+    pub write_sidecars: Option<String>,
+    /// 解包前不检查输出目录所在磁盘的可用空间
+    pub no_space_check: bool,
+    /// 解包写出的总大小上限，下一个文件会超出时停止解包
+    pub max_disk_usage: Option<u64>,
+}
+
+/// 解包多个层时累计的状态
+struct UnpackState<'a> {
+    tracker: SelectionTracker<'a>,
+    read_throttle: Option<Arc<Throttle>>,
+    write_throttle: Option<Arc<Throttle>>,
+    files_unpacked: u32,
+    locked_entries: u32,
+    hash_mismatches: Vec<String>,
+    extracted_files: Vec<ExtractedFile>,
+    conflicts: ConflictResolver,
+    /// 扁平化或按模板展开的输出路径 -> 条目路径，用于发现不同条目得到的相同路径
+    outputs: HashMap<String, String>,
+    /// 已写出的字节数，用于`max_disk_usage`
+    bytes_written: u64,
+}
 
 pub fn unpack_files(
     input: &str, 
     output: &str, 
-    selected_files: Option<&[String]>,
+    options: &UnpackOptions,
     running: Arc<AtomicBool>
 ) -> io::Result<()> {
-    let output_path = Path::new(output);
-    fs::create_dir_all(output_path)?;
+    let mut report = ExtractionReport::new(input, output);
+    let result = unpack_layers(input, output, options, &mut report, running);
 
-    let mut pak_file = BufReader::with_capacity(BUFFER_SIZE, File::open(input)?);
+    // 无论成功与否都写出报告，便于审计
+    if let Some(path) = options.report.as_deref() {
+        report.finish(result.as_ref().err());
+        report.write_to(path)?;
+        println!("解包报告已写入 {}", path);
+    }
 
-    // 验证Magic Number
-    let mut magic = [0u8; 4];
-    pak_file.read_exact(&mut magic)?;
-    if &magic != MAGIC_NUMBER {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "无效的文件格式"));
+    result
+}
+
+/// 依次解包输入包和叠加的各层，被上层覆盖的条目跳过
+fn unpack_layers(
+    input: &str,
+    output: &str,
+    options: &UnpackOptions,
+    report: &mut ExtractionReport,
+    running: Arc<AtomicBool>
+) -> io::Result<()> {
+    let layers: Vec<String> = std::iter::once(input.to_string()).chain(options.layers.iter().cloned()).collect();
+
+    // 解包任何内容之前确认各层的许可协议，同时合并各层的分组
+    let mut bundles: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut metadatas = Vec::with_capacity(layers.len());
+    for path in &layers {
+        let metadata = XpakReader::open(path)?.metadata;
+        if let Some(text) = &metadata.eula {
+            require_acceptance(path, text, options.accept_eula)?;
+        }
+        for (name, paths) in &metadata.bundles {
+            bundles.entry(name.clone()).or_default().extend(paths.iter().cloned());
+        }
+        metadatas.push(metadata);
+    }
+    let selector = if options.bundles.is_empty() {
+        options.selector.clone()
+    } else {
+        options.selector.with_files(bundle::resolve(&bundles, &options.bundles)?)
+    };
+    fs::create_dir_all(output)?;
+    if !options.no_space_check {
+        check_disk_space(&metadatas, output, &selector, options)?;
     }
 
-    // 读取metadata
-    let mut meta_len_bytes = [0u8; 4];
-    pak_file.read_exact(&mut meta_len_bytes)?;
-    let meta_len = u32::from_le_bytes(meta_len_bytes) as usize;
-    
-    let mut metadata_bytes = vec![0u8; meta_len];
-    pak_file.read_exact(&mut metadata_bytes)?;
-    
-    // 验证metadata结束标记
-    let mut metadata_end = [0u8; 8];
-    pak_file.read_exact(&mut metadata_end)?;
-    if metadata_end != MAGIC_METADATA_END {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "无效的metadata结束标记"));
+    let overlay = if layers.len() > 1 { Some(OverlayReader::open(&layers, options.path_policy)?) } else { None };
+    let mut state = UnpackState {
+        tracker: selector.tracker(),
+        read_throttle: options.throttle.map(Throttle::new),
+        write_throttle: options.throttle.map(Throttle::new),
+        files_unpacked: 0,
+        locked_entries: 0,
+        hash_mismatches: Vec::new(),
+        extracted_files: Vec::new(),
+        conflicts: ConflictResolver::new(options.on_existing),
+        outputs: HashMap::new(),
+        bytes_written: 0,
+    };
+
+    for (layer, path) in layers.iter().enumerate() {
+        unpack_entries(path, output, options, report, &running, &mut state, overlay.as_ref().map(|o| (o, layer)))
+            .map_err(|e| match e.kind() {
+                // 数据损坏时标明出错的包
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => error::with_path(e, path),
+                _ => e,
+            })?;
     }
 
-    let metadata: XpakMetadata = serde_json::from_slice(&metadata_bytes)?;
-    
-    // 读取文件数量
-    let mut count_bytes = [0u8; 4];
-    pak_file.read_exact(&mut count_bytes)?;
-    let count = u32::from_le_bytes(count_bytes);
+    println!("共解包 {} 个文件", state.files_unpacked);
+    if state.locked_entries > 0 {
+        println!("{} 个加密文件因缺少密钥被跳过", state.locked_entries);
+    }
 
-    if count != metadata.files_count {
+    // 检查没有匹配任何条目的选择
+    let unmatched = state.tracker.unmatched();
+    if !unmatched.is_empty() {
+        println!("以下选择没有匹配任何文件: {}", unmatched.join(", "));
+        if !options.ignore_missing {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} 个选择没有匹配任何文件", unmatched.len())
+            ));
+        }
+    }
+
+    if !state.hash_mismatches.is_empty() {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
-            format!("文件数量不匹配：metadata中为{}，实际为{}", metadata.files_count, count)
+            format!("{} 个文件哈希校验失败: {}", state.hash_mismatches.len(), state.hash_mismatches.join(", "))
         ));
     }
 
+    // 所有文件都已写出并校验通过后再运行钩子
+    if let Some(command) = options.post_hook.as_deref() {
+        info!(files = state.extracted_files.len(), "运行解包后钩子");
+        run_post_hooks(command, &state.extracted_files, options.parallel_hooks)?;
+        run_post_summary_hook(command, output, &report.summary)?;
+    }
+    Ok(())
+}
+
+/// 估算要解包的文件的总大小，与输出目录所在磁盘的可用空间比较
+///
+/// 按metadata中记录的原始大小计算，压缩条目解压后的大小已包含在内；叠加解包时被上层覆盖的
+/// 条目也计算在内，估算偏大。空间不足时在终端中询问是否继续，非交互环境下报错。
+fn check_disk_space(metadatas: &[XpakMetadata], output: &str, selector: &EntrySelector, options: &UnpackOptions) -> io::Result<()> {
+    let mut required = 0;
+    for metadata in metadatas {
+        let live = metadata.live_entries();
+        let mut tracker = selector.tracker();
+        for (index, file) in metadata.files.iter().enumerate() {
+            if live[index]
+                && tracker.matches(index as u32, &file.path)
+                && options.platform.as_deref().is_none_or(|p| for_platform(file, p))
+                && options.langs.as_deref().is_none_or(|l| for_langs(file, l))
+            {
+                required += file.size;
+            }
+        }
+    }
+    let available = available_space(Path::new(output))?;
+    if required <= available {
+        return Ok(());
+    }
+
+    let message = format!("{} 所在磁盘的可用空间为 {}，解包需要约 {}", output, format_size(available), format_size(required));
+    let term = console::Term::stderr();
+    if !term.is_term() {
+        return Err(io::Error::new(io::ErrorKind::StorageFull, format!("{}，确认空间足够时可使用 --no-space-check 跳过检查", message)));
+    }
+    term.write_line(&message)?;
+    term.write_str("磁盘空间可能不足，是否继续解包？[y/N] ")?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::StorageFull, "磁盘空间不足，已取消解包"))
+    }
+}
+
+/// 解包一个包，`overlay`为叠加解包时的所有层和当前层的序号
+fn unpack_entries(
+    input: &str,
+    output: &str,
+    options: &UnpackOptions,
+    report: &mut ExtractionReport,
+    running: &AtomicBool,
+    state: &mut UnpackState,
+    overlay: Option<(&OverlayReader, usize)>
+) -> io::Result<()> {
+    let output_path = Path::new(output);
+
+    let mut reader = XpakReader::open(input)?.with_consistency(options.consistency);
+
+    if reader.count != reader.metadata.files_count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("文件数量不匹配：metadata中为{}，实际为{}", reader.metadata.files_count, reader.count)
+        ));
+    }
+
+    // 下载未完成的包只读取完整的条目
+    if options.available_only {
+        let missing = reader.limit_to_complete()?;
+        if missing > 0 {
+            println!("{} 尚未下载完整，解包前 {} 个条目，跳过其余 {} 个", input, reader.count, missing);
+            if let Some(priority) = complete_priority(&reader.metadata.tiers, reader.count) {
+                println!("优先级不低于 {} 的条目已经完整", priority);
+            }
+        }
+    }
+
+    // metadata中记录的条目信息，用于校验摘要和解密；按条目序号对应，
+    // 同一路径的多个条目（追加的新内容、重复路径）各自使用自己的记录
+    let file_infos: Vec<FileInfo> = reader.metadata.files.clone();
+
+    // 派生提供的密钥，包内未使用的密钥直接忽略
+    let mut entry_keys = HashMap::new();
+    for (key_id, passphrase) in &options.keys {
+        if let Some(info) = reader.metadata.keys.get(key_id) {
+            entry_keys.insert(key_id.clone(), EntryKey::from_info(key_id, passphrase, info)?);
+        }
+    }
+    if !options.identities.is_empty() {
+        let identities = load_identities(&options.identities)?;
+        for (key_id, info) in reader.metadata.keys.iter().filter(|(_, info)| info.wrapped.is_some()) {
+            if let Some(key) = EntryKey::from_identities(key_id, info, &identities)? {
+                entry_keys.insert(key_id.clone(), key);
+            }
+        }
+    }
+
     // 创建进度条
-    let progress = ProgressBar::new(metadata.total_size);
+    let progress = ProgressBar::new(reader.metadata.total_size);
     progress.set_style(ProgressStyle::default_bar()
         .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
         .unwrap()
@@ -65,90 +319,400 @@ pub fn unpack_files(
 
     // 预分配缓冲区
     let mut buffer = vec![0u8; BUFFER_SIZE];
-    let mut files_unpacked = 0;
+    let mut heartbeat = Heartbeat::new("unpak");
+    // 所有条目按同一时刻判断有效期
+    let now = Utc::now();
+    info!(input, output, entries = reader.count, "开始解包");
 
-    for _ in 0..count {
+    while let Some(entry) = reader.next_entry()? {
         if !running.load(Ordering::SeqCst) {
             return Err(io::Error::new(io::ErrorKind::Interrupted, "操作被用户取消"));
         }
 
-        // 读取文件路径
-        let mut path_len_bytes = [0u8; 4];
-        pak_file.read_exact(&mut path_len_bytes)?;
-        let path_len = u32::from_le_bytes(path_len_bytes) as usize;
+        let started = Instant::now();
+
+        // 跳过已被之后追加的条目替换或删除的条目
+        if !reader.is_live(entry.index) {
+            report.push(EntryReport::skipped(entry.index, entry.path, started, Some("已被替换或删除".to_string())));
+            progress.inc(entry.size);
+            continue;
+        }
 
-        let mut path_bytes = vec![0u8; path_len];
-        pak_file.read_exact(&mut path_bytes)?;
-        let path_str = String::from_utf8(path_bytes)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // 跳过被上层覆盖的条目
+        if let Some((overlay, layer)) = overlay {
+            if let Some(top) = overlay.shadowed_by(layer, &entry.path) {
+                let reason = format!("被 {} 覆盖", overlay.layer_name(top));
+                report.push(EntryReport::skipped(entry.index, entry.path, started, Some(reason)));
+                progress.inc(entry.size);
+                continue;
+            }
+        }
 
-        // 读取文件大小
-        let mut content_len_bytes = [0u8; 4];
-        pak_file.read_exact(&mut content_len_bytes)?;
-        let content_len = u32::from_le_bytes(content_len_bytes) as usize;
+        // 检查是否需要解包此文件，内容由reader在定位下一个条目时跳过
+        if !state.tracker.matches(entry.index, &entry.path) {
+            report.push(EntryReport::skipped(entry.index, entry.path, started, None));
+            progress.inc(entry.size);
+            continue;
+        }
 
-        // 检查是否需要解包此文件
-        if selected_files.map_or(true, |files| files.iter().any(|f| path_str == *f)) {
-            let file_path = output_path.join(&path_str);
-            if let Some(parent) = file_path.parent() {
-                fs::create_dir_all(parent)?;
+        let info = file_infos.get(entry.index as usize);
+        let encryption = info.and_then(|f| f.encryption.as_ref());
+
+        // 跳过不在有效期内的条目
+        if options.respect_validity {
+            let availability = info.map_or(Availability::Valid, |f| availability(f, now));
+            if availability != Availability::Valid {
+                report.push(EntryReport::skipped(entry.index, entry.path, started, Some(availability.to_string())));
+                progress.inc(entry.size);
+                continue;
             }
+        }
 
-            let file = File::create(&file_path)?;
-            let mut writer = BufWriter::with_capacity(BUFFER_SIZE, file);
-
-            if content_len >= BUFFER_SIZE {
-                // 大文件使用 io::copy
-                let mut limited_reader = (&mut pak_file).take(content_len as u64);
-                io::copy(&mut limited_reader, &mut writer)?;
-            } else {
-                // 小文件使用缓冲区
-                let mut remaining = content_len;
-                while remaining > 0 {
-                    let to_read = remaining.min(BUFFER_SIZE);
-                    let buf = &mut buffer[..to_read];
-                    pak_file.read_exact(buf)?;
-                    writer.write_all(buf)?;
-                    remaining -= to_read;
-                }
+        // 跳过只用于其他平台的条目
+        if let (Some(platform), Some(info)) = (options.platform.as_deref(), info) {
+            if !for_platform(info, platform) {
+                let reason = format!("仅用于平台 {}", info.platforms.join(", "));
+                report.push(EntryReport::skipped(entry.index, entry.path, started, Some(reason)));
+                progress.inc(entry.size);
+                continue;
             }
+        }
 
-            writer.flush()?;
-            files_unpacked += 1;
-        } else {
-            // 跳过不需要的文件
-            pak_file.seek_relative(content_len as i64)?;
+        // 跳过其他语言的条目
+        if let (Some(langs), Some(info)) = (options.langs.as_deref(), info) {
+            if !for_langs(info, langs) {
+                let reason = format!("语言为 {}", info.langs.join(", "));
+                report.push(EntryReport::skipped(entry.index, entry.path, started, Some(reason)));
+                progress.inc(entry.size);
+                continue;
+            }
         }
 
-        progress.inc(content_len as u64);
+        // 没有对应密钥的加密条目直接跳过
+        if let Some(encryption) = encryption.filter(|e| !entry_keys.contains_key(&e.key_id)) {
+            let reason = format!("缺少密钥 {}", encryption.key_id);
+            report.push(EntryReport::skipped(entry.index, entry.path, started, Some(reason)));
+            state.locked_entries += 1;
+            progress.inc(entry.size);
+            continue;
+        }
+
+        // 计算输出路径，层级不足的条目跳过
+        let Some(mut relative_path) = output_relative_path(&entry.path, options) else {
+            let reason = format!("路径层级不足 {} 层", options.strip_components);
+            report.push(EntryReport::skipped(entry.index, entry.path, started, Some(reason)));
+            progress.inc(entry.size);
+            continue;
+        };
+        if let Some(template) = &options.output_template {
+            relative_path = template.render(&entry, &relative_path, info)?;
+        } else if options.flat {
+            relative_path = relative_path.rsplit('/').next().unwrap_or_default().to_string();
+        }
+        // 扁平化或按模板解包时不同条目可能得到同一路径，后者会覆盖前者
+        if options.flat || options.output_template.is_some() {
+            match claim_output(&mut state.outputs, relative_path, &entry.path, options.on_collision)? {
+                Some(path) => relative_path = path,
+                None => {
+                    report.push(EntryReport::skipped(entry.index, entry.path, started, Some("与之前的条目输出路径相同".to_string())));
+                    progress.inc(entry.size);
+                    continue;
+                }
+            }
+        }
+        // 超出上限时在写出之前停止，不留下不完整的文件
+        let size = info.map_or(entry.size, |f| f.size);
+        if let Some(limit) = options.max_disk_usage.filter(|limit| state.bytes_written + size > *limit) {
+            let written = format!("已解包 {} 个文件（{}）", state.files_unpacked, format_size(state.bytes_written));
+            let reason = format!("超过 --max-disk-usage 限制的 {}", format_size(limit));
+            report.push(EntryReport::skipped(entry.index, entry.path.clone(), started, Some(reason)));
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!("{}，解包 {}（{}）会超过 --max-disk-usage 限制的 {}，已停止解包", written, entry.path, format_size(size), format_size(limit))
+            ));
+        }
+        let file_path = match state.conflicts.resolve(&output_path.join(&relative_path), &progress)? {
+            Resolution::Write(path) => path,
+            Resolution::Skip => {
+                report.push(EntryReport::skipped(entry.index, entry.path, started, Some("输出文件已存在".to_string())));
+                progress.inc(entry.size);
+                continue;
+            }
+        };
+        let decryption = encryption.map(|e| (&entry_keys[&e.key_id], e.nonce.as_str()));
+        let mut source = Throttled::new(&mut reader, state.read_throttle.as_ref());
+        match write_entry(&mut source, &file_path, entry.size, info, decryption, state.write_throttle.as_ref(), &mut buffer) {
+            Ok((written, digest)) => {
+                // 校验摘要
+                let expected = info.and_then(|f| f.hash.as_ref());
+                let hash_verified = expected.zip(digest).map(|(hash, digest)| *hash == digest);
+                if hash_verified == Some(false) {
+                    progress.suspend(|| println!("警告：{} 哈希校验失败", entry.path));
+                    warn!(path = entry.path, "哈希校验失败");
+                    state.hash_mismatches.push(entry.path.clone());
+                }
+                heartbeat.tick(&entry.path, written);
+                if let (Some(ext), Some(info)) = (options.write_sidecars.as_deref(), info) {
+                    write_sidecar(&file_path, ext, info)?;
+                }
+                if options.post_hook.is_some() {
+                    state.extracted_files.push(ExtractedFile { path: entry.path.clone(), output: file_path.clone(), size: written });
+                }
+                report.push(EntryReport {
+                    index: entry.index,
+                    path: entry.path,
+                    status: EntryStatus::Extracted,
+                    output_path: Some(file_path.to_string_lossy().to_string()),
+                    bytes_written: written,
+                    hash_verified,
+                    duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+                    error: None,
+                    reason: None,
+                });
+                state.files_unpacked += 1;
+                state.bytes_written += written;
+            }
+            Err(e) => {
+                let e = error::with_path(e, &file_path);
+                error!(path = entry.path, error = %e, "解包失败");
+                report.push(EntryReport {
+                    index: entry.index,
+                    path: entry.path,
+                    status: EntryStatus::Failed,
+                    output_path: Some(file_path.to_string_lossy().to_string()),
+                    bytes_written: 0,
+                    hash_verified: None,
+                    duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+                    error: Some(e.to_string()),
+                    reason: None,
+                });
+                return Err(e);
+            }
+        }
+
+        progress.inc(entry.size);
     }
 
     progress.finish();
-    println!("共解包 {} 个文件", files_unpacked);
+    heartbeat.finish();
     Ok(())
 }
 
-pub fn list_files(input: &str, recheck: bool) -> io::Result<()> {
+/// 依次应用 strip-components 和前缀替换得到条目的输出路径
+fn output_relative_path(path: &str, options: &UnpackOptions) -> Option<String> {
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    if components.len() <= options.strip_components {
+        return None;
+    }
+    let stripped = components[options.strip_components..].join("/");
+
+    for (old, new) in &options.transforms {
+        if let Some(rest) = stripped.strip_prefix(old.as_str()) {
+            let mapped = format!("{}{}", new, rest);
+            let mapped = mapped.trim_start_matches('/');
+            return (!mapped.is_empty()).then(|| mapped.to_string());
+        }
+    }
+    Some(stripped)
+}
+
+/// 记录条目的输出路径，与之前的条目相同时按`policy`处理，返回`None`时跳过该条目
+fn claim_output(
+    outputs: &mut HashMap<String, String>,
+    path: String,
+    entry_path: &str,
+    policy: CollisionPolicy
+) -> io::Result<Option<String>> {
+    let Some(previous) = outputs.get(&path) else {
+        outputs.insert(path.clone(), entry_path.to_string());
+        return Ok(Some(path));
+    };
+    match policy {
+        CollisionPolicy::Error => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("条目 {} 和 {} 的输出路径都是 {}，可使用 --on-collision 指定处理方式", previous, entry_path, path)
+        )),
+        CollisionPolicy::Skip => Ok(None),
+        CollisionPolicy::Rename => {
+            let (dir, name) = path.rsplit_once('/').map_or(("", path.as_str()), |(d, n)| (d, n));
+            let (stem, ext) = match name.rfind('.') {
+                Some(i) if i > 0 => (&name[..i], &name[i..]),
+                _ => (name, ""),
+            };
+            let dir = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+            let renamed = (1..)
+                .map(|n| format!("{}{} ({}){}", dir, stem, n, ext))
+                .find(|p| !outputs.contains_key(p))
+                .unwrap();
+            outputs.insert(renamed.clone(), entry_path.to_string());
+            Ok(Some(renamed))
+        }
+    }
+}
+
+/// 将当前条目的内容写入`file_path`，返回写入的字节数和内容摘要
+///
+/// `info`为metadata中的条目信息，决定摘要算法以及是否需要解压。
+fn write_entry(
+    reader: &mut impl Read,
+    file_path: &Path,
+    content_len: u64,
+    info: Option<&FileInfo>,
+    decryption: Option<(&EntryKey, &str)>,
+    throttle: Option<&Arc<Throttle>>,
+    buffer: &mut [u8]
+) -> io::Result<(u64, Option<String>)> {
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = File::create(file_path)?;
+    let hash_algo = info.and_then(|f| f.algo);
+    let mut writer = HashingWriter::new(BufWriter::with_capacity(BUFFER_SIZE, Throttled::new(file, throttle)), hash_algo);
+
+    // 压缩的条目先解密再逐帧解压
+    if let Some(compression) = info.and_then(|f| f.compression.as_ref()) {
+        let mut decoder = FrameDecoder::new(&mut writer, compression);
+        match decryption {
+            Some((key, nonce)) => {
+                decrypt_stream(reader, &mut decoder, key, nonce, content_len)?;
+            }
+            None => {
+                io::copy(reader, &mut decoder)?;
+            }
+        }
+        let written = decoder.finish()?;
+        let (_, digest) = writer.finish();
+        return Ok((written, digest));
+    }
+
+    if let Some((key, nonce)) = decryption {
+        let written = decrypt_stream(reader, &mut writer, key, nonce, content_len)?;
+        writer.flush()?;
+        let (_, digest) = writer.finish();
+        return Ok((written, digest));
+    }
+
+    if content_len >= BUFFER_SIZE as u64 {
+        // 大文件使用 io::copy
+        io::copy(reader, &mut writer)?;
+    } else {
+        // 小文件使用缓冲区
+        let mut remaining = content_len as usize;
+        while remaining > 0 {
+            let to_read = remaining.min(BUFFER_SIZE);
+            let buf = &mut buffer[..to_read];
+            reader.read_exact(buf)?;
+            writer.write_all(buf)?;
+            remaining -= to_read;
+        }
+    }
+
+    writer.flush()?;
+    let (_, digest) = writer.finish();
+    Ok((content_len, digest))
+}
+
+/// 读取条目中从`offset`开始的`length`字节（未指定时读到末尾），写入文件或标准输出
+///
+/// 条目按`id`查找，没有指定时按`path`查找。
+pub fn cat_entry(input: &str, path: Option<&str>, id: Option<u64>, offset: u64, length: Option<u64>, output: Option<&str>) -> io::Result<()> {
+    let reader = XpakReader::open(input)?;
+    let mut entry = match id {
+        Some(id) => reader.by_id(id)?,
+        None => reader.open_entry(path.unwrap_or_default())?,
+    };
+    if offset > entry.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("偏移 {} 超出条目大小 {} 字节", offset, entry.len())
+        ));
+    }
+    entry.seek(SeekFrom::Start(offset))?;
+    let mut range = entry.take(length.unwrap_or(u64::MAX));
+    match output {
+        Some(output) => {
+            let mut writer = BufWriter::with_capacity(BUFFER_SIZE, File::create(output)?);
+            io::copy(&mut range, &mut writer)?;
+            writer.flush()
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            io::copy(&mut range, &mut out)?;
+            out.flush()
+        }
+    }
+}
+
+/// 把条目解压到临时文件后交给外部程序打开，返回程序的退出码
+///
+/// 临时文件在程序退出后删除。
+pub fn open_entry_with(input: &str, path: &str, command: &[String]) -> io::Result<i32> {
+    let temp = XpakReader::open(input)?.extract_temp(path)?;
+    let temp_path = temp.path().to_string_lossy().to_string();
+    let mut args: Vec<String> = command[1..].iter().map(|arg| arg.replace("{}", &temp_path)).collect();
+    if !command[1..].iter().any(|arg| arg.contains("{}")) {
+        args.push(temp_path);
+    }
+    let status = Command::new(&command[0]).args(&args).status()
+        .map_err(|e| io::Error::new(e.kind(), format!("无法运行 {}: {}", command[0], e)))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// 输出横幅、对齐的文件列表和大小统计
+fn write_listing(
+    out: &mut impl Write,
+    metadata: &XpakMetadata,
+    meta_len: u64,
+    format: &ListFormat,
+    banner: bool
+) -> io::Result<()> {
+    if banner {
+        write_banner(out, metadata)?;
+    }
+    let files = metadata.live_files();
+    writeln!(out, "文件列表 ({} 个文件):", files.len())?;
+    writeln!(out, "----------------------------------------")?;
+    write_table(out, &files, format)?;
+    writeln!(out, "----------------------------------------")?;
+    writeln!(out, "总大小: {}", format_size(metadata.total_size + meta_len))?;
+    writeln!(out, "├Metadata长度: {}", format_size(meta_len))?;
+    writeln!(out, "└─文件大小: {}", format_size(metadata.total_size))
+}
+
+pub fn list_files(input: &str, recheck: bool, format: &ListFormat, banner: bool, pager: bool) -> io::Result<()> {
+    if format.null {
+        let mut reader = XpakReader::open(input)?;
+        let mut out = BufWriter::new(io::stdout().lock());
+        if !recheck {
+            return write_paths_nul(&mut out, reader.metadata.live_files().into_iter().map(|f| f.path.as_str()));
+        }
+        // 文件数量字段未经校验，按metadata中的条目数预先分配
+        let mut paths = Vec::with_capacity(reader.metadata.files.len());
+        while let Some(entry) = reader.next_entry()? {
+            paths.push(entry.path);
+        }
+        return write_paths_nul(&mut out, paths.iter().map(String::as_str));
+    }
     if !recheck {
         // 快速模式：只读取metadata
         let file = File::open(input)?;
+        let file_len = file.metadata()?.len();
         let mut pak_file = BufReader::with_capacity(BUFFER_SIZE, file);
 
         // 验证Magic Number
         let mut magic = [0u8; 4];
         pak_file.read_exact(&mut magic)?;
-        if &magic != MAGIC_NUMBER {
+        if magic != MAGIC_NUMBER {
             return Err(io::Error::new(io::ErrorKind::InvalidData, "无效的文件格式"));
         }
 
         // 读取metadata
-        let mut meta_len_bytes = [0u8; 4];
-        pak_file.read_exact(&mut meta_len_bytes)?;
-        let meta_len = u32::from_le_bytes(meta_len_bytes) as usize;
+        let metadata_bytes = read_metadata(&mut pak_file, file_len)?;
+        let meta_len = metadata_bytes.len();
         if meta_len > 0 {
-            let mut metadata_bytes = vec![0u8; meta_len];
-            pak_file.read_exact(&mut metadata_bytes)?;
-            let metadata = match serde_json::from_slice::<XpakMetadata>(&metadata_bytes) {
+            let mut metadata = match parse_metadata(&metadata_bytes) {
                 Ok(metadata) => metadata,
                 Err(e) => {
                     println!("警告：无法从metadata读取文件列表，切换完扫描模式");
@@ -156,48 +720,45 @@ pub fn list_files(input: &str, recheck: bool) -> io::Result<()> {
                     return Err(io::Error::new(io::ErrorKind::InvalidData, "无法解析metadata"));
                 }
             };
-            println!("文件列表 ({} 个文件):", metadata.files_count);
-            println!("----------------------------------------");
-                
-            for (i, file) in metadata.files.iter().enumerate() {
-                println!("{:4}. {} ({} 字节)", i + 1, file.path, file.size);
+            // 条目列表可能拆分到了尾部区段
+            let mut data_offset = 8 + meta_len as u64 + 4;
+            if has_metadata_end(&metadata.format_version) {
+                data_offset += MAGIC_METADATA_END.len() as u64;
             }
-            
-            let total_size = metadata.total_size + meta_len as u64;
-            if total_size > GB as u64 {
-                println!("总大小: {} GB", total_size / GB as u64);
-            } else if total_size > MB as u64 {
-                println!("总大小: {} MB", total_size / MB as u64);
-            } else if total_size > KB as u64 {
-                println!("总大小: {} KB", total_size / KB as u64);
-            } else {
-                println!("总大小: {} 字节", total_size);
-            }
-            println!("├Metadata长度: {} 字节", meta_len);
-            println!("└─文件大小: {} 字节", metadata.total_size);
-
+            metadata.load_files(&mut pak_file, data_offset)?;
+            let mut out = Output::open(pager)?;
+            let mut writer = BufWriter::new(&mut out);
+            let result = write_listing(&mut writer, &metadata, meta_len as u64, format, banner)
+                .and_then(|_| writer.flush());
+            drop(writer);
+            out.finish(result)?;
             return Ok(());
         }
     }
     
     // 完整扫描模式
-    let mut pak_file = BufReader::new(File::open(input)?);
+    let file = File::open(input)?;
+    let file_len = file.metadata()?.len();
+    let mut pak_file = BufReader::new(file);
     
     // 验证Magic Number
     let mut magic = [0u8; 4];
     pak_file.read_exact(&mut magic)?;
-    if &magic != MAGIC_NUMBER {
+    if magic != MAGIC_NUMBER {
         return Err(io::Error::new(io::ErrorKind::InvalidData, "无效的文件格式"));
     }
 
     // 读取metadata
-    let mut meta_len_bytes = [0u8; 4];
-    pak_file.read_exact(&mut meta_len_bytes)?;
-    let meta_len = u32::from_le_bytes(meta_len_bytes) as usize;
-    if meta_len > 0 {
-        // 跳过metadata内容
-        pak_file.seek_relative(meta_len as i64)?;
-        
+    let metadata_bytes = read_metadata(&mut pak_file, file_len)?;
+    let meta_len = metadata_bytes.len();
+    // 完整扫描模式不依赖metadata，无法解析时不显示横幅
+    if banner && meta_len > 0 {
+        if let Ok(metadata) = parse_metadata(&metadata_bytes) {
+            write_banner(&mut io::stdout(), &metadata)?;
+        }
+    }
+    // 1.3之前的版本没有metadata结束标记
+    if meta_len > 0 && has_metadata_end(&probe_version(&metadata_bytes)) {
         // 读取metadata结束标记
         let mut metadata_end = [0u8; 8];
         pak_file.read_exact(&mut metadata_end)?;
@@ -213,26 +774,16 @@ pub fn list_files(input: &str, recheck: bool) -> io::Result<()> {
     let mut count_bytes = [0u8; 4];
     pak_file.read_exact(&mut count_bytes)?;
     let count = u32::from_le_bytes(count_bytes);
+    let path_length = PathLength::for_version(&probe_version(&metadata_bytes));
+    check_entry_count(count, path_length, file_len.saturating_sub(pak_file.stream_position()?))?;
 
     println!("文件列表 (完整扫描模式):");
     println!("----------------------------------------");
     
     let mut total_size = 0u64;
     for i in 0..count {
-        // 读取文件路径
-        let mut path_len_bytes = [0u8; 4];
-        pak_file.read_exact(&mut path_len_bytes)?;
-        let path_len = u32::from_le_bytes(path_len_bytes) as usize;
-
-        let mut path_bytes = vec![0u8; path_len];
-        pak_file.read_exact(&mut path_bytes)?;
-        let path_str = String::from_utf8(path_bytes)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        
-        // 读取文件大小
-        let mut content_len_bytes = [0u8; 4];
-        pak_file.read_exact(&mut content_len_bytes)?;
-        let content_len = u32::from_le_bytes(content_len_bytes) as u64;
+        // 读取文件路径和大小
+        let (path_str, content_len) = read_entry_header(&mut pak_file, path_length)?;
 
         println!("{:4}. {} ({} 字节)", i + 1, path_str, content_len);
         total_size += content_len;
@@ -0,0 +1,88 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fmt;
+use std::fs;
+use std::io;
+
+use crate::metadata::FileInfo;
+
+/// 条目的有效期，两端都可以不限
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidityWindow {
+    pub from: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// 条目在某一时刻是否可用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Availability {
+    Valid,
+    /// 尚未到生效时间
+    NotYet(DateTime<Utc>),
+    /// 已超过失效时间
+    Expired(DateTime<Utc>),
+}
+
+impl fmt::Display for Availability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Availability::Valid => f.write_str("有效"),
+            Availability::NotYet(from) => write!(f, "尚未生效，{} 起可用", from.format("%Y-%m-%d %H:%M:%S UTC")),
+            Availability::Expired(until) => write!(f, "已过期，{} 止", until.format("%Y-%m-%d %H:%M:%S UTC")),
+        }
+    }
+}
+
+/// 条目在`now`时是否处于有效期内
+pub fn availability(info: &FileInfo, now: DateTime<Utc>) -> Availability {
+    match (info.valid_from, info.valid_until) {
+        (Some(from), _) if now < from => Availability::NotYet(from),
+        (_, Some(until)) if now >= until => Availability::Expired(until),
+        _ => Availability::Valid,
+    }
+}
+
+/// 解析RFC 3339时间或`YYYY-MM-DD`日期（UTC零点）
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| format!("无效的时间 '{}'，应为 RFC 3339 时间或 YYYY-MM-DD", value))
+}
+
+/// 解析`FROM..UNTIL`形式的有效期，省略的一端不限
+fn parse_window(spec: &str) -> Result<ValidityWindow, String> {
+    let (from, until) = spec.split_once("..")
+        .ok_or_else(|| format!("无效的有效期 '{}'，应为 FROM..UNTIL", spec))?;
+    let parse = |value: &str| {
+        let value = value.trim();
+        (!value.is_empty()).then(|| parse_time(value)).transpose()
+    };
+    let window = ValidityWindow { from: parse(from)?, until: parse(until)? };
+    match (window.from, window.until) {
+        (None, None) => Err(format!("有效期 '{}' 至少需要指定一端", spec)),
+        (Some(from), Some(until)) if from >= until => Err(format!("有效期 '{}' 的开始时间不早于结束时间", spec)),
+        _ => Ok(window),
+    }
+}
+
+/// 读取有效期清单，每行一条`GLOB=FROM..UNTIL`规则，忽略空行和`#`开头的注释
+///
+/// 条目按顺序使用第一条匹配的规则。
+pub fn load_manifest(path: &str) -> io::Result<Vec<(String, ValidityWindow)>> {
+    let content = fs::read_to_string(path)?;
+    let mut rules = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let rule = line.rsplit_once('=')
+            .ok_or_else(|| "应为 GLOB=FROM..UNTIL".to_string())
+            .and_then(|(glob, spec)| Ok((glob.trim().to_string(), parse_window(spec.trim())?)))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{} 第 {} 行: {}", path, number + 1, e)))?;
+        rules.push(rule);
+    }
+    Ok(rules)
+}
@@ -0,0 +1,250 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::common::ARCHIVE_EXTENSION;
+use crate::compress::hash_decompressed;
+use crate::frames::{entry_ranges, FrameTable, FRAMES_TAG};
+use crate::hash::hash_reader;
+use crate::reader::{Consistency, XpakReader};
+use crate::trailer::unaccounted;
+
+/// 一个包的校验结果
+#[derive(Serialize, Debug, Default)]
+pub struct Verification {
+    /// 摘要校验通过的条目数
+    pub verified: u32,
+    pub problems: Vec<String>,
+    /// 不影响结果的提示，例如没有帧校验信息
+    pub notes: Vec<String>,
+}
+
+/// 检查包的结构与metadata是否一致，并校验未加密条目的摘要，返回是否没有发现问题
+pub fn verify(input: &str) -> io::Result<bool> {
+    let result = check(input)?;
+    for note in &result.notes {
+        println!("{}", note);
+    }
+    for problem in &result.problems {
+        println!("错误: {}", problem);
+    }
+    println!("----------------------------------------");
+    println!("已校验 {} 个条目的摘要，发现 {} 个问题", result.verified, result.problems.len());
+    Ok(result.problems.is_empty())
+}
+
+/// 校验一个包，不输出任何内容
+pub fn check(input: &str) -> io::Result<Verification> {
+    // 头部与metadata的比较结果作为问题报告，不需要读取器再警告
+    let mut reader = XpakReader::open(input)?.with_consistency(Consistency::Off);
+    let files = reader.metadata.files.clone();
+    let mut problems = Vec::new();
+    let mut notes = Vec::new();
+
+    if reader.count != reader.metadata.files_count || reader.count as usize != files.len() {
+        problems.push(format!(
+            "文件数量不一致: 头部 {} 个，metadata 记录 {} 个，文件列表 {} 个",
+            reader.count, reader.metadata.files_count, files.len()
+        ));
+    }
+    // 被替换的条目和删除标记不计入总大小，与写入时的规则一致
+    let total_size: u64 = reader.metadata.live_files().iter().map(|f| f.size).sum();
+    if total_size != reader.metadata.total_size {
+        problems.push(format!(
+            "总大小不一致: metadata 记录 {} 字节，有效条目合计 {} 字节",
+            reader.metadata.total_size, total_size
+        ));
+    }
+
+    // 中断的写入留下的多余数据
+    let trailer = reader.trailer()?;
+    for range in unaccounted(reader.data_end(), trailer.as_ref(), fs::metadata(input)?.len()) {
+        problems.push(format!(
+            "偏移 {}..{} 有 {} 字节无法识别的多余数据，可使用 optimize 清除",
+            range.start, range.end, range.end - range.start
+        ));
+    }
+
+    // 按帧校验数据区段，定位损坏的字节范围和受影响的条目
+    match trailer.and_then(|t| t.get(&FRAMES_TAG).map(|r| (t.offset, FrameTable::from_record(r)))) {
+        Some((trailer_offset, table)) => {
+            let table = table?;
+            let data_offset = reader.data_offset;
+            if data_offset + table.data_len != trailer_offset {
+                problems.push(format!(
+                    "数据区段长度不一致: 帧校验表记录 {} 字节，实际 {} 字节",
+                    table.data_len, trailer_offset.saturating_sub(data_offset)
+                ));
+            }
+            let ranges = entry_ranges(&reader.metadata);
+            for index in table.damaged_frames(&mut File::open(input)?, data_offset)? {
+                let range = table.frame_range(index);
+                let affected: Vec<&str> = ranges.iter().zip(&files)
+                    .filter(|(r, _)| r.start < range.end && range.start < r.end)
+                    .map(|(_, f)| f.path.as_str())
+                    .collect();
+                problems.push(format!(
+                    "帧 #{} 损坏 (偏移 {}..{})，影响条目: {}",
+                    index, data_offset + range.start, data_offset + range.end, affected.join(", ")
+                ));
+            }
+        }
+        None => notes.push("包中没有帧校验信息，跳过分帧校验".to_string()),
+    }
+
+    let mut verified = 0u32;
+    loop {
+        let entry = match reader.next_entry() {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                problems.push("包已被截断，条目数据不完整".to_string());
+                break;
+            }
+            Err(e) => {
+                // 前面的大小字段错误时，后续条目头部已无法对齐
+                problems.push(format!("无法读取后续条目头部: {}", e));
+                break;
+            }
+        };
+        let number = entry.index + 1;
+        let Some(info) = files.get(entry.index as usize) else {
+            problems.push(format!("#{} {}: metadata 中没有对应的记录", number, entry.path));
+            continue;
+        };
+        if info.path != entry.path {
+            problems.push(format!("#{} 路径不一致: 头部为 {}，metadata 为 {}", number, entry.path, info.path));
+            continue;
+        }
+
+        let expected = info.stored_size.unwrap_or(info.size);
+        if entry.size != expected {
+            problems.push(format!(
+                "#{} {}: 大小不一致，头部 {} 字节，metadata {} 字节",
+                number, entry.path, entry.size, expected
+            ));
+            continue;
+        }
+
+        if info.encryption.is_some() {
+            continue;
+        }
+        if let (Some(algo), Some(hash)) = (info.algo, info.hash.as_deref()) {
+            let actual = match &info.compression {
+                Some(compression) => hash_decompressed(algo, &mut reader, compression),
+                None => hash_reader(algo, &mut reader),
+            };
+            match actual {
+                Ok(actual) if actual == hash => verified += 1,
+                Ok(_) => problems.push(format!("#{} {}: {} 校验失败", number, entry.path, algo)),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    problems.push(format!("#{} {}: 条目数据不完整", number, entry.path));
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    problems.push(format!("#{} {}: {}", number, entry.path, e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    Ok(Verification { verified, problems, notes })
+}
+
+/// 发送校验结果的超时时间
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 发送到webhook的校验结果
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    archive: &'a str,
+    ok: bool,
+    checked_at: DateTime<Utc>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    result: Option<&'a Verification>,
+    /// 无法打开或读取包时的错误
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// 监视目录时记录的文件状态
+struct WatchedFile {
+    len: u64,
+    modified: Option<SystemTime>,
+    checked: bool,
+}
+
+/// 监视目录中新出现的包，逐个校验并把结果POST到`webhook`
+///
+/// 按`interval`轮询目录，文件的大小和修改时间在两次轮询之间没有变化时视为上传完成；
+/// 已校验的包再次被修改时重新校验。发送失败只输出警告，不影响之后的校验。
+pub fn watch(dir: &str, webhook: Option<&str>, interval: Duration, running: &AtomicBool) -> io::Result<()> {
+    if !Path::new(dir).is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("目录 '{}' 不存在", dir)));
+    }
+    let agent = ureq::AgentBuilder::new().timeout(WEBHOOK_TIMEOUT).build();
+    let mut watched: HashMap<PathBuf, WatchedFile> = HashMap::new();
+    println!("正在监视 {}，按 Ctrl-C 停止", dir);
+
+    while running.load(Ordering::SeqCst) {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if !path.extension().is_some_and(|e| e.eq_ignore_ascii_case(ARCHIVE_EXTENSION)) {
+                continue;
+            }
+            // 轮询之间文件可能已被移走
+            let Some(metadata) = fs::metadata(&path).ok().filter(|m| m.is_file()) else {
+                continue;
+            };
+            let (len, modified) = (metadata.len(), metadata.modified().ok());
+            match watched.get_mut(&path) {
+                Some(file) if file.len == len && file.modified == modified => {
+                    if !file.checked {
+                        file.checked = true;
+                        report(&agent, &path, webhook);
+                    }
+                }
+                _ => {
+                    watched.insert(path, WatchedFile { len, modified, checked: false });
+                }
+            }
+        }
+        watched.retain(|path, _| path.exists());
+
+        let deadline = Instant::now() + interval;
+        while running.load(Ordering::SeqCst) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+    Ok(())
+}
+
+/// 校验一个包，输出结果并发送到webhook
+fn report(agent: &ureq::Agent, path: &Path, webhook: Option<&str>) {
+    let archive = path.to_string_lossy();
+    let (result, error) = match check(&archive) {
+        Ok(result) => (Some(result), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let ok = result.as_ref().is_some_and(|r| r.problems.is_empty());
+    match (&result, &error) {
+        (Some(result), _) if ok => println!("通过: {}（已校验 {} 个条目）", archive, result.verified),
+        (Some(result), _) => println!("未通过: {}（{}）", archive, result.problems.join("; ")),
+        (None, error) => println!("未通过: {}（{}）", archive, error.as_deref().unwrap_or_default()),
+    }
+
+    let Some(url) = webhook else {
+        return;
+    };
+    let payload = WebhookPayload { archive: &archive, ok, checked_at: Utc::now(), result: result.as_ref(), error };
+    if let Err(e) = agent.post(url).send_json(&payload) {
+        println!("警告：无法把 {} 的校验结果发送到 {}: {}", archive, url, e);
+    }
+}
@@ -1,48 +1,100 @@
-use std::io::{self, Read, BufReader};
+use std::io::{self, Read, BufReader, Seek, SeekFrom};
 use std::fs::File;
-use console::style;
+use console::{style, StyledObject};
 
-use crate::common::{MAGIC_NUMBER, MAGIC_METADATA_END, KB, MB, GB};
-use crate::metadata::XpakMetadata;
+use crate::common::{format_size, MAGIC_NUMBER, MAGIC_METADATA_END};
+use crate::layout::{Layout, Region, RegionKind};
+use crate::metadata::FileInfo;
+use crate::migrate::{has_metadata_end, parse_metadata};
+use xpak::read_metadata;
 
-pub fn view_structure(input: &str) -> io::Result<()> {
-    let mut pak_file = BufReader::new(File::open(input)?);
+/// 每个区段最多显示的字节数
+const HEX_LIMIT: u64 = 1024;
+
+/// 十六进制查看的区段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexSection {
+    /// Magic Number 和 Metadata长度字段
+    Header,
+    /// Metadata内容、结束标记和文件数量字段
+    Metadata,
+    /// 第N个条目（从1开始）
+    Entry(u32),
+    /// 尾部区段
+    Trailer,
+}
+
+impl HexSection {
+    fn contains(&self, kind: &RegionKind) -> bool {
+        match (self, kind) {
+            (HexSection::Header, RegionKind::Magic | RegionKind::MetadataLength) => true,
+            (HexSection::Metadata, RegionKind::Metadata | RegionKind::MetadataEnd | RegionKind::EntryCount) => true,
+            (HexSection::Entry(n), RegionKind::EntryPathLength { index })
+            | (HexSection::Entry(n), RegionKind::EntryPath { index, .. })
+            | (HexSection::Entry(n), RegionKind::EntrySize { index, .. })
+            | (HexSection::Entry(n), RegionKind::EntryData { index, .. }) => index + 1 == *n,
+            (HexSection::Trailer, RegionKind::TrailerRecordHeader { .. } | RegionKind::TrailerRecordData { .. } | RegionKind::TrailerFooter) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for HexSection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HexSection::Header => write!(f, "header"),
+            HexSection::Metadata => write!(f, "metadata"),
+            HexSection::Entry(n) => write!(f, "entry:{}", n),
+            HexSection::Trailer => write!(f, "trailer"),
+        }
+    }
+}
+
+/// 解析`header`、`metadata`、`trailer`或`entry:N`形式的区段
+pub fn parse_hex_section(value: &str) -> Result<HexSection, String> {
+    let value = value.trim().to_ascii_lowercase();
+    match value.as_str() {
+        "header" => return Ok(HexSection::Header),
+        "metadata" => return Ok(HexSection::Metadata),
+        "trailer" => return Ok(HexSection::Trailer),
+        _ => {}
+    }
+    value.strip_prefix("entry")
+        .map(|n| n.trim_start_matches([':', ' ', '=']))
+        .and_then(|n| n.parse::<u32>().ok())
+        .filter(|&n| n > 0)
+        .map(HexSection::Entry)
+        .ok_or_else(|| format!("无效的区段 '{}'，应为 header、metadata、trailer 或 entry:N", value))
+}
+
+pub fn view_structure(input: &str, hex: Option<HexSection>) -> io::Result<()> {
+    if let Some(section) = hex {
+        return view_hex(input, section);
+    }
+    let file = File::open(input)?;
+    let file_len = file.metadata()?.len();
+    let mut pak_file = BufReader::new(file);
     
     // 读取Magic Number
     let mut magic = [0u8; 4];
     pak_file.read_exact(&mut magic)?;
-    let magic_valid = &magic == MAGIC_NUMBER;
+    let magic_valid = magic == MAGIC_NUMBER;
     
-    // 读取metadata长度
-    let mut meta_len_bytes = [0u8; 4];
-    pak_file.read_exact(&mut meta_len_bytes)?;
-    let meta_len = u32::from_le_bytes(meta_len_bytes) as usize;
-    
-    // 读取metadata内容
-    let mut metadata_bytes = vec![0u8; meta_len];
-    pak_file.read_exact(&mut metadata_bytes)?;
-    let metadata: XpakMetadata = serde_json::from_slice(&metadata_bytes)?;
+    // 读取metadata
+    let metadata_bytes = read_metadata(&mut pak_file, file_len)?;
+    let meta_len = metadata_bytes.len();
+    let metadata = parse_metadata(&metadata_bytes)?;
 
     // 获取metadata版本
     let metadata_version = metadata.format_version.clone();
     
-    // 读取metadata结束标记
+    // 读取metadata结束标记，1.3之前的版本没有该标记
+    let legacy = !has_metadata_end(&metadata_version);
     let mut metadata_end = [0u8; 8];
-    pak_file.read_exact(&mut metadata_end)?;
-    let end_valid = metadata_end == MAGIC_METADATA_END;
-    
-    // 格式化文件大小显示
-    let format_size = |size: u64| -> String {
-        if size > GB as u64 {
-            format!("{:.2} GB", size as f64 / GB as f64)
-        } else if size > MB as u64 {
-            format!("{:.2} MB", size as f64 / MB as f64)
-        } else if size > KB as u64 {
-            format!("{:.2} KB", size as f64 / KB as f64)
-        } else {
-            format!("{} 字节", size)
-        }
-    };
+    if !legacy {
+        pak_file.read_exact(&mut metadata_end)?;
+    }
+    let end_valid = legacy || metadata_end == MAGIC_METADATA_END;
     
     println!("\nPAK文件结构分析:");
     println!("┌{:─^100}┐", "");
@@ -58,36 +110,219 @@ pub fn view_structure(input: &str) -> io::Result<()> {
     // Metadata 部分
     println!("├{:─^100}┤", "");
     println!("│ Metadata 区段: {}", format_size(meta_len as u64));
-    println!("│  ├─ Format版本: {}", metadata_version);
+    if legacy {
+        println!("│  ├─ Format版本: {} {}", metadata_version, style("（旧版本，已转换为当前结构读取）").yellow());
+    } else {
+        println!("│  ├─ Format版本: {}", metadata_version);
+    }
     println!("│  ├─ 文件数量: {}", metadata.files_count);
     println!("│  ├─ 总文件大小: {}", format_size(metadata.total_size));
-    if let Some(desc) = metadata.description {
+    if let Some(table) = metadata.files_table {
+        println!("│  ├─ 条目列表: 在尾部区段中（数据区段起点 +{:#x}，{}），不读取", table.offset, format_size(table.len));
+    }
+    if let Some(desc) = &metadata.description {
         println!("│  └─ 描述: {}", desc);
     }
     
     // Metadata End 部分
     println!("├{:─^100}┤", "");
-    let end_status = if end_valid {
-        style("✓ 有效").green()
+    if legacy {
+        println!("│ Metadata End标记 {}", style("O 此版本不支持Metadata End").yellow());
+    } else if end_valid {
+        println!("│ Metadata End标记 {:02X?} {}", metadata_end, style("✓ 有效").green());
     } else {
-        if metadata_version == "1.0" || metadata_version == "1.1" || metadata_version == "1.2" {
-            style("O 无效 - 此版本不支持Metadata End").yellow()
-        } else {
-            style("X 无效 - 数据可能已损坏").red()
-        }
-    };
-    println!("│ Metadata End标记 {:02X?} {}", metadata_end, end_status);
+        println!("│ Metadata End标记 {:02X?} {}", metadata_end, style("X 无效 - 数据可能已损坏").red());
+    }
     
-    // Data 部分
+    // Data 部分，逐个读取条目头部而不是只看metadata的记录
+    println!("├{:─^100}┤", "");
+    if !end_valid {
+        println!("│ {:<98} │", style("警告：由于Metadata End标记无效，无法确认Data区段的完整性").yellow());
+    }
+    let layout = Layout::scan(input)?;
+    // 条目列表拆分到尾部区段时不与metadata比较
+    let files = metadata.files_table.is_none().then_some(metadata.files.as_slice());
+    let rows = entry_rows(&layout, files);
+    let mut problems = Vec::new();
+
+    println!("│ Data区段: {}", format_size(metadata.total_size));
+    println!("│  {:>6}  {:>12}  {:>12}  {:>12}  路径", "序号", "头部偏移", "内容偏移", "大小");
+    for row in &rows {
+        let status = match &row.problem {
+            Some(problem) => style(format!("X {}", problem)).red(),
+            None => style("✓".to_string()).green(),
+        };
+        println!(
+            "│  {:>6}  {:>#12x}  {:>#12x}  {:>12}  {} {}",
+            row.index + 1, row.header_offset, row.data_offset, row.size, row.path, status
+        );
+        if let Some(problem) = &row.problem {
+            problems.push(format!("条目 #{}: {}", row.index + 1, problem));
+        }
+    }
+
+    let complete = rows.iter().filter(|r| r.problem.is_none()).count();
+    let count = layout.entry_count.unwrap_or(0);
+    println!("│  └─ 文件数量字段 {}，metadata记录 {}，完整读取 {} 个条目", count, metadata.files_count, complete);
+    if count != metadata.files_count {
+        problems.push(format!("文件数量字段为 {}，metadata中为 {}", count, metadata.files_count));
+    }
+    if (rows.len() as u32) < count {
+        problems.push(format!("只找到 {} 个条目头部，缺少 {} 个", rows.len(), count - rows.len() as u32));
+    }
+    for region in &layout.regions {
+        match region.kind {
+            RegionKind::TrailingData => problems.push(format!(
+                "最后一个条目之后有 {} 字节多余数据 ({:#x}..{:#x})",
+                region.range.end - region.range.start, region.range.start, region.range.end
+            )),
+            RegionKind::Unparsed => problems.push(format!("从 {:#x} 起无法解析", region.range.start)),
+            _ => {}
+        }
+    }
+
     println!("├{:─^100}┤", "");
-    if end_valid {
-        println!("│ Data区段: {}", format_size(metadata.total_size));
-        println!("│  └─ 包含 {} 个文件", metadata.files_count);
+    if problems.is_empty() {
+        println!("│ Data区段 {}", style("✓ 有效").green());
     } else {
-        println!("│ {:<98} │", style("警告：由于Metadata End标记无效或版本不支持，无法确认Data区段的完整性").yellow());
+        println!("│ Data区段 {}", style("X 无效").red());
+        for problem in &problems {
+            println!("│  - {}", style(problem).red());
+        }
     }
-    
+
     println!("└{:─^100}┘", "");
 
     Ok(())
 }
+
+/// 从包结构中读取出的一个条目
+struct EntryRow {
+    index: u32,
+    header_offset: u64,
+    data_offset: u64,
+    path: String,
+    size: u64,
+    problem: Option<String>,
+}
+
+/// 汇总每个条目的头部和内容区段，并与metadata的记录比较
+fn entry_rows(layout: &Layout, files: Option<&[FileInfo]>) -> Vec<EntryRow> {
+    let mut rows: Vec<EntryRow> = Vec::new();
+    for region in &layout.regions {
+        match &region.kind {
+            RegionKind::EntryPathLength { index } => rows.push(EntryRow {
+                index: *index,
+                header_offset: region.range.start,
+                data_offset: region.range.end,
+                path: String::new(),
+                size: 0,
+                problem: None,
+            }),
+            RegionKind::EntryPath { path, .. } => {
+                if let Some(row) = rows.last_mut() {
+                    row.path = path.clone();
+                }
+            }
+            RegionKind::EntryData { .. } => {
+                if let Some(row) = rows.last_mut() {
+                    row.data_offset = region.range.start;
+                    row.size = region.range.end - region.range.start;
+                }
+            }
+            _ => {}
+        }
+        let entry_region = matches!(
+            region.kind,
+            RegionKind::EntryPathLength { .. } | RegionKind::EntryPath { .. } | RegionKind::EntrySize { .. } | RegionKind::EntryData { .. }
+        );
+        if let Some(row) = rows.last_mut().filter(|_| entry_region && region.problem.is_some()) {
+            row.problem = region.problem.clone();
+        }
+    }
+
+    let Some(files) = files else {
+        return rows;
+    };
+    for row in rows.iter_mut().filter(|r| r.problem.is_none()) {
+        match files.get(row.index as usize) {
+            None => row.problem = Some("metadata中没有对应的记录".to_string()),
+            Some(info) if info.path != row.path => {
+                row.problem = Some(format!("路径与metadata不符（{}）", info.path));
+            }
+            Some(info) if info.stored_size.unwrap_or(info.size) != row.size => {
+                row.problem = Some(format!("大小与metadata不符（{}）", info.stored_size.unwrap_or(info.size)));
+            }
+            Some(_) => {}
+        }
+    }
+    rows
+}
+
+/// 按字段显示区段的十六进制内容，不依赖metadata能否解析
+fn view_hex(input: &str, section: HexSection) -> io::Result<()> {
+    let layout = Layout::scan(input)?;
+    let regions: Vec<&Region> = layout.regions.iter().filter(|r| section.contains(&r.kind)).collect();
+    if regions.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("包内没有区段 {}", section)));
+    }
+
+    let mut file = File::open(input)?;
+    for region in regions {
+        let len = region.range.end - region.range.start;
+        let status = match &region.problem {
+            Some(problem) => style(format!("X {}", problem)).red(),
+            None => style("✓".to_string()).green(),
+        };
+        println!("\n{} [{:#x}..{:#x}, {} 字节] {}", style(&region.kind).bold(), region.range.start, region.range.end, len, status);
+
+        let shown = len.min(HEX_LIMIT);
+        let mut bytes = vec![0u8; shown as usize];
+        file.seek(SeekFrom::Start(region.range.start))?;
+        file.read_exact(&mut bytes)?;
+        print_hex(region.range.start, &bytes, |text| field_style(&region.kind, region.problem.is_some(), text));
+        if shown < len {
+            println!("          ... 省略 {} 字节", len - shown);
+        }
+    }
+    Ok(())
+}
+
+/// 定长字段和标记使用不同颜色，便于区分字段边界
+fn field_style(kind: &RegionKind, invalid: bool, text: String) -> StyledObject<String> {
+    if invalid {
+        return style(text).red();
+    }
+    match kind {
+        RegionKind::Magic | RegionKind::MetadataEnd | RegionKind::TrailerFooter => style(text).cyan(),
+        RegionKind::MetadataLength | RegionKind::EntryCount | RegionKind::EntryPathLength { .. }
+        | RegionKind::EntrySize { .. } | RegionKind::TrailerRecordHeader { .. } => style(text).yellow(),
+        RegionKind::EntryPath { .. } => style(text).green(),
+        _ => style(text),
+    }
+}
+
+/// 按16字节对齐输出，行首偏移与xxd一致
+fn print_hex(start: u64, bytes: &[u8], paint: impl Fn(String) -> StyledObject<String>) {
+    let mut offset = start - start % 16;
+    let end = start + bytes.len() as u64;
+    while offset < end {
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for position in offset..offset + 16 {
+            if position % 16 == 8 {
+                hex.push(' ');
+            }
+            if position < start || position >= end {
+                hex.push_str("   ");
+                ascii.push(' ');
+                continue;
+            }
+            let byte = bytes[(position - start) as usize];
+            hex.push_str(&format!("{:02x} ", byte));
+            ascii.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+        }
+        println!("{:08x}: {} |{}|", offset, paint(hex), paint(ascii));
+        offset += 16;
+    }
+}
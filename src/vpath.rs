@@ -0,0 +1,79 @@
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::io;
+
+/// 虚拟路径的匹配方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PathPolicy {
+    /// 路径必须完全一致
+    #[default]
+    Exact,
+    /// 忽略大小写，与大小写不敏感平台上的虚拟文件系统一致
+    IgnoreCase,
+}
+
+/// 统一分隔符并去掉开头的`/`和`./`
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 按策略得到用于查找的键
+pub fn lookup_key(path: &str, policy: PathPolicy) -> String {
+    let path = normalize(path);
+    match policy {
+        PathPolicy::Exact => path,
+        PathPolicy::IgnoreCase => path.to_lowercase(),
+    }
+}
+
+/// 条目的ID：规范化路径的64位FNV-1a散列
+///
+/// 只由路径决定，与打包顺序和包的版本无关，引擎可以在构建时由路径算出ID，运行时按ID查找条目。
+pub fn entry_id(path: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    lookup_key(path, PathPolicy::Exact).bytes()
+        .fold(OFFSET_BASIS, |hash, b| (hash ^ b as u64).wrapping_mul(PRIME))
+}
+
+/// 解析十六进制的条目ID，可以带`0x`前缀
+pub fn parse_entry_id(value: &str) -> Result<u64, String> {
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    u64::from_str_radix(digits, 16).map_err(|_| format!("无效的条目ID '{}'，应为十六进制，如 list --ids 显示的值", value))
+}
+
+/// 检查不同的路径是否得到了相同的条目ID，相同的路径允许重复出现
+pub fn check_entry_ids<'a>(paths: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+    let mut ids: HashMap<u64, &str> = HashMap::new();
+    for path in paths {
+        let id = entry_id(path);
+        match ids.insert(id, path) {
+            Some(other) if lookup_key(other, PathPolicy::Exact) != lookup_key(path, PathPolicy::Exact) => return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} 与 {} 的条目ID相同（{:016x}），请重命名其中一个", other, path, id)
+            )),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// 生成大小写折叠后的查找表（折叠后的路径 -> 实际路径），只有大小写不同的路径视为冲突
+pub fn build_lookup_index<'a>(paths: impl IntoIterator<Item = &'a str>) -> io::Result<HashMap<String, String>> {
+    let mut index: HashMap<String, String> = HashMap::new();
+    for path in paths {
+        let key = lookup_key(path, PathPolicy::IgnoreCase);
+        if let Some(existing) = index.get(&key) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} 与 {} 只有大小写不同，无法生成查找表", existing, path)
+            ));
+        }
+        index.insert(key, path.to_string());
+    }
+    Ok(index)
+}
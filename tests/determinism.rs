@@ -0,0 +1,70 @@
+//! 相同的文件树应当生成条目顺序和内容都相同的包，与遍历顺序和输入的先后无关
+//!
+//! 包的metadata中记录了创建时间和文件的修改时间，这里比较的是按包内顺序读出的条目。
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use xpak::testing::read_entries;
+
+/// 打包`inputs`，按包内顺序返回条目
+fn pak(inputs: &[&Path], output: &Path) -> Vec<(String, Vec<u8>)> {
+    let result = Command::new(env!("CARGO_BIN_EXE_xpak"))
+        .arg("pak")
+        .args(inputs)
+        .arg(output)
+        .env("XPAK_HISTORY", "")
+        .output()
+        .expect("无法运行 xpak");
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    read_entries(&fs::read(output).unwrap()).unwrap()
+}
+
+/// 按给定顺序创建文件，文件内容为其路径
+fn create_tree(root: &Path, files: &[&str]) {
+    for file in files {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, file).unwrap();
+    }
+}
+
+const FILES: &[&str] = &["b.txt", "a/z.txt", "a/b/c.txt", "A.txt", "a.txt", "a/b.txt", "c/d/e.txt"];
+
+#[test]
+fn creation_order_does_not_change_archive() {
+    let dir = tempfile::tempdir().unwrap();
+    let forward = dir.path().join("forward");
+    let reversed = dir.path().join("reversed");
+    create_tree(&forward, FILES);
+    let mut files = FILES.to_vec();
+    files.reverse();
+    create_tree(&reversed, &files);
+
+    let first = pak(&[&forward], &dir.path().join("forward.xpak"));
+    let second = pak(&[&reversed], &dir.path().join("reversed.xpak"));
+    assert_eq!(first, second);
+
+    // 按字节序排列，与区域设置无关
+    let paths: Vec<&str> = first.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths, ["A.txt", "a.txt", "a/b.txt", "a/b/c.txt", "a/z.txt", "b.txt", "c/d/e.txt"]);
+}
+
+#[test]
+fn input_order_does_not_change_archive() {
+    let dir = tempfile::tempdir().unwrap();
+    let left = dir.path().join("left");
+    let right = dir.path().join("right");
+    let single = dir.path().join("single.txt");
+    create_tree(&left, &["x.txt", "sub/y.txt"]);
+    create_tree(&right, &["a.txt", "sub/b.txt"]);
+    fs::write(&single, "single").unwrap();
+
+    let first = pak(&[&left, &right, &single], &dir.path().join("first.xpak"));
+    let second = pak(&[&single, &right, &left], &dir.path().join("second.xpak"));
+    assert_eq!(first, second);
+
+    let paths: Vec<&str> = first.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths, ["left/sub/y.txt", "left/x.txt", "right/a.txt", "right/sub/b.txt", "single.txt"]);
+}
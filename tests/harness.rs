@@ -0,0 +1,199 @@
+//! 用`testing`模块构造和破坏包，检查读取器和命令行工具的行为
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use xpak::testing::{assert_entries, assert_read_fails, read_entries, ArchiveBuilder};
+
+/// 运行命令行工具，不记录操作历史
+fn xpak(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_xpak"))
+        .args(args)
+        .env("XPAK_HISTORY", "")
+        .output()
+        .expect("无法运行 xpak")
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn sample() -> ArchiveBuilder {
+    ArchiveBuilder::new()
+        .entry("readme.txt", b"hello".to_vec())
+        .entry("assets/data.bin", vec![7u8; 3000])
+        .entry("assets/empty", Vec::new())
+}
+
+#[test]
+fn built_archive_unpacks() {
+    let archive = sample().build();
+    assert_entries(archive.bytes(), &[
+        ("readme.txt", b"hello"),
+        ("assets/data.bin", &[7u8; 3000]),
+        ("assets/empty", b""),
+    ]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("sample.xpak");
+    let output = dir.path().join("out");
+    archive.write_to(&input).unwrap();
+    let result = xpak(&["unpak", path_str(&input), path_str(&output)]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert_eq!(fs::read(output.join("readme.txt")).unwrap(), b"hello");
+    assert_eq!(fs::read(output.join("assets/data.bin")).unwrap(), vec![7u8; 3000]);
+    assert!(fs::read(output.join("assets/empty")).unwrap().is_empty());
+}
+
+#[test]
+fn pak_unpak_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("src");
+    fs::create_dir_all(source.join("nested/deeper")).unwrap();
+    fs::write(source.join("a.txt"), "first").unwrap();
+    fs::write(source.join("nested/b.txt"), "second").unwrap();
+    fs::write(source.join("nested/deeper/c.bin"), vec![1u8; 70000]).unwrap();
+
+    let archive = dir.path().join("round.xpak");
+    let result = xpak(&["pak", path_str(&source), path_str(&archive)]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+    let mut entries = read_entries(&fs::read(&archive).unwrap()).unwrap();
+    entries.sort();
+    let paths: Vec<&str> = entries.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths, ["a.txt", "nested/b.txt", "nested/deeper/c.bin"]);
+
+    let output = dir.path().join("out");
+    let result = xpak(&["unpak", path_str(&archive), path_str(&output)]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    for (path, data) in &entries {
+        assert_eq!(&fs::read(output.join(path)).unwrap(), data, "{}", path);
+        assert_eq!(&fs::read(source.join(path)).unwrap(), data, "{}", path);
+    }
+}
+
+#[test]
+fn flipped_magic_is_rejected() {
+    let archive = sample().build().flip_magic();
+    let error = assert_read_fails(archive.bytes());
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("magic.xpak");
+    archive.write_to(&input).unwrap();
+    let result = xpak(&["unpak", path_str(&input), path_str(&dir.path().join("out"))]);
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("无效的文件格式"));
+}
+
+#[test]
+fn truncated_entry_is_rejected() {
+    let archive = sample().build().truncate_entry(1);
+    let error = assert_read_fails(archive.bytes());
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+    // 截断在头部中间时同样报错
+    let archive = sample().build().truncate_entry(2);
+    let error = assert_read_fails(archive.bytes());
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn garbled_metadata_is_rejected() {
+    let archive = sample().build().garble_metadata();
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("garbled.xpak");
+    archive.write_to(&input).unwrap();
+    let result = xpak(&["unpak", path_str(&input), path_str(&dir.path().join("out"))]);
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("无法解析metadata"));
+}
+
+#[test]
+fn append_edits_verify_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("src");
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("a.txt"), vec![b'a'; 70000]).unwrap();
+    fs::write(source.join("b.txt"), "second").unwrap();
+    let replacement = dir.path().join("new.txt");
+    fs::write(&replacement, "replaced").unwrap();
+
+    let archive = dir.path().join("edited.xpak");
+    let archive = path_str(&archive);
+    for args in [
+        &["pak", path_str(&source), archive, "--compress", "zstd"][..],
+        &["touch", archive, "a.txt", "--from", path_str(&replacement), "--append"],
+        &["remove", archive, "b.txt"],
+    ] {
+        let result = xpak(args);
+        assert!(result.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&result.stderr));
+    }
+
+    // 被替换的条目和删除标记留在包内，不应被当作问题
+    let result = xpak(&["verify", "--porcelain=v1", archive]);
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    assert!(!stdout.lines().any(|line| line.starts_with("problem\t")), "{}", stdout);
+    assert!(result.status.success(), "{}", stdout);
+}
+
+#[test]
+fn checksums_use_the_appended_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("src");
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("a.txt"), vec![b'a'; 70000]).unwrap();
+    let replacement = dir.path().join("new.txt");
+    fs::write(&replacement, "replaced").unwrap();
+
+    let archive = dir.path().join("edited.xpak");
+    let archive = path_str(&archive);
+    for args in [
+        &["pak", path_str(&source), archive, "--compress", "zstd"][..],
+        &["touch", archive, "a.txt", "--from", path_str(&replacement), "--append"],
+    ] {
+        let result = xpak(args);
+        assert!(result.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&result.stderr));
+    }
+
+    // 新条目没有压缩，不能使用被替换的条目的帧表，被替换的条目也不再列出
+    let result = xpak(&["checksums", archive, "--algo", "md5"]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "91bb248359043fe98416e259c9bdf10d  a.txt\n");
+}
+
+#[test]
+fn regenerated_metadata_keeps_append_edits() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("src");
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("a.txt"), "first").unwrap();
+    fs::write(source.join("b.txt"), "second").unwrap();
+    let replacement = dir.path().join("new.txt");
+    fs::write(&replacement, "replaced").unwrap();
+
+    let archive = dir.path().join("edited.xpak");
+    let archive = path_str(&archive);
+    for args in [
+        &["pak", path_str(&source), archive][..],
+        &["touch", archive, "a.txt", "--from", path_str(&replacement), "--append"],
+        &["remove", archive, "b.txt"],
+        &["update", archive, "--all"],
+    ] {
+        let result = xpak(args);
+        assert!(result.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&result.stderr));
+    }
+
+    // 删除的条目不会恢复，被替换的条目不计入总大小
+    let result = xpak(&["verify", "--porcelain=v1", archive]);
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    assert!(result.status.success(), "{}", stdout);
+
+    let output = dir.path().join("out");
+    let result = xpak(&["unpak", archive, path_str(&output)]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"replaced");
+    assert!(!output.join("b.txt").exists());
+}